@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use matrix_sdk::ruma::RoomId;
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::now_millis, config, metrics, state};
+
+/// How many days of counts are kept for each input room
+pub const RETENTION_DAYS: u64 = 30;
+
+const DAY_MINUTES: i64 = 24 * 60;
+
+/// What happened to a submission, as it is counted for the input room it was posted in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// It was posted, whatever happened to it next
+    Received,
+    /// It was published, with or without moderation
+    Forwarded,
+    /// A moderator, a filter or the moderation webhook turned it down
+    Rejected,
+    /// It was taken out of the queue without a decision, like when its sender was erased
+    Expired,
+}
+
+/// How many submissions to one input room had each outcome
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counts {
+    pub received: u64,
+    pub forwarded: u64,
+    pub rejected: u64,
+    pub expired: u64,
+}
+
+/// Counts for each day, keyed by days since the Unix epoch
+pub type ByDay = BTreeMap<u64, Counts>;
+
+impl Counts {
+    fn add(&mut self, other: &Counts) {
+        self.received += other.received;
+        self.forwarded += other.forwarded;
+        self.rejected += other.rejected;
+        self.expired += other.expired;
+    }
+}
+
+/// Count a submission to `room_id` with `outcome`, for the day it is in `utc_offset` and for the
+/// `fourwarder_room_*` metrics
+pub fn record(room_id: &RoomId, outcome: Outcome) {
+    let (name, help) = match outcome {
+        Outcome::Received => (
+            "fourwarder_room_received_total",
+            "Submissions posted, by input room",
+        ),
+        Outcome::Forwarded => (
+            "fourwarder_room_forwarded_total",
+            "Submissions published, by input room",
+        ),
+        Outcome::Rejected => (
+            "fourwarder_room_rejected_total",
+            "Submissions turned down by a moderator, filter or webhook, by input room",
+        ),
+        Outcome::Expired => (
+            "fourwarder_room_expired_total",
+            "Submissions which expired before a decision, by input room",
+        ),
+    };
+    metrics::increment(name, help, &[("room", room_id.as_str())]);
+
+    let today = today();
+    state::update(|state| {
+        let days = state.room_activity.entry(room_id.clone()).or_default();
+        days.retain(|day, _| today.saturating_sub(*day) < RETENTION_DAYS);
+        let counts = days.entry(today).or_default();
+        match outcome {
+            Outcome::Received => counts.received += 1,
+            Outcome::Forwarded => counts.forwarded += 1,
+            Outcome::Rejected => counts.rejected += 1,
+            Outcome::Expired => counts.expired += 1,
+        }
+    });
+}
+
+/// Forget the counts of days past [`RETENTION_DAYS`] in every input room, and rooms left with
+/// none, returning how many days were forgotten
+///
+/// Each room's old days are also forgotten whenever it gets a submission, this catches rooms
+/// which stopped getting any.
+pub fn prune() -> usize {
+    let today = today();
+    state::update(|state| {
+        let mut pruned = 0;
+        for days in state.room_activity.values_mut() {
+            let before = days.len();
+            days.retain(|day, _| today.saturating_sub(*day) < RETENTION_DAYS);
+            pruned += before - days.len();
+        }
+        state.room_activity.retain(|_, days| !days.is_empty());
+        pruned
+    })
+}
+
+/// The counts of each input room over the last `days` days including today, most received first
+pub fn totals(days: u64) -> Vec<(RoomId, Counts)> {
+    let today = today();
+    let mut totals: Vec<(RoomId, Counts)> = state::read(|state| {
+        state
+            .room_activity
+            .iter()
+            .map(|(room_id, by_day)| {
+                let mut total = Counts::default();
+                by_day
+                    .iter()
+                    .filter(|(day, _)| today.saturating_sub(**day) < days)
+                    .for_each(|(_, counts)| total.add(counts));
+                (room_id.clone(), total)
+            })
+            .filter(|(_, total)| *total != Counts::default())
+            .collect()
+    });
+    totals.sort_by(|(a_room, a), (b_room, b)| {
+        b.received
+            .cmp(&a.received)
+            .then_with(|| a_room.as_str().cmp(b_room.as_str()))
+    });
+    totals
+}
+
+/// Days since the Unix epoch in `utc_offset`, which is what the counts are rolled up by
+fn today() -> u64 {
+    let minutes = (now_millis() / 1000 / 60) as i64 + i64::from(config::get().utc_offset);
+    minutes.div_euclid(DAY_MINUTES).max(0) as u64
+}
@@ -0,0 +1,245 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use matrix_sdk::ruma::{EventId, RoomId, UserId};
+use serde::{Deserialize, Serialize};
+
+use crate::{clock, config, error::FourwarderError};
+
+/// One JSON record per line is appended to this file, in `data_dir`
+pub const AUDIT_FILE: &str = "4warder_audit.jsonl";
+
+/// Where the audit log is, which is only known once the config is loaded
+pub fn location() -> PathBuf {
+    config::get().data_dir.join(AUDIT_FILE)
+}
+
+/// Something worth keeping a record of
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    Approved {
+        event_id: EventId,
+        /// `None` if we had no record of the submission
+        sender: Option<UserId>,
+    },
+    Rejected {
+        event_id: EventId,
+        sender: UserId,
+        /// Given when `require_rejection_reason` is set
+        reason: Option<String>,
+    },
+    Shadowbanned {
+        user: UserId,
+    },
+    Unshadowbanned {
+        user: UserId,
+    },
+    /// Everything `user` submitted was redacted and forgotten
+    Erased {
+        user: UserId,
+        redacted: usize,
+        pending: usize,
+        failed: usize,
+    },
+    /// A submission could not be delivered to some of its destinations
+    DeliveryFailed {
+        event_id: EventId,
+        delivered: Vec<String>,
+        failed: Vec<String>,
+    },
+    /// A submission could not be forwarded, and was kept for `!retry-failed`
+    DeadLettered {
+        event_id: EventId,
+        reason: String,
+    },
+    /// Dead-lettered submissions were forwarded again, and `failed` of them failed again
+    RetriedFailed {
+        retried: usize,
+        failed: usize,
+    },
+    /// `!reload-filters` read `blocklist_file` again, which left `blocklist` with `entries`
+    FiltersReloaded {
+        entries: usize,
+    },
+    /// `!ratelimit set` changed `rate_limit_per_minute`
+    RateLimitSet {
+        per_minute: u32,
+    },
+    /// `!ratelimit reset` let `user` send a full minute's worth of submissions again
+    RateLimitReset {
+        user: UserId,
+    },
+    /// `!schedule` approved a pending submission to be published at `at`, in milliseconds since
+    /// the Unix epoch
+    Scheduled {
+        event_id: EventId,
+        at: u64,
+    },
+    /// `!unschedule` put a scheduled submission back in the queue
+    Unscheduled {
+        event_id: EventId,
+    },
+    /// `!reveal` looked up who is behind a pseudonym
+    Revealed {
+        pseudonym: String,
+        user: UserId,
+    },
+    /// Someone tried to use a command they aren't allowed to
+    Denied {
+        command: String,
+    },
+    /// A rejected submission was put back in the queue
+    Requeued {
+        event_id: EventId,
+        new_event_id: EventId,
+    },
+    /// `!train` posted a training submission to the mod room
+    TrainingPosted {
+        event_id: EventId,
+    },
+    /// The number of the next published submission was changed
+    SeqSet {
+        seq: u64,
+    },
+    /// A command in `confirm_commands` was given, and waits to be confirmed
+    ConfirmationRequested {
+        command: String,
+    },
+    /// A command in `confirm_commands` was confirmed, and run
+    Confirmed {
+        command: String,
+    },
+    /// Every session of the bot's account but the running one was logged out
+    LoggedOutOthers {
+        devices: Vec<String>,
+    },
+    /// All forwarding was paused
+    Paused,
+    /// `!mute-room` had the messages in an input room ignored
+    Muted {
+        room_id: RoomId,
+    },
+    /// `!unmute-room` had the messages in an input room taken again
+    Unmuted {
+        room_id: RoomId,
+    },
+    /// Forwarding was resumed, and what was deferred meanwhile replayed
+    Resumed {
+        deferred: usize,
+    },
+    /// Recent history in the input rooms was read back, and the submissions we hadn't handled
+    /// were
+    Backfilled {
+        forwarded: usize,
+    },
+    /// A pending submission was held for discussion
+    Held {
+        event_id: EventId,
+    },
+    /// The last hold on a pending submission was removed
+    Released {
+        event_id: EventId,
+    },
+    /// `user` may approve and reject submissions until `expires_at`, in milliseconds since the
+    /// Unix epoch
+    Delegated {
+        user: UserId,
+        expires_at: u64,
+    },
+    /// A delegation ran out
+    DelegationExpired {
+        user: UserId,
+    },
+    /// A submission from a shadowbanned user was silently dropped
+    ShadowbannedMessage {
+        event_id: EventId,
+        sender: UserId,
+    },
+    /// Media in an input room was dropped for being on the hash denylist, or for not being
+    /// checked against it when `hash_denylist_fail_mode` is `closed`
+    HashDenied {
+        event_id: EventId,
+        sender: UserId,
+        /// The SHA-256 of the file the denylist has, if it was looked up at all
+        sha256: Option<String>,
+    },
+    /// A submission was dropped for repeating a recent one, going by `content_dedup`
+    Repeated {
+        event_id: EventId,
+        sender: UserId,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Record {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// The moderator responsible, `None` for things the bot did on its own
+    pub actor: Option<UserId>,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// Append a record to the audit log
+///
+/// Failing to write is logged rather than returned, so moderation carries on regardless.
+pub fn record(actor: Option<&UserId>, action: Action) {
+    let record = Record {
+        timestamp: clock::now_millis(),
+        actor: actor.cloned(),
+        action,
+    };
+
+    if let Err(e) = append(&record) {
+        tracing::error!(
+            "Could not write to {}: {}, {:?}",
+            location().display(),
+            e,
+            record
+        );
+    }
+}
+
+fn append(record: &Record) -> Result<(), FourwarderError> {
+    // Tests keep no audit log
+    if cfg!(test) {
+        return Ok(());
+    }
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(location())?
+        .write_all(&line)?;
+
+    Ok(())
+}
+
+/// The last `limit` records, oldest first
+///
+/// Lines which can't be parsed, say from a newer version of the bot, are skipped.
+pub fn recent(limit: usize) -> Result<Vec<Record>, FourwarderError> {
+    let location = location();
+    let file = match std::fs::File::open(&location) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        match serde_json::from_str(&line?) {
+            Ok(record) => records.push(record),
+            Err(e) => tracing::warn!("Skipping unreadable line in {}: {}", location.display(), e),
+        }
+    }
+    let skip = records.len().saturating_sub(limit);
+    records.drain(..skip);
+
+    Ok(records)
+}
@@ -0,0 +1,145 @@
+use std::{
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use matrix_sdk::{ruma::DeviceIdBox, Client};
+
+use crate::{
+    clock, config,
+    error::{self, ErrorClass, FourwarderError},
+};
+
+pub const DEVICE_NAME: &str = "4warder_bot";
+
+/// [`DEVICE_NAME`] along with the hostname, so the sessions of several instances can be told apart
+pub fn default_device_name() -> String {
+    let hostname = std::fs::read_to_string("/etc/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|hostname| hostname.trim().to_owned())
+        .filter(|hostname| !hostname.is_empty());
+
+    match hostname {
+        Some(hostname) => format!("{} ({})", DEVICE_NAME, hostname),
+        None => DEVICE_NAME.to_owned(),
+    }
+}
+
+/// How many times in a row we log in again before assuming the credentials are bad
+const MAX_REAUTH_ATTEMPTS: u32 = 5;
+
+/// Bumped on every successful login, so requests that failed with the same stale token only
+/// cause one login between them
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Logins since the last request which succeeded
+static ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+
+/// When the last login succeeded, which is how old our access token is
+static LOGGED_IN_AT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Held while logging in again
+static REAUTH: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// The current login generation, to pass to [`reauthenticate`] if a request made with it fails
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+/// Log in with the configured credentials, keeping our device id if we already have one
+pub async fn login(client: &Client) -> Result<(), matrix_sdk::Error> {
+    let device_id = client.device_id().await;
+    login_as(client, device_id).await
+}
+
+/// Log a freshly built `client` in as the same device as the `old` one it replaces
+///
+/// The SDK doesn't hand out the old access token, so this is a new login on the same device
+/// rather than a restored session.
+pub async fn login_replacing(client: &Client, old: &Client) -> Result<(), matrix_sdk::Error> {
+    login_as(client, old.device_id().await).await
+}
+
+async fn login_as(
+    client: &Client,
+    device_id: Option<DeviceIdBox>,
+) -> Result<(), matrix_sdk::Error> {
+    let config = config::get();
+
+    client
+        .login(
+            &config.username,
+            &config.password,
+            device_id.as_ref().map(|id| id.as_str()),
+            Some(&config.device_name),
+        )
+        .await?;
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    *LOGGED_IN_AT.lock().unwrap() = Some(clock::now());
+
+    Ok(())
+}
+
+/// When we last logged in, if we have yet
+pub fn logged_in_at() -> Option<Instant> {
+    *LOGGED_IN_AT.lock().unwrap()
+}
+
+/// Log in again after a request made during `generation` was rejected for a bad token
+///
+/// Gives up with an error once we have logged in [`MAX_REAUTH_ATTEMPTS`] times without a
+/// request succeeding in between. A login which couldn't reach the homeserver or was rate
+/// limited uses up an attempt and is tried again, but one which was refused is returned at once.
+pub async fn reauthenticate(client: &Client, generation: u64) -> Result<(), FourwarderError> {
+    let _guard = REAUTH.lock().await;
+
+    // Someone else already logged in again while we were waiting
+    if self::generation() != generation {
+        return Ok(());
+    }
+
+    loop {
+        let attempt = ATTEMPTS.fetch_add(1, Ordering::SeqCst) + 1;
+        if attempt > MAX_REAUTH_ATTEMPTS {
+            return Err(FourwarderError::Logic(
+                "Our access token keeps getting rejected, giving up on logging in again",
+            ));
+        }
+
+        // Back off in case the server is rejecting us for a reason logging in won't fix
+        let delay = Duration::from_secs(1 << (attempt - 1));
+        tracing::warn!(
+            "Access token rejected, logging in again in {}s (attempt {} of {})",
+            delay.as_secs(),
+            attempt,
+            MAX_REAUTH_ATTEMPTS
+        );
+        tokio::time::sleep(delay).await;
+
+        match login(client).await {
+            Ok(()) => break,
+            Err(e) => match error::classify(&e) {
+                ErrorClass::Transport => tracing::warn!("Could not log in again: {}", e),
+                ErrorClass::RateLimited(retry_after) => {
+                    tracing::warn!("Could not log in again: {}", e);
+                    tokio::time::sleep(retry_after).await;
+                }
+                ErrorClass::Auth | ErrorClass::Permanent => return Err(e.into()),
+            },
+        }
+    }
+    tracing::warn!("Logged in again");
+
+    Ok(())
+}
+
+/// Record that a request went through, so the next rejected token starts counting attempts
+/// from scratch
+pub fn succeeded() {
+    ATTEMPTS.store(0, Ordering::SeqCst);
+}
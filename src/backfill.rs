@@ -0,0 +1,147 @@
+use matrix_sdk::{
+    ruma::{
+        api::client::r0::message::get_message_events,
+        events::{room::message::MessageType, AnyMessageEvent, AnyRoomEvent},
+        UInt,
+    },
+    Client,
+};
+
+use crate::{
+    config::Config,
+    dedup,
+    error::FourwarderError,
+    marker,
+    pause::{self, Deferred},
+    rooms, timeout,
+};
+
+/// The most events asked for in one request while backfilling
+const PAGE_SIZE: u32 = 100;
+
+/// What a backfill did
+pub struct Backfilled {
+    /// Submissions run through the pipeline, which may have been dropped by filters
+    pub forwarded: usize,
+    /// Submissions skipped as they were already handled
+    pub skipped: usize,
+}
+
+/// Read back the last `count` events in each input room, and handle the submissions among them
+/// we haven't handled yet, oldest first
+///
+/// This is best effort, a room which can't be read is logged and skipped. Muted rooms are
+/// skipped too, so their messages are still there to backfill once they are unmuted.
+pub async fn run(
+    client: &Client,
+    config: &Config,
+    count: usize,
+) -> Result<Backfilled, FourwarderError> {
+    let own_user_id = client
+        .user_id()
+        .await
+        .ok_or(FourwarderError::Logic("We backfilled before logging in"))?;
+    let from = client.sync_token().await.ok_or(FourwarderError::Logic(
+        "We backfilled before our first sync",
+    ))?;
+
+    let mut backfilled = Backfilled {
+        forwarded: 0,
+        skipped: 0,
+    };
+    for input_room in &config.input_rooms {
+        let room_id = &input_room.room_id;
+        if config.is_muted(room_id) {
+            continue;
+        }
+        let room = match rooms::joined(client, room_id).await {
+            Ok(room) => room,
+            Err(e) => {
+                tracing::warn!("Could not backfill {}: {}", room_id, e);
+                continue;
+            }
+        };
+
+        let mut submissions = Vec::new();
+        let mut token = from.clone();
+        let mut read = 0;
+        while read < count {
+            let mut request = get_message_events::Request::backward(room_id, &token);
+            request.limit = UInt::from(PAGE_SIZE.min((count - read) as u32));
+            let response = match timeout::within(
+                "fetching history",
+                config.fetch_timeout,
+                room.messages(request),
+            )
+            .await
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::warn!("Could not backfill {}: {}", room_id, e);
+                    break;
+                }
+            };
+            if response.chunk.is_empty() {
+                break;
+            }
+            read += response.chunk.len();
+
+            for event in &response.chunk {
+                let msg = match event.deserialize() {
+                    Ok(AnyRoomEvent::Message(AnyMessageEvent::RoomMessage(msg)))
+                        if msg.sender != own_user_id
+                            && marker::instance_of_event(event.json()).is_none() =>
+                    {
+                        msg
+                    }
+                    _ => continue,
+                };
+                if let MessageType::Text(text) = msg.content.msgtype {
+                    submissions.push((msg.event_id, msg.sender, msg.origin_server_ts, text));
+                }
+            }
+            match response.end {
+                Some(end) if end != token => token = end,
+                _ => break,
+            }
+        }
+
+        tracing::info!(
+            "Read back {} events in {}, {} were submissions",
+            read,
+            room_id,
+            submissions.len()
+        );
+        // Paging goes backwards, but submissions are handled in the order they were made
+        for (event_id, sender, origin_server_ts, text) in submissions.into_iter().rev() {
+            if !dedup::first_time(&event_id, config.dedup_capacity) {
+                backfilled.skipped += 1;
+                continue;
+            }
+            backfilled.forwarded += 1;
+            if pause::is_paused() {
+                pause::defer(Deferred::Submission {
+                    room_id: room_id.clone(),
+                    event_id,
+                    sender,
+                    origin_server_ts,
+                    text,
+                });
+            } else if let Err(e) = crate::forward(
+                client,
+                config,
+                room_id,
+                event_id,
+                sender,
+                origin_server_ts,
+                text,
+            )
+            .await
+            {
+                tracing::warn!("Could not backfill a submission in {}: {}", room_id, e);
+            }
+        }
+    }
+
+    Ok(backfilled)
+}
@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use crate::{
+    error::FourwarderError,
+    filters::{FilterDecision, IncomingMessage, MessageFilter},
+    normalize::normalize_for_matching,
+};
+
+/// Drops submissions containing any of a list of words or phrases
+#[derive(Clone, Debug)]
+pub struct Blocklist {
+    /// Normalized and lowercased
+    words: Vec<String>,
+}
+
+impl Blocklist {
+    pub fn new(words: &[String]) -> Self {
+        Blocklist {
+            words: words
+                .iter()
+                .map(|word| normalize_for_matching(word).to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl MessageFilter for Blocklist {
+    fn name(&self) -> &'static str {
+        "blocklist"
+    }
+
+    fn evaluate(&self, msg: &IncomingMessage) -> FilterDecision {
+        let body = normalize_for_matching(&msg.text.body).to_lowercase();
+
+        match self.words.iter().find(|word| contains_word(&body, word)) {
+            Some(word) => FilterDecision::Drop(format!("contains blocked word \"{}\"", word)),
+            None => FilterDecision::Allow,
+        }
+    }
+}
+
+/// The words and phrases in a `blocklist_file`, one per line
+///
+/// Blank lines and lines starting with `#` are skipped, so the file can have comments.
+pub fn read_file(path: &Path) -> Result<Vec<String>, FourwarderError> {
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        tracing::error!("Could not read `blocklist_file` {}: {}", path.display(), e);
+        FourwarderError::Config("`blocklist_file` could not be read")
+    })?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Whether `word` appears in `text` on its own, rather than as part of a longer word
+pub fn contains_word(text: &str, word: &str) -> bool {
+    word_matches(text, word).next().is_some()
+}
+
+/// The byte offset of every place `word` appears in `text` on its own
+pub fn word_matches<'a>(text: &'a str, word: &'a str) -> impl Iterator<Item = usize> + 'a {
+    text.match_indices(word)
+        .map(|(start, _)| start)
+        .filter(move |start| {
+            let before = text[..*start].chars().next_back();
+            let after = text[start + word.len()..].chars().next();
+            !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+        })
+}
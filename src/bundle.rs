@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    clock,
+    config::{self, CONFIG_LOCATION},
+    error::FourwarderError,
+    state::{self, STATE_SCHEMA_VERSION},
+};
+
+/// What every bundle says it is, so some other JSON file is never imported as one
+const FORMAT: &str = "4warder-state-bundle";
+
+/// The version of the bundle around the state, which has a `state_schema_version` of its own
+///
+/// Bump it whenever a change to `Bundle` would be misread by an older version.
+const BUNDLE_VERSION: u64 = 1;
+
+/// The bot's state as `export-state` writes it, to be carried to another host or account
+///
+/// Nothing secret is in the state, the credentials stay in the config, which isn't included.
+/// Neither is the audit log, which is history rather than anything the bot works from.
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    format: String,
+    bundle_version: u64,
+    /// Milliseconds since the Unix epoch
+    exported_at: u64,
+    /// The state as the state file has it, `state_schema_version` and all
+    state: Value,
+}
+
+/// Write the state in `data_dir` to `path` as a bundle, for `4warder export-state`
+pub fn export(path: &str) -> Result<(), FourwarderError> {
+    config::load_offline(CONFIG_LOCATION)?;
+    state::load()?;
+
+    let bundle = Bundle {
+        format: FORMAT.to_owned(),
+        bundle_version: BUNDLE_VERSION,
+        exported_at: clock::now_millis(),
+        state: state::to_json()?,
+    };
+    std::fs::write(path, serde_json::to_vec_pretty(&bundle)?)?;
+
+    println!("Exported {} to {}", state::location().display(), path);
+    Ok(())
+}
+
+/// Replace the state in `data_dir` with the bundle at `path`, for `4warder import-state`
+///
+/// A bundle from an older version is upgraded like an old state file would be. One from a newer
+/// version, or which isn't a bundle at all, is refused before anything is touched. The state it
+/// replaces is kept next to the state file as `.bak`.
+pub fn import(path: &str) -> Result<(), FourwarderError> {
+    let bundle: Bundle = serde_json::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|_| FourwarderError::State("The file is not a state bundle from `export-state`"))?;
+    if bundle.format != FORMAT {
+        return Err(FourwarderError::State(
+            "The file is not a state bundle from `export-state`",
+        ));
+    }
+    if bundle.bundle_version > BUNDLE_VERSION {
+        return Err(FourwarderError::State(
+            "The bundle was exported by a newer version of the bot, upgrade this one to import it",
+        ));
+    }
+    let version = bundle.state.get("state_schema_version").and_then(Value::as_u64);
+    if version.is_some_and(|version| version > STATE_SCHEMA_VERSION) {
+        return Err(FourwarderError::State(
+            "The state in the bundle is from a newer version of the bot, upgrade this one to import it",
+        ));
+    }
+
+    config::load_offline(CONFIG_LOCATION)?;
+    config::get().prepare_data_dir()?;
+    let imported = state::migrate(bundle.state)?;
+
+    let location = state::location();
+    if location.exists() {
+        let backup = location.with_extension("json.bak");
+        std::fs::copy(&location, &backup)?;
+        println!("Kept the state it replaces in {}", backup.display());
+    }
+    state::replace(imported)?;
+
+    println!("Imported {} into {}", path, location.display());
+    Ok(())
+}
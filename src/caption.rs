@@ -0,0 +1,80 @@
+use matrix_sdk::ruma::events::room::message::MessageType;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+/// Extensions common enough that a `body` ending in one is taken for a file's name even if it has
+/// spaces, like `Screenshot 2024-05-01 at 10.00.00.png`
+const COMMON_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "webp", "heic", "svg", "mp4", "mov", "webm", "mkv", "mp3", "m4a",
+    "ogg", "opus", "wav", "flac", "pdf", "txt", "zip",
+];
+
+/// Whether a message carries a file, whose `body` may be a caption
+pub fn is_media(msgtype: &MessageType) -> bool {
+    matches!(
+        msgtype,
+        MessageType::Image(_) | MessageType::File(_) | MessageType::Video(_) | MessageType::Audio(_)
+    )
+}
+
+/// The caption of a media message, or `None` if it has none
+///
+/// Clients put the file's name in `body` when there is no caption. Where the content also gives
+/// a `filename`, a `body` which differs from it is the caption. Otherwise a `body` which looks
+/// like a file's name is taken for one, and only counts as a caption without `ignore_filenames`.
+pub fn of(msgtype: &MessageType, raw: &RawValue, ignore_filenames: bool) -> Option<String> {
+    let body = match msgtype {
+        MessageType::Image(content) => &content.body,
+        MessageType::File(content) => &content.body,
+        MessageType::Video(content) => &content.body,
+        MessageType::Audio(content) => &content.body,
+        _ => return None,
+    }
+    .trim();
+    if body.is_empty() {
+        return None;
+    }
+
+    let is_filename = match filename(raw) {
+        Some(filename) => filename.trim() == body,
+        None => looks_like_filename(body),
+    };
+    if is_filename && ignore_filenames {
+        return None;
+    }
+    Some(body.to_owned())
+}
+
+/// The `filename` the content gives separately from `body`, which newer clients send
+fn filename(raw: &RawValue) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Event {
+        content: Content,
+    }
+    #[derive(Deserialize)]
+    struct Content {
+        filename: Option<String>,
+    }
+
+    let event: Event = serde_json::from_str(raw.get()).ok()?;
+    event.content.filename
+}
+
+/// Whether `body` is a single line ending in a file extension, like `IMG_1234.jpg`
+///
+/// With spaces in it, only [`COMMON_EXTENSIONS`] count, so a caption ending in `v2.0` or a domain
+/// isn't mistaken for a file's name.
+fn looks_like_filename(body: &str) -> bool {
+    let (stem, extension) = match body.rsplit_once('.') {
+        Some(split) => split,
+        None => return false,
+    };
+    let is_extension = (1..=5).contains(&extension.len())
+        && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        && extension.chars().any(|c| c.is_ascii_alphabetic());
+    if stem.is_empty() || !is_extension || body.contains('\n') {
+        return false;
+    }
+    !body.contains(char::is_whitespace)
+        || COMMON_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+}
@@ -0,0 +1,119 @@
+use matrix_sdk::ruma::{EventId, RoomId, UserId};
+
+use crate::{config::Config, delegation, user_pattern::UserPattern};
+
+/// A `[[categories]]` entry, which approving with its emoji publishes to
+#[derive(Debug, Clone)]
+pub struct Category {
+    pub name: String,
+    /// Normalized with [`crate::normalize::normalize_emoji`]
+    pub approval_emoji: String,
+    pub output_room_id: RoomId,
+    /// Approvals toward this category are counted apart from the others, and it is published
+    /// once their weights add up to this
+    pub required_approval_weight: u32,
+    /// Who may approve toward this category, the top-level `moderators` if this is empty
+    pub moderators: Vec<UserPattern>,
+    /// Whether it is published in a thread of its own in its output room, rather than the main
+    /// timeline
+    pub output_thread: bool,
+    /// The thread to publish in, rather than one the bot starts
+    pub output_thread_root: Option<EventId>,
+}
+
+impl Category {
+    /// Whether `user` may approve submissions toward this category
+    pub fn allows(&self, config: &Config, user: &UserId) -> bool {
+        if self.moderators.is_empty() {
+            delegation::may_moderate(config, user)
+        } else {
+            self.moderators.iter().any(|pattern| pattern.matches(user))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        normalize::normalize_emoji,
+        quorum::{self, Vote},
+        testing::{self, user},
+    };
+
+    const CATEGORIES: &str = r#"
+moderators = ["@a:example.org", "@b:example.org"]
+
+[[categories]]
+name = "general"
+approval_emoji = "💬"
+output_room_id = "!general:example.org"
+
+[[categories]]
+name = "announcements"
+approval_emoji = "📢"
+output_room_id = "!announcements:example.org"
+required_approval_weight = 2
+moderators = ["@lead:example.org", "@a:example.org"]
+"#;
+
+    #[test]
+    fn each_category_has_its_own_quorum() {
+        let _test = testing::isolate();
+        let config = testing::config(CATEGORIES);
+        let general = config.category(&normalize_emoji("💬")).unwrap();
+        let announcements = config.category(&normalize_emoji("📢")).unwrap();
+        let mod_event_id = testing::queue("$a:example.org", "@sub:example.org", "hello");
+        let moderator = user("@a:example.org");
+
+        assert!(!quorum::vote(
+            &config,
+            &mod_event_id,
+            &moderator,
+            Vote::ApproveAs(announcements)
+        ));
+        assert!(quorum::vote(
+            &config,
+            &mod_event_id,
+            &moderator,
+            Vote::ApproveAs(general)
+        ));
+        assert!(quorum::vote(
+            &config,
+            &mod_event_id,
+            &user("@lead:example.org"),
+            Vote::ApproveAs(announcements)
+        ));
+    }
+
+    #[test]
+    fn categories_can_have_their_own_moderators() {
+        let _test = testing::isolate();
+        let config = testing::config(CATEGORIES);
+        let general = config.category(&normalize_emoji("💬")).unwrap();
+        let announcements = config.category(&normalize_emoji("📢")).unwrap();
+
+        assert!(general.allows(&config, &user("@b:example.org")));
+        assert!(!general.allows(&config, &user("@lead:example.org")));
+        assert!(announcements.allows(&config, &user("@lead:example.org")));
+        assert!(!announcements.allows(&config, &user("@b:example.org")));
+    }
+
+    #[test]
+    fn categories_are_checked_on_load() {
+        let invalid = |extra: &str| testing::try_config(extra).is_err();
+        let category = |name: &str, emoji: &str| {
+            format!(
+                "[[categories]]\nname = \"{}\"\napproval_emoji = \"{}\"\noutput_room_id = \"!x:example.org\"\n",
+                name, emoji
+            )
+        };
+
+        assert!(!invalid(&category("a", "💬")));
+        assert!(invalid(&format!("{}{}", category("a", "💬"), category("a", "📢"))));
+        assert!(invalid(&format!("{}{}", category("a", "💬"), category("b", "💬"))));
+        assert!(invalid(&category("a", "✅")));
+        assert!(invalid(
+            "[[categories]]\nname = \"a\"\napproval_emoji = \"💬\"\noutput_room_id = \"x\"\n"
+        ));
+    }
+}
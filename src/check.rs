@@ -0,0 +1,155 @@
+use crate::{
+    config::{self, Config},
+    error::FourwarderError,
+    space,
+};
+
+/// What `output_template` can contain
+const PLACEHOLDERS: [&str; 3] = ["body", "seq", "pseudonym"];
+
+/// Check the config file at `path` like startup would, without connecting, for `4warder check`
+///
+/// Prints `config OK` and a summary, or the problems which would keep the bot from starting.
+/// Validation stops at the first invalid setting, so a config can have more problems than are
+/// listed.
+pub fn run(path: &str) -> Result<(), FourwarderError> {
+    let mut source: toml::Value = match std::fs::read_to_string(path)
+        .map_err(FourwarderError::from)
+        .and_then(|text| toml::from_str(&text).map_err(FourwarderError::from))
+    {
+        Ok(source) => source,
+        Err(e) => return fail(path, &[e.to_string()], &[]),
+    };
+
+    let mut warnings = Vec::new();
+    for name in space::stand_in(&mut source) {
+        warnings.push(format!(
+            "`space:{}` can only be looked up in `space_id` when connecting",
+            name
+        ));
+    }
+    let unknown = config::unknown_keys(&source);
+    // With `strict`, the unknown keys are the problem
+    let strict = !unknown.is_empty() && source.get("strict") == Some(&toml::Value::Boolean(true));
+    let unknown = unknown.iter().map(|key| format!("unknown key `{}`", key));
+    let config = match config::validate(&source) {
+        Ok(config) => config,
+        Err(_) if strict => return fail(path, &unknown.collect::<Vec<_>>(), &warnings),
+        Err(FourwarderError::Schema(problems)) => return fail(path, &problems, &warnings),
+        Err(e) => return fail(path, &[e.to_string()], &warnings),
+    };
+    warnings.extend(unknown.map(|key| format!("{}, which is ignored", key)));
+    warnings.extend(suspicious(&config));
+
+    println!("config OK: {}", path);
+    for line in summary(&config) {
+        println!("  {}", line);
+    }
+    for warning in warnings {
+        println!("warning: {}", warning);
+    }
+    Ok(())
+}
+
+fn fail(path: &str, problems: &[String], warnings: &[String]) -> Result<(), FourwarderError> {
+    println!("{} has problems:", path);
+    for problem in problems {
+        println!("- {}", problem);
+    }
+    for warning in warnings {
+        println!("warning: {}", warning);
+    }
+    Err(FourwarderError::Config("The config has problems"))
+}
+
+/// What the bot would do with `config`, one line each
+fn summary(config: &Config) -> Vec<String> {
+    let mut lines: Vec<String> = config
+        .input_rooms
+        .iter()
+        .map(|room| {
+            let settings = config.input_room(&room.room_id).unwrap();
+            format!(
+                "input room {} -> {}{}",
+                room.room_id,
+                settings.output_room_id,
+                if settings.moderate {
+                    " (moderated)"
+                } else {
+                    ""
+                }
+            )
+        })
+        .collect();
+    lines.push(format!("mod room {}", config.mod_room_id));
+    for category in &config.categories {
+        lines.push(format!(
+            "category `{}`: {} -> {}",
+            category.name, category.approval_emoji, category.output_room_id
+        ));
+    }
+    let filters: Vec<&str> = config.filters.iter().map(|filter| filter.name()).collect();
+    if filters.is_empty() {
+        lines.push("no filters".to_owned());
+    } else {
+        lines.push(format!("filters: {}", filters.join(", ")));
+    }
+    if config.dry_run {
+        lines.push("dry run, nothing is sent".to_owned());
+    }
+    lines
+}
+
+/// Settings the bot starts with, but which are most likely mistakes
+fn suspicious(config: &Config) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for room in &config.input_rooms {
+        if room.room_id == config.mod_room_id {
+            warnings.push(format!("input room {} is also the mod room", room.room_id));
+        }
+        if config.output_room_of(&room.room_id) == &room.room_id {
+            warnings.push(format!(
+                "input room {} is also its own output room",
+                room.room_id
+            ));
+        }
+    }
+    // Each room's own, and the top-level one, shown once however many rooms use it
+    let mut templates: Vec<&str> = config
+        .input_rooms
+        .iter()
+        .filter_map(|room| config.output_template_of(&room.room_id))
+        .chain(config.output_template.as_deref())
+        .collect();
+    templates.sort_unstable();
+    templates.dedup();
+    for template in templates {
+        for placeholder in placeholders(template) {
+            if !PLACEHOLDERS.contains(&placeholder) {
+                warnings.push(format!(
+                    "`output_template` has `{{{}}}`, which is left as it is",
+                    placeholder
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// The names of the `{name}` placeholders in `template`
+fn placeholders(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        match rest.find(['{', '}']) {
+            Some(end) if rest[end..].starts_with('}') => {
+                names.push(&rest[..end]);
+                rest = &rest[end + 1..];
+            }
+            Some(end) => rest = &rest[end..],
+            None => break,
+        }
+    }
+    names
+}
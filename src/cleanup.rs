@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+use matrix_sdk::ruma::UserId;
+
+use crate::{
+    activity,
+    config::{self, Config},
+    content_dedup, dead_letter, decided, dedup, history, karma, leader, pause, pending, pseudonym,
+    quota,
+    state::{self, State},
+};
+
+/// How often the state is pruned
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A store in the state which grows with use, with how it is kept bounded and how `!erase`
+/// forgets a sender in it
+struct Store {
+    /// What the prune log and `!status` call its entries
+    name: &'static str,
+    size: fn(&State) -> usize,
+    /// Forget what the retention settings say is no longer needed, returning how many entries
+    /// were forgotten
+    prune: fn(&Config) -> usize,
+    /// Forget everything from `sender`
+    erase: fn(&UserId),
+}
+
+/// Every store which grows with use, so pruning and `!erase` reach the same ones
+///
+/// Pending submissions are never pruned, and neither are decisions still within
+/// `decided_retention_secs`, so `!requeue` works for as long as it says it does. What was
+/// deferred by `!pause` is kept for `!resume`.
+const STORES: &[Store] = &[
+    Store {
+        name: "pending submissions",
+        size: |state| state.pending.len(),
+        prune: |_| 0,
+        erase: |sender| {
+            pending::remove_sender(sender);
+        },
+    },
+    Store {
+        name: "decisions",
+        size: |state| state.decided.len(),
+        prune: |config| decided::prune(config.decided_retention),
+        erase: |sender| {
+            decided::remove_sender(sender);
+        },
+    },
+    Store {
+        name: "published submissions",
+        size: |state| state.published.len(),
+        prune: |config| config.published_retention.map_or(0, history::prune),
+        // `!erase` forgets each one as it redacts it, so one it couldn't is tried again next time
+        erase: |_| {},
+    },
+    Store {
+        name: "processed events",
+        size: |state| state.processed.len(),
+        prune: |config| dedup::prune(config.dedup_capacity),
+        // Only event ids, nothing about who sent them
+        erase: |_| {},
+    },
+    Store {
+        name: "seen submissions",
+        size: |state| state.content_seen.len(),
+        prune: |config| content_dedup::prune(config.content_dedup_window),
+        erase: content_dedup::remove_sender,
+    },
+    Store {
+        name: "karma entries",
+        size: |state| state.karma.len(),
+        prune: |_| karma::prune(),
+        erase: karma::reset,
+    },
+    Store {
+        name: "pseudonyms",
+        size: |state| state.pseudonyms.len(),
+        prune: |config| config.pseudonym_retention.map_or(0, pseudonym::prune),
+        erase: pseudonym::forget,
+    },
+    Store {
+        name: "dead letters",
+        size: |state| state.dead_letters.len(),
+        prune: |config| config.dead_letter_retention.map_or(0, dead_letter::prune),
+        erase: dead_letter::remove_sender,
+    },
+    Store {
+        name: "deferred actions",
+        size: |state| state.deferred.len(),
+        prune: |_| 0,
+        erase: pause::remove_sender,
+    },
+    Store {
+        name: "quota senders",
+        size: |state| state.submission_quota.len(),
+        prune: |config| quota::prune(config.submission_quota_window),
+        erase: quota::remove_sender,
+    },
+    Store {
+        name: "activity days",
+        size: |state| state.room_activity.len(),
+        prune: |_| activity::prune(),
+        // Only counts for each room, nothing about who sent what
+        erase: |_| {},
+    },
+];
+
+/// Prune the state every [`CLEANUP_INTERVAL`] for as long as the bot runs, so it stays bounded
+/// however long the bot runs
+///
+/// The first prune is right away, which catches anything a lowered retention left behind.
+pub fn spawn() {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if leader::is_active() {
+                prune(&config::get());
+            }
+        }
+    });
+}
+
+/// Forget what every retention setting says is no longer needed, logging how much was forgotten
+fn prune(config: &Config) {
+    let pruned: Vec<String> = STORES
+        .iter()
+        .map(|store| (store.name, (store.prune)(config)))
+        .filter(|(_, count)| *count > 0)
+        .map(|(store, count)| format!("{} {}", count, store))
+        .collect();
+    if !pruned.is_empty() {
+        tracing::info!("Pruned the state of {}", pruned.join(", "));
+    }
+}
+
+/// Forget everything from `sender` in every store, for `!erase`
+pub fn erase(sender: &UserId) {
+    for store in STORES {
+        (store.erase)(sender);
+    }
+}
+
+/// How many entries each store in the state has, for `!status`
+pub fn sizes() -> Vec<(&'static str, usize)> {
+    state::read(|state| {
+        STORES
+            .iter()
+            .map(|store| (store.name, (store.size)(state)))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::ruma::{
+        events::room::message::TextMessageEventContent, MilliSecondsSinceUnixEpoch, UInt,
+    };
+
+    use super::*;
+    use crate::{
+        content_dedup::ContentDedup,
+        dead_letter::DeadLetter,
+        pause::Deferred,
+        testing::{self, event, room, user, START_MILLIS},
+    };
+
+    fn submission(event_id: &str, sender: &str) -> Deferred {
+        Deferred::Submission {
+            room_id: room("!input:example.org"),
+            event_id: event(event_id),
+            sender: user(sender),
+            origin_server_ts: MilliSecondsSinceUnixEpoch(UInt::new_wrapping(START_MILLIS)),
+            text: TextMessageEventContent::plain("my address is 1 Main St"),
+        }
+    }
+
+    #[test]
+    fn erase_forgets_dead_letters_deferred_and_seen_submissions() {
+        let _test = testing::isolate();
+        let config = testing::config("daily_submission_quota = 5");
+        for sender in ["@sub:example.org", "@other:example.org"] {
+            let id = format!("${}", &sender[1..]);
+            state::update(|state| {
+                state.dead_letters.push(DeadLetter {
+                    submission: submission(&format!("{}-dead", id), sender),
+                    reason: "M_FORBIDDEN".to_owned(),
+                    attempts: 3,
+                    failed_at: START_MILLIS,
+                });
+            });
+            pause::defer(submission(&format!("{}-deferred", id), sender));
+            content_dedup::first_time(
+                ContentDedup::Composite,
+                Duration::from_secs(60),
+                10,
+                &user(sender),
+                sender,
+                None,
+            );
+            quota::count(&config, &user(sender));
+        }
+
+        erase(&user("@sub:example.org"));
+
+        let other = user("@other:example.org");
+        state::read(|state| {
+            assert_eq!(state.dead_letters.len(), 1);
+            assert_eq!(state.dead_letters[0].submission.sender(), Some(&other));
+            assert_eq!(state.deferred.len(), 1);
+            assert_eq!(state.deferred[0].sender(), Some(&other));
+            assert_eq!(state.content_seen.len(), 1);
+            assert_eq!(
+                state.submission_quota.keys().collect::<Vec<_>>(),
+                vec![&other]
+            );
+        });
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    sync::{Arc, LazyLock, RwLock},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Where time comes from, so everything which depends on it reads it the same way
+pub trait Clock: Send + Sync {
+    /// Milliseconds since the Unix epoch, for timestamps which are stored or compared with
+    /// the homeserver's
+    fn now_millis(&self) -> u64;
+    /// For measuring how long something took within this run
+    fn now(&self) -> Instant;
+}
+
+/// The system's clocks
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64)
+    }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+static CLOCK: LazyLock<RwLock<Arc<dyn Clock>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(SystemClock)));
+
+pub fn now_millis() -> u64 {
+    CLOCK.read().unwrap().now_millis()
+}
+
+pub fn now() -> Instant {
+    CLOCK.read().unwrap().now()
+}
+
+/// Read the time from `clock` from now on
+#[cfg(test)]
+pub fn set(clock: Arc<dyn Clock>) {
+    *CLOCK.write().unwrap() = clock;
+}
+
+/// A clock which only moves when it is told to, for tests
+#[cfg(test)]
+pub struct FakeClock {
+    start_millis: u64,
+    start: Instant,
+    elapsed: std::sync::Mutex<std::time::Duration>,
+}
+
+#[cfg(test)]
+impl FakeClock {
+    /// A clock standing at `start_millis` since the Unix epoch
+    pub fn new(start_millis: u64) -> Self {
+        FakeClock {
+            start_millis,
+            start: Instant::now(),
+            elapsed: std::sync::Mutex::new(std::time::Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, by: std::time::Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now_millis(&self) -> u64 {
+        self.start_millis + self.elapsed.lock().unwrap().as_millis() as u64
+    }
+
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+}
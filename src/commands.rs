@@ -0,0 +1,1133 @@
+use std::{fmt::Display, time::Duration};
+
+use matrix_sdk::{
+    ruma::{
+        events::room::message::{MessageEventContent, MessageFormat, MessageType},
+        EventId, UserId,
+    },
+    Client,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    activity,
+    audit::{self, Action},
+    backfill, cleanup, clock,
+    config::{self, Config, CONFIG_LOCATION},
+    confirm::{self, Confirmation},
+    cw, decided, delegation,
+    error::FourwarderError,
+    export, filters, health, help, history, karma,
+    lifecycle::{self, Transition},
+    pause,
+    pending::{self, PendingItem, Schedule, NORMAL_PRIORITY},
+    pseudonym, queue,
+    quorum::{self, Vote},
+    ratelimit, rooms, schedule, selftest, send, sequence, session, shadowban, stats, training,
+};
+
+/// How many days `!stats rooms` covers unless it is given a number
+const ROOM_STATS_DAYS: u64 = 7;
+
+/// Every command we know, without the prefix
+pub const COMMANDS: &[&str] = &[
+    "karma",
+    "shadowban",
+    "unshadowban",
+    "erase",
+    "export",
+    "stats",
+    "status",
+    "queue",
+    "show",
+    "preview",
+    "bump",
+    "addinput",
+    "addoutput",
+    "removeinput",
+    "requeue",
+    "setseq",
+    "selftest",
+    "pause",
+    "resume",
+    "backfill",
+    "delegate",
+    "retry-failed",
+    "ratelimit",
+    "reveal",
+    "schedule",
+    "unschedule",
+    "reload-filters",
+    "mute-room",
+    "unmute-room",
+    "session",
+    "logout-others",
+    "train",
+    "help",
+];
+
+/// Commands which only `admins` may use, unless `command_permissions` says otherwise
+const ADMIN_COMMANDS: &[&str] = &[
+    "shadowban",
+    "unshadowban",
+    "erase",
+    "export",
+    "addinput",
+    "addoutput",
+    "removeinput",
+    "setseq",
+    "selftest",
+    "pause",
+    "resume",
+    "backfill",
+    "delegate",
+    "retry-failed",
+    "ratelimit",
+    "reveal",
+    "reload-filters",
+    "mute-room",
+    "unmute-room",
+    "session",
+    "logout-others",
+];
+
+/// Commands which are confirmed first even if `confirm_commands` leaves them out, as they can't
+/// be undone from the mod room
+const ALWAYS_CONFIRMED: &[&str] = &["logout-others"];
+
+/// Who may use a command
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Anyone in the mod room
+    Moderator,
+    /// Only users in `admins`
+    Admin,
+}
+
+impl Permission {
+    /// Who may use `command` if `command_permissions` doesn't mention it
+    pub fn default_for(command: &str) -> Self {
+        if ADMIN_COMMANDS.contains(&command) {
+            Permission::Admin
+        } else {
+            Permission::Moderator
+        }
+    }
+}
+
+/// Whether a message in the mod room is meant as a command
+pub fn is_command(body: &str, prefix: &str) -> bool {
+    body.starts_with(prefix)
+}
+
+/// Run a command sent to the mod room, replying there
+///
+/// Commands we don't know are ignored, as they may be meant for another bot.
+pub async fn handle(
+    client: &Client,
+    config: &Config,
+    sender: &UserId,
+    body: &str,
+) -> Result<(), FourwarderError> {
+    let words: Vec<&str> = match body.strip_prefix(config.command_prefix.as_str()) {
+        Some(command) => command.split_whitespace().collect(),
+        None => return Ok(()),
+    };
+    let (command, args) = match words.split_first() {
+        Some((command, args)) if COMMANDS.contains(command) => (*command, args),
+        _ => return Ok(()),
+    };
+
+    if config.permission(command) == Permission::Admin && !config.is_admin(sender) {
+        tracing::info!("{} is not allowed to use {}", sender, command);
+        audit::record(
+            Some(sender),
+            Action::Denied {
+                command: command.to_owned(),
+            },
+        );
+        let reply = config.messages.get("not_allowed", &[("sender", sender)]);
+        return send_reply(client, config, reply).await;
+    }
+
+    if let Some(confirmation) = confirm::take_by_token(sender, &words) {
+        return confirmed(client, config, confirmation).await;
+    }
+    if config.confirm_commands.iter().any(|name| name == command)
+        || ALWAYS_CONFIRMED.contains(&command)
+    {
+        let token = confirm::request(sender, &words, config.confirm_window);
+        let command = words.join(" ");
+        tracing::info!(
+            "{} asked to run {}, waiting for confirmation",
+            sender,
+            command
+        );
+        audit::record(
+            Some(sender),
+            Action::ConfirmationRequested {
+                command: command.clone(),
+            },
+        );
+        let prompt = config.messages.get(
+            "confirm_prompt",
+            &[
+                ("prefix", &config.command_prefix),
+                ("command", &command),
+                ("token", &token),
+                (
+                    "window",
+                    &stats::format_duration(config.confirm_window.as_millis() as u64),
+                ),
+            ],
+        );
+        let prompt_event_id = send::message(
+            client,
+            &config.mod_room_id,
+            MessageEventContent::notice_plain(prompt),
+        )
+        .await?;
+        if let Some(prompt_event_id) = prompt_event_id {
+            confirm::set_prompt(&token, prompt_event_id);
+        }
+        return Ok(());
+    }
+
+    match run(client, config, sender, command, args).await? {
+        Some(reply) => send_reply(client, config, reply).await,
+        None => Ok(()),
+    }
+}
+
+/// Run a command in `confirm_commands` which the user who gave it has now confirmed
+pub async fn confirmed(
+    client: &Client,
+    config: &Config,
+    confirmation: Confirmation,
+) -> Result<(), FourwarderError> {
+    let command = confirmation.args.join(" ");
+    tracing::info!("{} confirmed {}", confirmation.sender, command);
+    audit::record(Some(&confirmation.sender), Action::Confirmed { command });
+
+    let args: Vec<&str> = confirmation.args.iter().map(String::as_str).collect();
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (*command, args),
+        None => return Ok(()),
+    };
+    match run(client, config, &confirmation.sender, command, args).await? {
+        Some(reply) => send_reply(client, config, reply).await,
+        None => Ok(()),
+    }
+}
+
+/// Run a command the sender is allowed to use, returning the reply to it
+///
+/// Returns `None` for arguments no command takes, as the command may be meant for another bot,
+/// and for commands which send their reply themselves.
+async fn run(
+    client: &Client,
+    config: &Config,
+    sender: &UserId,
+    command: &str,
+    args: &[&str],
+) -> Result<Option<String>, FourwarderError> {
+    let messages = &config.messages;
+    let prefix: &[(&str, &(dyn Display + Sync))] = &[("prefix", &config.command_prefix)];
+    let reply = match (command, args) {
+        ("karma", ["reset", user]) => match UserId::try_from(*user) {
+            Ok(user) => {
+                karma::reset(&user);
+                messages.get("karma_reset", &[("user", &user)])
+            }
+            Err(_) => messages.get("invalid_user", &[("user", user)]),
+        },
+        ("karma", [user]) => match UserId::try_from(*user) {
+            Ok(user) => messages.get("karma", &[("user", &user), ("karma", &karma::get(&user))]),
+            Err(_) => messages.get("invalid_user", &[("user", user)]),
+        },
+        ("karma", _) => messages.get("karma_usage", prefix),
+        ("shadowban", [user]) => match UserId::try_from(*user) {
+            Ok(user) if shadowban::add(&user) => {
+                audit::record(Some(sender), Action::Shadowbanned { user: user.clone() });
+                messages.get("shadowbanned", &[("user", &user)])
+            }
+            Ok(user) => messages.get("already_shadowbanned", &[("user", &user)]),
+            Err(_) => messages.get("invalid_user", &[("user", user)]),
+        },
+        ("unshadowban", [user]) => match UserId::try_from(*user) {
+            Ok(user) if shadowban::remove(&user) => {
+                audit::record(Some(sender), Action::Unshadowbanned { user: user.clone() });
+                messages.get("unshadowbanned", &[("user", &user)])
+            }
+            Ok(user) => messages.get("not_shadowbanned", &[("user", &user)]),
+            Err(_) => messages.get("invalid_user", &[("user", user)]),
+        },
+        ("shadowban" | "unshadowban", _) => messages.get("shadowban_usage", prefix),
+        ("erase", [user]) => match UserId::try_from(*user) {
+            Ok(user) => erase(client, config, sender, &user).await?,
+            Err(_) => messages.get("invalid_user", &[("user", user)]),
+        },
+        ("erase", _) => messages.get("erase_usage", prefix),
+        ("addinput", [room]) => rooms::add_input(client, config, room, None).await?,
+        ("addinput", [room, output]) => {
+            rooms::add_input(client, config, room, Some(output)).await?
+        }
+        ("addinput", _) => messages.get("addinput_usage", prefix),
+        ("addoutput", [room]) => rooms::set_output(client, config, room).await?,
+        ("addoutput", _) => messages.get("addoutput_usage", prefix),
+        ("removeinput", [room]) => rooms::remove_input(client, config, room).await?,
+        ("removeinput", _) => messages.get("removeinput_usage", prefix),
+        ("mute-room", [room]) => rooms::set_muted(config, sender, room, true),
+        ("mute-room", _) => messages.get("mute_room_usage", prefix),
+        ("unmute-room", [room]) => rooms::set_muted(config, sender, room, false),
+        ("unmute-room", _) => messages.get("unmute_room_usage", prefix),
+        ("session", []) => session::describe(client, config).await?,
+        ("session", _) => messages.get("session_usage", prefix),
+        ("logout-others", []) => match session::logout_others(client, config, sender).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                tracing::error!("Could not log out our other sessions: {}", e);
+                messages.get("logout_others_failed", &[("error", &e)])
+            }
+        },
+        ("logout-others", _) => messages.get("logout_others_usage", prefix),
+        ("train", []) => training::post(client, config, sender).await?,
+        ("train", _) => messages.get("train_usage", prefix),
+        ("help", []) => {
+            help::send(client, config, sender, None).await?;
+            return Ok(None);
+        }
+        ("help", [topic]) => {
+            help::send(client, config, sender, Some(topic)).await?;
+            return Ok(None);
+        }
+        ("help", _) => messages.get("help_usage", prefix),
+        ("export", []) => export::export(client, config).await?,
+        ("stats", []) => {
+            let summary = match stats::summary() {
+                Some(summary) => messages.get(
+                    "stats",
+                    &[
+                        ("count", &summary.count),
+                        ("median", &stats::format_duration(summary.median_ms)),
+                        ("p95", &stats::format_duration(summary.p95_ms)),
+                        ("max", &stats::format_duration(summary.max_ms)),
+                    ],
+                ),
+                None => messages.get("stats_empty", &[]),
+            };
+            let filtered: Vec<String> = filters::counts()
+                .into_iter()
+                .map(|(filter, dropped, count)| {
+                    let key = if dropped {
+                        "stats_filter_dropped"
+                    } else {
+                        "stats_filter_held"
+                    };
+                    messages.get(key, &[("filter", &filter), ("count", &count)])
+                })
+                .collect();
+            if filtered.is_empty() {
+                summary
+            } else {
+                let filtered = messages.get("stats_filtered", &[("filters", &filtered.join(", "))]);
+                format!("{}\n{}", summary, filtered)
+            }
+        }
+        ("stats", ["rooms"]) => room_stats(config, ROOM_STATS_DAYS),
+        ("stats", ["training"]) => training_stats(config),
+        ("stats", ["rooms", days]) => match days.parse::<u64>() {
+            Ok(days @ 1..=activity::RETENTION_DAYS) => room_stats(config, days),
+            _ => stats_usage(config),
+        },
+        ("stats", _) => stats_usage(config),
+        ("status", []) => status(client, config),
+        ("queue", []) => queue_listing(config),
+        ("show", [position]) => match position
+            .parse::<usize>()
+            .ok()
+            .and_then(|position| pending::queue().into_iter().nth(position.checked_sub(1)?))
+        {
+            Some((mod_event_id, item)) => show(config, &mod_event_id, &item),
+            None => messages.get("bump_missing", &[("position", position)]),
+        },
+        ("show", _) => messages.get("show_usage", prefix),
+        ("preview", [position]) => match position
+            .parse::<usize>()
+            .ok()
+            .and_then(|position| pending::queue().into_iter().nth(position.checked_sub(1)?))
+        {
+            Some((_, item)) => {
+                preview(client, config, &item).await?;
+                return Ok(None);
+            }
+            None => messages.get("bump_missing", &[("position", position)]),
+        },
+        ("preview", _) => messages.get("preview_usage", prefix),
+        ("bump", [position]) => match position
+            .parse::<usize>()
+            .ok()
+            .and_then(|position| pending::queue().into_iter().nth(position.checked_sub(1)?))
+        {
+            Some((mod_event_id, item)) => match pending::bump(&mod_event_id) {
+                Some(priority) => messages.get(
+                    "bumped",
+                    &[("sender", &item.sender), ("priority", &priority)],
+                ),
+                None => messages.get("bump_decided", &[]),
+            },
+            None => messages.get("bump_missing", &[("position", position)]),
+        },
+        ("bump", _) => messages.get("bump_usage", prefix),
+        ("schedule", [position, at]) => match (
+            position
+                .parse::<usize>()
+                .ok()
+                .and_then(|position| pending::queue().into_iter().nth(position.checked_sub(1)?)),
+            schedule::parse_time(at, config.utc_offset),
+        ) {
+            (Some((mod_event_id, item)), Some(at)) => {
+                schedule(config, sender, &mod_event_id, &item, at)
+            }
+            (None, _) => messages.get("bump_missing", &[("position", position)]),
+            (_, None) => messages.get("schedule_usage", prefix),
+        },
+        ("schedule", _) => messages.get("schedule_usage", prefix),
+        ("unschedule", [position]) => match position
+            .parse::<usize>()
+            .ok()
+            .and_then(|position| pending::queue().into_iter().nth(position.checked_sub(1)?))
+        {
+            Some((mod_event_id, item)) if pending::unschedule(&mod_event_id) => {
+                audit::record(
+                    Some(sender),
+                    Action::Unscheduled {
+                        event_id: mod_event_id.clone(),
+                    },
+                );
+                tracing::info!("{} unscheduled {}", sender, mod_event_id);
+                messages.get("unscheduled", &[("sender", &item.sender)])
+            }
+            Some(_) => messages.get("not_scheduled", &[("position", position)]),
+            None => messages.get("bump_missing", &[("position", position)]),
+        },
+        ("unschedule", _) => messages.get("unschedule_usage", prefix),
+        ("requeue", [event_id]) => requeue(client, config, sender, event_id).await?,
+        ("requeue", _) => messages.get("requeue_usage", prefix),
+        ("setseq", [seq]) => match seq.parse::<u64>() {
+            Ok(seq) if seq > 0 => {
+                sequence::set_next(seq);
+                audit::record(Some(sender), Action::SeqSet { seq });
+                messages.get("seq_set", &[("seq", &seq)])
+            }
+            _ => messages.get("setseq_usage", prefix),
+        },
+        ("setseq", _) => messages.get("setseq_usage", prefix),
+        ("selftest", []) => selftest::run(client, config, sender, false).await?,
+        ("selftest", ["send"]) => selftest::run(client, config, sender, true).await?,
+        ("selftest", _) => messages.get("selftest_usage", prefix),
+        ("pause", []) => {
+            if pause::pause() {
+                audit::record(Some(sender), Action::Paused);
+                tracing::info!("{} paused forwarding", sender);
+                messages.get("paused", prefix)
+            } else {
+                messages.get("already_paused", prefix)
+            }
+        }
+        ("backfill", [count]) => match count.parse::<usize>() {
+            Ok(count) if count > 0 => {
+                let capped = count.min(config.backfill_limit);
+                let backfilled = backfill::run(client, config, capped).await?;
+                audit::record(
+                    Some(sender),
+                    Action::Backfilled {
+                        forwarded: backfilled.forwarded,
+                    },
+                );
+                let args: &[(&str, &(dyn Display + Sync))] = &[
+                    ("forwarded", &backfilled.forwarded),
+                    ("skipped", &backfilled.skipped),
+                    ("limit", &config.backfill_limit),
+                ];
+                if capped < count {
+                    messages.get("backfilled_capped", args)
+                } else {
+                    messages.get("backfilled", args)
+                }
+            }
+            _ => messages.get("backfill_usage", prefix),
+        },
+        ("backfill", _) => messages.get("backfill_usage", prefix),
+        ("delegate", [user, minutes]) => match (UserId::try_from(*user), minutes.parse::<u64>()) {
+            (Ok(user), Ok(minutes)) if minutes > 0 => delegate(config, sender, &user, minutes),
+            (Err(_), _) => messages.get("invalid_user", &[("user", user)]),
+            _ => messages.get("delegate_usage", prefix),
+        },
+        ("delegate", _) => messages.get("delegate_usage", prefix),
+        ("ratelimit", ["show"]) => match config.rate_limit_per_minute {
+            Some(per_minute) => messages.get(
+                "ratelimit_show",
+                &[
+                    ("per_minute", &per_minute),
+                    ("throttled", &ratelimit::throttled(per_minute)),
+                ],
+            ),
+            None => messages.get("ratelimit_off", prefix),
+        },
+        ("ratelimit", ["set", per_minute]) => match per_minute.parse::<u32>() {
+            Ok(per_minute) if per_minute > 0 => set_rate_limit(config, sender, per_minute),
+            _ => messages.get("ratelimit_usage", prefix),
+        },
+        ("ratelimit", ["reset", user]) => match UserId::try_from(*user) {
+            Ok(user) => {
+                if ratelimit::reset(&user) {
+                    audit::record(Some(sender), Action::RateLimitReset { user: user.clone() });
+                    tracing::info!("{} reset the rate limit of {}", sender, user);
+                }
+                messages.get("ratelimit_reset", &[("user", &user)])
+            }
+            Err(_) => messages.get("invalid_user", &[("user", user)]),
+        },
+        ("ratelimit", _) => messages.get("ratelimit_usage", prefix),
+        ("reveal", []) => messages.get("reveal_usage", prefix),
+        ("reveal", words) => {
+            let pseudonym = words.join(" ");
+            match pseudonym::reveal(messages, &pseudonym) {
+                Some(user) => {
+                    audit::record(
+                        Some(sender),
+                        Action::Revealed {
+                            pseudonym: pseudonym.clone(),
+                            user: user.clone(),
+                        },
+                    );
+                    tracing::info!("{} revealed who {} is", sender, pseudonym);
+                    messages.get("revealed", &[("pseudonym", &pseudonym), ("user", &user)])
+                }
+                None => messages.get("no_such_pseudonym", &[("pseudonym", &pseudonym)]),
+            }
+        }
+        ("reload-filters", []) => match config::reload_filters() {
+            Ok(reloaded) => {
+                let entries = reloaded.blocklist.len();
+                audit::record(Some(sender), Action::FiltersReloaded { entries });
+                tracing::info!("{} reloaded the filters", sender);
+                messages.get("filters_reloaded", &[("entries", &entries)])
+            }
+            Err(e) => {
+                tracing::error!("Could not reload the filters, keeping the old ones: {}", e);
+                messages.get("filters_not_reloaded", &[("error", &e)])
+            }
+        },
+        ("retry-failed", []) => {
+            let (retried, failed) = crate::retry_failed(client, config).await;
+            if retried == 0 {
+                messages.get("no_dead_letters", &[])
+            } else {
+                audit::record(Some(sender), Action::RetriedFailed { retried, failed });
+                messages.get(
+                    "retried_failed",
+                    &[("retried", &retried), ("failed", &failed)],
+                )
+            }
+        }
+        ("resume", []) => match crate::resume(client, config).await {
+            Some(count) => {
+                audit::record(Some(sender), Action::Resumed { deferred: count });
+                messages.get("resumed", &[("count", &count)])
+            }
+            None => messages.get("not_paused", &[]),
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(reply))
+}
+
+async fn send_reply(
+    client: &Client,
+    config: &Config,
+    reply: String,
+) -> Result<(), FourwarderError> {
+    send::message(
+        client,
+        &config.mod_room_id,
+        MessageEventContent::notice_plain(reply),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Redact everything `user` submitted, and forget everything we know about them
+///
+/// Their shadowban, if any, is kept so that erasing can't be used to lift one. Redactions which
+/// fail are remembered so running the command again retries them.
+async fn erase(
+    client: &Client,
+    config: &Config,
+    sender: &UserId,
+    user: &UserId,
+) -> Result<String, FourwarderError> {
+    let mut redacted = 0;
+    let mut failed = 0;
+
+    let published = history::by_sender(user);
+    let pending = pending::remove_sender(user);
+    let events = published
+        .iter()
+        .map(|(id, published)| (id, &published.room_id))
+        .chain(pending.iter().map(|(id, _)| (id, &config.mod_room_id)));
+    for (event_id, room_id) in events {
+        match send::redact(
+            client,
+            room_id,
+            event_id,
+            Some("Erased at the sender's request"),
+        )
+        .await
+        {
+            Ok(()) => {
+                history::forget(event_id);
+                redacted += 1;
+            }
+            Err(e) => {
+                tracing::warn!("Could not redact {} in {}: {}", event_id, room_id, e);
+                failed += 1;
+            }
+        }
+    }
+    cleanup::erase(user);
+    queue::check(client, config).await?;
+
+    audit::record(
+        Some(sender),
+        Action::Erased {
+            user: user.clone(),
+            redacted,
+            pending: pending.len(),
+            failed,
+        },
+    );
+
+    let args: &[(&str, &(dyn std::fmt::Display + Sync))] = &[
+        ("user", user),
+        ("redacted", &redacted),
+        ("pending", &pending.len()),
+        ("failed", &failed),
+    ];
+    Ok(if failed == 0 {
+        config.messages.get("erased", args)
+    } else {
+        config.messages.get("erased_with_failures", args)
+    })
+}
+
+/// Post what approving `item` would publish to the mod room, under a line saying it is a preview
+///
+/// It is sent as a notice, which approving doesn't publish, and it isn't kept track of anywhere.
+async fn preview(
+    client: &Client,
+    config: &Config,
+    item: &PendingItem,
+) -> Result<(), FourwarderError> {
+    let (output_room_id, text) = crate::rendered(client, config, item).await;
+    let header = config.messages.get("preview", &[("room", &output_room_id)]);
+    let html = match &text.formatted {
+        Some(formatted) if formatted.format == MessageFormat::Html => formatted.body.clone(),
+        _ => cw::escape_html(&text.body).replace('\n', "<br>"),
+    };
+    send::message(
+        client,
+        &config.mod_room_id,
+        MessageEventContent::notice_html(
+            format!("{}\n\n{}", header, text.body),
+            format!("<p><em>{}</em></p>{}", cw::escape_html(&header), html),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Change `rate_limit_per_minute` in the live config, which takes effect with the next
+/// submission
+fn set_rate_limit(config: &Config, sender: &UserId, per_minute: u32) -> String {
+    let edited = config::edit(CONFIG_LOCATION, |table| {
+        table.insert(
+            "rate_limit_per_minute".to_owned(),
+            i64::from(per_minute).into(),
+        );
+    });
+    match edited {
+        Ok(_) => {
+            audit::record(Some(sender), Action::RateLimitSet { per_minute });
+            tracing::info!("{} set the rate limit to {} a minute", sender, per_minute);
+            config
+                .messages
+                .get("ratelimit_set", &[("per_minute", &per_minute)])
+        }
+        Err(e) => {
+            tracing::warn!("Could not change the rate limit: {}", e);
+            config
+                .messages
+                .get("ratelimit_change_failed", &[("error", &e)])
+        }
+    }
+}
+
+/// Let `user` approve and reject submissions for the next `minutes`
+fn delegate(config: &Config, sender: &UserId, user: &UserId, minutes: u64) -> String {
+    let messages = &config.messages;
+    if config.moderators.is_empty() {
+        return messages.get("delegate_everyone", &[]);
+    }
+    if config.is_moderator(user) {
+        return messages.get("delegate_moderator", &[("user", user)]);
+    }
+
+    let duration = Duration::from_secs(minutes * 60);
+    let expires_at = delegation::grant(user, duration);
+    audit::record(
+        Some(sender),
+        Action::Delegated {
+            user: user.clone(),
+            expires_at,
+        },
+    );
+    tracing::info!(
+        "{} delegated moderation to {} for {} minutes",
+        sender,
+        user,
+        minutes
+    );
+    messages.get(
+        "delegated",
+        &[
+            ("user", user),
+            (
+                "duration",
+                &stats::format_duration(duration.as_millis() as u64),
+            ),
+        ],
+    )
+}
+
+/// Put a rejected submission back in the queue, with a fresh copy in the mod room
+///
+/// Its old votes, holds and escalation are dropped along with the decision, so it is decided on
+/// from scratch.
+async fn requeue(
+    client: &Client,
+    config: &Config,
+    sender: &UserId,
+    event_id: &str,
+) -> Result<String, FourwarderError> {
+    let messages = &config.messages;
+    let decided = EventId::try_from(event_id).ok().and_then(|old_event_id| {
+        decided::get(&old_event_id, config.decided_retention).map(|decided| (old_event_id, decided))
+    });
+    let (old_event_id, decided) = match decided {
+        Some((_, decided)) if decided.approved => {
+            return Ok(messages.get("requeue_approved", &[("event_id", &event_id)]))
+        }
+        Some(found) => found,
+        None => {
+            return Ok(messages.get(
+                "requeue_missing",
+                &[
+                    ("event_id", &event_id),
+                    (
+                        "retention",
+                        &stats::format_duration(config.decided_retention.as_millis() as u64),
+                    ),
+                ],
+            ))
+        }
+    };
+
+    let mod_event_id = send::forwarded(
+        client,
+        &config.mod_room_id,
+        MessageEventContent::new(MessageType::Text(decided.item.content.clone())),
+        None,
+    )
+    .await?;
+    let mod_event_id = match mod_event_id {
+        Some(mod_event_id) => mod_event_id,
+        // Dry run, so there is nothing to queue against
+        None => return Ok(messages.get("requeued", &[("sender", &decided.item.sender)])),
+    };
+
+    let old = decided.item;
+    // It starts over as it was submitted, from the status it was decided with
+    let mut item = PendingItem {
+        training: old.training,
+        status: old.status,
+        ..PendingItem::new(
+            old.source_room_id,
+            old.source_event_id,
+            old.sender,
+            old.origin_server_ts,
+            old.content,
+            old.priority,
+        )
+    };
+    lifecycle::apply_transition(&mut item, Transition::Requeue)
+        .map_err(|_| FourwarderError::Logic("A rejected submission was not marked rejected"))?;
+    let submitter = item.sender.clone();
+    decided::remove(&old_event_id);
+    pending::insert(mod_event_id.clone(), item);
+    queue::check(client, config).await?;
+    audit::record(
+        Some(sender),
+        Action::Requeued {
+            event_id: old_event_id,
+            new_event_id: mod_event_id,
+        },
+    );
+
+    Ok(messages.get("requeued", &[("sender", &submitter)]))
+}
+
+/// What happened to the submissions to each input room over the last `days` days, busiest room
+/// first
+fn room_stats(config: &Config, days: u64) -> String {
+    let messages = &config.messages;
+    let totals = activity::totals(days);
+    if totals.is_empty() {
+        return messages.get("stats_rooms_empty", &[("days", &days)]);
+    }
+
+    let mut lines = vec![messages.get("stats_rooms", &[("days", &days)])];
+    for (room_id, counts) in totals {
+        lines.push(messages.get(
+            "stats_room",
+            &[
+                ("room", &room_id),
+                ("received", &counts.received),
+                ("forwarded", &counts.forwarded),
+                ("rejected", &counts.rejected),
+                ("expired", &counts.expired),
+            ],
+        ));
+    }
+    lines.join("\n")
+}
+
+/// What each moderator decided on training submissions, kept apart from the real decisions
+fn training_stats(config: &Config) -> String {
+    let messages = &config.messages;
+    let totals = training::totals();
+    if totals.is_empty() {
+        return messages.get("stats_training_empty", &[]);
+    }
+
+    let mut lines = vec![messages.get("stats_training", &[])];
+    for (moderator, counts) in totals {
+        lines.push(messages.get(
+            "stats_training_moderator",
+            &[
+                ("moderator", &moderator),
+                ("approved", &counts.approved),
+                ("rejected", &counts.rejected),
+            ],
+        ));
+    }
+    lines.join("\n")
+}
+
+fn stats_usage(config: &Config) -> String {
+    config.messages.get(
+        "stats_usage",
+        &[
+            ("prefix", &config.command_prefix),
+            ("max", &activity::RETENTION_DAYS),
+        ],
+    )
+}
+
+/// A one line summary of how the bot is doing
+fn status(client: &Client, config: &Config) -> String {
+    let messages = &config.messages;
+    let health = health::get();
+    let yes_no = |flag: bool| messages.get(if flag { "status_yes" } else { "status_no" }, &[]);
+    let ago = |duration: Option<Duration>| match duration {
+        Some(duration) => messages.get(
+            "status_ago",
+            &[(
+                "duration",
+                &stats::format_duration(duration.as_millis() as u64),
+            )],
+        ),
+        None => messages.get("status_never", &[]),
+    };
+    let muted = if config.muted_rooms.is_empty() {
+        messages.get("status_none", &[])
+    } else {
+        let muted: Vec<String> = config.muted_rooms.iter().map(ToString::to_string).collect();
+        muted.join(", ")
+    };
+    let sizes: Vec<String> = cleanup::sizes()
+        .into_iter()
+        .map(|(store, size)| format!("{} {}", size, store))
+        .collect();
+
+    messages.get(
+        "status",
+        &[
+            ("initial_sync", &yes_no(health.initial_sync)),
+            ("last_sync", &ago(health.since_last_sync)),
+            ("token_age", &ago(health.sync_token_age)),
+            ("stalled", &yes_no(health.stalled)),
+            ("rooms", &client.joined_rooms().len()),
+            ("queue", &pending::len()),
+            ("moderate", &yes_no(config.moderate)),
+            ("paused", &yes_no(pause::is_paused())),
+            ("dry_run", &yes_no(config.dry_run)),
+            ("muted", &muted),
+            ("state", &sizes.join(", ")),
+            (
+                "uptime",
+                &stats::format_duration(health.uptime.as_millis() as u64),
+            ),
+        ],
+    )
+}
+
+/// Everything we know about a pending submission, with its text in full
+fn show(config: &Config, mod_event_id: &EventId, item: &PendingItem) -> String {
+    let messages = &config.messages;
+    let submitted_at: u64 = item.origin_server_ts.get().into();
+    let mut lines = vec![messages.get(
+        "show",
+        &[
+            ("sender", &item.sender),
+            ("room", &item.source_room_id),
+            (
+                "ago",
+                &stats::format_duration(clock::now_millis().saturating_sub(submitted_at)),
+            ),
+            (
+                "link",
+                &format!(
+                    "https://matrix.to/#/{}/{}",
+                    config.mod_room_id, mod_event_id
+                ),
+            ),
+        ],
+    )];
+    if item.priority > NORMAL_PRIORITY {
+        lines.push(messages.get("queue_priority", &[("priority", &item.priority)]));
+    }
+    if item.is_held() {
+        lines.push(messages.get("queue_held", &[]));
+    }
+    if let Some(reason) = &item.content_warning {
+        lines.push(messages.get("show_content_warning", &[("reason", reason)]));
+    }
+    if !item.approvers.is_empty() || !item.rejecters.is_empty() {
+        lines.push(messages.get(
+            "show_votes",
+            &[
+                ("approvals", &item.approvers.len()),
+                ("rejections", &item.rejecters.len()),
+            ],
+        ));
+    }
+    lines.push(String::new());
+    lines.push(item.content.body.clone());
+    lines.join("\n")
+}
+
+/// Approve the pending item for `mod_event_id` to be published at `at`
+fn schedule(
+    config: &Config,
+    sender: &UserId,
+    mod_event_id: &EventId,
+    item: &PendingItem,
+    at: u64,
+) -> String {
+    let messages = &config.messages;
+    let formatted = schedule::format_time(at, config.utc_offset);
+    // Scheduling approves it, so it takes what a ✅ would
+    if !delegation::may_moderate(config, sender) {
+        return messages.get("not_allowed", &[("sender", sender)]);
+    }
+    if at <= clock::now_millis() {
+        return messages.get("schedule_past", &[("at", &formatted)]);
+    }
+    if item.is_held() {
+        return messages.get("schedule_held", &[]);
+    }
+    if let Some(claimant) = item
+        .claimant(config.claim_timeout)
+        .filter(|claimant| *claimant != sender)
+    {
+        return messages.get("schedule_claimed", &[("claimant", claimant)]);
+    }
+    match quorum::would_pass(config, mod_event_id, sender, Vote::Approve) {
+        Some((weight, required)) if weight < required => {
+            return messages.get(
+                "schedule_needs_approvals",
+                &[("weight", &weight), ("required", &required)],
+            );
+        }
+        Some(_) => {
+            quorum::vote(config, mod_event_id, sender, Vote::Approve);
+        }
+        None => return messages.get("bump_decided", &[]),
+    }
+    let scheduled = pending::schedule(
+        mod_event_id,
+        Schedule {
+            at,
+            moderator: sender.clone(),
+        },
+    );
+    if !scheduled {
+        return messages.get("bump_decided", &[]);
+    }
+    audit::record(
+        Some(sender),
+        Action::Scheduled {
+            event_id: mod_event_id.clone(),
+            at,
+        },
+    );
+    tracing::info!("{} scheduled {} for {}", sender, mod_event_id, formatted);
+    messages.get(
+        "scheduled",
+        &[("sender", &item.sender), ("at", &formatted)],
+    )
+}
+
+/// One line per pending submission, in the order moderators should get to them
+fn queue_listing(config: &Config) -> String {
+    let messages = &config.messages;
+    let queue = pending::queue();
+    if queue.is_empty() {
+        return messages.get("queue_empty", &[]);
+    }
+
+    queue
+        .iter()
+        .enumerate()
+        .map(|(i, (_, item))| {
+            let priority = if item.priority > NORMAL_PRIORITY {
+                format!(
+                    " {}",
+                    messages.get("queue_priority", &[("priority", &item.priority)])
+                )
+            } else {
+                String::new()
+            };
+            let held = if item.is_held() {
+                format!(" {}", messages.get("queue_held", &[]))
+            } else {
+                String::new()
+            };
+            let claimed = match item.claimant(config.claim_timeout) {
+                Some(claimant) => format!(
+                    " {}",
+                    messages.get("queue_claimed", &[("moderator", claimant)])
+                ),
+                None => String::new(),
+            };
+            let scheduled = match &item.schedule {
+                Some(schedule) => format!(
+                    " {}",
+                    messages.get(
+                        "queue_scheduled",
+                        &[(
+                            "at",
+                            &schedule::format_time(schedule.at, config.utc_offset)
+                        )],
+                    )
+                ),
+                None => String::new(),
+            };
+            let reactions = match item
+                .source_reaction_summary(config.source_reactions_limit)
+                .filter(|_| config.show_source_reactions)
+            {
+                Some(summary) => format!(" ({})", summary),
+                None => String::new(),
+            };
+            format!(
+                "{}.{}{}{}{} {}: {}{}",
+                i + 1,
+                priority,
+                held,
+                claimed,
+                scheduled,
+                item.sender,
+                send::truncate(&item.content.body),
+                reactions
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, user};
+
+    /// An hour after the fake clock starts
+    const AT: u64 = testing::START_MILLIS + 60 * 60 * 1000;
+
+    fn schedule_as(config: &Config, moderator: &str, mod_event_id: &EventId) -> bool {
+        let item = pending::get(mod_event_id).unwrap();
+        schedule(config, &user(moderator), mod_event_id, &item, AT);
+        pending::get(mod_event_id).unwrap().schedule.is_some()
+    }
+
+    #[test]
+    fn scheduling_needs_a_moderator() {
+        let _test = testing::isolate();
+        let config = testing::config(r#"moderators = ["@mod:example.org"]"#);
+        let mod_event_id = testing::queue("$a:example.org", "@sub:example.org", "hello");
+
+        assert!(!schedule_as(&config, "@member:example.org", &mod_event_id));
+        assert!(schedule_as(&config, "@mod:example.org", &mod_event_id));
+    }
+
+    #[test]
+    fn held_or_claimed_items_are_not_scheduled() {
+        let _test = testing::isolate();
+        let config = testing::config(r#"claim_emoji = "👀""#);
+        let held = testing::queue("$held:example.org", "@sub:example.org", "hello");
+        assert!(pending::hold(&held, testing::event("$hold:example.org")));
+        assert!(!schedule_as(&config, "@mod:example.org", &held));
+
+        let claimed = testing::queue("$claimed:example.org", "@sub:example.org", "hello");
+        assert!(pending::claim(
+            &claimed,
+            &user("@other:example.org"),
+            testing::event("$claim:example.org"),
+            config.claim_timeout
+        ));
+        assert!(!schedule_as(&config, "@mod:example.org", &claimed));
+        assert!(schedule_as(&config, "@other:example.org", &claimed));
+    }
+
+    #[test]
+    fn scheduling_counts_toward_the_quorum() {
+        let _test = testing::isolate();
+        let config = testing::config("required_approval_weight = 2");
+        let mod_event_id = testing::queue("$a:example.org", "@sub:example.org", "hello");
+
+        assert!(!schedule_as(&config, "@first:example.org", &mod_event_id));
+        assert!(!quorum::vote(
+            &config,
+            &mod_event_id,
+            &user("@first:example.org"),
+            Vote::Approve
+        ));
+        assert!(schedule_as(&config, "@second:example.org", &mod_event_id));
+    }
+}
@@ -0,0 +1,1976 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use matrix_sdk::ruma::{
+    events::room::message::{
+        MessageEventContent, MessageType, NoticeMessageEventContent, TextMessageEventContent,
+    },
+    EventId, RoomId, UserId,
+};
+use serde::{de::Visitor, Deserialize, Deserializer};
+
+use std::time::Duration;
+
+use url::Url;
+
+use crate::{
+    auth, blocklist,
+    category::Category,
+    commands::{Permission, COMMANDS},
+    content_dedup::ContentDedup,
+    error::FourwarderError,
+    escalation::Escalation,
+    filters::{self, MessageFilter},
+    hash_denylist::HashDenylist,
+    include::Include,
+    media::MediaMode,
+    messages::{Messages, DEFAULT_LANGUAGE},
+    normalize::{normalize_emoji, normalize_for_matching},
+    ordering::OrderBy,
+    queue::QueueLimit,
+    quote::QuoteStyle,
+    schedule, schema, space,
+    spam::SpamConfig,
+    urls::UrlPolicy,
+    user_pattern::{self, UserPattern},
+    webhook::{FailMode, ModerationWebhook, OutputWebhook},
+};
+
+pub const CONFIG_LOCATION: &str = "4warder.toml";
+
+/// Keys which are only read at startup, so changing them needs a restart
+const RESTART_KEYS: &[&str] = &[
+    "homeserver",
+    "username",
+    "password",
+    "device_name",
+    "metrics_address",
+    "startup_scan_messages",
+    "max_concurrent_sends",
+    "failover_after_secs",
+    "initial_sync_retries",
+    "space_id",
+    "data_dir",
+];
+
+/// Every `msgtype` in the Matrix spec, which `allowed_message_types` can choose from
+const MESSAGE_TYPES: &[&str] = &[
+    "m.text",
+    "m.emote",
+    "m.notice",
+    "m.image",
+    "m.file",
+    "m.audio",
+    "m.video",
+    "m.location",
+    "m.server_notice",
+    "m.key.verification.request",
+];
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_command_prefix() -> String {
+    "!".to_owned()
+}
+
+fn default_dedup_capacity() -> usize {
+    1000
+}
+
+fn default_utc_offset() -> String {
+    "+00:00".to_owned()
+}
+
+fn default_index_size() -> usize {
+    10
+}
+
+fn default_index_interval_secs() -> u64 {
+    10 * 60
+}
+
+fn default_submission_quota_window_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_content_dedup_window_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_decided_retention_secs() -> u64 {
+    7 * 24 * 60 * 60
+}
+
+fn default_source_reactions_limit() -> usize {
+    5
+}
+
+fn default_mirror_reactions_interval_secs() -> u64 {
+    5 * 60
+}
+
+fn default_max_output_chars() -> usize {
+    4000
+}
+
+fn default_max_concurrent_sends() -> usize {
+    2
+}
+
+fn default_request_timeout_secs() -> u64 {
+    30
+}
+
+fn default_sync_timeout_secs() -> u64 {
+    60
+}
+
+fn default_sync_stall_timeout_secs() -> u64 {
+    60 * 60
+}
+
+fn default_confirm_commands() -> Vec<String> {
+    vec!["erase".to_owned(), "shadowban".to_owned()]
+}
+
+fn default_confirm_window_secs() -> u64 {
+    60
+}
+
+fn default_instance_id() -> String {
+    auth::DEVICE_NAME.to_owned()
+}
+
+fn default_cw_emoji() -> String {
+    "🔞".to_owned()
+}
+
+fn default_plain_publish_emoji() -> String {
+    "🔓".to_owned()
+}
+
+fn default_source_approved_emoji() -> String {
+    "✅".to_owned()
+}
+
+fn default_source_rejected_emoji() -> String {
+    "❌".to_owned()
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_approval_reply_keywords() -> Vec<String> {
+    vec!["publish".to_owned(), "✅".to_owned()]
+}
+
+fn default_hold_emoji() -> String {
+    "🤔".to_owned()
+}
+
+fn default_claim_timeout_secs() -> u64 {
+    30 * 60
+}
+
+fn default_language() -> String {
+    DEFAULT_LANGUAGE.to_owned()
+}
+
+fn default_webhook_timeout_secs() -> u64 {
+    5
+}
+
+fn default_webhook_retries() -> u32 {
+    3
+}
+
+fn default_allowed_message_types() -> Vec<String> {
+    vec!["m.text".to_owned()]
+}
+
+fn default_backfill_limit() -> usize {
+    100
+}
+
+fn default_initial_sync_retries() -> u32 {
+    5
+}
+
+fn default_data_dir() -> PathBuf {
+    PathBuf::from(".")
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RawConfig {
+    #[serde(default)]
+    strict: bool,
+    homeserver: String,
+    username: String,
+    password: String,
+    device_name: Option<String>,
+    input_room_id: Option<String>,
+    mod_room_id: String,
+    output_room_id: String,
+    #[serde(default = "default_true")]
+    moderate: bool,
+    #[serde(default)]
+    rooms: Vec<RawRoomConfig>,
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    url_policy: UrlPolicy,
+    #[serde(default)]
+    url_allowlist: Vec<String>,
+    spam: Option<SpamConfig>,
+    #[serde(default)]
+    blocklist: Vec<String>,
+    blocklist_file: Option<PathBuf>,
+    #[serde(default)]
+    include_patterns: Vec<String>,
+    submission_prefix: Option<String>,
+    #[serde(default)]
+    content_dedup: ContentDedup,
+    #[serde(default = "default_content_dedup_window_secs")]
+    content_dedup_window_secs: u64,
+    auto_approve_karma: Option<i64>,
+    moderation_webhook_url: Option<String>,
+    #[serde(default = "default_webhook_timeout_secs")]
+    moderation_webhook_timeout_secs: u64,
+    #[serde(default)]
+    webhook_fail_mode: FailMode,
+    output_webhook_url: Option<String>,
+    #[serde(default = "default_webhook_timeout_secs")]
+    output_webhook_timeout_secs: u64,
+    hash_denylist_url: Option<String>,
+    #[serde(default = "default_webhook_timeout_secs")]
+    hash_denylist_timeout_secs: u64,
+    #[serde(default)]
+    hash_denylist_fail_mode: FailMode,
+    #[serde(default = "default_webhook_retries")]
+    output_webhook_retries: u32,
+    max_queue_size: Option<usize>,
+    queue_low_watermark: Option<usize>,
+    #[serde(default)]
+    drop_when_queue_full: bool,
+    metrics_address: Option<SocketAddr>,
+    #[serde(default)]
+    priority_keywords: Vec<String>,
+    #[serde(default)]
+    priority_senders: Vec<String>,
+    #[serde(default)]
+    profanity_mask: Vec<String>,
+    rate_limit_per_minute: Option<u32>,
+    daily_submission_quota: Option<u32>,
+    #[serde(default = "default_submission_quota_window_secs")]
+    submission_quota_window_secs: u64,
+    min_membership_age_secs: Option<u64>,
+    #[serde(default = "default_utc_offset")]
+    utc_offset: String,
+    #[serde(default)]
+    trim_output: bool,
+    #[serde(default)]
+    markdown_to_html: bool,
+    #[serde(default)]
+    split_long_output: bool,
+    #[serde(default = "default_max_output_chars")]
+    max_output_chars: usize,
+    #[serde(default = "default_true")]
+    sync_filter: bool,
+    #[serde(default)]
+    persist_runtime_changes: bool,
+    #[serde(default = "default_command_prefix")]
+    command_prefix: String,
+    #[serde(default)]
+    admins: Vec<String>,
+    #[serde(default)]
+    moderators: Vec<String>,
+    #[serde(default)]
+    pin_instructions: bool,
+    index_room_id: Option<String>,
+    #[serde(default = "default_index_size")]
+    index_size: usize,
+    #[serde(default = "default_index_interval_secs")]
+    index_interval_secs: u64,
+    #[serde(default)]
+    categories: Vec<RawCategory>,
+    #[serde(default)]
+    command_permissions: HashMap<String, Permission>,
+    #[serde(default = "default_confirm_commands")]
+    confirm_commands: Vec<String>,
+    #[serde(default = "default_confirm_window_secs")]
+    confirm_window_secs: u64,
+    #[serde(default = "default_dedup_capacity")]
+    dedup_capacity: usize,
+    startup_scan_messages: Option<usize>,
+    preview_room_id: Option<String>,
+    training_output_room_id: Option<String>,
+    dead_letter_room_id: Option<String>,
+    #[serde(default = "default_max_concurrent_sends")]
+    max_concurrent_sends: usize,
+    #[serde(default = "default_request_timeout_secs")]
+    send_timeout_secs: u64,
+    #[serde(default = "default_request_timeout_secs")]
+    fetch_timeout_secs: u64,
+    #[serde(default = "default_sync_timeout_secs")]
+    sync_timeout_secs: u64,
+    #[serde(default = "default_sync_stall_timeout_secs")]
+    sync_stall_timeout_secs: u64,
+    #[serde(default)]
+    order_by: OrderBy,
+    #[serde(default)]
+    redact_source_on_publish: bool,
+    #[serde(default)]
+    mirror_unknown_events: bool,
+    #[serde(default)]
+    muted_rooms: Vec<String>,
+    #[serde(default = "default_instance_id")]
+    instance_id: String,
+    #[serde(default)]
+    media_mode: MediaMode,
+    #[serde(default)]
+    forward_media_captions: bool,
+    #[serde(default = "default_true")]
+    ignore_filename_captions: bool,
+    #[serde(default)]
+    require_media_caption: bool,
+    #[serde(default)]
+    show_source_reactions: bool,
+    #[serde(default)]
+    mirror_source_reactions: bool,
+    #[serde(default = "default_mirror_reactions_interval_secs")]
+    mirror_reactions_interval_secs: u64,
+    #[serde(default)]
+    approve_in_input_room: bool,
+    #[serde(default)]
+    react_on_source_decision: bool,
+    #[serde(default = "default_source_approved_emoji")]
+    source_approved_emoji: String,
+    #[serde(default = "default_source_rejected_emoji")]
+    source_rejected_emoji: String,
+    #[serde(default = "default_source_reactions_limit")]
+    source_reactions_limit: usize,
+    #[serde(default = "default_decided_retention_secs")]
+    decided_retention_secs: u64,
+    published_retention_secs: Option<u64>,
+    pseudonym_retention_secs: Option<u64>,
+    dead_letter_retention_secs: Option<u64>,
+    min_send_interval_ms: Option<u64>,
+    failover_after_secs: Option<u64>,
+    #[serde(default = "default_initial_sync_retries")]
+    initial_sync_retries: u32,
+    #[serde(default = "default_data_dir")]
+    data_dir: PathBuf,
+    #[serde(default = "default_allowed_message_types")]
+    allowed_message_types: Vec<String>,
+    #[serde(default)]
+    in_place_approval: InPlaceApproval,
+    #[serde(default = "default_backfill_limit")]
+    backfill_limit: usize,
+    #[serde(default = "default_cw_emoji")]
+    cw_emoji: String,
+    #[serde(default)]
+    cw_keywords: BTreeMap<String, String>,
+    #[serde(default = "default_plain_publish_emoji")]
+    plain_publish_emoji: String,
+    #[serde(default = "default_hold_emoji")]
+    hold_emoji: String,
+    #[serde(default)]
+    claim_emoji: Option<String>,
+    #[serde(default = "default_claim_timeout_secs")]
+    claim_timeout_secs: u64,
+    #[serde(default = "default_true")]
+    warn_on_claimed_decision: bool,
+    #[serde(default)]
+    approval_via_reply: bool,
+    #[serde(default = "default_approval_reply_keywords")]
+    approval_reply_keywords: Vec<String>,
+    #[serde(default)]
+    moderator_weights: HashMap<String, u32>,
+    #[serde(default = "default_weight")]
+    required_approval_weight: u32,
+    #[serde(default = "default_weight")]
+    required_rejection_weight: u32,
+    #[serde(default)]
+    require_rejection_reason: bool,
+    #[serde(default)]
+    present_as_sender: bool,
+    output_template: Option<String>,
+    #[serde(default)]
+    quote_style: QuoteStyle,
+    #[serde(default)]
+    output_msgtype: OutputMsgtype,
+    escalation_after_secs: Option<u64>,
+    escalation_room_id: Option<String>,
+    #[serde(default)]
+    escalation_users: Vec<String>,
+    #[serde(default = "default_language")]
+    language: String,
+    messages_file: Option<String>,
+}
+
+/// A `[[categories]]` entry
+#[derive(Deserialize, Debug)]
+struct RawCategory {
+    name: String,
+    approval_emoji: String,
+    output_room_id: String,
+    #[serde(default = "default_weight")]
+    required_approval_weight: u32,
+    #[serde(default)]
+    moderators: Vec<String>,
+    #[serde(default)]
+    output_thread: bool,
+    output_thread_root: Option<String>,
+}
+
+/// A `[[rooms]]` entry, any field left out falls back to the top-level value
+#[derive(Deserialize, Debug)]
+struct RawRoomConfig {
+    input_room_id: String,
+    output_room_id: Option<String>,
+    moderate: Option<bool>,
+    output_template: Option<String>,
+    blocklist: Option<Vec<String>>,
+}
+
+pub struct Config {
+    pub homeserver: String,
+    pub username: String,
+    pub password: String,
+    /// What our session is called in the account's device list
+    pub device_name: String,
+    /// Every room we take submissions from, including the top-level `input_room_id`
+    pub input_rooms: Vec<InputRoom>,
+    pub mod_room_id: RoomId,
+    pub output_room_id: RoomId,
+    pub moderate: bool,
+    /// Log what would be sent instead of sending it
+    pub dry_run: bool,
+    /// What to do with links in messages sent to an output room
+    pub url_policy: UrlPolicy,
+    /// Lowercase hosts whose links are kept, along with their subdomains
+    pub url_allowlist: Vec<String>,
+    /// How to score submissions for spam, if at all
+    pub spam: Option<SpamConfig>,
+    /// Submissions containing any of these words or phrases are dropped, which are those in
+    /// `blocklist` along with those in `blocklist_file`
+    pub blocklist: Vec<String>,
+    /// Only submissions containing one of `include_patterns` are forwarded, if there are any
+    pub include: Include,
+    /// What submissions have to start with, like `!submit`, which is taken out before they are
+    /// forwarded
+    pub submission_prefix: Option<String>,
+    /// What makes a submission a repeat of a recent one, which is then dropped
+    pub content_dedup: ContentDedup,
+    /// How long a submission is remembered for `content_dedup`
+    pub content_dedup_window: Duration,
+    /// Submitters with more karma than this skip the mod room
+    pub auto_approve_karma: Option<i64>,
+    /// Every submission is checked by these, in order
+    pub filters: Vec<Box<dyn MessageFilter>>,
+    /// An external service which decides what happens to submissions
+    pub moderation_webhook: Option<ModerationWebhook>,
+    /// An external service which receives published submissions, as well as the output room
+    pub output_webhook: Option<OutputWebhook>,
+    /// An external service listing the hashes of media which is never forwarded
+    pub hash_denylist: Option<HashDenylist>,
+    /// When to warn that moderators are falling behind
+    pub queue_limit: Option<QueueLimit>,
+    /// Where to serve Prometheus metrics, if anywhere
+    pub metrics_address: Option<SocketAddr>,
+    /// Submissions containing any of these go to the front of the queue, normalized and lowercased
+    pub priority_keywords: Vec<String>,
+    /// Submissions from users matching these go to the front of the queue
+    pub priority_senders: Vec<UserPattern>,
+    /// Words which are replaced with asterisks in output rooms, normalized and lowercased
+    pub profanity_mask: Vec<String>,
+    /// How many submissions each sender may make a minute, any more are dropped; unlimited if
+    /// unset
+    pub rate_limit_per_minute: Option<u32>,
+    /// How many submissions each sender may have taken within `submission_quota_window`, any
+    /// more are refused until the oldest leaves it; unlimited if unset
+    pub daily_submission_quota: Option<u32>,
+    /// How far back submissions count toward `daily_submission_quota`
+    pub submission_quota_window: Duration,
+    /// How long senders must have been in an input room before their submissions are taken, or
+    /// `None` to take them straight away
+    pub min_membership_age: Option<Duration>,
+    /// How many minutes ahead of UTC the times given to and shown by `!schedule` are
+    pub utc_offset: i32,
+    /// Trim trailing whitespace and collapse blank lines in output rooms
+    pub trim_output: bool,
+    /// Format the Markdown-ish markup of plain text submissions as HTML in output rooms
+    pub markdown_to_html: bool,
+    /// Publish submissions longer than `max_output_chars` as several messages, marked like `(1/3)`
+    pub split_long_output: bool,
+    /// How many characters each part of a split submission has at most
+    pub max_output_chars: usize,
+    /// Only sync the rooms and events we use
+    pub sync_filter: bool,
+    /// Write changes made with commands back to the config file
+    pub persist_runtime_changes: bool,
+    /// What mod room messages start with to be treated as commands
+    pub command_prefix: String,
+    /// Users who may use admin commands, anyone in the mod room may if this is empty
+    pub admins: Vec<UserPattern>,
+    /// Users whose reactions and replies decide on submissions, anyone in the mod room may if
+    /// this is empty, see also [`crate::delegation`]
+    pub moderators: Vec<UserPattern>,
+    /// Keep instructions for moderating, made from the emoji and commands set here, pinned in
+    /// the mod room
+    pub pin_instructions: bool,
+    /// The output room to keep a pinned index of the latest submissions in, if any
+    pub index_room_id: Option<RoomId>,
+    /// How many submissions the index lists
+    pub index_size: usize,
+    /// How often the index is updated
+    pub index_interval: Duration,
+    /// Emoji which publish to an output room of their own, with approval settings of their own
+    pub categories: Vec<Category>,
+    /// Overrides [`Permission::default_for`], keyed by command name without the prefix
+    pub command_permissions: HashMap<String, Permission>,
+    /// Commands which only run once the user who gave them confirms them, without the prefix
+    pub confirm_commands: Vec<String>,
+    /// How long a command in `confirm_commands` waits to be confirmed
+    pub confirm_window: Duration,
+    /// How many recent input events to remember, so ones delivered twice are only handled once
+    pub dedup_capacity: usize,
+    /// How many recent events in each output room to check for submissions we already
+    /// published, if any
+    pub startup_scan_messages: Option<usize>,
+    /// Where approved submissions wait for a second confirmation before being published, if
+    /// anywhere
+    pub preview_room_id: Option<RoomId>,
+    /// Where `!train` submissions are published once approved, if anywhere
+    pub training_output_room_id: Option<RoomId>,
+    /// Where submissions which could not be forwarded are recorded, if anywhere
+    pub dead_letter_room_id: Option<RoomId>,
+    /// How many messages, redactions and files may be sent at once
+    pub max_concurrent_sends: usize,
+    /// How long sending a message, redaction or file may take before it is given up on
+    pub send_timeout: Duration,
+    /// How long fetching an event, a page of history or a file may take before it is given up on
+    pub fetch_timeout: Duration,
+    /// How long a sync may take before it is given up on, including the time the homeserver
+    /// holds it open waiting for events
+    pub sync_timeout: Duration,
+    /// How long syncs may go on without a new sync token before the client is rebuilt, if the
+    /// watchdog is on at all
+    pub sync_stall_timeout: Option<Duration>,
+    /// Which order submissions are published in, when several are published at once
+    pub order_by: OrderBy,
+    /// Redact approved submissions in their input room once they are published
+    pub redact_source_on_publish: bool,
+    /// Copy event types we have no handler for from unmoderated input rooms, content and all
+    pub mirror_unknown_events: bool,
+    /// Input rooms whose messages are ignored for now, set with `!mute-room`
+    pub muted_rooms: Vec<RoomId>,
+    /// Marked on everything we forward, which no instance of the bot will forward again
+    pub instance_id: String,
+    /// Whether mirrored media keeps pointing at the original files, or at copies we upload
+    pub media_mode: MediaMode,
+    /// Submit the caption of media sent to an input room as a text message of its own
+    pub forward_media_captions: bool,
+    /// Whether a media `body` which is only the file's name counts as no caption
+    pub ignore_filename_captions: bool,
+    /// Turn away media sent to an input room without a caption, asking for one
+    pub require_media_caption: bool,
+    /// Count reactions to pending submissions in their input rooms, and show them in `!queue`
+    pub show_source_reactions: bool,
+    /// Count reactions to published submissions in their input rooms, and add them to the end
+    /// of the published copy
+    pub mirror_source_reactions: bool,
+    /// How often the reactions added to published copies are updated
+    pub mirror_reactions_interval: Duration,
+    /// Let moderators approve a submission by reacting to it in its input room
+    pub approve_in_input_room: bool,
+    /// React to a submission in its input room once it is approved or rejected
+    pub react_on_source_decision: bool,
+    /// What `react_on_source_decision` reacts with to an approved submission
+    pub source_approved_emoji: String,
+    /// What `react_on_source_decision` reacts with to a rejected submission
+    pub source_rejected_emoji: String,
+    /// How many kinds of reaction `!queue` shows for each submission, and
+    /// `mirror_source_reactions` adds to each published copy
+    pub source_reactions_limit: usize,
+    /// How long decided submissions are kept around to be requeued
+    pub decided_retention: Duration,
+    /// How long published submissions are remembered for `!erase` to redact, or `None` to
+    /// remember them for good
+    pub published_retention: Option<Duration>,
+    /// How long a sender's pseudonym is kept after it was last used, or `None` to keep it for good
+    pub pseudonym_retention: Option<Duration>,
+    /// How long dead-lettered submissions are kept for `!retry-failed`, or `None` to keep them
+    /// until then
+    pub dead_letter_retention: Option<Duration>,
+    /// The least time between the start of two sends to the same output room, if any
+    pub min_send_interval: Option<Duration>,
+    /// How long the leader among instances sharing the mod room may go silent before another
+    /// takes over, if instances stand by for each other at all
+    pub failover_after: Option<Duration>,
+    /// How many times to retry a failed first sync before giving up on starting
+    pub initial_sync_retries: u32,
+    /// Where the state file and audit log are kept, so instances on one host can keep theirs
+    /// apart
+    pub data_dir: PathBuf,
+    /// The `msgtype`s submissions may have, anything else is turned away
+    pub allowed_message_types: Vec<String>,
+    /// What approving does for submissions whose output room is the mod room
+    pub in_place_approval: InPlaceApproval,
+    /// The most events `!backfill` reads back in each input room
+    pub backfill_limit: usize,
+    /// Reacting with this in the mod room publishes a submission behind a content warning
+    pub cw_emoji: String,
+    /// Submissions containing a keyword are published behind a content warning with its label,
+    /// keywords are normalized and lowercased
+    pub cw_keywords: Vec<(String, String)>,
+    /// Reacting with this in the mod room publishes a submission without a content warning, even
+    /// if it contains one of `cw_keywords`
+    pub plain_publish_emoji: String,
+    /// Reacting with this in the mod room holds a submission for discussion, until the reaction
+    /// is removed
+    pub hold_emoji: String,
+    /// Reacting with this in the mod room claims a submission, marking it as being reviewed by
+    /// whoever reacted until `claim_timeout` has passed or the reaction is removed
+    pub claim_emoji: Option<String>,
+    /// How long a claim lasts
+    pub claim_timeout: Duration,
+    /// Warn in the mod room when a moderator decides on a submission someone else has claimed
+    pub warn_on_claimed_decision: bool,
+    /// Replying to a mod room copy with one of `approval_reply_keywords` approves it, like ✅
+    pub approval_via_reply: bool,
+    /// Normalized with [`normalize_reply`]
+    pub approval_reply_keywords: Vec<String>,
+    /// How much each moderator's approvals and rejections count for, anyone not listed counts
+    /// for 1
+    pub moderator_weights: Vec<(UserPattern, u32)>,
+    /// A submission is published once the weights of everyone approving it add up to this
+    pub required_approval_weight: u32,
+    /// A submission is rejected once the weights of everyone rejecting it add up to this
+    pub required_rejection_weight: u32,
+    /// Only count a ❌ once a moderator has replied to the submission with why it is rejected,
+    /// which its submitter is then told
+    pub require_rejection_reason: bool,
+    /// Show each submitter's name and avatar with their published submissions
+    pub present_as_sender: bool,
+    /// How published submissions are laid out, with `{body}` for the submission, `{seq}` for its
+    /// number and `{pseudonym}` for its sender's pseudonym
+    pub output_template: Option<String>,
+    /// Whether published submissions are quoted, which sets them apart from the bot's own
+    /// messages
+    pub quote_style: QuoteStyle,
+    /// What kind of message submissions are published as
+    pub output_msgtype: OutputMsgtype,
+    /// Who to ping about submissions which have waited too long, if anyone
+    pub escalation: Option<Escalation>,
+    /// The text of everything the bot says
+    pub messages: Messages,
+}
+
+/// What approving a submission does when its output room is the mod room itself
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum InPlaceApproval {
+    /// Post the published version and redact the mod room copy, so only the clean one is left
+    #[default]
+    Repost,
+    /// Leave the mod room copy where it is, as the published one
+    Keep,
+}
+
+/// What kind of message submissions are published as, the mod room copy is always text
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMsgtype {
+    /// `m.text`, like anyone else's message
+    #[default]
+    Text,
+    /// `m.notice`, which clients show apart from people's messages and which doesn't trigger
+    /// some notification rules
+    Notice,
+}
+
+impl OutputMsgtype {
+    /// The `msgtype` of the messages it publishes
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OutputMsgtype::Text => "m.text",
+            OutputMsgtype::Notice => "m.notice",
+        }
+    }
+
+    /// `text` as a message of this kind
+    pub fn content(self, text: TextMessageEventContent) -> MessageEventContent {
+        match self {
+            OutputMsgtype::Text => MessageEventContent::new(MessageType::Text(text)),
+            OutputMsgtype::Notice => {
+                let mut notice = NoticeMessageEventContent::plain(text.body);
+                notice.formatted = text.formatted;
+                MessageEventContent::new(MessageType::Notice(notice))
+            }
+        }
+    }
+}
+
+/// An input room and whatever it overrides from the top-level config
+pub struct InputRoom {
+    pub room_id: RoomId,
+    output_room_id: Option<RoomId>,
+    moderate: Option<bool>,
+    output_template: Option<String>,
+    /// Used instead of the top-level `blocklist`, not along with it
+    blocklist: Option<Vec<String>>,
+    /// The filter pipeline with this room's own `blocklist`, if it has one
+    filters: Option<Vec<Box<dyn MessageFilter>>>,
+}
+
+/// The settings that apply to one input room, after falling back to the top-level config
+pub struct RoomSettings<'a> {
+    pub output_room_id: &'a RoomId,
+    /// Whether submissions go through the mod room, or straight to the output room
+    pub moderate: bool,
+    pub output_template: Option<&'a str>,
+    /// What its submissions are run through before they are forwarded
+    pub filters: &'a [Box<dyn MessageFilter>],
+}
+
+impl Config {
+    /// Resolve the effective settings for `room_id`, or `None` if it is not an input room
+    pub fn input_room(&self, room_id: &RoomId) -> Option<RoomSettings<'_>> {
+        let room = self
+            .input_rooms
+            .iter()
+            .find(|room| &room.room_id == room_id)?;
+
+        Some(RoomSettings {
+            output_room_id: room.output_room_id.as_ref().unwrap_or(&self.output_room_id),
+            moderate: room.moderate.unwrap_or(self.moderate),
+            output_template: room
+                .output_template
+                .as_deref()
+                .or(self.output_template.as_deref()),
+            filters: room.filters.as_deref().unwrap_or(&self.filters),
+        })
+    }
+
+    /// Whether `!mute-room` has us ignoring the messages in `room_id`
+    pub fn is_muted(&self, room_id: &RoomId) -> bool {
+        self.muted_rooms.contains(room_id)
+    }
+
+    /// Where submissions from `room_id` are published, the top-level output room for rooms we
+    /// no longer take submissions from
+    pub fn output_room_of(&self, room_id: &RoomId) -> &RoomId {
+        self.input_room(room_id)
+            .map_or(&self.output_room_id, |settings| settings.output_room_id)
+    }
+
+    /// What submissions from `room_id` are published in, the top-level `output_template` for
+    /// rooms we no longer take submissions from
+    pub fn output_template_of(&self, room_id: &RoomId) -> Option<&str> {
+        self.input_room(room_id)
+            .map_or(self.output_template.as_deref(), |settings| {
+                settings.output_template
+            })
+    }
+
+    /// Log the settings in effect, so misconfiguration shows up in the first lines of the log
+    ///
+    /// The password is never logged, and webhook URLs lose their credentials and query, which
+    /// often hold tokens.
+    pub fn log_effective(&self) {
+        let input_rooms = self
+            .input_rooms
+            .iter()
+            .map(|room| {
+                let settings = self.input_room(&room.room_id).unwrap();
+                format!(
+                    "{} -> {}{}",
+                    room.room_id,
+                    settings.output_room_id,
+                    if settings.moderate {
+                        " (moderated)"
+                    } else {
+                        ""
+                    }
+                )
+            })
+            .collect::<Vec<_>>();
+        let filters = self
+            .filters
+            .iter()
+            .map(|filter| filter.name())
+            .collect::<Vec<_>>();
+        let moderation_webhook = self
+            .moderation_webhook
+            .as_ref()
+            .map(|webhook| redact_url(&webhook.url));
+        let output_webhook = self
+            .output_webhook
+            .as_ref()
+            .map(|webhook| redact_url(&webhook.url));
+        let hash_denylist = self
+            .hash_denylist
+            .as_ref()
+            .map(|denylist| redact_url(&denylist.url));
+
+        tracing::info!(
+            homeserver = %self.homeserver,
+            username = %self.username,
+            password = "<redacted>",
+            device_name = %self.device_name,
+            input_rooms = ?input_rooms,
+            mod_room_id = %self.mod_room_id,
+            output_room_id = %self.output_room_id,
+            moderate = self.moderate,
+            auto_approve_karma = ?self.auto_approve_karma,
+            required_approval_weight = self.required_approval_weight,
+            required_rejection_weight = self.required_rejection_weight,
+            require_rejection_reason = self.require_rejection_reason,
+            filters = ?filters,
+            url_policy = ?self.url_policy,
+            present_as_sender = self.present_as_sender,
+            moderation_webhook = ?moderation_webhook,
+            output_webhook = ?output_webhook,
+            hash_denylist = ?hash_denylist,
+            max_queue_size = ?self.queue_limit.as_ref().map(|limit| limit.max),
+            metrics_address = ?self.metrics_address,
+            sync_filter = self.sync_filter,
+            dry_run = self.dry_run,
+            data_dir = %self.data_dir.display(),
+            "Effective config"
+        );
+    }
+
+    /// Who may use `command`, which is given without the prefix
+    pub fn permission(&self, command: &str) -> Permission {
+        self.command_permissions
+            .get(command)
+            .copied()
+            .unwrap_or_else(|| Permission::default_for(command))
+    }
+
+    /// Whether a moderator's reply to a mod room copy approves it
+    ///
+    /// `reply` should be without the quote of the message replied to.
+    pub fn is_approval_reply(&self, reply: &str) -> bool {
+        self.approval_via_reply
+            && self
+                .approval_reply_keywords
+                .contains(&normalize_reply(reply))
+    }
+
+    /// How much `user`'s approvals and rejections count for
+    ///
+    /// Their own entry beats any pattern, and if several patterns match them the highest weight
+    /// counts.
+    pub fn weight(&self, user: &UserId) -> u32 {
+        let matching = || {
+            self.moderator_weights
+                .iter()
+                .filter(|(pattern, _)| pattern.matches(user))
+        };
+        matching()
+            .find(|(pattern, _)| pattern.is_exact())
+            .or_else(|| matching().max_by_key(|(_, weight)| *weight))
+            .map_or(1, |(_, weight)| *weight)
+    }
+
+    pub fn is_admin(&self, user: &UserId) -> bool {
+        self.admins.is_empty() || self.admins.iter().any(|pattern| pattern.matches(user))
+    }
+
+    /// Create `data_dir` if it is missing, and make sure we can write to it
+    ///
+    /// This is checked at startup, so a bad `data_dir` stops the bot before it has lost anything.
+    pub fn prepare_data_dir(&self) -> Result<(), FourwarderError> {
+        let probe = self.data_dir.join(".4warder_write_test");
+        let result = std::fs::create_dir_all(&self.data_dir)
+            .and_then(|()| std::fs::write(&probe, b""))
+            .and_then(|()| std::fs::remove_file(&probe));
+        if let Err(e) = result {
+            tracing::error!("Can't write to {}: {}", self.data_dir.display(), e);
+            return Err(FourwarderError::Config(
+                "`data_dir` can't be created or written to",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The category approving with `emoji` publishes to, if any
+    pub fn category(&self, emoji: &str) -> Option<&Category> {
+        self.categories
+            .iter()
+            .find(|category| category.approval_emoji == emoji)
+    }
+
+    /// Make sure no two categories share a name or emoji, and that no category emoji already
+    /// means something else
+    fn check_categories(&self) -> Result<(), FourwarderError> {
+        let taken = [
+            crate::APPROVE_EMOJI,
+            crate::REJECT_EMOJI,
+            &self.hold_emoji,
+            &self.cw_emoji,
+            &self.plain_publish_emoji,
+            self.claim_emoji.as_deref().unwrap_or_default(),
+        ];
+        for (i, category) in self.categories.iter().enumerate() {
+            let others = &self.categories[..i];
+            if others.iter().any(|other| other.name == category.name) {
+                tracing::error!("`categories` has `{}` more than once", category.name);
+                return Err(FourwarderError::Config(
+                    "Every entry in `categories` needs a name of its own",
+                ));
+            }
+            if taken.contains(&category.approval_emoji.as_str())
+                || others
+                    .iter()
+                    .any(|other| other.approval_emoji == category.approval_emoji)
+            {
+                tracing::error!(
+                    "The `approval_emoji` of `{}` is already used",
+                    category.name
+                );
+                return Err(FourwarderError::Config(
+                    "Every entry in `categories` needs an `approval_emoji` which means nothing else",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `user` is one of `moderators`, not counting delegations
+    pub fn is_moderator(&self, user: &UserId) -> bool {
+        self.moderators.is_empty() || self.moderators.iter().any(|pattern| pattern.matches(user))
+    }
+
+    /// Whether `user` is spared the limits on how much and how soon they may submit
+    ///
+    /// That is anyone with more karma than `auto_approve_karma`, and anyone named in
+    /// `priority_senders`, `moderators` or `admins`. An empty `moderators` or `admins` lets
+    /// everyone moderate, but trusts no one.
+    pub fn is_trusted(&self, user: &UserId) -> bool {
+        self.auto_approve_karma
+            .is_some_and(|threshold| crate::karma::get(user) > threshold)
+            || self
+                .priority_senders
+                .iter()
+                .chain(&self.moderators)
+                .chain(&self.admins)
+                .any(|pattern| pattern.matches(user))
+    }
+
+    /// Every room the bot needs to be in, possibly with duplicates
+    pub fn rooms(&self) -> impl Iterator<Item = &RoomId> {
+        self.input_rooms
+            .iter()
+            .flat_map(|room| std::iter::once(&room.room_id).chain(&room.output_room_id))
+            .chain([&self.mod_room_id, &self.output_room_id])
+            .chain(
+                self.escalation
+                    .as_ref()
+                    .and_then(|escalation| escalation.room_id.as_ref()),
+            )
+            .chain(&self.preview_room_id)
+            .chain(&self.training_output_room_id)
+            .chain(&self.dead_letter_room_id)
+            .chain(&self.index_room_id)
+            .chain(
+                self.categories
+                    .iter()
+                    .map(|category| &category.output_room_id),
+            )
+    }
+}
+
+/// Normalize a reply, or a keyword it is compared against, so `Publish ` and `✅\u{FE0F}` match
+fn normalize_reply(reply: &str) -> String {
+    normalize_emoji(&normalize_for_matching(reply))
+        .trim()
+        .to_lowercase()
+}
+
+/// `url` without anything secret in it
+fn redact_url(url: &Url) -> String {
+    let mut url = url.clone();
+    // These only fail for URLs which can't have credentials anyway
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.set_query(None);
+    url.to_string()
+}
+
+impl TryFrom<RawRoomConfig> for InputRoom {
+    type Error = FourwarderError;
+    fn try_from(room: RawRoomConfig) -> Result<Self, Self::Error> {
+        if room
+            .output_template
+            .as_ref()
+            .is_some_and(|template| !template.contains("{body}"))
+        {
+            return Err(FourwarderError::Config(
+                "`rooms.output_template` must contain `{body}`",
+            ));
+        }
+        Ok(InputRoom {
+            room_id: RoomId::try_from(room.input_room_id.as_str()).map_err(|_| {
+                FourwarderError::Config("`rooms.input_room_id` is not a valid `RoomId`")
+            })?,
+            output_room_id: room
+                .output_room_id
+                .map(|id| RoomId::try_from(id.as_str()))
+                .transpose()
+                .map_err(|_| {
+                    FourwarderError::Config("`rooms.output_room_id` is not a valid `RoomId`")
+                })?,
+            moderate: room.moderate,
+            output_template: room.output_template,
+            blocklist: room.blocklist,
+            filters: None,
+        })
+    }
+}
+
+impl TryFrom<RawCategory> for Category {
+    type Error = FourwarderError;
+    fn try_from(category: RawCategory) -> Result<Self, Self::Error> {
+        if category.name.is_empty() {
+            return Err(FourwarderError::Config("`categories.name` can't be empty"));
+        }
+        if category.required_approval_weight == 0 {
+            return Err(FourwarderError::Config(
+                "`categories.required_approval_weight` must be at least 1",
+            ));
+        }
+        Ok(Category {
+            name: category.name,
+            approval_emoji: normalize_emoji(&category.approval_emoji),
+            output_room_id: RoomId::try_from(category.output_room_id.as_str()).map_err(|_| {
+                FourwarderError::Config("`categories.output_room_id` is not a valid `RoomId`")
+            })?,
+            required_approval_weight: category.required_approval_weight,
+            moderators: user_pattern::parse_all(
+                &category.moderators,
+                "`categories.moderators` contains an invalid `UserId` or pattern",
+            )?,
+            // Giving a root is enough to ask for a thread
+            output_thread: category.output_thread || category.output_thread_root.is_some(),
+            output_thread_root: category
+                .output_thread_root
+                .map(|root| EventId::try_from(root.as_str()))
+                .transpose()
+                .map_err(|_| {
+                    FourwarderError::Config(
+                        "`categories.output_thread_root` is not a valid `EventId`",
+                    )
+                })?,
+        })
+    }
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = FourwarderError;
+    fn try_from(config: RawConfig) -> Result<Self, Self::Error> {
+        let mut input_rooms = Vec::new();
+        if let Some(input_room_id) = config.input_room_id {
+            input_rooms.push(InputRoom {
+                room_id: RoomId::try_from(input_room_id.as_str()).map_err(|_| {
+                    FourwarderError::Config("`input_room_id` is not a valid `RoomId`")
+                })?,
+                output_room_id: None,
+                moderate: None,
+                output_template: None,
+                blocklist: None,
+                filters: None,
+            });
+        }
+        for room in config.rooms {
+            let room = InputRoom::try_from(room)?;
+            if input_rooms
+                .iter()
+                .any(|other| other.room_id == room.room_id)
+            {
+                return Err(FourwarderError::Config(
+                    "An input room is listed more than once in `input_room_id` and `rooms`",
+                ));
+            }
+            input_rooms.push(room);
+        }
+        if input_rooms.is_empty() {
+            return Err(FourwarderError::Config(
+                "No input rooms, set `input_room_id` or add a `[[rooms]]` entry",
+            ));
+        }
+        if let Some(spam) = &config.spam {
+            if !(0.0..=1.0).contains(&spam.flag_threshold) {
+                return Err(FourwarderError::Config(
+                    "`spam.flag_threshold` must be between 0 and 1",
+                ));
+            }
+            if spam
+                .drop_threshold
+                .is_some_and(|threshold| threshold < spam.flag_threshold)
+            {
+                return Err(FourwarderError::Config(
+                    "`spam.drop_threshold` must not be lower than `spam.flag_threshold`",
+                ));
+            }
+        }
+        let moderation_webhook = match config.moderation_webhook_url {
+            Some(url) => Some(ModerationWebhook {
+                url: Url::parse(&url).map_err(|_| {
+                    FourwarderError::Config("`moderation_webhook_url` is not a valid URL")
+                })?,
+                timeout: Duration::from_secs(config.moderation_webhook_timeout_secs),
+                fail_mode: config.webhook_fail_mode,
+            }),
+            None => None,
+        };
+        let output_webhook = match config.output_webhook_url {
+            Some(url) => Some(OutputWebhook {
+                url: Url::parse(&url).map_err(|_| {
+                    FourwarderError::Config("`output_webhook_url` is not a valid URL")
+                })?,
+                timeout: Duration::from_secs(config.output_webhook_timeout_secs),
+                retries: config.output_webhook_retries,
+            }),
+            None => None,
+        };
+        let hash_denylist = match config.hash_denylist_url {
+            Some(url) => Some(HashDenylist {
+                url: Url::parse(&url).map_err(|_| {
+                    FourwarderError::Config("`hash_denylist_url` is not a valid URL")
+                })?,
+                timeout: Duration::from_secs(config.hash_denylist_timeout_secs),
+                fail_mode: config.hash_denylist_fail_mode,
+            }),
+            None => None,
+        };
+
+        if config
+            .output_template
+            .as_ref()
+            .is_some_and(|template| !template.contains("{body}"))
+        {
+            return Err(FourwarderError::Config(
+                "`output_template` must contain `{body}`",
+            ));
+        }
+
+        if config.backfill_limit == 0 {
+            return Err(FourwarderError::Config(
+                "`backfill_limit` must be at least 1",
+            ));
+        }
+
+        if config.max_output_chars < 100 {
+            return Err(FourwarderError::Config(
+                "`max_output_chars` must be at least 100",
+            ));
+        }
+
+        if config.max_concurrent_sends == 0 {
+            return Err(FourwarderError::Config(
+                "`max_concurrent_sends` must be at least 1",
+            ));
+        }
+        if config.send_timeout_secs == 0 || config.fetch_timeout_secs == 0 {
+            return Err(FourwarderError::Config(
+                "`send_timeout_secs` and `fetch_timeout_secs` must be at least 1",
+            ));
+        }
+        // The homeserver holds a sync open for up to 30 seconds when there is nothing new
+        if config.sync_timeout_secs <= 30 {
+            return Err(FourwarderError::Config(
+                "`sync_timeout_secs` must be more than 30",
+            ));
+        }
+        if (1..300).contains(&config.sync_stall_timeout_secs) {
+            return Err(FourwarderError::Config(
+                "`sync_stall_timeout_secs` must be 0, to turn it off, or at least 300",
+            ));
+        }
+
+        if config.failover_after_secs.is_some_and(|secs| secs < 3) {
+            return Err(FourwarderError::Config(
+                "`failover_after_secs` must be at least 3",
+            ));
+        }
+
+        if let Some(msgtype) = config
+            .allowed_message_types
+            .iter()
+            .find(|msgtype| !MESSAGE_TYPES.contains(&msgtype.as_str()))
+        {
+            tracing::error!("`allowed_message_types` has unknown type `{}`", msgtype);
+            return Err(FourwarderError::Config(
+                "`allowed_message_types` contains a message type which doesn't exist",
+            ));
+        }
+
+        if config.instance_id.is_empty() {
+            return Err(FourwarderError::Config("`instance_id` can't be empty"));
+        }
+
+        let escalation = match config.escalation_after_secs {
+            Some(after) => Some(Escalation {
+                after: Duration::from_secs(after),
+                room_id: config
+                    .escalation_room_id
+                    .as_deref()
+                    .map(RoomId::try_from)
+                    .transpose()
+                    .map_err(|_| {
+                        FourwarderError::Config("`escalation_room_id` is not a valid `RoomId`")
+                    })?,
+                users: config
+                    .escalation_users
+                    .iter()
+                    .map(|user| UserId::try_from(user.as_str()))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| {
+                        FourwarderError::Config("`escalation_users` contains an invalid `UserId`")
+                    })?,
+            }),
+            None => None,
+        };
+
+        let queue_limit = match config.max_queue_size {
+            Some(max) => {
+                // Three quarters of the way down, so the alert doesn't flap around the limit
+                let low_watermark = config.queue_low_watermark.unwrap_or(max * 3 / 4);
+                if low_watermark > max {
+                    return Err(FourwarderError::Config(
+                        "`queue_low_watermark` must not be higher than `max_queue_size`",
+                    ));
+                }
+                Some(QueueLimit {
+                    max,
+                    low_watermark,
+                    drop_when_full: config.drop_when_queue_full,
+                })
+            }
+            None => None,
+        };
+
+        let priority_senders = user_pattern::parse_all(
+            &config.priority_senders,
+            "`priority_senders` contains an invalid `UserId` or pattern",
+        )?;
+
+        let admins = user_pattern::parse_all(
+            &config.admins,
+            "`admins` contains an invalid `UserId` or pattern",
+        )?;
+        let moderators = user_pattern::parse_all(
+            &config.moderators,
+            "`moderators` contains an invalid `UserId` or pattern",
+        )?;
+        // Anyone in an input room can react, so an empty list can't mean everyone there
+        if config.approve_in_input_room && moderators.is_empty() {
+            return Err(FourwarderError::Config(
+                "`approve_in_input_room` needs `moderators`, or anyone in an input room could approve",
+            ));
+        }
+        let moderator_weights = config
+            .moderator_weights
+            .iter()
+            .map(|(user, weight)| UserPattern::parse(user).map(|pattern| (pattern, *weight)))
+            .collect::<Option<_>>()
+            .ok_or(FourwarderError::Config(
+                "`moderator_weights` contains an invalid `UserId` or pattern",
+            ))?;
+        if config.rate_limit_per_minute == Some(0) {
+            return Err(FourwarderError::Config(
+                "`rate_limit_per_minute` must be at least 1",
+            ));
+        }
+        if config.daily_submission_quota == Some(0) {
+            return Err(FourwarderError::Config(
+                "`daily_submission_quota` must be at least 1",
+            ));
+        }
+        if config.submission_quota_window_secs < 60 {
+            return Err(FourwarderError::Config(
+                "`submission_quota_window_secs` must be at least 60",
+            ));
+        }
+        if config.required_approval_weight == 0 || config.required_rejection_weight == 0 {
+            return Err(FourwarderError::Config(
+                "`required_approval_weight` and `required_rejection_weight` must be at least 1",
+            ));
+        }
+
+        if config.command_prefix.trim().is_empty() {
+            return Err(FourwarderError::Config(
+                "`command_prefix` must not be empty",
+            ));
+        }
+        if let Some(command) = config
+            .command_permissions
+            .keys()
+            .find(|command| !COMMANDS.contains(&command.as_str()))
+        {
+            tracing::error!("`command_permissions` has unknown command `{}`", command);
+            return Err(FourwarderError::Config(
+                "`command_permissions` contains a command which doesn't exist",
+            ));
+        }
+        if let Some(command) = config
+            .confirm_commands
+            .iter()
+            .find(|command| !COMMANDS.contains(&command.as_str()))
+        {
+            tracing::error!("`confirm_commands` has unknown command `{}`", command);
+            return Err(FourwarderError::Config(
+                "`confirm_commands` contains a command which doesn't exist",
+            ));
+        }
+        if config.confirm_window_secs == 0 {
+            return Err(FourwarderError::Config(
+                "`confirm_window_secs` must be at least 1",
+            ));
+        }
+        if config.index_size == 0 {
+            return Err(FourwarderError::Config("`index_size` must be at least 1"));
+        }
+        if config.claim_timeout_secs < 60 {
+            return Err(FourwarderError::Config(
+                "`claim_timeout_secs` must be at least 60",
+            ));
+        }
+        if config.mirror_reactions_interval_secs < 60 {
+            return Err(FourwarderError::Config(
+                "`mirror_reactions_interval_secs` must be at least 60",
+            ));
+        }
+        if config.index_interval_secs < 60 {
+            return Err(FourwarderError::Config(
+                "`index_interval_secs` must be at least 60",
+            ));
+        }
+
+        let mut blocklist = config.blocklist;
+        if let Some(path) = &config.blocklist_file {
+            blocklist.extend(blocklist::read_file(path)?);
+        }
+        let include = Include::new(&config.include_patterns)?;
+        let submission_prefix = config
+            .submission_prefix
+            .map(|prefix| prefix.trim().to_owned());
+        if submission_prefix.as_ref().is_some_and(String::is_empty) {
+            return Err(FourwarderError::Config("`submission_prefix` can't be empty"));
+        }
+
+        let mut config = Config {
+            homeserver: config.homeserver,
+            username: config.username,
+            password: config.password,
+            device_name: config.device_name.unwrap_or_else(auth::default_device_name),
+            input_rooms,
+            mod_room_id: RoomId::try_from(config.mod_room_id.as_str())
+                .map_err(|_| FourwarderError::Config("`mod_room_id` is not a valid `RoomId`"))?,
+            output_room_id: RoomId::try_from(config.output_room_id.as_str())
+                .map_err(|_| FourwarderError::Config("`output_room_id` is not a valid `RoomId`"))?,
+            moderate: config.moderate,
+            dry_run: config.dry_run,
+            url_policy: config.url_policy,
+            url_allowlist: config
+                .url_allowlist
+                .iter()
+                .map(|host| host.to_lowercase())
+                .collect(),
+            spam: config.spam,
+            blocklist,
+            include,
+            submission_prefix,
+            content_dedup: config.content_dedup,
+            content_dedup_window: Duration::from_secs(config.content_dedup_window_secs),
+            auto_approve_karma: config.auto_approve_karma,
+            moderation_webhook,
+            output_webhook,
+            hash_denylist,
+            queue_limit,
+            metrics_address: config.metrics_address,
+            priority_keywords: config
+                .priority_keywords
+                .iter()
+                .map(|keyword| normalize_for_matching(keyword).to_lowercase())
+                .filter(|keyword| !keyword.is_empty())
+                .collect(),
+            priority_senders,
+            profanity_mask: config
+                .profanity_mask
+                .iter()
+                .map(|word| normalize_for_matching(word).to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+            rate_limit_per_minute: config.rate_limit_per_minute,
+            daily_submission_quota: config.daily_submission_quota,
+            submission_quota_window: Duration::from_secs(config.submission_quota_window_secs),
+            min_membership_age: config
+                .min_membership_age_secs
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            utc_offset: schedule::parse_offset(&config.utc_offset).ok_or(
+                FourwarderError::Config("`utc_offset` must look like `+02:00` or `-05:30`"),
+            )?,
+            trim_output: config.trim_output,
+            markdown_to_html: config.markdown_to_html,
+            split_long_output: config.split_long_output,
+            max_output_chars: config.max_output_chars,
+            sync_filter: config.sync_filter,
+            persist_runtime_changes: config.persist_runtime_changes,
+            command_prefix: config.command_prefix,
+            admins,
+            moderators,
+            pin_instructions: config.pin_instructions,
+            index_room_id: config
+                .index_room_id
+                .map(|room_id| RoomId::try_from(room_id.as_str()))
+                .transpose()
+                .map_err(|_| FourwarderError::Config("`index_room_id` is not a valid `RoomId`"))?,
+            index_size: config.index_size,
+            index_interval: Duration::from_secs(config.index_interval_secs),
+            categories: config
+                .categories
+                .into_iter()
+                .map(Category::try_from)
+                .collect::<Result<_, _>>()?,
+            command_permissions: config.command_permissions,
+            confirm_commands: config.confirm_commands,
+            confirm_window: Duration::from_secs(config.confirm_window_secs),
+            dedup_capacity: config.dedup_capacity,
+            startup_scan_messages: config.startup_scan_messages,
+            preview_room_id: config
+                .preview_room_id
+                .as_deref()
+                .map(RoomId::try_from)
+                .transpose()
+                .map_err(|_| {
+                    FourwarderError::Config("`preview_room_id` is not a valid `RoomId`")
+                })?,
+            training_output_room_id: config
+                .training_output_room_id
+                .as_deref()
+                .map(RoomId::try_from)
+                .transpose()
+                .map_err(|_| {
+                    FourwarderError::Config("`training_output_room_id` is not a valid `RoomId`")
+                })?,
+            dead_letter_room_id: config
+                .dead_letter_room_id
+                .as_deref()
+                .map(RoomId::try_from)
+                .transpose()
+                .map_err(|_| {
+                    FourwarderError::Config("`dead_letter_room_id` is not a valid `RoomId`")
+                })?,
+            max_concurrent_sends: config.max_concurrent_sends,
+            send_timeout: Duration::from_secs(config.send_timeout_secs),
+            fetch_timeout: Duration::from_secs(config.fetch_timeout_secs),
+            sync_timeout: Duration::from_secs(config.sync_timeout_secs),
+            sync_stall_timeout: Some(config.sync_stall_timeout_secs)
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            order_by: config.order_by,
+            redact_source_on_publish: config.redact_source_on_publish,
+            mirror_unknown_events: config.mirror_unknown_events,
+            muted_rooms: config
+                .muted_rooms
+                .iter()
+                .map(|room_id| RoomId::try_from(room_id.as_str()))
+                .collect::<Result<_, _>>()
+                .map_err(|_| FourwarderError::Config("`muted_rooms` contains an invalid `RoomId`"))?,
+            instance_id: config.instance_id,
+            media_mode: config.media_mode,
+            forward_media_captions: config.forward_media_captions,
+            ignore_filename_captions: config.ignore_filename_captions,
+            require_media_caption: config.require_media_caption,
+            show_source_reactions: config.show_source_reactions,
+            mirror_source_reactions: config.mirror_source_reactions,
+            mirror_reactions_interval: Duration::from_secs(config.mirror_reactions_interval_secs),
+            approve_in_input_room: config.approve_in_input_room,
+            react_on_source_decision: config.react_on_source_decision,
+            source_approved_emoji: config.source_approved_emoji,
+            source_rejected_emoji: config.source_rejected_emoji,
+            source_reactions_limit: config.source_reactions_limit,
+            decided_retention: Duration::from_secs(config.decided_retention_secs),
+            published_retention: config
+                .published_retention_secs
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            pseudonym_retention: config
+                .pseudonym_retention_secs
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            dead_letter_retention: config
+                .dead_letter_retention_secs
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            min_send_interval: config
+                .min_send_interval_ms
+                .filter(|ms| *ms > 0)
+                .map(Duration::from_millis),
+            failover_after: config.failover_after_secs.map(Duration::from_secs),
+            initial_sync_retries: config.initial_sync_retries,
+            data_dir: config.data_dir,
+            allowed_message_types: config.allowed_message_types,
+            in_place_approval: config.in_place_approval,
+            backfill_limit: config.backfill_limit,
+            cw_emoji: normalize_emoji(&config.cw_emoji),
+            cw_keywords: config
+                .cw_keywords
+                .into_iter()
+                .map(|(keyword, label)| (normalize_for_matching(&keyword).to_lowercase(), label))
+                .filter(|(keyword, _)| !keyword.is_empty())
+                .collect(),
+            plain_publish_emoji: normalize_emoji(&config.plain_publish_emoji),
+            hold_emoji: normalize_emoji(&config.hold_emoji),
+            claim_emoji: config
+                .claim_emoji
+                .as_deref()
+                .map(normalize_emoji)
+                .filter(|emoji| !emoji.is_empty()),
+            claim_timeout: Duration::from_secs(config.claim_timeout_secs),
+            warn_on_claimed_decision: config.warn_on_claimed_decision,
+            approval_via_reply: config.approval_via_reply,
+            approval_reply_keywords: config
+                .approval_reply_keywords
+                .iter()
+                .map(|keyword| normalize_reply(keyword))
+                .filter(|keyword| !keyword.is_empty())
+                .collect(),
+            moderator_weights,
+            required_approval_weight: config.required_approval_weight,
+            required_rejection_weight: config.required_rejection_weight,
+            require_rejection_reason: config.require_rejection_reason,
+            present_as_sender: config.present_as_sender,
+            output_template: config.output_template,
+            quote_style: config.quote_style,
+            output_msgtype: config.output_msgtype,
+            escalation,
+            messages: Messages::load(&config.language, config.messages_file.as_deref())?,
+            filters: Vec::new(),
+        };
+        config.filters = filters::build(&config, &config.blocklist);
+        let room_filters: Vec<_> = config
+            .input_rooms
+            .iter()
+            .map(|room| {
+                room.blocklist
+                    .as_ref()
+                    .map(|blocklist| filters::build(&config, blocklist))
+            })
+            .collect();
+        for (room, filters) in config.input_rooms.iter_mut().zip(room_filters) {
+            room.filters = filters;
+        }
+        config.check_categories()?;
+
+        // Approving in place is supported, but confirming has to happen somewhere else
+        if config.preview_room_id.as_ref() == Some(&config.mod_room_id) {
+            return Err(FourwarderError::Config(
+                "`preview_room_id` can't be the mod room",
+            ));
+        }
+        // Training submissions must never reach the people reading the real output
+        if let Some(training_output_room_id) = &config.training_output_room_id {
+            let is_output = training_output_room_id == &config.output_room_id
+                || config.input_rooms.iter().any(|room| {
+                    config.output_room_of(&room.room_id) == training_output_room_id
+                })
+                || config
+                    .categories
+                    .iter()
+                    .any(|category| &category.output_room_id == training_output_room_id)
+                || config.index_room_id.as_ref() == Some(training_output_room_id);
+            if is_output {
+                return Err(FourwarderError::Config(
+                    "`training_output_room_id` can't be an output room",
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// The live config, along with the TOML it was built from so reloads can report what changed
+struct Loaded {
+    config: Arc<Config>,
+    source: toml::Value,
+}
+
+static CONFIG: OnceLock<RwLock<Loaded>> = OnceLock::new();
+
+/// Read and validate the config file at `path`, returning the parsed TOML as well
+fn read_config(path: &str) -> Result<(Config, toml::Value), FourwarderError> {
+    let config_raw = std::fs::read_to_string(path)?;
+    let source: toml::Value = toml::from_str(&config_raw)?;
+
+    Ok((parse(&source)?, source))
+}
+
+/// Validate a config, after filling in the rooms it names by space
+///
+/// `source` is left as it is, so writing it back keeps the names.
+fn parse(source: &toml::Value) -> Result<Config, FourwarderError> {
+    let mut resolved = source.clone();
+    space::substitute(&mut resolved)?;
+    validate(&resolved)
+}
+
+/// Validate a config which gives every room by id
+pub fn validate(source: &toml::Value) -> Result<Config, FourwarderError> {
+    let unknown = unknown_keys(source);
+    let raw: RawConfig = source.clone().try_into().map_err(|e| {
+        let problems = schema::problems(source);
+        // Should the probes miss what went wrong, serde's own error is better than none
+        if problems.is_empty() {
+            FourwarderError::Toml(e)
+        } else {
+            FourwarderError::Schema(problems)
+        }
+    })?;
+
+    for key in &unknown {
+        if raw.strict {
+            tracing::error!("The config has an unknown key `{}`", key);
+        } else {
+            tracing::warn!("Ignoring the unknown key `{}` in the config", key);
+        }
+    }
+    if raw.strict && !unknown.is_empty() {
+        return Err(FourwarderError::Config(
+            "The config has keys the bot doesn't know, and `strict` is set",
+        ));
+    }
+    Config::try_from(raw)
+}
+
+/// The keys in `source` which nothing reads, which are most likely typos
+///
+/// Only the top level and the `[[rooms]]` and `[[categories]]` entries are checked, as the other
+/// tables are keyed by users, commands or message names.
+pub fn unknown_keys(source: &toml::Value) -> Vec<String> {
+    let mut unknown = Vec::new();
+    let mut check = |table: &toml::value::Table, fields: &[&str], prefix: &str| {
+        for key in table.keys() {
+            if !fields.contains(&key.as_str()) {
+                unknown.push(format!("{}{}", prefix, key));
+            }
+        }
+    };
+
+    let table = match source.as_table() {
+        Some(table) => table,
+        None => return unknown,
+    };
+    // `space_id` is read by [`space::resolve`] before the rest of the config
+    let top_level: Vec<&str> = fields_of::<RawConfig>()
+        .iter()
+        .copied()
+        .chain(["space_id"])
+        .collect();
+    check(table, &top_level, "");
+    for (list, fields) in [
+        ("rooms", fields_of::<RawRoomConfig>()),
+        ("categories", fields_of::<RawCategory>()),
+    ] {
+        let entries = table.get(list).and_then(toml::Value::as_array);
+        for entry in entries
+            .into_iter()
+            .flatten()
+            .filter_map(toml::Value::as_table)
+        {
+            check(entry, fields, &format!("{}.", list));
+        }
+    }
+    unknown
+}
+
+/// The keys `T` is deserialized from, which serde only tells the deserializer
+fn fields_of<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    struct Fields<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for Fields<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(serde::de::Error::custom("only structs have fields"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(serde::de::Error::custom("only the fields were wanted"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+            ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    // This always fails, once it has the fields
+    let _ = T::deserialize(Fields(&mut fields));
+    fields
+}
+
+/// Load the config file at `path` and make it the live config
+///
+/// Must be called once at startup, before anything calls [`get`].
+pub fn load_config(path: &str) -> Result<(), FourwarderError> {
+    let (config, source) = read_config(path)?;
+
+    CONFIG
+        .set(RwLock::new(Loaded {
+            config: Arc::new(config),
+            source,
+        }))
+        .map_err(|_| FourwarderError::Logic("The config was loaded twice"))
+}
+
+/// Load the config file at `path` without connecting, for the subcommands which only need the
+/// files in `data_dir`
+///
+/// Rooms named by space get made-up ids like in `4warder check`, as the space can't be looked
+/// up.
+pub fn load_offline(path: &str) -> Result<(), FourwarderError> {
+    let mut source: toml::Value = toml::from_str(&std::fs::read_to_string(path)?)?;
+    let original = source.clone();
+    space::stand_in(&mut source);
+    let config = validate(&source)?;
+
+    CONFIG
+        .set(RwLock::new(Loaded {
+            config: Arc::new(config),
+            source: original,
+        }))
+        .map_err(|_| FourwarderError::Logic("The config was loaded twice"))
+}
+
+/// Get a snapshot of the live config
+///
+/// The snapshot is not affected by later reloads, so hold on to it for the
+/// duration of an event rather than calling this repeatedly.
+pub fn get() -> Arc<Config> {
+    CONFIG
+        .get()
+        .expect("Config used before it was loaded")
+        .read()
+        .unwrap()
+        .config
+        .clone()
+}
+
+/// Re-read the config file at `path` and swap it in if it is valid
+///
+/// Connection-level keys (see [`RESTART_KEYS`]) keep their current values, as
+/// they are only used when logging in. On error the live config is left untouched.
+pub fn reload(path: &str) -> Result<(), FourwarderError> {
+    let (mut config, source) = read_config(path)?;
+
+    let lock = CONFIG.get().ok_or(FourwarderError::Logic(
+        "The config was reloaded before it was loaded",
+    ))?;
+    let mut loaded = lock.write().unwrap();
+
+    // Swapped in even if nothing changed, as `blocklist_file` may have
+    let changed = changed_keys(&loaded.source, &source);
+    if changed.is_empty() {
+        tracing::info!("Reloaded config, nothing changed");
+    } else {
+        for key in changed
+            .iter()
+            .filter(|key| RESTART_KEYS.contains(&key.as_str()))
+        {
+            tracing::warn!(
+                "`{}` changed, but will only take effect after a restart",
+                key
+            );
+        }
+        tracing::info!("Reloaded config, changed: {}", changed.join(", "));
+    }
+    keep_restart_keys(&mut config, &loaded.config);
+
+    *loaded = Loaded {
+        config: Arc::new(config),
+        source,
+    };
+
+    Ok(())
+}
+
+/// Build the live config again from the TOML it was last loaded from, for `!reload-filters`
+///
+/// This reads `blocklist_file` again without reading the config file. On error the live config,
+/// with its filters, is left untouched.
+pub fn reload_filters() -> Result<Arc<Config>, FourwarderError> {
+    let lock = CONFIG.get().ok_or(FourwarderError::Logic(
+        "The filters were reloaded before the config was loaded",
+    ))?;
+    let mut loaded = lock.write().unwrap();
+
+    let mut config = parse(&loaded.source)?;
+    keep_restart_keys(&mut config, &loaded.config);
+    loaded.config = Arc::new(config);
+    Ok(loaded.config.clone())
+}
+
+/// Change the config as if its file had been edited, and make the result the live config
+///
+/// The result is validated like any other config before being swapped in, and only written
+/// back to `path` if `persist_runtime_changes` is set. Writing it back loses any comments.
+pub fn edit(
+    path: &str,
+    f: impl FnOnce(&mut toml::value::Table),
+) -> Result<Arc<Config>, FourwarderError> {
+    let lock = CONFIG.get().ok_or(FourwarderError::Logic(
+        "The config was edited before it was loaded",
+    ))?;
+    let mut loaded = lock.write().unwrap();
+
+    let mut source = loaded.source.clone();
+    let table = source
+        .as_table_mut()
+        .ok_or(FourwarderError::Logic("The config is not a TOML table"))?;
+    f(table);
+
+    let mut config = parse(&source)?;
+    keep_restart_keys(&mut config, &loaded.config);
+
+    if config.persist_runtime_changes {
+        let toml = toml::to_string(&source).map_err(|_| {
+            FourwarderError::Logic("The edited config could not be written as TOML")
+        })?;
+        std::fs::write(path, toml)?;
+    }
+
+    let changed = changed_keys(&loaded.source, &source);
+    tracing::info!("Edited config, changed: {}", changed.join(", "));
+
+    *loaded = Loaded {
+        config: Arc::new(config),
+        source,
+    };
+
+    Ok(loaded.config.clone())
+}
+
+/// Carry over the keys in [`RESTART_KEYS`] from the config we started with
+fn keep_restart_keys(config: &mut Config, old: &Config) {
+    config.homeserver = old.homeserver.clone();
+    config.username = old.username.clone();
+    config.password = old.password.clone();
+    config.device_name = old.device_name.clone();
+    config.metrics_address = old.metrics_address;
+    config.startup_scan_messages = old.startup_scan_messages;
+    config.max_concurrent_sends = old.max_concurrent_sends;
+    config.failover_after = old.failover_after;
+    config.initial_sync_retries = old.initial_sync_retries;
+    config.data_dir = old.data_dir.clone();
+}
+
+/// List the top-level keys whose values differ between two config files
+pub fn changed_keys(old: &toml::Value, new: &toml::Value) -> Vec<String> {
+    let empty = toml::value::Table::new();
+    let old = old.as_table().unwrap_or(&empty);
+    let new = new.as_table().unwrap_or(&empty);
+
+    let mut changed: Vec<String> = old
+        .keys()
+        .chain(new.keys().filter(|key| !old.contains_key(*key)))
+        .filter(|key| old.get(*key) != new.get(*key))
+        .cloned()
+        .collect();
+    changed.sort();
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        filters::{FilterDecision, IncomingMessage},
+        testing::{self, event, room, user},
+    };
+
+    const ROOMS: &str = r#"
+output_template = "{body}"
+blocklist = ["spam"]
+
+[[rooms]]
+input_room_id = "!news:example.org"
+output_template = "📰 {body}"
+blocklist = ["rumour"]
+"#;
+
+    fn dropped(config: &Config, room_id: &str, body: &str) -> bool {
+        let room_id = room(room_id);
+        let settings = config.input_room(&room_id).unwrap();
+        let decision = filters::evaluate(
+            settings.filters,
+            &IncomingMessage {
+                room_id: &room_id,
+                event_id: &event("$a:example.org"),
+                sender: &user("@sub:example.org"),
+                text: &TextMessageEventContent::plain(body),
+            },
+        );
+        matches!(decision, FilterDecision::Drop(_))
+    }
+
+    #[test]
+    fn rooms_can_have_their_own_template() {
+        let config = testing::config(ROOMS);
+
+        assert_eq!(
+            config.output_template_of(&room("!news:example.org")),
+            Some("📰 {body}")
+        );
+        assert_eq!(
+            config.output_template_of(&room("!input:example.org")),
+            Some("{body}")
+        );
+        assert_eq!(
+            config.output_template_of(&room("!gone:example.org")),
+            Some("{body}")
+        );
+    }
+
+    #[test]
+    fn rooms_can_have_their_own_blocklist() {
+        let config = testing::config(ROOMS);
+
+        assert!(dropped(&config, "!news:example.org", "a rumour"));
+        assert!(!dropped(&config, "!news:example.org", "some spam"));
+        assert!(dropped(&config, "!input:example.org", "some spam"));
+        assert!(!dropped(&config, "!input:example.org", "a rumour"));
+    }
+
+    #[test]
+    fn room_templates_need_the_body() {
+        let invalid = r#"
+[[rooms]]
+input_room_id = "!news:example.org"
+output_template = "📰"
+"#;
+        assert!(testing::try_config(invalid).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use matrix_sdk::{
+    ruma::{EventId, UserId},
+    uuid::Uuid,
+};
+
+use crate::clock;
+
+/// How many characters of a fresh uuid make up a token
+const TOKEN_LEN: usize = 6;
+
+/// A command in `confirm_commands` waiting for the user who gave it to confirm it
+#[derive(Clone, Debug)]
+pub struct Confirmation {
+    pub sender: UserId,
+    /// The command and its arguments, without the prefix
+    pub args: Vec<String>,
+    pub token: String,
+    /// The mod room message asking for confirmation, once it is sent
+    prompt_event_id: Option<EventId>,
+    expires: Instant,
+}
+
+/// Kept in memory only, so a restart forgets them and they have to be given again
+static WAITING: LazyLock<Mutex<Vec<Confirmation>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+/// Start waiting for `sender` to confirm `args` within `window`, returning the token which
+/// confirms them
+pub fn request(sender: &UserId, args: &[&str], window: Duration) -> String {
+    let now = clock::now();
+    let token: String = Uuid::new_v4()
+        .to_simple()
+        .to_string()
+        .chars()
+        .take(TOKEN_LEN)
+        .collect();
+
+    let mut waiting = WAITING.lock().unwrap();
+    waiting.retain(|confirmation| confirmation.expires > now);
+    waiting.push(Confirmation {
+        sender: sender.clone(),
+        args: args.iter().map(|arg| (*arg).to_owned()).collect(),
+        token: token.clone(),
+        prompt_event_id: None,
+        expires: now + window,
+    });
+    token
+}
+
+/// Remember that `prompt_event_id` asks for the confirmation of `token`, so a ✅ on it confirms
+pub fn set_prompt(token: &str, prompt_event_id: EventId) {
+    let mut waiting = WAITING.lock().unwrap();
+    if let Some(confirmation) = waiting
+        .iter_mut()
+        .find(|confirmation| confirmation.token == token)
+    {
+        confirmation.prompt_event_id = Some(prompt_event_id);
+    }
+}
+
+/// Take the confirmation `sender` gave by sending `args` again followed by its token, if that is
+/// what they did and it hasn't expired
+pub fn take_by_token(sender: &UserId, args: &[&str]) -> Option<Confirmation> {
+    let (token, args) = args.split_last()?;
+    take(|confirmation| {
+        confirmation.sender == *sender && confirmation.token == *token && confirmation.args == args
+    })
+}
+
+/// Take the confirmation `sender` gave by reacting to `prompt_event_id`, if it is one of our
+/// prompts to them and it hasn't expired
+pub fn take_by_prompt(sender: &UserId, prompt_event_id: &EventId) -> Option<Confirmation> {
+    take(|confirmation| {
+        confirmation.sender == *sender
+            && confirmation.prompt_event_id.as_ref() == Some(prompt_event_id)
+    })
+}
+
+fn take(matches: impl Fn(&Confirmation) -> bool) -> Option<Confirmation> {
+    let now = clock::now();
+    let mut waiting = WAITING.lock().unwrap();
+    waiting.retain(|confirmation| confirmation.expires > now);
+    let index = waiting.iter().position(matches)?;
+    Some(waiting.remove(index))
+}
@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use matrix_sdk::ruma::UserId;
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::now_millis, media::hex_sha256, normalize::normalize_for_matching, state};
+
+/// Which parts of a submission make it a repeat of an earlier one, for `content_dedup`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentDedup {
+    /// Only the same event delivered twice is a repeat
+    #[default]
+    Off,
+    /// The same text with the same file, or the same text without one
+    Composite,
+    /// The same text, or the same file, whatever comes with it
+    PerField,
+}
+
+/// A submission's text or file, hashed, which later ones are compared against
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Seen {
+    key: String,
+    /// Milliseconds since the Unix epoch
+    at: u64,
+    /// Who sent it, for `!erase`, `None` for ones seen before this was kept
+    #[serde(default)]
+    sender: Option<UserId>,
+}
+
+/// Remember `sender`'s submission with `text` and the file with SHA-256 `media`, returning
+/// `false` if one like it was already seen within `window`
+///
+/// Text is compared normalized, ignoring case, invisible characters and how it is spaced. Only
+/// the last `capacity` submissions are remembered. Checking and remembering happen under one
+/// lock, so two copies arriving at once can't both be taken for the first.
+pub fn first_time(
+    mode: ContentDedup,
+    window: Duration,
+    capacity: usize,
+    sender: &UserId,
+    text: &str,
+    media: Option<&str>,
+) -> bool {
+    let text = normalize_for_matching(text)
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let keys: Vec<String> = match mode {
+        ContentDedup::Off => return true,
+        ContentDedup::Composite => vec![format!(
+            "{}:{}",
+            hex_sha256(text.as_bytes()),
+            media.unwrap_or_default()
+        )],
+        ContentDedup::PerField => {
+            let text = (!text.is_empty()).then(|| format!("text:{}", hex_sha256(text.as_bytes())));
+            let media = media.map(|media| format!("media:{}", media));
+            text.into_iter().chain(media).collect()
+        }
+    };
+
+    let now = now_millis();
+    state::update(|state| {
+        let seen = &mut state.content_seen;
+        seen.retain(|seen| now.saturating_sub(seen.at) <= window.as_millis() as u64);
+        if seen.iter().any(|seen| keys.contains(&seen.key)) {
+            return false;
+        }
+        seen.extend(keys.into_iter().map(|key| Seen {
+            key,
+            at: now,
+            sender: Some(sender.clone()),
+        }));
+        while seen.len() > capacity {
+            seen.pop_front();
+        }
+        true
+    })
+}
+
+/// Forget submissions seen longer than `window` ago, which nothing is compared against any more,
+/// returning how many there were
+pub fn prune(window: Duration) -> usize {
+    let now = now_millis();
+    state::update(|state| {
+        let before = state.content_seen.len();
+        state
+            .content_seen
+            .retain(|seen| now.saturating_sub(seen.at) <= window.as_millis() as u64);
+        before - state.content_seen.len()
+    })
+}
+
+/// Forget every submission seen from `sender`
+pub fn remove_sender(sender: &UserId) {
+    state::update(|state| {
+        state
+            .content_seen
+            .retain(|seen| seen.sender.as_ref() != Some(sender))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const WINDOW: Duration = Duration::from_secs(3600);
+
+    fn sender() -> UserId {
+        testing::user("@sub:example.org")
+    }
+
+    #[test]
+    fn repeat_is_forgotten_after_the_window() {
+        let test = testing::isolate();
+        let seen = |text| first_time(ContentDedup::Composite, WINDOW, 100, &sender(), text, None);
+
+        assert!(seen("Lost cat, answers to Tom"));
+        test.clock.advance(WINDOW);
+        assert!(!seen("lost  CAT, answers to Tom"));
+        test.clock.advance(Duration::from_millis(1));
+        assert!(seen("Lost cat, answers to Tom"));
+    }
+
+    #[test]
+    fn oldest_is_evicted_past_capacity() {
+        let _test = testing::isolate();
+        let seen = |text| first_time(ContentDedup::Composite, WINDOW, 2, &sender(), text, None);
+
+        assert!(seen("one"));
+        assert!(seen("two"));
+        assert!(seen("three"));
+        assert!(!seen("three"));
+        assert!(seen("one"));
+    }
+
+    const IMAGE: &str = "aaaa";
+    const OTHER_IMAGE: &str = "bbbb";
+
+    #[test]
+    fn composite_needs_both_to_match() {
+        let _test = testing::isolate();
+        let seen =
+            |text, media| first_time(ContentDedup::Composite, WINDOW, 100, &sender(), text, media);
+
+        assert!(seen("my cat", Some(IMAGE)));
+        assert!(seen("my dog", Some(IMAGE)));
+        assert!(seen("my cat", Some(OTHER_IMAGE)));
+        assert!(seen("my cat", None));
+        assert!(!seen("My  cat", Some(IMAGE)));
+    }
+
+    #[test]
+    fn per_field_matches_either() {
+        let _test = testing::isolate();
+        let seen =
+            |text, media| first_time(ContentDedup::PerField, WINDOW, 100, &sender(), text, media);
+
+        assert!(seen("my cat", Some(IMAGE)));
+        // The same image with a different caption
+        assert!(!seen("my dog", Some(IMAGE)));
+        // The same caption with a different image
+        assert!(!seen("my cat", Some(OTHER_IMAGE)));
+        assert!(seen("my bird", Some("cccc")));
+    }
+
+    #[test]
+    fn per_field_ignores_empty_captions() {
+        let _test = testing::isolate();
+        let seen =
+            |text, media| first_time(ContentDedup::PerField, WINDOW, 100, &sender(), text, media);
+
+        assert!(seen("", Some(IMAGE)));
+        assert!(seen("", Some(OTHER_IMAGE)));
+        assert!(!seen("", Some(IMAGE)));
+    }
+
+    #[test]
+    fn off_takes_everything() {
+        let _test = testing::isolate();
+        for _ in 0..2 {
+            assert!(first_time(
+                ContentDedup::Off,
+                WINDOW,
+                100,
+                &sender(),
+                "my cat",
+                Some(IMAGE)
+            ));
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use matrix_sdk::ruma::events::room::message::{
+    FormattedBody, MessageFormat, TextMessageEventContent,
+};
+
+use crate::{blocklist::contains_word, messages::Messages, normalize::normalize_for_matching};
+
+/// Published submissions with one of these are hidden behind a spoiler
+#[derive(Clone, Debug)]
+pub struct ContentWarning {
+    /// Shown on the spoiler, if there is one
+    pub reason: Option<String>,
+}
+
+impl ContentWarning {
+    /// Hide `text` behind a spoiler, with a plain text fallback for clients without HTML
+    pub fn wrap(
+        &self,
+        text: &TextMessageEventContent,
+        messages: &Messages,
+    ) -> TextMessageEventContent {
+        let label = match &self.reason {
+            Some(reason) => messages.get("content_warning", &[("reason", reason)]),
+            None => messages.get("content_warning_no_reason", &[]),
+        };
+        let html = match &text.formatted {
+            Some(formatted) if formatted.format == MessageFormat::Html => formatted.body.clone(),
+            _ => escape_html(&text.body).replace('\n', "<br>"),
+        };
+        let spoiler = match &self.reason {
+            Some(reason) => format!(
+                "<span data-mx-spoiler=\"{}\">{}</span>",
+                escape_html(reason),
+                html
+            ),
+            None => format!("<span data-mx-spoiler>{}</span>", html),
+        };
+
+        let mut text = text.clone();
+        text.body = format!("{} {}", label, text.body);
+        text.formatted = Some(FormattedBody::html(spoiler));
+        text
+    }
+}
+
+/// The content warning for a submission containing any of `keywords`, labelled with what they map to
+///
+/// `keywords` must be normalized and lowercased.
+pub fn for_keywords(keywords: &[(String, String)], body: &str) -> Option<ContentWarning> {
+    let body = normalize_for_matching(body).to_lowercase();
+    let mut labels: Vec<&str> = Vec::new();
+    for (keyword, label) in keywords {
+        if contains_word(&body, keyword) && !labels.contains(&label.as_str()) {
+            labels.push(label);
+        }
+    }
+
+    (!labels.is_empty()).then(|| ContentWarning {
+        reason: Some(labels.join(", ")),
+    })
+}
+
+/// The text a moderator wrote in a reply, without the quote of the message replied to
+pub fn strip_reply_fallback(body: &str) -> &str {
+    let mut rest = body;
+    while rest.starts_with("> ") || rest.starts_with(">\n") {
+        rest = rest.split_once('\n').map_or("", |(_, rest)| rest);
+    }
+    rest.trim()
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
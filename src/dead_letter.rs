@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use matrix_sdk::{
+    ruma::{events::room::message::MessageEventContent, UserId},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{self, Action},
+    clock,
+    config::Config,
+    error::FourwarderError,
+    pause::Deferred,
+    send, state,
+};
+
+/// A submission which could not be forwarded, kept until `!retry-failed` tries it again
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// Always a [`Deferred::Submission`], which is everything needed to forward it again
+    pub submission: Deferred,
+    /// Why forwarding it failed the last time
+    pub reason: String,
+    /// How many times forwarding it has failed
+    pub attempts: u32,
+    /// When it last failed, in milliseconds since the Unix epoch
+    pub failed_at: u64,
+}
+
+/// Keep `submission`, which failed to forward with `error`, and post a record of it to
+/// `dead_letter_room_id`
+///
+/// It is not tried again until `!retry-failed`, which is what keeps a submission which can never
+/// be forwarded from failing over and over.
+pub async fn record(
+    client: &Client,
+    config: &Config,
+    submission: Deferred,
+    error: &FourwarderError,
+    attempts: u32,
+) -> Result<(), FourwarderError> {
+    let room_id = match &config.dead_letter_room_id {
+        Some(room_id) => room_id,
+        None => return Ok(()),
+    };
+    let (source_room_id, event_id, sender) = match &submission {
+        Deferred::Submission {
+            room_id,
+            event_id,
+            sender,
+            ..
+        } => (room_id.clone(), event_id.clone(), sender.clone()),
+        _ => {
+            return Err(FourwarderError::Logic(
+                "Only submissions can be dead-lettered",
+            ))
+        }
+    };
+    let reason = error.to_string();
+    tracing::error!(
+        "Could not forward {} (attempt {}), keeping it: {}",
+        event_id,
+        attempts,
+        reason
+    );
+    audit::record(
+        None,
+        Action::DeadLettered {
+            event_id: event_id.clone(),
+            reason: reason.clone(),
+        },
+    );
+    state::update(|state| {
+        state.dead_letters.push(DeadLetter {
+            submission,
+            reason: reason.clone(),
+            attempts,
+            failed_at: clock::now_millis(),
+        })
+    });
+
+    let notice = config.messages.get(
+        "dead_lettered",
+        &[
+            ("sender", &sender),
+            ("room", &source_room_id),
+            (
+                "link",
+                &format!("https://matrix.to/#/{}/{}", source_room_id, event_id),
+            ),
+            ("attempts", &attempts),
+            ("reason", &reason),
+            ("prefix", &config.command_prefix),
+        ],
+    );
+    send::message(client, room_id, MessageEventContent::notice_plain(notice)).await?;
+    Ok(())
+}
+
+/// Forget dead-lettered submissions which last failed longer than `retention` ago, returning how
+/// many there were
+pub fn prune(retention: Duration) -> usize {
+    let now = clock::now_millis();
+    state::update(|state| {
+        let before = state.dead_letters.len();
+        state.dead_letters.retain(|dead_letter| {
+            now.saturating_sub(dead_letter.failed_at) <= retention.as_millis() as u64
+        });
+        before - state.dead_letters.len()
+    })
+}
+
+/// Forget every dead-lettered submission from `sender`
+pub fn remove_sender(sender: &UserId) {
+    state::update(|state| {
+        state
+            .dead_letters
+            .retain(|dead_letter| dead_letter.submission.sender() != Some(sender))
+    });
+}
+
+/// Forget every dead-lettered submission, returning them oldest first to be tried again
+pub fn take() -> Vec<DeadLetter> {
+    state::update(|state| std::mem::take(&mut state.dead_letters))
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use matrix_sdk::ruma::{EventId, RoomId, UserId};
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::now_millis, pending::PendingItem, state};
+
+/// A submission moderators have made up their minds about, kept so it can be requeued
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DecidedItem {
+    pub item: PendingItem,
+    pub approved: bool,
+    /// Milliseconds since the Unix epoch
+    pub decided_at: u64,
+}
+
+/// Remember a decision on the pending item for `mod_event_id`, and forget any older than
+/// `retention`
+pub fn record(mod_event_id: EventId, item: PendingItem, approved: bool, retention: Duration) {
+    let decided_at = now_millis();
+    state::update(|state| {
+        state
+            .decided
+            .retain(|_, decided| !expired(decided, decided_at, retention));
+        state.decided.insert(
+            mod_event_id,
+            DecidedItem {
+                item,
+                approved,
+                decided_at,
+            },
+        );
+    });
+}
+
+/// Look up a decision made within `retention`
+pub fn get(mod_event_id: &EventId, retention: Duration) -> Option<DecidedItem> {
+    state::read(|state| state.decided.get(mod_event_id).cloned())
+        .filter(|decided| !expired(decided, now_millis(), retention))
+}
+
+/// Whether the submission `event_id` in its input room `room_id` was decided within `retention`
+pub fn of_source(room_id: &RoomId, event_id: &EventId, retention: Duration) -> bool {
+    let now = now_millis();
+    state::read(|state| {
+        state.decided.values().any(|decided| {
+            !expired(decided, now, retention)
+                && &decided.item.source_room_id == room_id
+                && decided.item.source_event_id.as_ref() == Some(event_id)
+        })
+    })
+}
+
+/// Forget a decision, once it has been requeued
+pub fn remove(mod_event_id: &EventId) -> Option<DecidedItem> {
+    state::update(|state| state.decided.remove(mod_event_id))
+}
+
+/// Forget every decision on submissions from `sender`, returning how many there were
+pub fn remove_sender(sender: &UserId) -> usize {
+    state::update(|state| {
+        let before = state.decided.len();
+        state
+            .decided
+            .retain(|_, decided| &decided.item.sender != sender);
+        before - state.decided.len()
+    })
+}
+
+/// Forget every decision older than `retention`, returning how many there were
+pub fn prune(retention: Duration) -> usize {
+    let now = now_millis();
+    state::update(|state| {
+        let before = state.decided.len();
+        state
+            .decided
+            .retain(|_, decided| !expired(decided, now, retention));
+        before - state.decided.len()
+    })
+}
+
+fn expired(decided: &DecidedItem, now: u64, retention: Duration) -> bool {
+    now.saturating_sub(decided.decided_at) > retention.as_millis() as u64
+}
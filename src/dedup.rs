@@ -0,0 +1,65 @@
+use matrix_sdk::ruma::EventId;
+
+use crate::state;
+
+/// Remember that `event_id` is being processed, returning `false` if it already was
+///
+/// Only the last `capacity` events are remembered, with a capacity of 0 remembering nothing. This
+/// runs for every event, so they are saved with the next change to the state rather than each
+/// on its own. Anything forwarded changes the state, so only ignored events can be forgotten.
+pub fn first_time(event_id: &EventId, capacity: usize) -> bool {
+    if capacity == 0 {
+        return true;
+    }
+
+    state::update_unsaved(|state| {
+        if state.processed.contains(event_id) {
+            return false;
+        }
+
+        state.processed.push_back(event_id.clone());
+        while state.processed.len() > capacity {
+            state.processed.pop_front();
+        }
+        true
+    })
+}
+
+/// Forget all but the last `capacity` events, like after `dedup_capacity` was lowered, returning
+/// how many were forgotten
+pub fn prune(capacity: usize) -> usize {
+    state::update(|state| {
+        let excess = state.processed.len().saturating_sub(capacity);
+        state.processed.drain(..excess);
+        excess
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, event};
+
+    #[test]
+    fn an_event_delivered_twice_is_handled_once() {
+        let _test = testing::isolate();
+        let event_id = event("$a:example.org");
+
+        assert!(first_time(&event_id, 10));
+        assert!(!first_time(&event_id, 10));
+        assert!(first_time(&event("$b:example.org"), 10));
+    }
+
+    #[test]
+    fn only_the_last_events_are_remembered() {
+        let _test = testing::isolate();
+        for id in ["$a:example.org", "$b:example.org", "$c:example.org"] {
+            assert!(first_time(&event(id), 2));
+        }
+
+        assert!(first_time(&event("$a:example.org"), 2));
+        assert!(!first_time(&event("$c:example.org"), 2));
+        assert!(first_time(&event("$a:example.org"), 0));
+        assert!(first_time(&event("$a:example.org"), 0));
+    }
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use matrix_sdk::ruma::UserId;
+
+use crate::{
+    audit::{self, Action},
+    clock,
+    config::Config,
+    leader, state,
+};
+
+/// How often delegations are checked for ones which have run out
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Let `user` approve and reject submissions for `duration`, replacing any delegation they
+/// already have
+///
+/// Returns when it runs out, in milliseconds since the Unix epoch.
+pub fn grant(user: &UserId, duration: Duration) -> u64 {
+    let expires_at = clock::now_millis() + duration.as_millis() as u64;
+    state::update(|state| state.delegations.insert(user.clone(), expires_at));
+    expires_at
+}
+
+/// Whether `user` may approve and reject submissions, as one of `moderators` or by a delegation
+/// which hasn't run out
+pub fn may_moderate(config: &Config, user: &UserId) -> bool {
+    config.is_moderator(user) || is_delegated(user)
+}
+
+/// Whether `user` may approve a submission by reacting to it in its input room with `emoji`
+///
+/// Everyone in an input room can react, so only being named in `moderators`, or the category's
+/// own `moderators`, or a delegation counts. An empty `moderators` lets no one approve there.
+pub fn may_approve_in_input_room(config: &Config, user: &UserId, emoji: &str) -> bool {
+    let listed = match config.category(emoji) {
+        Some(category) if !category.moderators.is_empty() => &category.moderators,
+        _ => &config.moderators,
+    };
+    listed.iter().any(|pattern| pattern.matches(user)) || is_delegated(user)
+}
+
+/// Whether `user` has a delegation which hasn't run out
+fn is_delegated(user: &UserId) -> bool {
+    state::read(|state| state.delegations.get(user).copied())
+        .is_some_and(|expires_at| expires_at > clock::now_millis())
+}
+
+/// Forget every delegation which has run out, every [`CHECK_INTERVAL`] for as long as the bot
+/// runs
+///
+/// [`may_moderate`] doesn't wait for this, so a delegation stops counting as soon as it runs out.
+/// This only keeps the state file tidy and records the expiry in the audit log.
+pub fn spawn() {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if leader::is_active() {
+                expire();
+            }
+        }
+    });
+}
+
+fn expire() {
+    let now = clock::now_millis();
+    let expired: Vec<UserId> = state::read(|state| {
+        state
+            .delegations
+            .iter()
+            .filter(|(_, expires_at)| **expires_at <= now)
+            .map(|(user, _)| user.clone())
+            .collect()
+    });
+    if expired.is_empty() {
+        return;
+    }
+
+    state::update(|state| state.delegations.retain(|_, expires_at| *expires_at > now));
+    for user in expired {
+        tracing::info!("The delegation to {} has run out", user);
+        audit::record(None, Action::DelegationExpired { user });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, user};
+
+    #[test]
+    fn delegation_runs_out_on_time() {
+        let test = testing::isolate();
+        let config = testing::config(r#"moderators = ["@mod:example.org"]"#);
+        let helper = user("@helper:example.org");
+
+        grant(&helper, Duration::from_secs(60));
+        assert!(may_moderate(&config, &helper));
+        test.clock.advance(Duration::from_millis(59_999));
+        assert!(may_moderate(&config, &helper));
+        test.clock.advance(Duration::from_millis(1));
+        assert!(!may_moderate(&config, &helper));
+    }
+
+    #[test]
+    fn plain_member_cannot_approve_in_input_room() {
+        let _test = testing::isolate();
+        let config = testing::config(
+            r#"
+approve_in_input_room = true
+moderators = ["@mod:example.org"]
+"#,
+        );
+
+        assert!(!may_approve_in_input_room(
+            &config,
+            &user("@member:example.org"),
+            crate::APPROVE_EMOJI
+        ));
+        assert!(may_approve_in_input_room(
+            &config,
+            &user("@mod:example.org"),
+            crate::APPROVE_EMOJI
+        ));
+
+        grant(&user("@helper:example.org"), Duration::from_secs(60));
+        assert!(may_approve_in_input_room(
+            &config,
+            &user("@helper:example.org"),
+            crate::APPROVE_EMOJI
+        ));
+    }
+
+    #[test]
+    fn input_room_approval_needs_moderators() {
+        let source = r#"
+homeserver = "https://example.org"
+username = "bot"
+password = "hunter2"
+mod_room_id = "!mod:example.org"
+output_room_id = "!output:example.org"
+input_room_id = "!input:example.org"
+approve_in_input_room = true
+"#;
+        assert!(crate::config::validate(&toml::from_str(source).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+
+use matrix_sdk::{
+    ruma::{events::room::message::MessageEventContent, EventId, RoomId},
+    Client,
+};
+use url::Url;
+
+use crate::{
+    audit::{self, Action},
+    config::Config,
+    error::FourwarderError,
+    send,
+};
+
+/// Somewhere a published submission goes
+#[derive(Clone, Debug)]
+pub enum Destination {
+    Room(RoomId),
+    Webhook(Url),
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Destination::Room(room_id) => write!(f, "{}", room_id),
+            Destination::Webhook(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+/// How delivering a submission to each of its destinations went
+///
+/// A failure at one destination doesn't stop delivery to the others.
+#[derive(Default, Debug)]
+pub struct Report {
+    pub outcomes: Vec<(Destination, Result<(), FourwarderError>)>,
+}
+
+impl Report {
+    pub fn push(&mut self, destination: Destination, result: Result<(), FourwarderError>) {
+        self.outcomes.push((destination, result));
+    }
+
+    /// Whether the submission made it to at least one room
+    pub fn reached_a_room(&self) -> bool {
+        self.outcomes.iter().any(|(destination, result)| {
+            matches!(destination, Destination::Room(_)) && result.is_ok()
+        })
+    }
+
+    /// Why delivery failed, if it failed at every destination it was meant for
+    pub fn failed_everywhere(&self) -> Option<String> {
+        let failed_everywhere =
+            !self.outcomes.is_empty() && self.outcomes.iter().all(|(_, result)| result.is_err());
+        failed_everywhere.then(|| {
+            self.outcomes
+                .iter()
+                .filter_map(|(destination, result)| {
+                    result
+                        .as_ref()
+                        .err()
+                        .map(|e| format!("{}: {}", destination, e))
+                })
+                .collect::<Vec<_>>()
+                .join("; ")
+        })
+    }
+
+    /// Tell the mod room and the audit log about any failed destinations
+    ///
+    /// `submission` is the event the delivery was for, nothing is reported if it all went well.
+    pub async fn report(
+        &self,
+        client: &Client,
+        config: &Config,
+        submission: &EventId,
+    ) -> Result<(), FourwarderError> {
+        let (delivered, failed): (Vec<_>, Vec<_>) =
+            self.outcomes.iter().partition(|(_, result)| result.is_ok());
+        if failed.is_empty() {
+            return Ok(());
+        }
+
+        for (destination, result) in &failed {
+            if let Err(e) = result {
+                tracing::error!("Could not deliver {} to {}: {}", submission, destination, e);
+            }
+        }
+        audit::record(
+            None,
+            Action::DeliveryFailed {
+                event_id: submission.clone(),
+                delivered: delivered.iter().map(|(d, _)| d.to_string()).collect(),
+                failed: failed.iter().map(|(d, _)| d.to_string()).collect(),
+            },
+        );
+
+        let list = |outcomes: &[&(Destination, Result<(), FourwarderError>)]| {
+            outcomes
+                .iter()
+                .map(|(destination, _)| destination.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let notice = if delivered.is_empty() {
+            config.messages.get(
+                "delivery_failed",
+                &[("submission", submission), ("failed", &list(&failed))],
+            )
+        } else {
+            config.messages.get(
+                "delivery_partly_failed",
+                &[
+                    ("submission", submission),
+                    ("delivered", &list(&delivered)),
+                    ("failed", &list(&failed)),
+                ],
+            )
+        };
+        send::message(
+            client,
+            &config.mod_room_id,
+            MessageEventContent::notice_plain(notice),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use core::fmt;
+use std::{error::Error, io, time::Duration};
+
+use matrix_sdk::{
+    ruma::{
+        api::{
+            client::error::ErrorKind,
+            error::{FromHttpResponseError, ServerError},
+        },
+        EventId, RoomId,
+    },
+    HttpError,
+};
+use reqwest::StatusCode;
+
+/// How long to wait when the homeserver rate limits us without saying for how long
+const DEFAULT_RATE_LIMIT_DELAY: Duration = Duration::from_secs(1);
+
+/// The longest we wait for a rate limit, so a confused homeserver can't stall us for hours
+const MAX_RATE_LIMIT_DELAY: Duration = Duration::from_secs(60);
+
+/// What a failed Matrix request means for trying it again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The homeserver couldn't be reached or had trouble of its own, so trying again can work
+    Transport,
+    /// Our access token was rejected or missing, so logging in again can fix it
+    Auth,
+    /// We are sending too fast, and should wait this long before trying again
+    RateLimited(Duration),
+    /// The homeserver understood the request and refused it, so trying again won't help
+    Permanent,
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Transport => write!(f, "transport error"),
+            Self::Auth => write!(f, "authentication error"),
+            Self::RateLimited(delay) => write!(f, "rate limited for {}ms", delay.as_millis()),
+            Self::Permanent => write!(f, "permanent error"),
+        }
+    }
+}
+
+/// Sort a failed Matrix request into what trying it again would do
+pub fn classify(err: &matrix_sdk::Error) -> ErrorClass {
+    let err = match err {
+        matrix_sdk::Error::Http(err) => err,
+        matrix_sdk::Error::AuthenticationRequired => return ErrorClass::Auth,
+        // What `timeout::within` gives for a request which took too long
+        matrix_sdk::Error::Io(err) if err.kind() == io::ErrorKind::TimedOut => {
+            return ErrorClass::Transport
+        }
+        _ => return ErrorClass::Permanent,
+    };
+    match err {
+        HttpError::Reqwest(err) => {
+            if err.is_connect() || err.is_timeout() || err.is_request() || err.is_body() {
+                ErrorClass::Transport
+            } else {
+                ErrorClass::Permanent
+            }
+        }
+        HttpError::AuthenticationRequired | HttpError::ForcedAuthenticationWithoutAccessToken => {
+            ErrorClass::Auth
+        }
+        HttpError::Server(status) => classify_status(*status),
+        HttpError::ClientApi(FromHttpResponseError::Http(ServerError::Known(err))) => {
+            match err.kind {
+                ErrorKind::UnknownToken { .. } | ErrorKind::MissingToken => ErrorClass::Auth,
+                ErrorKind::LimitExceeded { retry_after_ms } => rate_limited(retry_after_ms),
+                _ => classify_status(err.status_code),
+            }
+        }
+        // Without a Matrix error body it most likely came from a proxy in front of the
+        // homeserver, like a 502 while the homeserver restarts
+        HttpError::ClientApi(FromHttpResponseError::Http(ServerError::Unknown(_))) => {
+            ErrorClass::Transport
+        }
+        _ => ErrorClass::Permanent,
+    }
+}
+
+fn classify_status(status: StatusCode) -> ErrorClass {
+    if status == StatusCode::UNAUTHORIZED {
+        ErrorClass::Auth
+    } else if status == StatusCode::TOO_MANY_REQUESTS {
+        rate_limited(None)
+    } else if status.is_server_error() {
+        ErrorClass::Transport
+    } else {
+        ErrorClass::Permanent
+    }
+}
+
+fn rate_limited(retry_after: Option<Duration>) -> ErrorClass {
+    ErrorClass::RateLimited(
+        retry_after
+            .unwrap_or(DEFAULT_RATE_LIMIT_DELAY)
+            .min(MAX_RATE_LIMIT_DELAY),
+    )
+}
+
+#[derive(Debug)]
+pub enum FourwarderError {
+    Config(&'static str),
+    /// A file could not be read or written
+    Io(std::io::Error),
+    /// The config file is not valid TOML, or does not match the expected layout
+    Toml(toml::de::Error),
+    /// The config file does not match the expected layout, for each of these reasons
+    Schema(Vec<String>),
+    /// The state file could not be (de)serialized
+    Json(serde_json::Error),
+    /// The state file was written in a format this version can't read
+    State(&'static str),
+    Matrix(matrix_sdk::Error),
+    /// A request to a webhook failed
+    Http(reqwest::Error),
+    /// A false assumption has been made in the code, but is recoverable
+    Logic(&'static str),
+    /// A submission could not be delivered to any of its destinations, for these reasons
+    Undelivered(String),
+    /// A room the bot can't do without could not be joined at startup
+    Join(RoomId),
+    /// Any of the others, while handling the event `event_id`
+    InEvent {
+        room_id: RoomId,
+        event_id: EventId,
+        error: Box<FourwarderError>,
+    },
+}
+
+impl FourwarderError {
+    /// Say which event was being handled when this happened, unless that is already said
+    pub fn in_event(self, room_id: &RoomId, event_id: &EventId) -> Self {
+        match self {
+            Self::InEvent { .. } => self,
+            error => Self::InEvent {
+                room_id: room_id.clone(),
+                event_id: event_id.clone(),
+                error: Box::new(error),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for FourwarderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            Self::Config(err) => write!(f, "{}", err),
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Toml(err) => write!(f, "{}", err),
+            Self::Schema(problems) => write!(f, "{}", problems.join("; ")),
+            Self::Json(err) => write!(f, "{}", err),
+            Self::State(err) => write!(f, "{}", err),
+            Self::Matrix(err) => write!(f, "{} ({})", err, classify(err)),
+            Self::Http(err) => write!(f, "{}", err),
+            Self::Logic(err) => write!(f, "{}", err),
+            Self::Join(room_id) => write!(f, "Could not join {}, which the bot needs", room_id),
+            Self::Undelivered(reasons) => {
+                write!(f, "Could not be delivered anywhere ({})", reasons)
+            }
+            Self::InEvent {
+                room_id,
+                event_id,
+                error,
+            } => write!(f, "{} (while handling {} in {})", error, event_id, room_id),
+        }
+    }
+}
+
+impl Error for FourwarderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InEvent { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<matrix_sdk::Error> for FourwarderError {
+    fn from(err: matrix_sdk::Error) -> Self {
+        FourwarderError::Matrix(err)
+    }
+}
+
+impl From<std::io::Error> for FourwarderError {
+    fn from(err: std::io::Error) -> Self {
+        FourwarderError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for FourwarderError {
+    fn from(err: toml::de::Error) -> Self {
+        FourwarderError::Toml(err)
+    }
+}
+
+impl From<serde_json::Error> for FourwarderError {
+    fn from(err: serde_json::Error) -> Self {
+        FourwarderError::Json(err)
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use matrix_sdk::{
+    ruma::{events::room::message::MessageEventContent, RoomId, UserId},
+    Client,
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    clock,
+    config::{self, Config},
+    error::FourwarderError,
+    leader, pending, send, state, stats,
+};
+
+/// How often pending items are checked for ones which have waited too long
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The `escalation_*` settings
+pub struct Escalation {
+    /// How long a submission can wait on a decision before someone is pinged about it
+    pub after: Duration,
+    /// Where to ping, the mod room if not set
+    pub room_id: Option<RoomId>,
+    /// Who to ping
+    pub users: Vec<UserId>,
+}
+
+/// Check for stalled submissions every [`CHECK_INTERVAL`] until the returned task is aborted
+pub fn spawn(client: Client) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = check(&client, &config::get()).await {
+                tracing::error!("Could not escalate stalled submissions: {}", e);
+            }
+        }
+    })
+}
+
+/// Ping about every pending submission which has waited longer than `escalation_after_secs`
+///
+/// Each submission is only escalated once. Held submissions are skipped, as they are already
+/// being discussed.
+async fn check(client: &Client, config: &Config) -> Result<(), FourwarderError> {
+    let escalation = match &config.escalation {
+        Some(escalation) if leader::is_active() => escalation,
+        _ => return Ok(()),
+    };
+
+    let now = clock::now_millis();
+    let stalled = pending::queue().into_iter().filter(|(_, item)| {
+        let submitted_at: u64 = item.origin_server_ts.get().into();
+        !item.escalated
+            && !item.is_held()
+            && now.saturating_sub(submitted_at) >= escalation.after.as_millis() as u64
+    });
+
+    let users = escalation
+        .users
+        .iter()
+        .map(|user| user.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let room_id = escalation.room_id.as_ref().unwrap_or(&config.mod_room_id);
+    for (mod_event_id, item) in stalled {
+        let submitted_at: u64 = item.origin_server_ts.get().into();
+        let notice = config.messages.get(
+            "escalation",
+            &[
+                ("sender", &item.sender),
+                (
+                    "waited",
+                    &stats::format_duration(now.saturating_sub(submitted_at)),
+                ),
+                (
+                    "link",
+                    &format!(
+                        "https://matrix.to/#/{}/{}",
+                        config.mod_room_id, mod_event_id
+                    ),
+                ),
+                ("users", &users),
+            ],
+        );
+        send::message(
+            client,
+            room_id,
+            MessageEventContent::notice_plain(notice.trim_end()),
+        )
+        .await?;
+
+        tracing::info!("Escalated {} to {}", mod_event_id, room_id);
+        state::update(|state| {
+            if let Some(item) = state.pending.get_mut(&mod_event_id) {
+                item.escalated = true;
+            }
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use matrix_sdk::Client;
+use serde_json::json;
+
+use crate::{audit, clock, config::Config, error::FourwarderError, pending, send, state};
+
+/// How many audit records to include, at most
+const AUDIT_RECORDS: usize = 1000;
+/// Older audit records are left out until the export fits in this many bytes
+const MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// Upload a JSON export of the recent audit log and the bot's state to the mod room
+///
+/// The config is never included, so neither are the credentials in it.
+pub async fn export(client: &Client, config: &Config) -> Result<String, FourwarderError> {
+    let generated_at = clock::now_millis();
+    let mut audit = audit::recent(AUDIT_RECORDS)?;
+    let queue: Vec<_> = pending::queue()
+        .into_iter()
+        .map(|(mod_event_id, item)| json!({ "mod_event_id": mod_event_id, "item": item }))
+        .collect();
+    let (karma, shadowbanned) = state::read(|state| {
+        (
+            serde_json::to_value(&state.karma),
+            serde_json::to_value(&state.shadowbanned),
+        )
+    });
+    let (karma, shadowbanned) = (karma?, shadowbanned?);
+
+    let mut left_out = 0;
+    let data = loop {
+        let data = serde_json::to_vec_pretty(&json!({
+            "generated_at": generated_at,
+            "audit": audit,
+            "queue": queue,
+            "karma": karma,
+            "shadowbanned": shadowbanned,
+        }))?;
+        if data.len() <= MAX_BYTES || audit.is_empty() {
+            break data;
+        }
+
+        let drop = audit.len().div_ceil(2);
+        audit.drain(..drop);
+        left_out += drop;
+    };
+
+    send::attachment(
+        client,
+        &config.mod_room_id,
+        &format!("4warder_export_{}.json", generated_at),
+        &mime::APPLICATION_JSON,
+        &data,
+    )
+    .await?;
+
+    Ok(if left_out == 0 {
+        config
+            .messages
+            .get("exported", &[("records", &audit.len())])
+    } else {
+        config.messages.get(
+            "exported_truncated",
+            &[("records", &audit.len()), ("left_out", &left_out)],
+        )
+    })
+}
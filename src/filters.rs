@@ -0,0 +1,128 @@
+use std::{
+    collections::BTreeMap,
+    sync::{LazyLock, Mutex},
+};
+
+use matrix_sdk::ruma::{events::room::message::TextMessageEventContent, EventId, RoomId, UserId};
+
+use crate::{blocklist::Blocklist, config::Config, metrics, ratelimit::RateLimit};
+
+/// How many submissions each filter dropped and held since we started, by filter name and
+/// whether it dropped them
+static FILTERED: LazyLock<Mutex<BTreeMap<(&'static str, bool), u64>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/// A submission, as seen by a [`MessageFilter`]
+// Not every field is needed by the built-in filters
+#[allow(dead_code)]
+pub struct IncomingMessage<'a> {
+    pub room_id: &'a RoomId,
+    pub event_id: &'a EventId,
+    pub sender: &'a UserId,
+    pub text: &'a TextMessageEventContent,
+}
+
+/// What a [`MessageFilter`] thinks should happen to a submission
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterDecision {
+    /// Carry on as normal
+    Allow,
+    /// Throw the submission away, leaving a notice with the reason in the mod room
+    Drop(String),
+    /// Send the submission to the mod room with the reason attached, even if its input room is
+    /// unmoderated
+    Hold(String),
+}
+
+/// A check run on every submission before it is forwarded
+///
+/// To add a filter, implement this trait and push it onto the pipeline in [`build`], at the
+/// point in the order where it should run. Filters are rebuilt every time the config is
+/// loaded, so read any settings from the [`Config`] there.
+pub trait MessageFilter: Send + Sync {
+    /// What the filter is called in logs, metrics and `!stats`
+    fn name(&self) -> &'static str;
+    fn evaluate(&self, msg: &IncomingMessage) -> FilterDecision;
+}
+
+/// Build the filter pipeline for `config`, in the order the filters run, with `blocklist` as
+/// the blocklist
+///
+/// That is the top-level one, or the one a `[[rooms]]` entry has instead.
+pub fn build(config: &Config, blocklist: &[String]) -> Vec<Box<dyn MessageFilter>> {
+    let mut filters: Vec<Box<dyn MessageFilter>> = Vec::new();
+
+    // First, so every submission is counted toward the limit
+    if let Some(per_minute) = config.rate_limit_per_minute {
+        filters.push(Box::new(RateLimit::new(per_minute)));
+    }
+
+    if !blocklist.is_empty() {
+        filters.push(Box::new(Blocklist::new(blocklist)));
+    }
+
+    if let Some(spam) = &config.spam {
+        filters.push(Box::new(spam.clone()));
+    }
+
+    filters
+}
+
+/// Run `msg` through every filter in order
+///
+/// The first filter to drop the submission wins, otherwise the reasons of every filter
+/// holding it are combined. Each filter which drops or holds it is counted, see [`counts`].
+pub fn evaluate(filters: &[Box<dyn MessageFilter>], msg: &IncomingMessage) -> FilterDecision {
+    let mut holds = Vec::new();
+
+    for filter in filters {
+        let decision = filter.evaluate(msg);
+        if decision != FilterDecision::Allow {
+            tracing::debug!(
+                "{} decided {:?} on {}",
+                filter.name(),
+                decision,
+                msg.event_id
+            );
+            count(filter.name(), matches!(decision, FilterDecision::Drop(_)));
+        }
+        match decision {
+            FilterDecision::Allow => {}
+            FilterDecision::Drop(reason) => return FilterDecision::Drop(reason),
+            FilterDecision::Hold(reason) => holds.push(reason),
+        }
+    }
+
+    if holds.is_empty() {
+        FilterDecision::Allow
+    } else {
+        FilterDecision::Hold(holds.join(", "))
+    }
+}
+
+fn count(filter: &'static str, dropped: bool) {
+    *FILTERED
+        .lock()
+        .unwrap()
+        .entry((filter, dropped))
+        .or_default() += 1;
+    metrics::increment(
+        "fourwarder_filtered_total",
+        "Submissions a filter dropped or held",
+        &[
+            ("filter", filter),
+            ("decision", if dropped { "drop" } else { "hold" }),
+        ],
+    );
+}
+
+/// How many submissions each filter dropped and held since we started, as `(filter, dropped,
+/// count)`
+pub fn counts() -> Vec<(&'static str, bool, u64)> {
+    FILTERED
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((filter, dropped), count)| (*filter, *dropped, *count))
+        .collect()
+}
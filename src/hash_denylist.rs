@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use matrix_sdk::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use url::Url;
+
+use crate::{
+    clock,
+    error::FourwarderError,
+    media,
+    webhook::{FailMode, HTTP},
+};
+
+/// How long the service's answer for a hash is trusted before asking again
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How many answers are cached at most, the oldest are forgotten first
+const CACHE_CAPACITY: usize = 10_000;
+
+/// What the service last said about each hash, and when
+static CACHE: LazyLock<Mutex<HashMap<String, (bool, Instant)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Why media was turned away
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Denial {
+    /// The service lists the file with this SHA-256
+    Listed { sha256: String },
+    /// The file couldn't be checked, and we fail closed
+    Unchecked,
+}
+
+#[derive(Deserialize)]
+struct Answer {
+    listed: bool,
+}
+
+/// An external service listing the SHA-256 hashes of files which are not to be forwarded
+pub struct HashDenylist {
+    pub url: Url,
+    pub timeout: Duration,
+    pub fail_mode: FailMode,
+}
+
+impl HashDenylist {
+    /// Check every file `content` refers to, returning why it can't be forwarded if it can't
+    ///
+    /// Files are downloaded as they are stored, so encrypted media is hashed still encrypted.
+    pub async fn check(&self, client: &Client, content: &Value) -> Option<Denial> {
+        for url in media::urls(content) {
+            match self.is_listed(client, url).await {
+                Ok(Some(sha256)) => return Some(Denial::Listed { sha256 }),
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!(
+                        "Could not check {} against the hash denylist, failing {:?}: {}",
+                        url,
+                        self.fail_mode,
+                        e
+                    );
+                    if self.fail_mode == FailMode::Closed {
+                        return Some(Denial::Unchecked);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// The SHA-256 of the file at `url` if the service lists it
+    async fn is_listed(&self, client: &Client, url: &str) -> Result<Option<String>, FourwarderError> {
+        let sha256 = media::sha256(client, url).await?;
+
+        let listed = match cached(&sha256) {
+            Some(listed) => listed,
+            None => {
+                let listed = self.ask(&sha256).await.map_err(FourwarderError::Http)?;
+                remember(&sha256, listed);
+                listed
+            }
+        };
+        Ok(listed.then_some(sha256))
+    }
+
+    /// Ask the service with `GET <url>?sha256=<hash>`, which answers `{"listed": true}` if it
+    /// lists the hash
+    async fn ask(&self, sha256: &str) -> Result<bool, reqwest::Error> {
+        let answer: Answer = HTTP
+            .get(self.url.clone())
+            .query(&[("sha256", sha256)])
+            .timeout(self.timeout)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(answer.listed)
+    }
+}
+
+fn cached(sha256: &str) -> Option<bool> {
+    let cache = CACHE.lock().unwrap();
+    let (listed, at) = cache.get(sha256)?;
+    (clock::now().duration_since(*at) < CACHE_TTL).then_some(*listed)
+}
+
+fn remember(sha256: &str, listed: bool) {
+    let mut cache = CACHE.lock().unwrap();
+    if cache.len() >= CACHE_CAPACITY {
+        let now = clock::now();
+        cache.retain(|_, (_, at)| now.duration_since(*at) < CACHE_TTL);
+    }
+    if cache.len() >= CACHE_CAPACITY {
+        if let Some(oldest) = cache
+            .iter()
+            .min_by_key(|(_, (_, at))| *at)
+            .map(|(sha256, _)| sha256.clone())
+        {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(sha256.to_owned(), (listed, clock::now()));
+}
@@ -0,0 +1,107 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{clock, metrics, stats::format_duration};
+
+static STARTED: LazyLock<Instant> = LazyLock::new(clock::now);
+static INITIAL_SYNC: AtomicBool = AtomicBool::new(false);
+/// When a sync last succeeded, and when the sync token last changed
+static SYNCS: Mutex<(Option<Instant>, Option<Instant>)> = Mutex::new((None, None));
+/// When the client was last rebuilt for a stalled sync, while it is stalled
+static STALL: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// How the connection to the homeserver is doing
+pub struct Health {
+    pub initial_sync: bool,
+    pub since_last_sync: Option<Duration>,
+    /// How long ago the homeserver last gave us a new sync token
+    pub sync_token_age: Option<Duration>,
+    /// Whether syncs have gone on for longer than `sync_stall_timeout_secs` without a new sync
+    /// token
+    pub stalled: bool,
+    pub uptime: Duration,
+}
+
+/// Start counting uptime from now
+pub fn started() {
+    LazyLock::force(&STARTED);
+}
+
+/// Record that a sync succeeded, `initial` if it was the first one
+pub fn synced(initial: bool, token_changed: bool) {
+    let now = clock::now();
+    if initial {
+        INITIAL_SYNC.store(true, Ordering::SeqCst);
+    }
+
+    let mut syncs = SYNCS.lock().unwrap();
+    syncs.0 = Some(now);
+    if token_changed && STALL.lock().unwrap().take().is_some() {
+        let stalled_for = syncs.1.map_or(Duration::ZERO, |at| now.duration_since(at));
+        tracing::info!(
+            "Sync recovered after {} without a new batch",
+            format_duration(stalled_for.as_millis() as u64)
+        );
+        set_stalled(false);
+    }
+    if token_changed || syncs.1.is_none() {
+        syncs.1 = Some(now);
+    }
+}
+
+/// Whether syncs have gone on for longer than `timeout` without a new sync token, so the client
+/// should be rebuilt
+///
+/// This is `true` once, then again every `timeout` the stall goes on for, so a rebuild which
+/// doesn't help is tried again without rebuilding on every sync. Until a new sync token comes,
+/// the health check answers that we are unhealthy.
+pub fn stalled(timeout: Duration) -> bool {
+    let now = clock::now();
+    let token_changed = SYNCS.lock().unwrap().1.unwrap_or(*STARTED);
+    let quiet = now.saturating_duration_since(token_changed);
+    if quiet < timeout {
+        return false;
+    }
+
+    let mut stall = STALL.lock().unwrap();
+    if stall.is_some_and(|rebuilt| now.saturating_duration_since(rebuilt) < timeout) {
+        return false;
+    }
+    tracing::warn!(
+        "No new sync batch for {}, the sync looks stalled, rebuilding the client",
+        format_duration(quiet.as_millis() as u64)
+    );
+    *stall = Some(now);
+    set_stalled(true);
+    true
+}
+
+/// Whether the health check should pass: the first sync is done and syncing isn't stalled
+pub fn is_healthy() -> bool {
+    INITIAL_SYNC.load(Ordering::SeqCst) && STALL.lock().unwrap().is_none()
+}
+
+fn set_stalled(stalled: bool) {
+    metrics::set_gauge(
+        "fourwarder_sync_stalled",
+        "Whether syncs have gone on for longer than sync_stall_timeout_secs without new batches",
+        if stalled { 1.0 } else { 0.0 },
+    );
+}
+
+pub fn get() -> Health {
+    let (last_sync, token_changed) = *SYNCS.lock().unwrap();
+    let now = clock::now();
+    Health {
+        initial_sync: INITIAL_SYNC.load(Ordering::SeqCst),
+        since_last_sync: last_sync.map(|at| now.saturating_duration_since(at)),
+        sync_token_age: token_changed.map(|at| now.saturating_duration_since(at)),
+        stalled: STALL.lock().unwrap().is_some(),
+        uptime: now.saturating_duration_since(*STARTED),
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt::Display;
+
+use matrix_sdk::{
+    ruma::{events::room::message::MessageEventContent, UserId},
+    Client,
+};
+
+use crate::{
+    activity,
+    commands::{Permission, COMMANDS},
+    config::Config,
+    cw::escape_html,
+    error::FourwarderError,
+    instructions, send, stats, APPROVE_EMOJI,
+};
+
+/// A heading and the lines under it
+type Section = (String, Vec<String>);
+
+/// Post `!help`, or `!help <topic>`, to the mod room for `sender`
+///
+/// Everything in it comes from `config`, so it shows the emoji, prefix and features actually in
+/// use, and only the commands `sender` may use.
+pub async fn send(
+    client: &Client,
+    config: &Config,
+    sender: &UserId,
+    topic: Option<&str>,
+) -> Result<(), FourwarderError> {
+    let sections = match topic {
+        None => overview(config, sender),
+        Some("approval") => vec![approval(config)],
+        Some("filters") => vec![filters(config, sender)],
+        // `!help !queue` is as good as `!help queue`
+        Some(command) => command_help(
+            config,
+            sender,
+            command
+                .strip_prefix(config.command_prefix.as_str())
+                .unwrap_or(command),
+        ),
+    };
+    send::message(client, &config.mod_room_id, render(&sections)).await?;
+    Ok(())
+}
+
+/// The reactions, the commands `sender` may use and what else there is help on
+fn overview(config: &Config, sender: &UserId) -> Vec<Section> {
+    let messages = &config.messages;
+    let prefix = &config.command_prefix;
+    let commands = allowed(config, sender)
+        .map(|command| format!("{}{}", prefix, command))
+        .collect::<Vec<_>>()
+        .join(", ");
+    vec![
+        (
+            messages.get("help_reactions", &[]),
+            instructions::reactions(config),
+        ),
+        (messages.get("help_commands", &[]), vec![commands]),
+        (
+            messages.get("help_topics", &[]),
+            ["help_topic_approval", "help_topic_filters", "help_topic_command"]
+                .iter()
+                .map(|key| messages.get(key, &[("prefix", prefix)]))
+                .collect(),
+        ),
+    ]
+}
+
+/// How submissions get from the mod room to the output rooms
+fn approval(config: &Config) -> Section {
+    let messages = &config.messages;
+    let mut lines = instructions::reactions(config);
+    if let Some(room) = &config.preview_room_id {
+        lines.push(messages.get(
+            "help_preview",
+            &[("room", room), ("emoji", &APPROVE_EMOJI)],
+        ));
+    }
+    if config.approve_in_input_room {
+        lines.push(messages.get("help_input_room", &[]));
+    }
+    (messages.get("help_approval", &[]), lines)
+}
+
+/// What keeps a message from reaching the mod room
+fn filters(config: &Config, sender: &UserId) -> Section {
+    let messages = &config.messages;
+    let mut lines = Vec::new();
+    if let Some(submission_prefix) = &config.submission_prefix {
+        lines.push(messages.get(
+            "help_submission_prefix",
+            &[("prefix", submission_prefix)],
+        ));
+    }
+    let names: Vec<&str> = config.filters.iter().map(|filter| filter.name()).collect();
+    if names.is_empty() {
+        lines.push(messages.get("help_no_filters", &[]));
+    } else {
+        lines.push(messages.get("help_filter_list", &[("filters", &names.join(", "))]));
+    }
+    if let Some(per_minute) = config.rate_limit_per_minute {
+        lines.push(messages.get("help_rate_limit", &[("count", &per_minute)]));
+    }
+    if let Some(quota) = config.daily_submission_quota {
+        lines.push(messages.get(
+            "help_quota",
+            &[
+                ("count", &quota),
+                (
+                    "window",
+                    &stats::format_duration(config.submission_quota_window.as_millis() as u64),
+                ),
+            ],
+        ));
+    }
+    if let Some(min_age) = config.min_membership_age {
+        lines.push(messages.get(
+            "help_membership_age",
+            &[(
+                "age",
+                &stats::format_duration(min_age.as_millis() as u64),
+            )],
+        ));
+    }
+    if allowed(config, sender).any(|command| command == "reload-filters") {
+        lines.push(messages.get(
+            "help_reload_filters",
+            &[("prefix", &config.command_prefix)],
+        ));
+    }
+    (messages.get("help_filters", &[]), lines)
+}
+
+/// How to use `command`, if `sender` may
+fn command_help(config: &Config, sender: &UserId, command: &str) -> Vec<Section> {
+    let messages = &config.messages;
+    let args: &[(&str, &(dyn Display + Sync))] = &[
+        ("prefix", &config.command_prefix),
+        ("command", &command),
+        ("max", &activity::RETENTION_DAYS),
+    ];
+    if !allowed(config, sender).any(|allowed| allowed == command) {
+        return vec![(messages.get("help_unknown", args), Vec::new())];
+    }
+    // Both share one usage message
+    let key = match command {
+        "unshadowban" => "shadowban_usage".to_owned(),
+        command => format!("{}_usage", command.replace('-', "_")),
+    };
+    let usage = if messages.has(&key) {
+        messages.get(&key, args)
+    } else {
+        messages.get("help_no_arguments", args)
+    };
+    vec![(messages.get("help_command", args), vec![usage])]
+}
+
+/// The commands `sender` may use, in the order of [`COMMANDS`]
+fn allowed<'a>(config: &'a Config, sender: &'a UserId) -> impl Iterator<Item = &'static str> + 'a {
+    COMMANDS.iter().copied().filter(move |command| {
+        config.permission(command) == Permission::Moderator || config.is_admin(sender)
+    })
+}
+
+/// `sections` as a notice, with an HTML heading and list for each
+fn render(sections: &[Section]) -> MessageEventContent {
+    let mut plain = Vec::new();
+    let mut html = String::new();
+    for (heading, lines) in sections {
+        plain.push(heading.clone());
+        plain.extend(lines.iter().map(|line| format!("- {}", line)));
+        plain.push(String::new());
+
+        html.push_str(&format!("<p><strong>{}</strong></p>", escape_html(heading)));
+        if !lines.is_empty() {
+            html.push_str("<ul>");
+            for line in lines {
+                html.push_str(&format!("<li>{}</li>", escape_html(line)));
+            }
+            html.push_str("</ul>");
+        }
+    }
+    MessageEventContent::notice_html(plain.join("\n").trim_end(), html)
+}
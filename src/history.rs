@@ -0,0 +1,73 @@
+use matrix_sdk::ruma::{EventId, RoomId, UserId};
+use serde::{Deserialize, Serialize};
+
+use std::time::Duration;
+
+use crate::{clock::now_millis, index, state, tally::Tally};
+
+/// A submission we posted to an output room
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PublishedEvent {
+    pub room_id: RoomId,
+    /// Who submitted it in the first place
+    pub sender: UserId,
+    /// Milliseconds since the Unix epoch, or when the state was loaded for ones published before
+    /// this was kept
+    #[serde(default = "now_millis")]
+    pub published_at: u64,
+    /// What it is called in the index, empty for ones published before this was kept
+    #[serde(default)]
+    pub title: String,
+    /// The reactions on its original, with `mirror_source_reactions`
+    #[serde(default)]
+    pub tally: Option<Tally>,
+}
+
+/// Remember a submission we published as `event_id`, with `body` for its title in the index
+pub fn record(event_id: EventId, room_id: RoomId, sender: UserId, body: &str) {
+    state::update(|state| {
+        state.published.insert(
+            event_id,
+            PublishedEvent {
+                room_id,
+                sender,
+                published_at: now_millis(),
+                title: index::title(body),
+                tally: None,
+            },
+        )
+    });
+}
+
+/// Every published submission from `sender`
+pub fn by_sender(sender: &UserId) -> Vec<(EventId, PublishedEvent)> {
+    state::read(|state| {
+        state
+            .published
+            .iter()
+            .filter(|(_, published)| &published.sender == sender)
+            .map(|(id, published)| (id.clone(), published.clone()))
+            .collect()
+    })
+}
+
+/// Whether `event_id` is a submission we published
+pub fn contains(event_id: &EventId) -> bool {
+    state::read(|state| state.published.contains_key(event_id))
+}
+
+pub fn forget(event_id: &EventId) {
+    state::update(|state| state.published.remove(event_id));
+}
+
+/// Forget submissions published longer than `retention` ago, returning how many there were
+pub fn prune(retention: Duration) -> usize {
+    let now = now_millis();
+    state::update(|state| {
+        let before = state.published.len();
+        state.published.retain(|_, published| {
+            now.saturating_sub(published.published_at) <= retention.as_millis() as u64
+        });
+        before - state.published.len()
+    })
+}
@@ -0,0 +1,59 @@
+use std::sync::OnceLock;
+
+use matrix_sdk::ruma::{EventId, RoomId, UserId};
+
+/// A submission posted to an output room
+#[derive(Debug, Clone)]
+pub struct Forwarded {
+    pub room_id: RoomId,
+    /// The id of the copy in the output room
+    pub event_id: EventId,
+    /// `None` if it was approved from a mod room message we had no record of
+    pub sender: Option<UserId>,
+    pub body: String,
+}
+
+/// A decision by a moderator on a submission in the mod room
+#[derive(Debug, Clone)]
+pub struct Decided {
+    /// The id of the submission's copy in the mod room
+    pub mod_event_id: EventId,
+    pub moderator: UserId,
+    /// `None` if it was approved from a mod room message we had no record of
+    pub sender: Option<UserId>,
+}
+
+/// Called as things happen, for code embedding the bot to react to them
+///
+/// Every method does nothing unless it is overridden. They are called in the middle of handling
+/// an event, so anything slow should be spawned off rather than done in place.
+pub trait Hooks: Send + Sync {
+    fn forwarded(&self, _forwarded: &Forwarded) {}
+    fn approved(&self, _decided: &Decided) {}
+    fn rejected(&self, _decided: &Decided) {}
+}
+
+static HOOKS: OnceLock<Box<dyn Hooks>> = OnceLock::new();
+
+/// Returns `false` if hooks were already set, which can only be done once
+pub fn set(hooks: Box<dyn Hooks>) -> bool {
+    HOOKS.set(hooks).is_ok()
+}
+
+pub fn forwarded(forwarded: Forwarded) {
+    if let Some(hooks) = HOOKS.get() {
+        hooks.forwarded(&forwarded);
+    }
+}
+
+pub fn approved(decided: Decided) {
+    if let Some(hooks) = HOOKS.get() {
+        hooks.approved(&decided);
+    }
+}
+
+pub fn rejected(decided: Decided) {
+    if let Some(hooks) = HOOKS.get() {
+        hooks.rejected(&decided);
+    }
+}
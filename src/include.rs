@@ -0,0 +1,38 @@
+use crate::{blocklist::contains_word, error::FourwarderError, normalize::normalize_for_matching};
+
+/// The words and phrases one of which a submission has to contain to be forwarded at all, from
+/// `include_patterns`
+///
+/// They are matched like [`crate::blocklist::Blocklist`] words, ignoring case, invisible
+/// characters and look-alike forms, so `#submit` matches `#Submit` but not `#submitted`.
+#[derive(Clone, Debug, Default)]
+pub struct Include {
+    /// Normalized and lowercased
+    patterns: Vec<String>,
+}
+
+impl Include {
+    pub fn new(patterns: &[String]) -> Result<Self, FourwarderError> {
+        let patterns: Vec<String> = patterns
+            .iter()
+            .map(|pattern| normalize_for_matching(pattern).to_lowercase())
+            .collect();
+        if patterns.iter().any(|pattern| pattern.trim().is_empty()) {
+            return Err(FourwarderError::Config(
+                "`include_patterns` can't have an empty pattern",
+            ));
+        }
+        Ok(Include { patterns })
+    }
+
+    /// Whether `body` may be forwarded, which is always the case without any patterns
+    pub fn matches(&self, body: &str) -> bool {
+        if self.patterns.is_empty() {
+            return true;
+        }
+        let body = normalize_for_matching(body).to_lowercase();
+        self.patterns
+            .iter()
+            .any(|pattern| contains_word(&body, pattern))
+    }
+}
@@ -0,0 +1,171 @@
+use matrix_sdk::{
+    ruma::{events::room::message::MessageEventContent, EventId, RoomId},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::task::JoinHandle;
+
+use crate::{
+    config::{self, Config},
+    cw::escape_html,
+    error::FourwarderError,
+    instructions, leader, send, state,
+};
+
+/// How many characters of a submission's first line make up its title in the index
+const TITLE_CHARS: usize = 60;
+
+/// The index we last posted, which later updates edit
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Index {
+    pub room_id: RoomId,
+    pub event_id: EventId,
+    /// The plain text it says now, so an update which changes nothing isn't sent
+    pub text: String,
+}
+
+/// Keep the index in `index_room_id` up to date, every `index_interval_secs` until the returned
+/// task is aborted
+pub fn spawn(client: Client) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config::get().index_interval);
+        loop {
+            interval.tick().await;
+            let config = config::get();
+            if config.index_room_id.is_none() || !leader::is_active() {
+                continue;
+            }
+            if let Err(e) = refresh(&client, &config).await {
+                tracing::warn!("Could not update the index: {}", e);
+            }
+        }
+    })
+}
+
+/// A short title for a submission, from the first line of its `body`
+pub fn title(body: &str) -> String {
+    let line = body
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    match line.char_indices().nth(TITLE_CHARS) {
+        Some((end, _)) => format!("{}…", line[..end].trim_end()),
+        None => line.to_owned(),
+    }
+}
+
+/// Post the index of the last `index_size` submissions published to `index_room_id` and pin it,
+/// or edit the one posted before if it says something else now
+///
+/// A new index is posted if `index_room_id` changed, and the old one is left as it is.
+async fn refresh(client: &Client, config: &Config) -> Result<(), FourwarderError> {
+    let room_id = match &config.index_room_id {
+        Some(room_id) => room_id,
+        None => return Ok(()),
+    };
+    let (body, html) = render(config, room_id);
+    let old = state::read(|state| state.index.clone()).filter(|old| &old.room_id == room_id);
+    if old.as_ref().is_some_and(|old| old.text == body) {
+        return Ok(());
+    }
+
+    let old = match old {
+        Some(old) => old,
+        None => {
+            let content = MessageEventContent::notice_html(body.clone(), html);
+            let event_id = match send::message(client, room_id, content).await? {
+                Some(event_id) => event_id,
+                // Dry run, so there is nothing to pin
+                None => return Ok(()),
+            };
+            tracing::info!("Posted the index in {} as {}", room_id, event_id);
+            // Remembered before pinning, so failing to pin doesn't post another one every time
+            remember(room_id, &event_id, body);
+            return instructions::pin(client, room_id, event_id, None).await;
+        }
+    };
+
+    // Clients which don't show edits show the fallback, marked as an edit with `*`
+    let edit = json!({
+        "msgtype": "m.notice",
+        "body": format!("* {}", body),
+        "format": "org.matrix.custom.html",
+        "formatted_body": format!("* {}", html),
+        "m.new_content": {
+            "msgtype": "m.notice",
+            "body": body,
+            "format": "org.matrix.custom.html",
+            "formatted_body": html,
+        },
+        "m.relates_to": { "rel_type": "m.replace", "event_id": old.event_id },
+    });
+    send::raw(client, room_id, "m.room.message", edit).await?;
+    tracing::debug!("Updated the index in {}", room_id);
+    remember(room_id, &old.event_id, body);
+    Ok(())
+}
+
+fn remember(room_id: &RoomId, event_id: &EventId, text: String) {
+    state::update(|state| {
+        state.index = Some(Index {
+            room_id: room_id.clone(),
+            event_id: event_id.clone(),
+            text,
+        })
+    });
+}
+
+/// The index of what was published to `room_id` last, newest first, with links to each, as plain
+/// text and HTML
+fn render(config: &Config, room_id: &RoomId) -> (String, String) {
+    let mut published: Vec<(EventId, u64, String)> = state::read(|state| {
+        state
+            .published
+            .iter()
+            .filter(|(_, published)| &published.room_id == room_id)
+            .map(|(event_id, published)| {
+                (
+                    event_id.clone(),
+                    published.published_at,
+                    published.title.clone(),
+                )
+            })
+            .collect()
+    });
+    published.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.as_str().cmp(b.0.as_str())));
+    published.truncate(config.index_size);
+
+    let heading = config.messages.get("index", &[]);
+    if published.is_empty() {
+        let empty = config.messages.get("index_empty", &[]);
+        return (
+            format!("{}\n{}", heading, empty),
+            format!(
+                "<p><strong>{}</strong></p><p>{}</p>",
+                escape_html(&heading),
+                escape_html(&empty)
+            ),
+        );
+    }
+
+    let mut body = heading.clone();
+    let mut html = format!("<p><strong>{}</strong></p><ol>", escape_html(&heading));
+    for (event_id, _, title) in published {
+        let link = format!("https://matrix.to/#/{}/{}", room_id, event_id);
+        let title = if title.is_empty() {
+            config.messages.get("index_untitled", &[])
+        } else {
+            title
+        };
+        body.push_str(&format!("\n• {} {}", title, link));
+        html.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>",
+            escape_html(&link),
+            escape_html(&title)
+        ));
+    }
+    html.push_str("</ol>");
+    (body, html)
+}
@@ -0,0 +1,202 @@
+use matrix_sdk::{
+    ruma::{
+        api::client::{
+            error::ErrorKind,
+            r0::state::{get_state_events_for_key, send_state_event},
+        },
+        api::error::{FromHttpResponseError, ServerError},
+        events::{room::message::MessageEventContent, EventType},
+        serde::Raw,
+        EventId, RoomId,
+    },
+    Client, HttpError,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commands::{Permission, COMMANDS},
+    config::Config,
+    error::FourwarderError,
+    send, state, APPROVE_EMOJI, REJECT_EMOJI,
+};
+
+/// The instructions we last pinned in the mod room
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Pinned {
+    pub event_id: EventId,
+    pub text: String,
+}
+
+/// The content of `m.room.pinned_events`
+#[derive(Serialize, Deserialize, Default)]
+struct PinnedEvents {
+    #[serde(default)]
+    pinned: Vec<EventId>,
+}
+
+/// Pin instructions for moderating in the mod room, if `pin_instructions` is set and the ones
+/// pinned last don't match the config any more
+///
+/// The old instructions are unpinned and redacted. This is cheap when nothing changed, so it is
+/// run after every sync, which picks up config reloads.
+pub async fn refresh(client: &Client, config: &Config) -> Result<(), FourwarderError> {
+    if !config.pin_instructions {
+        return Ok(());
+    }
+    let text = instructions(config);
+    let old = state::read(|state| state.instructions.clone());
+    if old.as_ref().is_some_and(|old| old.text == text) {
+        return Ok(());
+    }
+
+    let event_id = match send::message(
+        client,
+        &config.mod_room_id,
+        MessageEventContent::notice_plain(text.clone()),
+    )
+    .await?
+    {
+        Some(event_id) => event_id,
+        // Dry run, so there is nothing to pin
+        None => return Ok(()),
+    };
+    // Remembered before pinning, so failing to pin doesn't post them again after every sync
+    state::update(|state| {
+        state.instructions = Some(Pinned {
+            event_id: event_id.clone(),
+            text,
+        })
+    });
+    tracing::info!("Posted the instructions for the mod room as {}", event_id);
+
+    pin(
+        client,
+        &config.mod_room_id,
+        event_id,
+        old.as_ref().map(|old| &old.event_id),
+    )
+    .await?;
+
+    if let Some(old) = old {
+        crate::redact_quietly(client, &config.mod_room_id, &old.event_id, "Outdated").await;
+    }
+    Ok(())
+}
+
+/// Pin `event_id` first in `room_id`, unpinning `replaced` if it is given
+pub async fn pin(
+    client: &Client,
+    room_id: &RoomId,
+    event_id: EventId,
+    replaced: Option<&EventId>,
+) -> Result<(), FourwarderError> {
+    let mut pinned = fetch_pinned(client, room_id).await?;
+    if let Some(replaced) = replaced {
+        pinned.pinned.retain(|pinned| pinned != replaced);
+    }
+    pinned.pinned.insert(0, event_id);
+    let content = serde_json::value::to_raw_value(&pinned)?;
+    client
+        .send(
+            send_state_event::Request::new_raw(
+                room_id,
+                "m.room.pinned_events",
+                "",
+                Raw::from_json(content),
+            ),
+            None,
+        )
+        .await
+        .map_err(matrix_sdk::Error::Http)?;
+    Ok(())
+}
+
+/// What is pinned in `room_id` now, which is nothing if nothing was ever pinned there
+async fn fetch_pinned(client: &Client, room_id: &RoomId) -> Result<PinnedEvents, FourwarderError> {
+    let request = get_state_events_for_key::Request::new(room_id, EventType::RoomPinnedEvents, "");
+    match client.send(request, None).await {
+        Ok(response) => Ok(serde_json::from_str(response.content.json().get()).unwrap_or_default()),
+        Err(HttpError::ClientApi(FromHttpResponseError::Http(ServerError::Known(e))))
+            if e.kind == ErrorKind::NotFound =>
+        {
+            Ok(PinnedEvents::default())
+        }
+        Err(e) => Err(matrix_sdk::Error::Http(e).into()),
+    }
+}
+
+/// What each reaction to a submission does with `config`, one line each, for the pinned
+/// instructions and `!help`
+pub fn reactions(config: &Config) -> Vec<String> {
+    let messages = &config.messages;
+    let mut lines = vec![
+        messages.get("instructions_approve", &[("emoji", &APPROVE_EMOJI)]),
+        messages.get("instructions_reject", &[("emoji", &REJECT_EMOJI)]),
+        messages.get("instructions_hold", &[("emoji", &config.hold_emoji)]),
+        messages.get("instructions_cw", &[("emoji", &config.cw_emoji)]),
+        messages.get(
+            "instructions_plain",
+            &[("emoji", &config.plain_publish_emoji)],
+        ),
+    ];
+    if let Some(emoji) = &config.claim_emoji {
+        lines.push(messages.get("instructions_claim", &[("emoji", emoji)]));
+    }
+    for category in &config.categories {
+        lines.push(messages.get(
+            "instructions_category",
+            &[
+                ("emoji", &category.approval_emoji),
+                ("category", &category.name),
+                ("room", &category.output_room_id),
+            ],
+        ));
+    }
+    if config.require_rejection_reason {
+        lines.push(messages.get("instructions_rejection_reason", &[("emoji", &REJECT_EMOJI)]));
+    }
+    if config.approval_via_reply {
+        lines.push(messages.get(
+            "instructions_reply",
+            &[("keywords", &config.approval_reply_keywords.join(", "))],
+        ));
+    }
+    if config.required_approval_weight > 1 || config.required_rejection_weight > 1 {
+        lines.push(messages.get(
+            "instructions_quorum",
+            &[
+                ("approval", &config.required_approval_weight),
+                ("rejection", &config.required_rejection_weight),
+            ],
+        ));
+    }
+    lines
+}
+
+/// How to moderate with `config`, from the emoji and commands it actually uses
+fn instructions(config: &Config) -> String {
+    let messages = &config.messages;
+    let mut lines = vec![messages.get("instructions", &[])];
+    lines.extend(reactions(config));
+
+    let commands = |permission| {
+        COMMANDS
+            .iter()
+            .filter(|command| config.permission(command) == permission)
+            .map(|command| format!("{}{}", config.command_prefix, command))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    lines.push(messages.get(
+        "instructions_commands",
+        &[("commands", &commands(Permission::Moderator))],
+    ));
+    let admin_commands = commands(Permission::Admin);
+    if !admin_commands.is_empty() {
+        lines.push(messages.get(
+            "instructions_admin_commands",
+            &[("commands", &admin_commands)],
+        ));
+    }
+    lines.join("\n")
+}
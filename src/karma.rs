@@ -0,0 +1,31 @@
+use matrix_sdk::ruma::UserId;
+
+use crate::state;
+
+/// Approvals minus rejections of `user`'s submissions
+pub fn get(user: &UserId) -> i64 {
+    state::read(|state| state.karma.get(user).copied().unwrap_or_default())
+}
+
+/// Add `delta` to `user`'s karma, returning the new value
+pub fn adjust(user: &UserId, delta: i64) -> i64 {
+    state::update(|state| {
+        let karma = state.karma.entry(user.clone()).or_default();
+        *karma += delta;
+        *karma
+    })
+}
+
+pub fn reset(user: &UserId) {
+    state::update(|state| state.karma.remove(user));
+}
+
+/// Forget everyone whose karma is back at 0, which is what they'd have without an entry, returning
+/// how many there were
+pub fn prune() -> usize {
+    state::update(|state| {
+        let before = state.karma.len();
+        state.karma.retain(|_, karma| *karma != 0);
+        before - state.karma.len()
+    })
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use matrix_sdk::{
+    ruma::{
+        api::client::{
+            error::ErrorKind,
+            r0::state::{get_state_events_for_key, send_state_event},
+        },
+        api::error::{FromHttpResponseError, ServerError},
+        events::EventType,
+        serde::Raw,
+        RoomId, UserId,
+    },
+    Client, HttpError,
+};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::{
+    clock,
+    config::{self, Config},
+    error::FourwarderError,
+    pause::Deferred,
+};
+
+/// The state event in the mod room naming the active instance, with an empty state key
+const EVENT_TYPE: &str = "org.4warder.leader";
+
+/// Who holds the lead, as written in [`EVENT_TYPE`]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct Lease {
+    user_id: UserId,
+    instance_id: String,
+    /// Bumped by every heartbeat, so the others can tell the leader is alive without having to
+    /// agree on the time
+    beat: u64,
+}
+
+/// Whether we act on events, which is always the case without `failover_after_secs`
+static ACTIVE: AtomicBool = AtomicBool::new(true);
+
+/// Submissions seen while standing by, oldest first
+///
+/// The leader should have handled them, but if it went silent before it did, these are what we
+/// handle on taking over.
+static MISSED: LazyLock<Mutex<VecDeque<Deferred>>> = LazyLock::new(|| Mutex::new(VecDeque::new()));
+
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Remember a submission seen while standing by, keeping at most `capacity`
+pub fn missed(submission: Deferred, capacity: usize) {
+    let mut missed = MISSED.lock().unwrap();
+    missed.push_back(submission);
+    while missed.len() > capacity {
+        missed.pop_front();
+    }
+}
+
+/// Stop acting on events until an election makes us the leader
+pub fn stand_by() {
+    ACTIVE.store(false, Ordering::SeqCst);
+}
+
+/// Elect a leader among the instances sharing our mod room every third of `failover_after`,
+/// until the returned task is aborted
+///
+/// We take the lead if nobody holds it, or if its holder's heartbeat hasn't changed for
+/// `failover_after`. Whenever we take it, [`crate::take_over`] picks up where the last leader
+/// stopped.
+pub fn spawn(client: Client, failover_after: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(failover_after / 3);
+        // The lease as we last saw it, and since when it has been that
+        let mut seen: (Option<Lease>, Instant) = (None, clock::now());
+        loop {
+            interval.tick().await;
+            if let Err(e) = elect(&client, &config::get(), failover_after, &mut seen).await {
+                tracing::warn!("Could not check who leads: {}", e);
+            }
+        }
+    })
+}
+
+async fn elect(
+    client: &Client,
+    config: &Config,
+    failover_after: Duration,
+    seen: &mut (Option<Lease>, Instant),
+) -> Result<(), FourwarderError> {
+    let user_id = client.user_id().await.ok_or(FourwarderError::Logic(
+        "We elected a leader before logging in",
+    ))?;
+    let lease = fetch(client, &config.mod_room_id).await?;
+    let now = clock::now();
+    if seen.0 != lease {
+        *seen = (lease.clone(), now);
+    }
+
+    let beat = match lease {
+        Some(lease) if lease.user_id == user_id && lease.instance_id == config.instance_id => {
+            if !ACTIVE.swap(true, Ordering::SeqCst) {
+                tracing::info!("We lead now, taking over");
+                let missed = std::mem::take(&mut *MISSED.lock().unwrap());
+                crate::take_over(client, config, missed).await?;
+            }
+            lease.beat + 1
+        }
+        Some(lease) if now.duration_since(seen.1) < failover_after => {
+            if ACTIVE.swap(false, Ordering::SeqCst) {
+                tracing::warn!(
+                    "{} ({}) took the lead, standing by",
+                    lease.user_id,
+                    lease.instance_id
+                );
+            }
+            return Ok(());
+        }
+        // Nobody leads, or the leader went silent, so claim the lead and act on it once the
+        // next check shows nobody claimed it after us
+        Some(lease) => {
+            tracing::warn!(
+                "{} ({}) went silent, claiming the lead",
+                lease.user_id,
+                lease.instance_id
+            );
+            lease.beat + 1
+        }
+        None => {
+            tracing::info!("Nobody leads, claiming the lead");
+            0
+        }
+    };
+
+    let lease = Lease {
+        user_id,
+        instance_id: config.instance_id.clone(),
+        beat,
+    };
+    let content = serde_json::value::to_raw_value(&lease)?;
+    client
+        .send(
+            send_state_event::Request::new_raw(
+                &config.mod_room_id,
+                EVENT_TYPE,
+                "",
+                Raw::from_json(content),
+            ),
+            None,
+        )
+        .await
+        .map_err(matrix_sdk::Error::Http)?;
+    Ok(())
+}
+
+/// The lease in `room_id`, if anyone ever took one
+async fn fetch(client: &Client, room_id: &RoomId) -> Result<Option<Lease>, FourwarderError> {
+    let request = get_state_events_for_key::Request::new(room_id, EventType::from(EVENT_TYPE), "");
+    match client.send(request, None).await {
+        Ok(response) => Ok(serde_json::from_str(response.content.json().get()).ok()),
+        Err(HttpError::ClientApi(FromHttpResponseError::Http(ServerError::Known(e))))
+            if e.kind == ErrorKind::NotFound =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(matrix_sdk::Error::Http(e).into()),
+    }
+}
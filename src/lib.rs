@@ -0,0 +1,2583 @@
+//! Forwards messages from input rooms to output rooms, with moderation in between
+//!
+//! [`run`] runs the bot standalone, as the `fourwarder_bot` binary does. To embed it in a larger
+//! bot instead, hand its events to a [`Forwarder`] and set [`Hooks`] to hear what it does.
+
+// `FourwarderError` wraps `matrix_sdk::Error` by value, which is large but only
+// ever travels up a handful of frames
+#![allow(clippy::result_large_err)]
+
+mod activity;
+mod audit;
+mod auth;
+mod backfill;
+mod blocklist;
+mod bundle;
+mod caption;
+mod category;
+mod check;
+mod cleanup;
+mod clock;
+mod commands;
+mod config;
+mod confirm;
+mod content_dedup;
+mod cw;
+mod dead_letter;
+mod decided;
+mod dedup;
+mod delegation;
+mod delivery;
+mod error;
+mod escalation;
+mod export;
+mod filters;
+mod hash_denylist;
+mod health;
+mod help;
+mod history;
+mod hooks;
+mod include;
+mod index;
+mod instructions;
+mod karma;
+mod leader;
+mod lifecycle;
+mod marker;
+mod media;
+mod membership;
+mod messages;
+mod metrics;
+mod migrate;
+mod mirror;
+mod normalize;
+mod ordering;
+mod output_scan;
+mod pacing;
+mod pause;
+mod pending;
+mod power;
+mod prefix;
+mod profanity;
+mod profile;
+mod pseudonym;
+mod queue;
+mod quorum;
+mod quota;
+mod quote;
+mod ratelimit;
+mod rooms;
+mod schedule;
+mod schema;
+mod selftest;
+mod send;
+mod sequence;
+mod session;
+mod shadowban;
+mod space;
+mod spam;
+mod split;
+mod state;
+mod stats;
+mod tally;
+#[cfg(test)]
+mod testing;
+mod thread;
+mod tidy;
+mod timeout;
+mod training;
+mod urls;
+mod user_pattern;
+mod webhook;
+
+use matrix_sdk::{
+    self,
+    deserialized_responses::SyncResponse,
+    event_handler::RawEvent,
+    room::Room,
+    ruma::{
+        self,
+        api::client::r0::filter::{
+            Filter, FilterDefinition, LazyLoadOptions, RoomEventFilter, RoomFilter,
+        },
+        events::{
+            reaction::ReactionEventContent,
+            room::member::{MemberEventContent, MembershipState},
+            room::message::{
+                FormattedBody, MessageEventContent, MessageFormat, MessageType, Relation,
+                TextMessageEventContent,
+            },
+            room::power_levels::PowerLevelsEventContent,
+            AnyMessageEvent, AnyRoomEvent, AnySyncMessageEvent, AnySyncRoomEvent, SyncMessageEvent,
+            SyncStateEvent,
+        },
+        EventId, MilliSecondsSinceUnixEpoch, RoomId, UserId,
+    },
+    Client, SyncSettings,
+};
+
+use std::{
+    collections::VecDeque,
+    time::Duration,
+};
+
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use url::Url;
+
+use audit::Action;
+use config::{Config, InPlaceApproval, CONFIG_LOCATION};
+use content_dedup::ContentDedup;
+use cw::ContentWarning;
+use delivery::{Destination, Report};
+use error::ErrorClass;
+pub use error::FourwarderError;
+use filters::{FilterDecision, IncomingMessage};
+use hash_denylist::Denial;
+pub use hooks::{Decided, Forwarded, Hooks};
+use lifecycle::Transition;
+use normalize::normalize_emoji;
+use ordering::OrderBy;
+use pause::Deferred;
+use pending::{PendingItem, Preview};
+use power::Capability;
+use profanity::ProfanityMask;
+use quorum::Vote;
+use quote::QuoteStyle;
+use tidy::Tidy;
+use urls::UrlFilter;
+use webhook::{Decision, Published, Submission};
+
+/// Reacting with this in the mod room publishes a submission
+const APPROVE_EMOJI: &str = "✅";
+/// Reacting with this in the mod room throws a submission away
+const REJECT_EMOJI: &str = "❌";
+
+/// How many syncs in a row can fail to reach the homeserver before we replace the client
+const REBUILD_AFTER_FAILURES: u32 = 5;
+
+/// Build the text to post in an output room from the text of a submission
+fn render(text: &TextMessageEventContent, config: &Config) -> TextMessageEventContent {
+    let text = UrlFilter::new(config.url_policy, &config.url_allowlist).apply(text);
+    let text = ProfanityMask::new(&config.profanity_mask).apply(&text);
+    Tidy::new(config.trim_output, config.markdown_to_html).apply(&text)
+}
+
+/// Dress rendered text the way it is published: behind its content warning, quoted, as its
+/// sender, and in the `output_template` of its input room
+async fn lay_out(
+    client: &Client,
+    config: &Config,
+    mut text: TextMessageEventContent,
+    item: Option<&PendingItem>,
+    content_warning: Option<&ContentWarning>,
+    take_seq: bool,
+) -> TextMessageEventContent {
+    // Messages we have no record of are laid out with the top-level template
+    let template = item.map_or(config.output_template.as_deref(), |item| {
+        config.output_template_of(&item.source_room_id)
+    });
+    if let Some(content_warning) = content_warning {
+        text = content_warning.wrap(&text, &config.messages);
+    }
+    if config.quote_style == QuoteStyle::Blockquote {
+        // Naming the sender again would be redundant with `present_as_sender`, and would give
+        // away who is behind a pseudonym
+        let named = config.present_as_sender
+            || template.is_some_and(|template| template.contains("{pseudonym}"));
+        let attribution = match item.filter(|_| !named) {
+            Some(item) => {
+                let name = profile::get(client, &item.sender)
+                    .await
+                    .display_name
+                    .unwrap_or_else(|| item.sender.to_string());
+                Some(
+                    config
+                        .messages
+                        .get("quote_attribution", &[("sender", &name)]),
+                )
+            }
+            None => None,
+        };
+        text = quote::blockquote(&text, attribution.as_deref());
+    }
+    if let Some(item) = item.filter(|_| config.present_as_sender) {
+        text = profile::get(client, &item.sender)
+            .await
+            .present(&item.sender, &text);
+    }
+    if let Some(template) = template {
+        let sender = item.map(|item| &item.sender);
+        text = apply_template(config, template, &text, sender, take_seq);
+    }
+    text
+}
+
+/// What approving a pending item with ✅ would publish, and where, without publishing it
+pub(crate) async fn rendered(
+    client: &Client,
+    config: &Config,
+    item: &PendingItem,
+) -> (RoomId, TextMessageEventContent) {
+    let text = render(&item.content, config);
+    let content_warning = content_warning_for(config, Some(item), &item.content, APPROVE_EMOJI);
+    let text = lay_out(
+        client,
+        config,
+        text,
+        Some(item),
+        content_warning.as_ref(),
+        false,
+    )
+    .await;
+    (config.output_room_of(&item.source_room_id).clone(), text)
+}
+
+/// Lay out `text` with `output_template`, numbering it if the template has `{seq}`, and giving
+/// its `sender` a pseudonym if it has `{pseudonym}`
+///
+/// Without `take_seq` it gets the number the next published submission would, which stays free,
+/// and a sender without a pseudonym isn't given one yet.
+fn apply_template(
+    config: &Config,
+    template: &str,
+    text: &TextMessageEventContent,
+    sender: Option<&UserId>,
+    take_seq: bool,
+) -> TextMessageEventContent {
+    let seq = if template.contains("{seq}") && take_seq {
+        sequence::next().to_string()
+    } else if template.contains("{seq}") {
+        sequence::peek().to_string()
+    } else {
+        String::new()
+    };
+    let pseudonym = match sender.filter(|_| template.contains("{pseudonym}")) {
+        Some(sender) if take_seq => pseudonym::name(&config.messages, pseudonym::number(sender)),
+        Some(sender) => pseudonym::name(&config.messages, pseudonym::peek(sender)),
+        None => String::new(),
+    };
+    let html = match &text.formatted {
+        Some(formatted) if formatted.format == MessageFormat::Html => formatted.body.clone(),
+        _ => cw::escape_html(&text.body).replace('\n', "<br>"),
+    };
+
+    let mut laid_out = text.clone();
+    laid_out.body = messages::fill(
+        template,
+        &[
+            ("seq", &seq),
+            ("pseudonym", &pseudonym),
+            ("body", &text.body),
+        ],
+    );
+    laid_out.formatted = Some(FormattedBody::html(messages::fill(
+        &cw::escape_html(template),
+        &[
+            ("seq", &seq),
+            ("pseudonym", &cw::escape_html(&pseudonym)),
+            ("body", &html),
+        ],
+    )));
+    laid_out
+}
+
+/// Post a submission to `output_room_id`, and to the output webhook if there is one
+///
+/// `item` is `None` if we don't know where the submission came from. Both destinations are
+/// tried even if the other fails, and the report says how each went.
+async fn publish(
+    client: &Client,
+    config: &Config,
+    output_room_id: &RoomId,
+    text: &TextMessageEventContent,
+    item: Option<&PendingItem>,
+    content_warning: Option<&ContentWarning>,
+    thread_root: Option<&EventId>,
+) -> Report {
+    let text = render(text, config);
+    if output_scan::already_published(output_room_id, &text.body) {
+        tracing::info!(
+            "Not publishing to {}, the startup scan found it there already",
+            output_room_id
+        );
+        let mut report = Report::default();
+        report.push(Destination::Room(output_room_id.clone()), Ok(()));
+        return report;
+    }
+    let text = lay_out(client, config, text, item, content_warning, true).await;
+
+    let webhook = async {
+        let webhook = config.output_webhook.as_ref()?;
+        let published = Published {
+            body: text.body.clone(),
+            formatted_body: text
+                .formatted
+                .as_ref()
+                .map(|formatted| formatted.body.clone()),
+            sender: item.map(|item| item.sender.to_string()),
+            timestamp: item.map(|item| item.origin_server_ts.get().into()),
+            source_room_id: item.map(|item| item.source_room_id.to_string()),
+        };
+
+        let result = if config.dry_run {
+            tracing::info!(
+                target: "dry_run",
+                url = %webhook.url,
+                body = %published.body,
+                "Would post to output webhook"
+            );
+            Ok(())
+        } else {
+            webhook.deliver(&published).await
+        };
+        Some((Destination::Webhook(webhook.url.clone()), result))
+    };
+    let room = async {
+        let key = match config.order_by {
+            OrderBy::Approval => 0,
+            OrderBy::OriginalTs => {
+                item.map_or_else(clock::now_millis, |item| item.origin_server_ts.get().into())
+            }
+        };
+        let _queued = config.min_send_interval.map(|_| pacing::queue());
+        let _turn = ordering::turn(output_room_id, key).await;
+        if let Some(interval) = config.min_send_interval {
+            pacing::wait(output_room_id, interval).await;
+        }
+        // The parts of a long submission go out one after another while we have the turn, so
+        // nothing is published between them
+        let parts = if config.split_long_output {
+            split::split(&text, config.max_output_chars)
+        } else {
+            vec![text.clone()]
+        };
+        let last = parts.len().saturating_sub(1);
+        for (i, part) in parts.into_iter().enumerate() {
+            let event_id = send::forwarded(
+                client,
+                output_room_id,
+                config.output_msgtype.content(part.clone()),
+                thread_root,
+            )
+            .await?;
+            if let Some(event_id) = event_id {
+                if let Some(item) = item {
+                    history::record(
+                        event_id.clone(),
+                        output_room_id.clone(),
+                        item.sender.clone(),
+                        &part.body,
+                    );
+                    // The reactions go at the end of the last part
+                    if let Some(source_event_id) = item
+                        .source_event_id
+                        .as_ref()
+                        .filter(|_| i == last && config.mirror_source_reactions)
+                    {
+                        tally::track(
+                            &event_id,
+                            &item.source_room_id,
+                            source_event_id,
+                            part.clone(),
+                        );
+                    }
+                }
+                hooks::forwarded(Forwarded {
+                    room_id: output_room_id.clone(),
+                    event_id,
+                    sender: item.map(|item| item.sender.clone()),
+                    body: part.body,
+                });
+            }
+        }
+        Ok(())
+    };
+    let (webhook, room) = tokio::join!(webhook, room);
+
+    let mut report = Report::default();
+    report.push(Destination::Room(output_room_id.clone()), room);
+    if let Some((destination, result)) = webhook {
+        report.push(destination, result);
+    }
+    report
+}
+
+/// Mark the mod room copy of a submission with why a filter held it
+fn flag_as_held(text: &TextMessageEventContent, reason: &str) -> TextMessageEventContent {
+    let flag = format!("⚠️ {}", reason);
+
+    let mut text = text.clone();
+    text.body = format!("{}\n{}", flag, text.body);
+    if let Some(formatted) = &mut text.formatted {
+        formatted.body = format!("<p>{}</p>{}", flag, formatted.body);
+    }
+    text
+}
+
+async fn on_room_message(
+    event: SyncMessageEvent<MessageEventContent>,
+    room: Room,
+    client: Client,
+    raw: RawEvent,
+) -> Result<(), FourwarderError> {
+    let room_id = room.room_id().clone();
+    let event_id = event.event_id.clone();
+    handle_room_message(event, room, client, raw)
+        .await
+        .map_err(|e| e.in_event(&room_id, &event_id))
+}
+
+async fn handle_room_message(
+    event: SyncMessageEvent<MessageEventContent>,
+    room: Room,
+    client: Client,
+    raw: RawEvent,
+) -> Result<(), FourwarderError> {
+    let config = config::get();
+
+    if let Room::Joined(room) = room {
+        let msgtype = event.content.msgtype.msgtype();
+        let in_input_room = config.input_room(room.room_id()).is_some();
+        // Left unhandled rather than dropped, so `!backfill` can pick them up after `!unmute-room`
+        if in_input_room
+            && config.is_muted(room.room_id())
+            && room.room_id() != &config.mod_room_id
+        {
+            tracing::debug!("Ignoring {}, {} is muted", event.event_id, room.room_id());
+            return Ok(());
+        }
+        if leader::is_active()
+            && in_input_room
+            && !config
+                .allowed_message_types
+                .iter()
+                .any(|allowed| allowed == msgtype)
+        {
+            let notice = config.messages.get(
+                "message_type_notice",
+                &[("sender", &event.sender), ("msgtype", &msgtype)],
+            );
+            let reason = format!("{} is not allowed", msgtype);
+            return refuse(&client, &config, room.room_id(), &event, &raw, &reason, notice).await;
+        }
+
+        // Checked before anything else is done with media, so nothing from listed media, not even
+        // its caption, is forwarded
+        if in_input_room
+            && leader::is_active()
+            && caption::is_media(&event.content.msgtype)
+            && marker::instance_of_event(&raw).is_none()
+        {
+            if let Some(denylist) = &config.hash_denylist {
+                if let Some(denial) = denylist.check(&client, &raw_content(&raw)).await {
+                    return deny_media(&client, &config, room.room_id(), &event, denial).await;
+                }
+            }
+        }
+
+        // Taken before the caption stands in for the file
+        let media_sha256 = if in_input_room
+            && leader::is_active()
+            && config.content_dedup != ContentDedup::Off
+            && caption::is_media(&event.content.msgtype)
+        {
+            media_sha256(&client, &event.event_id, &raw).await
+        } else {
+            None
+        };
+
+        // Media is submitted as its caption, if it has one and `forward_media_captions` is set
+        let mut event = event;
+        if in_input_room && caption::is_media(&event.content.msgtype) {
+            match caption::of(&event.content.msgtype, &raw, config.ignore_filename_captions) {
+                Some(caption) if config.forward_media_captions => {
+                    event.content.msgtype =
+                        MessageType::Text(TextMessageEventContent::plain(caption));
+                }
+                None if config.require_media_caption && leader::is_active() => {
+                    let notice = config
+                        .messages
+                        .get("media_caption_required", &[("sender", &event.sender)]);
+                    return refuse(
+                        &client,
+                        &config,
+                        room.room_id(),
+                        &event,
+                        &raw,
+                        "it has no caption",
+                        notice,
+                    )
+                    .await;
+                }
+                _ => {}
+            }
+        }
+
+        if let SyncMessageEvent {
+            content:
+                MessageEventContent {
+                    msgtype: MessageType::Text(text),
+                    relates_to,
+                    ..
+                },
+            sender,
+            event_id,
+            origin_server_ts,
+            ..
+        } = event
+        {
+            if !leader::is_active() {
+                if config.input_room(room.room_id()).is_some()
+                    && marker::instance_of_event(&raw).is_none()
+                {
+                    leader::missed(
+                        Deferred::Submission {
+                            room_id: room.room_id().clone(),
+                            event_id,
+                            sender,
+                            origin_server_ts,
+                            text,
+                        },
+                        config.dedup_capacity,
+                    );
+                }
+                return Ok(());
+            }
+
+            if room.room_id() == &config.mod_room_id {
+                if commands::is_command(&text.body, &config.command_prefix) {
+                    return commands::handle(&client, &config, &sender, &text.body).await;
+                }
+
+                if let Some(Relation::Reply { in_reply_to }) = relates_to {
+                    if !delegation::may_moderate(&config, &sender) {
+                        return Ok(());
+                    }
+                    let reply = cw::strip_reply_fallback(&text.body);
+                    if config.is_approval_reply(reply) {
+                        return approve(
+                            &client,
+                            &config,
+                            room.room_id(),
+                            &sender,
+                            &in_reply_to.event_id,
+                            APPROVE_EMOJI,
+                        )
+                        .await;
+                    }
+
+                    // A reply to a submission someone wants to reject gives the reason for
+                    // rejecting it, and lets their ❌ count
+                    let reason = reply.to_owned();
+                    if config.require_rejection_reason && !reason.is_empty() {
+                        if let Some(rejecters) =
+                            pending::give_rejection_reason(&in_reply_to.event_id, reason.clone())
+                        {
+                            tracing::info!(
+                                "{} gave a reason to reject {}",
+                                sender,
+                                in_reply_to.event_id
+                            );
+                            for rejecter in rejecters {
+                                if quorum::vote(
+                                    &config,
+                                    &in_reply_to.event_id,
+                                    &rejecter,
+                                    Vote::Reject,
+                                ) {
+                                    return reject(
+                                        &client,
+                                        &config,
+                                        &rejecter,
+                                        &in_reply_to.event_id,
+                                    )
+                                    .await;
+                                }
+                            }
+                            return Ok(());
+                        }
+                    }
+
+                    // Any other reply to a pending submission gives the reason for a content
+                    // warning
+                    if !reason.is_empty()
+                        && pending::set_content_warning(&in_reply_to.event_id, reason)
+                    {
+                        tracing::info!(
+                            "{} gave a content warning for {}",
+                            sender,
+                            in_reply_to.event_id
+                        );
+                    }
+                    return Ok(());
+                }
+            }
+
+            if config.input_room(room.room_id()).is_some() {
+                if !dedup::first_time(&event_id, config.dedup_capacity) {
+                    tracing::info!("Ignoring {}, it was already handled", event_id);
+                    return Ok(());
+                }
+                // Already forwarded by us or another instance, so forwarding it again could
+                // start a loop between rooms
+                if let Some(instance) = marker::instance_of_event(&raw) {
+                    tracing::info!("Ignoring {}, it was forwarded by {}", event_id, instance);
+                    return Ok(());
+                }
+                if !content_dedup::first_time(
+                    config.content_dedup,
+                    config.content_dedup_window,
+                    config.dedup_capacity,
+                    &sender,
+                    &text.body,
+                    media_sha256.as_deref(),
+                ) {
+                    tracing::info!(
+                        "Dropped {} from {}, it repeats a recent one",
+                        event_id,
+                        sender
+                    );
+                    activity::record(room.room_id(), activity::Outcome::Received);
+                    activity::record(room.room_id(), activity::Outcome::Rejected);
+                    audit::record(None, Action::Repeated { event_id, sender });
+                    return Ok(());
+                }
+                tracing::info!("Recieved message in input room, {:?}", text.body);
+
+                if pause::is_paused() {
+                    tracing::info!("Deferring {}, forwarding is paused", event_id);
+                    pause::defer(Deferred::Submission {
+                        room_id: room.room_id().clone(),
+                        event_id,
+                        sender,
+                        origin_server_ts,
+                        text,
+                    });
+                    return Ok(());
+                }
+                forward(
+                    &client,
+                    &config,
+                    room.room_id(),
+                    event_id,
+                    sender,
+                    origin_server_ts,
+                    text,
+                )
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Turn away a submission to an input room, such as one whose `msgtype` isn't in
+/// `allowed_message_types`, telling the submitter why with `notice`
+async fn refuse(
+    client: &Client,
+    config: &Config,
+    room_id: &RoomId,
+    event: &SyncMessageEvent<MessageEventContent>,
+    raw: &RawEvent,
+    reason: &str,
+    notice: String,
+) -> Result<(), FourwarderError> {
+    // Our own notices, and anything already forwarded, are never submissions
+    if client.user_id().await.as_ref() == Some(&event.sender)
+        || marker::instance_of_event(raw).is_some()
+        || !dedup::first_time(&event.event_id, config.dedup_capacity)
+    {
+        return Ok(());
+    }
+
+    activity::record(room_id, activity::Outcome::Received);
+    activity::record(room_id, activity::Outcome::Rejected);
+    tracing::info!(
+        "Dropped {} from {}, {}",
+        event.event_id,
+        event.sender,
+        reason
+    );
+    // The submitter isn't told if we may not post in their room
+    if power::allowed(room_id, Capability::Send) {
+        send::message(client, room_id, MessageEventContent::notice_plain(notice)).await?;
+    }
+    Ok(())
+}
+
+/// The `content` of a raw event, or `null` if it has none
+fn raw_content(raw: &RawEvent) -> serde_json::Value {
+    serde_json::from_str::<serde_json::Value>(raw.get())
+        .ok()
+        .and_then(|mut event| event.get_mut("content").map(serde_json::Value::take))
+        .unwrap_or_default()
+}
+
+/// The SHA-256 of the file a media message carries, for `content_dedup`
+///
+/// If it can't be downloaded, the submission is only compared by its text.
+async fn media_sha256(client: &Client, event_id: &EventId, raw: &RawEvent) -> Option<String> {
+    let content = raw_content(raw);
+    let url = media::file_url(&content)?;
+    match media::sha256(client, url).await {
+        Ok(sha256) => Some(sha256),
+        Err(e) => {
+            tracing::warn!("Could not hash the file of {}: {}", event_id, e);
+            None
+        }
+    }
+}
+
+/// Drop media which `hash_denylist_url` turned away, telling the mod room but not the submitter
+async fn deny_media(
+    client: &Client,
+    config: &Config,
+    room_id: &RoomId,
+    event: &SyncMessageEvent<MessageEventContent>,
+    denial: Denial,
+) -> Result<(), FourwarderError> {
+    if !dedup::first_time(&event.event_id, config.dedup_capacity) {
+        return Ok(());
+    }
+
+    let (reason, sha256) = match denial {
+        Denial::Listed { sha256 } => ("its media is on the hash denylist", Some(sha256)),
+        Denial::Unchecked => ("its media could not be checked against the hash denylist", None),
+    };
+    tracing::info!(
+        "Dropped {} from {}, {} (SHA-256 {})",
+        event.event_id,
+        event.sender,
+        reason,
+        sha256.as_deref().unwrap_or("unknown")
+    );
+    activity::record(room_id, activity::Outcome::Received);
+    activity::record(room_id, activity::Outcome::Rejected);
+    audit::record(
+        None,
+        Action::HashDenied {
+            event_id: event.event_id.clone(),
+            sender: event.sender.clone(),
+            sha256,
+        },
+    );
+    send::message(
+        client,
+        &config.mod_room_id,
+        MessageEventContent::notice_plain(
+            config
+                .messages
+                .get("dropped", &[("sender", &event.sender), ("reason", &reason)]),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Run a submission to an input room through the filters, and on to the mod room or its output
+/// room
+///
+/// If that fails and `dead_letter_room_id` is set, the submission is kept there for
+/// `!retry-failed` instead of being lost.
+async fn forward(
+    client: &Client,
+    config: &Config,
+    room_id: &RoomId,
+    event_id: EventId,
+    sender: UserId,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+    text: TextMessageEventContent,
+) -> Result<(), FourwarderError> {
+    let submission = config
+        .dead_letter_room_id
+        .is_some()
+        .then(|| Deferred::Submission {
+            room_id: room_id.clone(),
+            event_id: event_id.clone(),
+            sender: sender.clone(),
+            origin_server_ts,
+            text: text.clone(),
+        });
+    match try_forward(
+        client,
+        config,
+        room_id,
+        event_id,
+        sender,
+        origin_server_ts,
+        text,
+    )
+    .await
+    {
+        Err(e) => match submission {
+            Some(submission) => dead_letter::record(client, config, submission, &e, 1).await,
+            None => Err(e),
+        },
+        Ok(()) => Ok(()),
+    }
+}
+
+async fn try_forward(
+    client: &Client,
+    config: &Config,
+    room_id: &RoomId,
+    event_id: EventId,
+    sender: UserId,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+    text: TextMessageEventContent,
+) -> Result<(), FourwarderError> {
+    // The room may have been removed while forwarding was paused
+    let settings = match config.input_room(room_id) {
+        Some(settings) => settings,
+        None => return Ok(()),
+    };
+    // Not a submission at all, so nobody is told, and it counts toward nothing
+    let text = match &config.submission_prefix {
+        Some(submission_prefix) => match prefix::strip(&text, submission_prefix) {
+            Some(text) => text,
+            None => {
+                tracing::debug!(
+                    "Ignoring {}, it doesn't start with `{}`",
+                    event_id,
+                    submission_prefix
+                );
+                return Ok(());
+            }
+        },
+        None => text,
+    };
+    if !config.include.matches(&text.body) {
+        tracing::debug!("Ignoring {}, it matches no `include_patterns`", event_id);
+        return Ok(());
+    }
+    activity::record(room_id, activity::Outcome::Received);
+
+    if shadowban::is_shadowbanned(&sender) {
+        activity::record(room_id, activity::Outcome::Rejected);
+        tracing::info!("Dropped {} from shadowbanned {}", event_id, sender);
+        audit::record(None, Action::ShadowbannedMessage { event_id, sender });
+        return Ok(());
+    }
+
+    let trusted = config
+        .auto_approve_karma
+        .is_some_and(|threshold| karma::get(&sender) > threshold);
+    if let Some(resets_at) = quota::exceeded(config, &sender) {
+        activity::record(room_id, activity::Outcome::Rejected);
+        tracing::info!("Dropped {} from {}, who is over their quota", event_id, sender);
+        // The submitter isn't told if we may not post in their room
+        if power::allowed(room_id, Capability::Send) {
+            let notice = config.messages.get(
+                "quota_notice",
+                &[
+                    ("sender", &sender),
+                    ("quota", &config.daily_submission_quota.unwrap_or_default()),
+                    ("at", &schedule::format_time(resets_at, config.utc_offset)),
+                ],
+            );
+            send::message(client, room_id, MessageEventContent::notice_plain(notice)).await?;
+        }
+        return Ok(());
+    }
+    if let Some(min_age) = config
+        .min_membership_age
+        .filter(|_| !config.is_trusted(&sender))
+    {
+        let submitted_at = origin_server_ts.get().into();
+        if let Some(left) =
+            membership::too_new(client, room_id, &sender, submitted_at, min_age).await?
+        {
+            activity::record(room_id, activity::Outcome::Rejected);
+            tracing::info!("Dropped {} from {}, who joined too recently", event_id, sender);
+            if power::allowed(room_id, Capability::Send) {
+                let notice = config.messages.get(
+                    "membership_age_notice",
+                    &[
+                        ("sender", &sender),
+                        (
+                            "left",
+                            &stats::format_duration(left.as_millis() as u64),
+                        ),
+                    ],
+                );
+                send::message(client, room_id, MessageEventContent::notice_plain(notice)).await?;
+            }
+            return Ok(());
+        }
+    }
+
+    let decision = filters::evaluate(
+        settings.filters,
+        &IncomingMessage {
+            room_id,
+            event_id: &event_id,
+            sender: &sender,
+            text: &text,
+        },
+    );
+    if let FilterDecision::Drop(reason) = decision {
+        activity::record(room_id, activity::Outcome::Rejected);
+        tracing::info!("Dropped {} from {}: {}", event_id, sender, reason);
+        send::message(
+            client,
+            &config.mod_room_id,
+            MessageEventContent::notice_plain(
+                config
+                    .messages
+                    .get("dropped", &[("sender", &sender), ("reason", &reason)]),
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let webhook_decision = match &config.moderation_webhook {
+        Some(webhook) => {
+            webhook
+                .decide(&Submission {
+                    event_id: event_id.as_str(),
+                    room_id: room_id.as_str(),
+                    sender: sender.as_str(),
+                    body: &text.body,
+                    formatted_body: text
+                        .formatted
+                        .as_ref()
+                        .map(|formatted| formatted.body.as_str()),
+                })
+                .await
+        }
+        None => None,
+    };
+    let moderate = match webhook_decision {
+        Some(Decision::Deny) => {
+            activity::record(room_id, activity::Outcome::Rejected);
+            tracing::info!("Moderation webhook denied {} from {}", event_id, sender);
+            return Ok(());
+        }
+        Some(Decision::Allow) => false,
+        Some(Decision::Review) => true,
+        None => settings.moderate && !trusted,
+    };
+
+    let mod_copy = match decision {
+        FilterDecision::Hold(reason) => Some(flag_as_held(&text, &reason)),
+        _ if moderate => Some(text.clone()),
+        _ => None,
+    };
+
+    let priority = queue::priority(config, &sender, &text.body);
+    let item = PendingItem::new(
+        room_id.clone(),
+        Some(event_id.clone()),
+        sender,
+        origin_server_ts,
+        text,
+        priority,
+    );
+    if mod_copy.is_some() && queue::rejects_new(config, item.priority) {
+        activity::record(room_id, activity::Outcome::Rejected);
+        tracing::info!(
+            "Dropped {} from {}, the queue is full",
+            event_id,
+            item.sender
+        );
+        // The submitter isn't told if we may not post in their room
+        if power::allowed(room_id, Capability::Send) {
+            send::message(
+                client,
+                room_id,
+                MessageEventContent::notice_plain(
+                    config
+                        .messages
+                        .get("queue_full_notice", &[("sender", &item.sender)]),
+                ),
+            )
+            .await?;
+        }
+    } else if let Some(mod_copy) = mod_copy {
+        let mod_event_id = send::forwarded(
+            client,
+            &config.mod_room_id,
+            MessageEventContent::new(MessageType::Text(mod_copy)),
+            None,
+        )
+        .await?;
+        if let Some(mod_event_id) = mod_event_id {
+            quota::count(config, &item.sender);
+            pending::insert(mod_event_id, item);
+            queue::check(client, config).await?;
+        }
+    } else {
+        let report = publish(
+            client,
+            config,
+            settings.output_room_id,
+            &item.content,
+            Some(&item),
+            cw::for_keywords(&config.cw_keywords, &item.content.body).as_ref(),
+            None,
+        )
+        .await;
+        report.report(client, config, &event_id).await?;
+        if let Some(reasons) = report.failed_everywhere() {
+            return Err(FourwarderError::Undelivered(reasons));
+        }
+        quota::count(config, &item.sender);
+        activity::record(room_id, activity::Outcome::Forwarded);
+    }
+    Ok(())
+}
+
+async fn on_room_react(
+    event: SyncMessageEvent<ReactionEventContent>,
+    room: Room,
+    client: Client,
+) -> Result<(), FourwarderError> {
+    let room_id = room.room_id().clone();
+    let event_id = event.event_id.clone();
+    handle_room_react(event, room, client)
+        .await
+        .map_err(|e| e.in_event(&room_id, &event_id))
+}
+
+async fn handle_room_react(
+    event: SyncMessageEvent<ReactionEventContent>,
+    room: Room,
+    client: Client,
+) -> Result<(), FourwarderError> {
+    let reactor = event.sender;
+    let reaction_id = event.event_id;
+    let reacted_to = event.content.relates_to.event_id;
+    let emoji = normalize_emoji(&event.content.relates_to.emoji);
+
+    let config = config::get();
+    if !leader::is_active() {
+        return Ok(());
+    }
+    // Our own reactions, like the ones `react_on_source_decision` leaves, never decide anything
+    if client.user_id().await.as_ref() == Some(&reactor) {
+        return Ok(());
+    }
+
+    // Confirming a command is up to whoever gave it, moderator or not
+    if emoji == APPROVE_EMOJI && room.room_id() == &config.mod_room_id {
+        if let Some(confirmation) = confirm::take_by_prompt(&reactor, &reacted_to) {
+            return commands::confirmed(&client, &config, confirmation).await;
+        }
+    }
+
+    let decides = room.room_id() == &config.mod_room_id
+        || config.preview_room_id.as_ref() == Some(room.room_id());
+    let allowed = match config.category(&emoji) {
+        Some(category) => category.allows(&config, &reactor),
+        None => delegation::may_moderate(&config, &reactor),
+    };
+    if decides && !allowed {
+        tracing::debug!("Ignoring {} from {}, who doesn't moderate", emoji, reactor);
+        return Ok(());
+    }
+
+    if config.preview_room_id.as_ref() == Some(room.room_id()) {
+        if let Some((mod_event_id, item)) = pending::by_preview(&reacted_to) {
+            let preview = item.preview.unwrap();
+            if emoji == APPROVE_EMOJI && pause::is_paused() {
+                tracing::info!(
+                    "Deferring the confirmation of {}, forwarding is paused",
+                    mod_event_id
+                );
+                pause::defer(Deferred::Confirmation {
+                    moderator: reactor,
+                    mod_event_id,
+                    emoji: preview.emoji,
+                });
+            } else if emoji == APPROVE_EMOJI {
+                publish_approved(
+                    &client,
+                    &config,
+                    &config.mod_room_id,
+                    &reactor,
+                    &mod_event_id,
+                    &preview.emoji,
+                )
+                .await?;
+            } else if emoji == REJECT_EMOJI {
+                // Back to the mod room queue, to be approved again or rejected there
+                pending::clear_preview(&mod_event_id);
+                redact_quietly(&client, room.room_id(), &reacted_to, "Cancelled").await;
+                tracing::info!("{} cancelled the preview of {}", reactor, mod_event_id);
+            }
+        }
+        return Ok(());
+    }
+
+    let approves = [APPROVE_EMOJI, &config.cw_emoji, &config.plain_publish_emoji];
+    let approving = approves.contains(&emoji.as_str()) || config.category(&emoji).is_some();
+    let in_input_room = config.approve_in_input_room
+        && config
+            .input_room(room.room_id())
+            .is_some_and(|settings| settings.moderate);
+    // Anyone else's approving reaction is left to count toward `show_source_reactions`
+    if approving
+        && in_input_room
+        && delegation::may_approve_in_input_room(&config, &reactor, &emoji)
+    {
+        return approve_from_input_room(
+            &client,
+            &config,
+            room.room_id(),
+            &reactor,
+            &reacted_to,
+            &emoji,
+        )
+        .await;
+    }
+
+    if room.room_id() == &config.mod_room_id {
+        if config.claim_emoji.as_ref() == Some(&emoji) {
+            if pending::claim(&reacted_to, &reactor, reaction_id, config.claim_timeout) {
+                tracing::info!("{} claimed {}", reactor, reacted_to);
+            }
+            return Ok(());
+        }
+        if (approving || emoji == REJECT_EMOJI) && config.warn_on_claimed_decision {
+            warn_if_claimed(&client, &config, &reactor, &reacted_to).await?;
+        }
+    }
+
+    if emoji == REJECT_EMOJI && room.room_id() == &config.mod_room_id {
+        let has_reason =
+            pending::get(&reacted_to).is_some_and(|item| item.rejection_reason.is_some());
+        if config.require_rejection_reason && !has_reason {
+            if pending::await_rejection_reason(&reacted_to, &reactor) {
+                tracing::info!("Asking for a reason to reject {}", reacted_to);
+                send::message(
+                    &client,
+                    &config.mod_room_id,
+                    MessageEventContent::notice_plain(
+                        config
+                            .messages
+                            .get("rejection_reason_required", &[("emoji", &REJECT_EMOJI)]),
+                    ),
+                )
+                .await?;
+            }
+        } else if quorum::vote(&config, &reacted_to, &reactor, Vote::Reject) {
+            reject(&client, &config, &reactor, &reacted_to).await?;
+        }
+    }
+
+    if config.show_source_reactions
+        && config.input_room(room.room_id()).is_some()
+        && pending::count_source_reaction(
+            room.room_id(),
+            &reacted_to,
+            reaction_id.clone(),
+            &reactor,
+            &emoji,
+        )
+    {
+        tracing::debug!("Counted {} on {}", emoji, reacted_to);
+        return Ok(());
+    }
+    if config.mirror_source_reactions
+        && config.input_room(room.room_id()).is_some()
+        && tally::count(
+            room.room_id(),
+            &reacted_to,
+            reaction_id.clone(),
+            &reactor,
+            &emoji,
+        )
+    {
+        tracing::debug!("Tallied {} on {}", emoji, reacted_to);
+        return Ok(());
+    }
+
+    if emoji == config.hold_emoji
+        && room.room_id() == &config.mod_room_id
+        && pending::hold(&reacted_to, reaction_id)
+    {
+        audit::record(
+            Some(&reactor),
+            Action::Held {
+                event_id: reacted_to.clone(),
+            },
+        );
+        tracing::info!("{} held {} for discussion", reactor, reacted_to);
+    }
+
+    if approving && room.room_id() == &config.mod_room_id {
+        approve(
+            &client,
+            &config,
+            room.room_id(),
+            &reactor,
+            &reacted_to,
+            &emoji,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Warn the mod room when `moderator` decides on a submission another moderator's claim on
+/// hasn't lapsed
+async fn warn_if_claimed(
+    client: &Client,
+    config: &Config,
+    moderator: &UserId,
+    mod_event_id: &EventId,
+) -> Result<(), FourwarderError> {
+    let claimant = match pending::get(mod_event_id)
+        .and_then(|item| item.claimant(config.claim_timeout).cloned())
+    {
+        Some(claimant) if &claimant != moderator => claimant,
+        _ => return Ok(()),
+    };
+    tracing::info!(
+        "{} decided on {}, which {} claimed",
+        moderator,
+        mod_event_id,
+        claimant
+    );
+    send::message(
+        client,
+        &config.mod_room_id,
+        MessageEventContent::notice_plain(config.messages.get(
+            "claimed_decision",
+            &[("moderator", moderator), ("claimant", &claimant)],
+        )),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Approve a submission with a reaction to it in its input room, see `approve_in_input_room`
+///
+/// A submission with a copy in the mod room is approved as if the reaction were on that copy.
+/// Anything else, such as a submission the filters dropped, is fetched from the input room and
+/// queued without a mod room copy, so it still takes the approvals any other submission does.
+async fn approve_from_input_room(
+    client: &Client,
+    config: &Config,
+    room_id: &RoomId,
+    moderator: &UserId,
+    event_id: &EventId,
+    emoji: &str,
+) -> Result<(), FourwarderError> {
+    if let Some((mod_event_id, _)) = pending::by_source(room_id, event_id) {
+        return approve(client, config, room_id, moderator, &mod_event_id, emoji).await;
+    }
+    if decided::of_source(room_id, event_id, config.decided_retention) {
+        tracing::info!("Ignoring approval of {}, it was already decided", event_id);
+        return Ok(());
+    }
+
+    let (sender, origin_server_ts, content) = match fetch_message(client, room_id, event_id).await?
+    {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    // Our own notices are never submissions
+    if client.user_id().await.as_ref() == Some(&sender) {
+        return Ok(());
+    }
+    let priority = queue::priority(config, &sender, &content.body);
+    let item = PendingItem::new(
+        room_id.clone(),
+        Some(event_id.clone()),
+        sender,
+        origin_server_ts,
+        content,
+        priority,
+    );
+    tracing::info!("{} approved {} in its input room", moderator, event_id);
+    pending::insert(event_id.clone(), item);
+    approve(client, config, room_id, moderator, event_id, emoji).await
+}
+
+/// Reject a pending item which enough moderators voted against, `moderator` being the last
+///
+/// If it was given a reason, its submitter is told why in the room they submitted it to.
+async fn reject(
+    client: &Client,
+    config: &Config,
+    moderator: &UserId,
+    mod_event_id: &EventId,
+) -> Result<(), FourwarderError> {
+    let item = match pending::transition(mod_event_id, Transition::Reject) {
+        Some(item) => item,
+        None => return Ok(()),
+    };
+    audit::record(
+        Some(moderator),
+        Action::Rejected {
+            event_id: mod_event_id.clone(),
+            sender: item.sender.clone(),
+            reason: item.rejection_reason.clone(),
+        },
+    );
+    if item.training {
+        training::record(moderator, false);
+        decided::record(
+            mod_event_id.clone(),
+            item.clone(),
+            false,
+            config.decided_retention,
+        );
+        tracing::info!("{} rejected the training submission {}", moderator, mod_event_id);
+        return queue::check(client, config).await;
+    }
+    hooks::rejected(Decided {
+        mod_event_id: mod_event_id.clone(),
+        moderator: moderator.clone(),
+        sender: Some(item.sender.clone()),
+    });
+    stats::record_decision(&item);
+    let karma = karma::adjust(&item.sender, -1);
+    decided::record(
+        mod_event_id.clone(),
+        item.clone(),
+        false,
+        config.decided_retention,
+    );
+    tracing::info!(
+        "Rejected {}, {} now has {} karma",
+        mod_event_id,
+        item.sender,
+        karma
+    );
+    // Anyone can read the mod room when it is also the output room
+    if config.output_room_of(&item.source_room_id) == &config.mod_room_id {
+        redact_quietly(client, &config.mod_room_id, mod_event_id, "Rejected").await;
+    }
+    react_to_source(client, config, &item, &config.source_rejected_emoji).await;
+    // The submitter isn't told if we may not post in their room
+    if let Some(reason) = &item.rejection_reason {
+        if power::allowed(&item.source_room_id, Capability::Send) {
+            send::message(
+                client,
+                &item.source_room_id,
+                MessageEventContent::notice_plain(config.messages.get(
+                    "rejected_notice",
+                    &[("sender", &item.sender), ("reason", reason)],
+                )),
+            )
+            .await?;
+        }
+    }
+    queue::check(client, config).await
+}
+
+/// Resume forwarding, then do everything deferred while it was paused in the order it happened
+///
+/// Returns how many deferred actions there were, or `None` if forwarding wasn't paused. A
+/// deferred action which fails is logged and skipped, so it can't hold up the rest.
+pub(crate) async fn resume(client: &Client, config: &Config) -> Option<usize> {
+    let deferred = pause::resume()?;
+    let count = deferred.len();
+    tracing::info!("Resumed forwarding, replaying {} deferred actions", count);
+
+    for deferred in deferred {
+        if let Err(e) = replay(client, config, deferred).await {
+            tracing::warn!("Could not replay a deferred action: {}", e);
+        }
+    }
+    Some(count)
+}
+
+/// Forward every dead-lettered submission again, oldest first, once whatever made them fail has
+/// been fixed
+///
+/// Returns how many there were and how many of those failed again, which are dead-lettered
+/// again with the attempt counted. While forwarding is paused they are deferred instead.
+pub(crate) async fn retry_failed(client: &Client, config: &Config) -> (usize, usize) {
+    let letters = dead_letter::take();
+    let mut failed = 0;
+    for letter in &letters {
+        tracing::info!("Retrying a dead-lettered submission: {}", letter.reason);
+        if pause::is_paused() {
+            pause::defer(letter.submission.clone());
+            continue;
+        }
+        let result = match letter.submission.clone() {
+            Deferred::Submission {
+                room_id,
+                event_id,
+                sender,
+                origin_server_ts,
+                text,
+            } => {
+                try_forward(
+                    client,
+                    config,
+                    &room_id,
+                    event_id,
+                    sender,
+                    origin_server_ts,
+                    text,
+                )
+                .await
+            }
+            _ => continue,
+        };
+        if let Err(e) = result {
+            failed += 1;
+            let attempts = letter.attempts + 1;
+            if let Err(e) =
+                dead_letter::record(client, config, letter.submission.clone(), &e, attempts).await
+            {
+                tracing::warn!("Could not post to the dead-letter room: {}", e);
+            }
+        }
+    }
+    (letters.len(), failed)
+}
+
+/// Become the active instance for `failover_after_secs`, picking up where the last one stopped
+///
+/// The state file is shared with the other instances, so it is read again for what the last
+/// leader did. Of the submissions we `missed` while standing by, those it never handled are
+/// handled now.
+pub(crate) async fn take_over(
+    client: &Client,
+    config: &Config,
+    missed: VecDeque<Deferred>,
+) -> Result<(), FourwarderError> {
+    state::load()?;
+
+    for missed in missed {
+        let event_id = match &missed {
+            Deferred::Submission { event_id, .. } => event_id,
+            _ => continue,
+        };
+        if !dedup::first_time(event_id, config.dedup_capacity) {
+            continue;
+        }
+        tracing::info!("Handling {}, which the last leader missed", event_id);
+        if pause::is_paused() {
+            pause::defer(missed);
+        } else if let Err(e) = replay(client, config, missed).await {
+            tracing::warn!("Could not forward a missed submission: {}", e);
+        }
+    }
+    queue::check(client, config).await
+}
+
+/// Do something which was put off
+async fn replay(
+    client: &Client,
+    config: &Config,
+    deferred: Deferred,
+) -> Result<(), FourwarderError> {
+    match deferred {
+        Deferred::Submission {
+            room_id,
+            event_id,
+            sender,
+            origin_server_ts,
+            text,
+        } => {
+            forward(
+                client,
+                config,
+                &room_id,
+                event_id,
+                sender,
+                origin_server_ts,
+                text,
+            )
+            .await
+        }
+        Deferred::Approval {
+            room_id,
+            moderator,
+            mod_event_id,
+            emoji,
+        } => approve(client, config, &room_id, &moderator, &mod_event_id, &emoji).await,
+        Deferred::Confirmation {
+            moderator,
+            mod_event_id,
+            emoji,
+        } => {
+            publish_approved(
+                client,
+                config,
+                &config.mod_room_id,
+                &moderator,
+                &mod_event_id,
+                &emoji,
+            )
+            .await
+        }
+        Deferred::Mirror {
+            room_id,
+            event_type,
+            content,
+        } => mirror::send(client, config, &room_id, &event_type, content).await,
+    }
+}
+
+/// Check our power again whenever the power levels of a room change
+async fn on_power_levels(
+    _event: SyncStateEvent<PowerLevelsEventContent>,
+    room: Room,
+    client: Client,
+) -> Result<(), FourwarderError> {
+    power::check_room(&client, &config::get(), room.room_id()).await
+}
+
+/// Check our power in a room as soon as we join it
+async fn on_room_member(
+    event: SyncStateEvent<MemberEventContent>,
+    room: Room,
+    client: Client,
+) -> Result<(), FourwarderError> {
+    if event.content.membership == MembershipState::Join
+        && client.user_id().await.as_ref().map(UserId::as_str) == Some(event.state_key.as_str())
+    {
+        power::check_room(&client, &config::get(), room.room_id()).await?;
+    }
+    Ok(())
+}
+
+/// Publish the mod room message `mod_event_id` because `moderator` approved it with `emoji`
+///
+/// This does nothing until enough moderators have approved it, or if it has already been
+/// approved, so approving twice by reaction and reply doesn't publish it twice.
+async fn approve(
+    client: &Client,
+    config: &Config,
+    room_id: &RoomId,
+    moderator: &UserId,
+    mod_event_id: &EventId,
+    emoji: &str,
+) -> Result<(), FourwarderError> {
+    if pause::is_paused() {
+        tracing::info!(
+            "Deferring the approval of {}, forwarding is paused",
+            mod_event_id
+        );
+        pause::defer(Deferred::Approval {
+            room_id: room_id.clone(),
+            moderator: moderator.clone(),
+            mod_event_id: mod_event_id.clone(),
+            emoji: emoji.to_owned(),
+        });
+        return Ok(());
+    }
+    if decided::get(mod_event_id, config.decided_retention).is_some() {
+        tracing::info!(
+            "Ignoring approval of {}, it was already decided",
+            mod_event_id
+        );
+        return Ok(());
+    }
+    // Where the mod room is also an output room, what we published there can be reacted to
+    if history::contains(mod_event_id) {
+        tracing::info!(
+            "Ignoring approval of {}, it is a published submission",
+            mod_event_id
+        );
+        return Ok(());
+    }
+    let vote = match config.category(emoji) {
+        Some(category) => Vote::ApproveAs(category),
+        None => Vote::Approve,
+    };
+    if !quorum::vote(config, mod_event_id, moderator, vote) {
+        return Ok(());
+    }
+
+    let item = pending::get(mod_event_id);
+    if item.is_some() && pending::transition(mod_event_id, Transition::Approve).is_none() {
+        return Ok(());
+    }
+    // Training submissions aren't previewed, so they can't be confirmed into a real output room
+    let previewed_item = item.as_ref().filter(|item| !item.training);
+    if let (Some(preview_room_id), Some(item)) = (&config.preview_room_id, previewed_item) {
+        let previewed = preview(client, config, preview_room_id, mod_event_id, item, emoji).await;
+        if !matches!(previewed, Ok(true)) {
+            // Nothing is waiting in the preview room, so approving it again retries
+            pending::clear_preview(mod_event_id);
+        }
+        return previewed.map(|_| ());
+    }
+
+    publish_approved(client, config, room_id, moderator, mod_event_id, emoji).await
+}
+
+/// Post an approved submission to the preview room, where confirming it publishes it
+///
+/// `emoji` is what it was approved with, which decides its content warning once it is published.
+/// Returns whether it was posted, which it isn't in a dry run.
+async fn preview(
+    client: &Client,
+    config: &Config,
+    preview_room_id: &RoomId,
+    mod_event_id: &EventId,
+    item: &PendingItem,
+    emoji: &str,
+) -> Result<bool, FourwarderError> {
+    let mut text = render(&item.content, config);
+    if let Some(content_warning) = content_warning_for(config, Some(item), &item.content, emoji) {
+        text = content_warning.wrap(&text, &config.messages);
+    }
+    let preview_event_id = send::forwarded(
+        client,
+        preview_room_id,
+        config.output_msgtype.content(text),
+        None,
+    )
+    .await?;
+
+    if let Some(preview_event_id) = preview_event_id {
+        pending::set_preview(
+            mod_event_id,
+            Preview {
+                event_id: preview_event_id,
+                emoji: emoji.to_owned(),
+            },
+        );
+        tracing::info!("Previewing {} in {}", mod_event_id, preview_room_id);
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Which content warning to publish `text` behind, if any, when it is approved with `emoji`
+fn content_warning_for(
+    config: &Config,
+    item: Option<&PendingItem>,
+    text: &TextMessageEventContent,
+    emoji: &str,
+) -> Option<ContentWarning> {
+    let keyword_warning = || cw::for_keywords(&config.cw_keywords, &text.body);
+    if emoji == config.plain_publish_emoji {
+        None
+    } else if emoji == config.cw_emoji {
+        // A moderator's reason beats the keyword labels
+        let reason = item.and_then(|item| item.content_warning.clone());
+        Some(
+            reason
+                .map(|reason| ContentWarning {
+                    reason: Some(reason),
+                })
+                .or_else(keyword_warning)
+                .unwrap_or(ContentWarning { reason: None }),
+        )
+    } else {
+        keyword_warning()
+    }
+}
+
+/// Where a submission approved with `emoji` is published
+///
+/// Messages we have no record of go to the top-level output room.
+fn approved_output_room<'a>(
+    config: &'a Config,
+    item: Option<&PendingItem>,
+    emoji: &str,
+) -> &'a RoomId {
+    match config.category(emoji) {
+        Some(category) => &category.output_room_id,
+        None => item.map_or(&config.output_room_id, |item| {
+            config.output_room_of(&item.source_room_id)
+        }),
+    }
+}
+
+/// How a submission is published to `output_room_id` if that is the mod room it was approved
+/// in, or `None` if it is published somewhere else
+fn in_place_approval(
+    config: &Config,
+    item: Option<&PendingItem>,
+    output_room_id: &RoomId,
+) -> Option<InPlaceApproval> {
+    let training = item.is_some_and(|item| item.training);
+    (!training && output_room_id == &config.mod_room_id).then_some(config.in_place_approval)
+}
+
+/// Publish the mod room message `mod_event_id`, now that it has been approved with `emoji`
+async fn publish_approved(
+    client: &Client,
+    config: &Config,
+    room_id: &RoomId,
+    moderator: &UserId,
+    mod_event_id: &EventId,
+    emoji: &str,
+) -> Result<(), FourwarderError> {
+    // Publish from the pending item if we have one, so this doesn't need the homeserver to give
+    // us the mod room copy back
+    let item = pending::get(mod_event_id);
+    let text = match &item {
+        Some(item) => item.content.clone(),
+        None => match fetch_message(client, room_id, mod_event_id).await? {
+            Some((_, _, text)) => text,
+            None => return Ok(()),
+        },
+    };
+
+    let output_room_id = approved_output_room(config, item.as_ref(), emoji);
+    let training = item.as_ref().is_some_and(|item| item.training);
+    let in_place = in_place_approval(config, item.as_ref(), output_room_id);
+
+    let content_warning = content_warning_for(config, item.as_ref(), &text, emoji);
+    let report = if training {
+        training::publish(client, config, &text).await
+    } else if in_place == Some(InPlaceApproval::Keep) {
+        // The mod room copy stands as the published one
+        if let Some(item) = &item {
+            history::record(
+                mod_event_id.clone(),
+                output_room_id.clone(),
+                item.sender.clone(),
+                &item.content.body,
+            );
+        }
+        let mut report = Report::default();
+        report.push(Destination::Room(output_room_id.clone()), Ok(()));
+        report
+    } else {
+        let thread_root = match config.category(emoji) {
+            Some(category) => thread::root(client, config, category).await?,
+            None => None,
+        };
+        publish(
+            client,
+            config,
+            output_room_id,
+            &text,
+            item.as_ref(),
+            content_warning.as_ref(),
+            thread_root.as_ref(),
+        )
+        .await
+    };
+    report.report(client, config, mod_event_id).await?;
+    // A training submission with nowhere to go is done with once it is approved
+    let nowhere = training && config.training_output_room_id.is_none();
+    if !report.reached_a_room() && !nowhere {
+        // Back in the queue, so approving it again retries, unless it waits on a preview which
+        // can be confirmed again instead
+        if item.as_ref().is_some_and(|item| item.preview.is_none()) {
+            pending::transition(mod_event_id, Transition::Cancel);
+        }
+        return Ok(());
+    }
+
+    let item = pending::transition(mod_event_id, Transition::Publish);
+    audit::record(
+        Some(moderator),
+        Action::Approved {
+            event_id: mod_event_id.clone(),
+            sender: item.as_ref().map(|item| item.sender.clone()),
+        },
+    );
+    if let Some(item) = item.as_ref().filter(|item| item.training) {
+        training::record(moderator, true);
+        decided::record(
+            mod_event_id.clone(),
+            item.clone(),
+            true,
+            config.decided_retention,
+        );
+        tracing::info!("{} approved the training submission {}", moderator, mod_event_id);
+        return queue::check(client, config).await;
+    }
+    hooks::approved(Decided {
+        mod_event_id: mod_event_id.clone(),
+        moderator: moderator.clone(),
+        sender: item.as_ref().map(|item| item.sender.clone()),
+    });
+    if in_place == Some(InPlaceApproval::Repost) {
+        redact_quietly(client, &config.mod_room_id, mod_event_id, "Published").await;
+    }
+    if let Some(item) = item {
+        stats::record_decision(&item);
+        let karma = karma::adjust(&item.sender, 1);
+        decided::record(
+            mod_event_id.clone(),
+            item.clone(),
+            true,
+            config.decided_retention,
+        );
+        tracing::info!(
+            "Approved {}, {} now has {} karma",
+            mod_event_id,
+            item.sender,
+            karma
+        );
+        // A redacted submission has nothing left to react to
+        if config.redact_source_on_publish {
+            redact_source(client, &item).await;
+        } else {
+            react_to_source(client, config, &item, &config.source_approved_emoji).await;
+        }
+        if let (Some(preview), Some(preview_room_id)) = (&item.preview, &config.preview_room_id) {
+            redact_quietly(client, preview_room_id, &preview.event_id, "Published").await;
+        }
+        queue::check(client, config).await?;
+    }
+    Ok(())
+}
+
+/// Fetch the sender, timestamp and text of a message we have no pending item for
+///
+/// This is `None` if the event isn't a message at all.
+async fn fetch_message(
+    client: &Client,
+    room_id: &RoomId,
+    event_id: &EventId,
+) -> Result<Option<(UserId, MilliSecondsSinceUnixEpoch, TextMessageEventContent)>, FourwarderError>
+{
+    // Fetch from the room the approval is in, so this keeps working if that's ever not the mod room
+    let room = rooms::joined(client, room_id).await?;
+
+    let raw = timeout::within(
+        "fetching an event",
+        config::get().fetch_timeout,
+        room.event(ruma::api::client::r0::room::get_room_event::Request::new(
+            room_id, event_id,
+        )),
+    )
+    .await
+    .map_err(FourwarderError::Matrix)?
+    .event;
+
+    let orig_event = match raw.deserialize() {
+        Ok(orig_event) => orig_event,
+        Err(e) => {
+            // An event ruma doesn't model yet can still be a plain text message underneath
+            let lenient = lenient_message(raw.json(), room_id);
+            if lenient.is_none() {
+                return Err(FourwarderError::Matrix(matrix_sdk::Error::SerdeJson(e)));
+            }
+            tracing::warn!(
+                "Approving {} from its raw JSON, it could not be read as an event: {}",
+                event_id,
+                e
+            );
+            return Ok(lenient);
+        }
+    };
+    if orig_event.room_id() != room_id {
+        return Err(FourwarderError::Logic(
+            "The event being approved is not in the room the approval is in",
+        ));
+    }
+
+    // This mess of destructuring assignment gets us to the body of the message being approved
+    match orig_event {
+        AnyRoomEvent::Message(AnyMessageEvent::RoomMessage(msg)) => match msg.content.msgtype {
+            MessageType::Text(text) => Ok(Some((msg.sender, msg.origin_server_ts, text))),
+            // Our replies, such as a `!preview`, are never published
+            MessageType::Notice(_) => Ok(None),
+            _ => Err(FourwarderError::Logic(
+                "We assumed that the message being approved was a text message",
+            )),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// The sender, timestamp and text of a text message in `room_id`, read from its JSON with only
+/// the fields [`fetch_message`] needs, for events ruma fails to deserialize
+///
+/// Anything else ruma doesn't model in the event is ignored. This is `None` if it isn't a text
+/// message, or is missing one of those fields.
+fn lenient_message(
+    raw: &RawValue,
+    room_id: &RoomId,
+) -> Option<(UserId, MilliSecondsSinceUnixEpoch, TextMessageEventContent)> {
+    #[derive(Deserialize)]
+    struct Event {
+        #[serde(rename = "type")]
+        event_type: String,
+        room_id: Option<RoomId>,
+        sender: UserId,
+        origin_server_ts: MilliSecondsSinceUnixEpoch,
+        content: Content,
+    }
+    #[derive(Deserialize)]
+    struct Content {
+        msgtype: String,
+        body: String,
+        format: Option<String>,
+        formatted_body: Option<String>,
+    }
+
+    let event: Event = serde_json::from_str(raw.get()).ok()?;
+    if event.event_type != "m.room.message"
+        || event.content.msgtype != "m.text"
+        || event.room_id.is_some_and(|id| &id != room_id)
+    {
+        return None;
+    }
+    let mut text = TextMessageEventContent::plain(event.content.body);
+    if event.content.format.as_deref() == Some("org.matrix.custom.html") {
+        text.formatted = event.content.formatted_body.map(FormattedBody::html);
+    }
+    Some((event.sender, event.origin_server_ts, text))
+}
+
+/// Redact a published submission in its input room
+///
+/// The bot may not have the power to, so failures are only logged.
+async fn redact_source(client: &Client, item: &PendingItem) {
+    if let Some(event_id) = &item.source_event_id {
+        redact_quietly(client, &item.source_room_id, event_id, "Published").await;
+    }
+}
+
+/// React to an approved or rejected submission in its input room with `emoji`, if
+/// `react_on_source_decision` is set
+///
+/// The bot may not have the power to, so failures are only logged.
+async fn react_to_source(client: &Client, config: &Config, item: &PendingItem, emoji: &str) {
+    let event_id = match &item.source_event_id {
+        Some(event_id) if config.react_on_source_decision => event_id,
+        _ => return,
+    };
+    let room_id = &item.source_room_id;
+    if !power::allowed(room_id, Capability::React) {
+        tracing::debug!("Not reacting to {}, we can't react in {}", event_id, room_id);
+        return;
+    }
+    let content = serde_json::json!({
+        "m.relates_to": {
+            "rel_type": "m.annotation",
+            "event_id": event_id,
+            "key": emoji,
+        }
+    });
+    if let Err(e) = send::raw(client, room_id, "m.reaction", content).await {
+        tracing::warn!("Could not react to {} in {}: {}", event_id, room_id, e);
+    }
+}
+
+/// Redact an event, logging rather than returning failures
+///
+/// Rooms where we lack the power to redact are skipped without trying.
+async fn redact_quietly(client: &Client, room_id: &RoomId, event_id: &EventId, reason: &str) {
+    if !power::allowed(room_id, Capability::Redact) {
+        tracing::debug!("Not redacting {}, we can't redact in {}", event_id, room_id);
+        return;
+    }
+    if let Err(e) = send::redact(client, room_id, event_id, Some(reason)).await {
+        tracing::warn!("Could not redact {} in {}: {}", event_id, room_id, e);
+    }
+}
+
+/// What a sync filter lets through
+struct SyncFilter {
+    rooms: Vec<RoomId>,
+    /// `None` lets every type through, for `mirror_unknown_events`
+    types: Option<Vec<String>>,
+}
+
+/// The event types we handle, everything else is left out of syncs
+const SYNC_EVENT_TYPES: &[&str] = &[
+    "m.room.message",
+    "m.reaction",
+    "m.room.redaction",
+    "m.room.member",
+    "m.room.power_levels",
+];
+
+/// Only sync the rooms we use, and only the events in them we handle
+fn sync_filter(config: &Config) -> Option<SyncFilter> {
+    if !config.sync_filter {
+        return None;
+    }
+
+    let mut rooms: Vec<RoomId> = config.rooms().cloned().collect();
+    rooms.sort();
+    rooms.dedup();
+    Some(SyncFilter {
+        rooms,
+        types: (!config.mirror_unknown_events)
+            .then(|| SYNC_EVENT_TYPES.iter().map(|t| t.to_string()).collect()),
+    })
+}
+
+fn with_filter<'a>(settings: SyncSettings<'a>, filter: &'a Option<SyncFilter>) -> SyncSettings<'a> {
+    let filter = match filter {
+        Some(filter) => filter,
+        None => return settings,
+    };
+
+    let mut timeline = RoomEventFilter::empty();
+    timeline.types = filter.types.as_deref();
+    let mut state = RoomEventFilter::empty();
+    state.lazy_load_options = LazyLoadOptions::Enabled {
+        include_redundant_members: false,
+    };
+    let mut room = RoomFilter::empty();
+    room.rooms = Some(&filter.rooms);
+    room.timeline = timeline;
+    room.state = state;
+    room.ephemeral = RoomEventFilter::ignore_all();
+    room.account_data = RoomEventFilter::ignore_all();
+    let mut definition = FilterDefinition::empty();
+    definition.room = room;
+    definition.presence = Filter::ignore_all();
+
+    settings.filter(definition.into())
+}
+
+/// Log how much a sync brought in, to see what difference `sync_filter` makes
+fn log_sync_volume(response: &SyncResponse) {
+    let events: usize = response
+        .rooms
+        .join
+        .values()
+        .map(|room| room.timeline.events.len() + room.state.events.len())
+        .sum();
+    tracing::debug!(
+        "Sync returned {} events in {} rooms",
+        events,
+        response.rooms.join.len()
+    );
+}
+
+/// Keep the instructions pinned in the mod room up to date, logging rather than returning
+/// failures, as the bot works without them
+async fn refresh_instructions(client: &Client, config: &Config) {
+    if let Err(e) = instructions::refresh(client, config).await {
+        tracing::warn!("Could not pin the instructions in the mod room: {}", e);
+    }
+}
+
+/// Release holds whose reactions were removed in the mod room, and stop counting reactions
+/// removed in an input room
+///
+/// The SDK has no handler for redactions, so they are picked out of each sync by hand.
+fn handle_redactions(response: &SyncResponse, config: &Config) {
+    for (room_id, room) in &response.rooms.join {
+        let in_mod_room = room_id == &config.mod_room_id;
+        let counted = config.show_source_reactions || config.mirror_source_reactions;
+        let in_input_room = counted && config.input_room(room_id).is_some();
+        if !in_mod_room && !in_input_room {
+            continue;
+        }
+
+        for event in &room.timeline.events {
+            if let Ok(AnySyncRoomEvent::Message(AnySyncMessageEvent::RoomRedaction(redaction))) =
+                event.event.deserialize()
+            {
+                if in_input_room
+                    && config.show_source_reactions
+                    && pending::uncount_source_reaction(room_id, &redaction.redacts)
+                {
+                    tracing::debug!("Stopped counting {} in {}", redaction.redacts, room_id);
+                }
+                if in_input_room
+                    && config.mirror_source_reactions
+                    && tally::uncount(room_id, &redaction.redacts)
+                {
+                    tracing::debug!("Stopped tallying {} in {}", redaction.redacts, room_id);
+                }
+                if in_mod_room {
+                    release_hold(&redaction.sender, &redaction.redacts);
+                }
+            }
+        }
+    }
+}
+
+/// Release the hold or claim made with the reaction `redacts`, which `sender` removed
+fn release_hold(sender: &UserId, redacts: &EventId) {
+    if let Some(mod_event_id) = pending::release(redacts) {
+        audit::record(
+            Some(sender),
+            Action::Released {
+                event_id: mod_event_id.clone(),
+            },
+        );
+        tracing::info!("{} released {}", sender, mod_event_id);
+    }
+    if let Some(mod_event_id) = pending::unclaim(redacts) {
+        tracing::info!("{} gave up their claim on {}", sender, mod_event_id);
+    }
+}
+
+/// Log into the homesever, sync the client and register event handlers
+///
+/// This function only returns if our access token keeps being rejected, as it syncs forever.
+async fn login_and_sync(homeserver_url: &str) -> Result<(), FourwarderError> {
+    let homeserver_url = Url::parse(homeserver_url)
+        .map_err(|e| FourwarderError::Matrix(matrix_sdk::Error::Url(e)))?;
+    let mut client = Client::new(homeserver_url.clone())?;
+
+    auth::login(&client).await?;
+
+    let response = initial_sync(&client).await?;
+    log_sync_volume(&response);
+    health::synced(true, true);
+
+    let config = config::get();
+    handle_redactions(&response, &config);
+    rooms::accept_invites(&client, &config).await?;
+
+    client.register_event_handler(on_room_message).await;
+
+    client.register_event_handler(on_room_react).await;
+
+    client.register_event_handler(on_power_levels).await;
+
+    client.register_event_handler(on_room_member).await;
+
+    power::check(&client, &config).await?;
+    // In dry runs nothing is sent, so we couldn't take the lead, and act on everything instead
+    let failover_after = config.failover_after.filter(|_| !config.dry_run);
+    if failover_after.is_some() {
+        leader::stand_by();
+    }
+    let mut leader = failover_after.map(|after| leader::spawn(client.clone(), after));
+
+    // Until we lead, these wait for `take_over`
+    if leader::is_active() {
+        output_scan::scan(&client, &config, &response.next_batch).await?;
+        // Pick up the queue left behind by the last run
+        queue::check(&client, &config).await?;
+        refresh_instructions(&client, &config).await;
+    }
+    let mut escalation = escalation::spawn(client.clone());
+    let mut schedule = schedule::spawn(client.clone());
+    let mut index = index::spawn(client.clone());
+    let mut tally = tally::spawn(client.clone());
+    delegation::spawn();
+    cleanup::spawn();
+
+    let mut sync_token = match client.sync_token().await {
+        Some(s) => s,
+        None => {
+            return Err(FourwarderError::Logic(
+                "Could not get sync token... if we don't have it now, what's going on?",
+            ))
+        }
+    };
+
+    // Sync by hand rather than with `client.sync`, so we notice when our token is rejected
+    let mut connection_failures = 0;
+    // A rebuilt client starts without any room state, so it needs all of it on its first sync
+    let mut full_state = false;
+    loop {
+        let generation = auth::generation();
+        let config = config::get();
+        let mut rebuild = false;
+        match timeout::within(
+            "syncing",
+            config.sync_timeout,
+            client.sync_once(with_filter(
+                SyncSettings::default()
+                    .token(sync_token.as_str())
+                    .full_state(full_state),
+                &sync_filter(&config),
+            )),
+        )
+        .await
+        {
+            Ok(response) => {
+                connection_failures = 0;
+                full_state = false;
+                log_sync_volume(&response);
+                if leader::is_active() {
+                    handle_redactions(&response, &config::get());
+                    mirror::relay(&client, &config::get(), &response).await?;
+                    refresh_instructions(&client, &config::get()).await;
+                }
+                auth::succeeded();
+                health::synced(false, response.next_batch != sync_token);
+                sync_token = response.next_batch;
+            }
+            Err(e) => match error::classify(&e) {
+                ErrorClass::Auth => auth::reauthenticate(&client, generation).await?,
+                ErrorClass::Transport => {
+                    connection_failures += 1;
+                    tracing::warn!("Sync could not reach the homeserver, retrying: {}", e);
+                    if connection_failures >= REBUILD_AFTER_FAILURES {
+                        tracing::warn!(
+                        "{} syncs in a row could not reach the homeserver, rebuilding the client",
+                        connection_failures
+                    );
+                        rebuild = true;
+                        connection_failures = 0;
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                ErrorClass::RateLimited(retry_after) => {
+                    tracing::warn!("Sync was rate limited, retrying: {}", e);
+                    tokio::time::sleep(retry_after).await;
+                }
+                // The loop can't stop syncing, so it keeps trying in case the cause goes away
+                ErrorClass::Permanent => {
+                    tracing::warn!("Sync failed, retrying: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            },
+        }
+        // Syncs can keep succeeding with nothing new when the homeserver has stalled on its side
+        if config.sync_stall_timeout.is_some_and(health::stalled) {
+            rebuild = true;
+        }
+
+        if rebuild {
+            match rebuild_client(&homeserver_url, &client).await {
+                Ok(rebuilt) => {
+                    client = rebuilt;
+                    full_state = true;
+                    escalation.abort();
+                    escalation = escalation::spawn(client.clone());
+                    schedule.abort();
+                    schedule = schedule::spawn(client.clone());
+                    index.abort();
+                    index = index::spawn(client.clone());
+                    tally.abort();
+                    tally = tally::spawn(client.clone());
+                    if let Some(task) = leader.take() {
+                        task.abort();
+                        leader = failover_after.map(|after| leader::spawn(client.clone(), after));
+                    }
+                    tracing::info!("Rebuilt the client");
+                }
+                Err(e) => tracing::warn!("Could not rebuild the client: {}", e),
+            }
+        }
+    }
+}
+
+/// Make the first sync, retrying with exponential backoff up to `initial_sync_retries` times
+///
+/// If our access token was rejected, we log in again first, and if we were rate limited we wait
+/// at least as long as the homeserver asks. An error which trying again won't fix is returned
+/// at once. A first sync which brings in no rooms at all still succeeded, but likely means the
+/// account isn't in any of them yet.
+async fn initial_sync(client: &Client) -> Result<SyncResponse, FourwarderError> {
+    let retries = config::get().initial_sync_retries;
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 0;
+
+    loop {
+        let generation = auth::generation();
+        let config = config::get();
+        match timeout::within(
+            "syncing",
+            config.sync_timeout,
+            client.sync_once(with_filter(SyncSettings::default(), &sync_filter(&config))),
+        )
+        .await
+        {
+            Ok(response) => {
+                auth::succeeded();
+                if response.rooms.join.is_empty() && response.rooms.invite.is_empty() {
+                    tracing::warn!("The first sync found us in no rooms, and invited to none");
+                }
+                return Ok(response);
+            }
+            Err(e) => {
+                let class = error::classify(&e);
+                match class {
+                    ErrorClass::Auth => auth::reauthenticate(client, generation).await?,
+                    ErrorClass::Permanent => {
+                        tracing::error!("First sync failed, and trying again won't help: {}", e);
+                        return Err(e.into());
+                    }
+                    _ if attempt < retries => {
+                        if let ErrorClass::RateLimited(retry_after) = class {
+                            delay = delay.max(retry_after);
+                        }
+                        tracing::warn!(
+                            "First sync failed, retrying in {}s: {}",
+                            delay.as_secs(),
+                            e
+                        );
+                        tokio::time::sleep(delay).await;
+                        delay *= 2;
+                        attempt += 1;
+                    }
+                    _ => {
+                        tracing::error!("First sync failed {} times, giving up", attempt + 1);
+                        return Err(e.into());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Build a new client to replace `old`, logged in as the same device and with our handlers
+///
+/// A new client resolves the homeserver again and opens new connections, which gets us past
+/// a changed address or certificate that the old client's connections are stuck on.
+async fn rebuild_client(homeserver_url: &Url, old: &Client) -> Result<Client, FourwarderError> {
+    let client = Client::new(homeserver_url.clone())?;
+    auth::login_replacing(&client, old).await?;
+    client.register_event_handler(on_room_message).await;
+    client.register_event_handler(on_room_react).await;
+    client.register_event_handler(on_power_levels).await;
+    client.register_event_handler(on_room_member).await;
+    Ok(client)
+}
+
+/// Reload the config file every time we receive a SIGHUP
+///
+/// A config that fails to load is logged and ignored, leaving the previous one in place.
+#[cfg(unix)]
+fn reload_on_sighup() -> Result<(), FourwarderError> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            tracing::info!("Received SIGHUP, reloading {}", CONFIG_LOCATION);
+            if let Err(e) = config::reload(CONFIG_LOCATION) {
+                tracing::error!("Could not reload config, keeping the old one: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// The bot's forwarding and moderation, for a larger bot which does its own logging in and
+/// syncing
+///
+/// The config and state are kept per process, as they are for the standalone bot, so there can
+/// only be one `Forwarder` at a time.
+pub struct Forwarder {
+    client: Client,
+}
+
+impl Forwarder {
+    /// Load the config at `config_path` and the state file, to work through `client`
+    ///
+    /// `client` should already be logged in, and in every room the config names.
+    pub fn new(client: Client, config_path: &str) -> Result<Self, FourwarderError> {
+        config::load_config(config_path)?;
+        config::get().prepare_data_dir()?;
+        state::load()?;
+        Ok(Forwarder { client })
+    }
+
+    /// Call `hooks` as things happen, which can only be set once
+    pub fn set_hooks(&self, hooks: impl Hooks + 'static) -> Result<(), FourwarderError> {
+        if hooks::set(Box::new(hooks)) {
+            Ok(())
+        } else {
+            Err(FourwarderError::Logic("Hooks were set twice"))
+        }
+    }
+
+    /// Check our power in every room and pick up the queue left behind by the last run, as the
+    /// standalone bot does once it has logged in
+    pub async fn start(&self) -> Result<(), FourwarderError> {
+        let config = config::get();
+        power::check(&self.client, &config).await?;
+        queue::check(&self.client, &config).await
+    }
+
+    /// Handle a message in any room, as the standalone bot's message handler does
+    pub async fn handle_message(
+        &self,
+        event: SyncMessageEvent<MessageEventContent>,
+        room: Room,
+        raw: RawEvent,
+    ) -> Result<(), FourwarderError> {
+        on_room_message(event, room, self.client.clone(), raw).await
+    }
+
+    /// Handle a reaction in any room, as the standalone bot's reaction handler does
+    pub async fn handle_reaction(
+        &self,
+        event: SyncMessageEvent<ReactionEventContent>,
+        room: Room,
+    ) -> Result<(), FourwarderError> {
+        on_room_react(event, room, self.client.clone()).await
+    }
+
+    /// Do what the standalone bot does after each sync, which releases holds and mirrors
+    /// `mirror_unknown_events`
+    pub async fn handle_sync(&self, response: &SyncResponse) -> Result<(), FourwarderError> {
+        let config = config::get();
+        handle_redactions(response, &config);
+        refresh_instructions(&self.client, &config).await;
+        mirror::relay(&self.client, &config, response).await
+    }
+}
+
+/// Run the bot standalone, with the config in `4warder.toml`, until it fails
+pub async fn run() -> Result<(), FourwarderError> {
+    health::started();
+
+    space::resolve(CONFIG_LOCATION).await?;
+    config::load_config(CONFIG_LOCATION)?;
+    config::get().prepare_data_dir()?;
+    state::load()?;
+    if pause::is_paused() {
+        tracing::warn!("Forwarding is paused, nothing will be forwarded until `!resume`");
+    }
+    #[cfg(unix)]
+    reload_on_sighup()?;
+
+    let config = config::get();
+    tracing::info!(
+        "Launching 4warder_bot on {} as {}",
+        config.homeserver,
+        config.username
+    );
+    config.log_effective();
+
+    if let Some(address) = config.metrics_address {
+        metrics::serve(address).await?;
+    }
+
+    login_and_sync(&config.homeserver).await?;
+
+    Ok(())
+}
+
+/// Check the config file at `path` without connecting, printing what is wrong with it
+pub fn check_config(path: &str) -> Result<(), FourwarderError> {
+    check::run(path)
+}
+
+/// Upgrade the config file at `path` to the current layout
+pub fn migrate_config(path: &str) -> Result<(), FourwarderError> {
+    migrate::run(path)
+}
+
+/// Write the bot's state to a bundle at `path`, to move it to another host
+pub fn export_state(path: &str) -> Result<(), FourwarderError> {
+    bundle::export(path)
+}
+
+/// Replace the bot's state with the bundle at `path`, which `export_state` wrote
+pub fn import_state(path: &str) -> Result<(), FourwarderError> {
+    bundle::import(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, room};
+
+    /// A config which moderates and publishes in `!mod:example.org`, plus the TOML in `extra`
+    fn single_room(extra: &str) -> Result<Config, FourwarderError> {
+        let source = format!(
+            r#"
+homeserver = "https://example.org"
+username = "bot"
+password = "hunter2"
+mod_room_id = "!mod:example.org"
+output_room_id = "!mod:example.org"
+input_room_id = "!input:example.org"
+{}
+"#,
+            extra
+        );
+        config::validate(&toml::from_str(&source).unwrap())
+    }
+
+    #[test]
+    fn single_room_approval_reposts_in_place() {
+        let _test = testing::isolate();
+        let config = single_room("").unwrap();
+        let mod_event_id = testing::queue("$a:example.org", "@sub:example.org", "hello");
+        let item = pending::get(&mod_event_id);
+
+        let output_room_id = approved_output_room(&config, item.as_ref(), APPROVE_EMOJI);
+        assert_eq!(output_room_id, &config.mod_room_id);
+        assert_eq!(
+            in_place_approval(&config, item.as_ref(), output_room_id),
+            Some(InPlaceApproval::Repost)
+        );
+    }
+
+    #[test]
+    fn single_room_approval_can_keep_the_copy() {
+        let _test = testing::isolate();
+        let config = single_room(r#"in_place_approval = "keep""#).unwrap();
+        let mod_event_id = testing::queue("$a:example.org", "@sub:example.org", "hello");
+        let item = pending::get(&mod_event_id);
+
+        let output_room_id = approved_output_room(&config, item.as_ref(), APPROVE_EMOJI);
+        assert_eq!(
+            in_place_approval(&config, item.as_ref(), output_room_id),
+            Some(InPlaceApproval::Keep)
+        );
+    }
+
+    #[test]
+    fn other_output_rooms_are_not_in_place() {
+        let _test = testing::isolate();
+        let config = single_room(
+            r#"
+[[categories]]
+name = "news"
+approval_emoji = "📰"
+output_room_id = "!news:example.org"
+"#,
+        )
+        .unwrap();
+        let mod_event_id = testing::queue("$a:example.org", "@sub:example.org", "hello");
+        let item = pending::get(&mod_event_id);
+
+        let output_room_id = approved_output_room(&config, item.as_ref(), "📰");
+        assert_eq!(output_room_id, &room("!news:example.org"));
+        assert_eq!(in_place_approval(&config, item.as_ref(), output_room_id), None);
+
+        let separate = testing::config("");
+        let output_room_id = approved_output_room(&separate, item.as_ref(), APPROVE_EMOJI);
+        assert_eq!(output_room_id, &room("!output:example.org"));
+        assert_eq!(in_place_approval(&separate, item.as_ref(), output_room_id), None);
+    }
+
+    #[test]
+    fn single_room_needs_a_preview_room_of_its_own() {
+        assert!(single_room(r#"preview_room_id = "!mod:example.org""#).is_err());
+        assert!(single_room(r#"preview_room_id = "!preview:example.org""#).is_ok());
+    }
+
+    /// A text message with a reply field ruma can't read, which a newer client might send
+    const UNKNOWN_TEXT: &str = r#"{
+        "type": "m.room.message",
+        "event_id": "$new:example.org",
+        "room_id": "!mod:example.org",
+        "sender": "@sub:example.org",
+        "origin_server_ts": 1700000000000,
+        "content": {
+            "msgtype": "m.text",
+            "body": "hello",
+            "m.relates_to": { "m.in_reply_to": "$old:example.org" }
+        }
+    }"#;
+
+    #[test]
+    fn unknown_text_events_are_read_leniently() {
+        let raw: Box<RawValue> = serde_json::from_str(UNKNOWN_TEXT).unwrap();
+        assert!(serde_json::from_str::<AnyRoomEvent>(raw.get()).is_err());
+
+        let (sender, origin_server_ts, text) =
+            lenient_message(&raw, &room("!mod:example.org")).unwrap();
+        assert_eq!(sender, testing::user("@sub:example.org"));
+        assert_eq!(u64::from(origin_server_ts.get()), testing::START_MILLIS);
+        assert_eq!(text.body, "hello");
+        assert!(text.formatted.is_none());
+    }
+
+    #[test]
+    fn only_text_from_the_room_is_read_leniently() {
+        let lenient = |json: String| {
+            let raw: Box<RawValue> = serde_json::from_str(&json).unwrap();
+            lenient_message(&raw, &room("!mod:example.org"))
+        };
+
+        assert!(lenient(UNKNOWN_TEXT.replace("!mod:example.org", "!other:example.org")).is_none());
+        assert!(lenient(UNKNOWN_TEXT.replace("m.text", "m.image")).is_none());
+        assert!(lenient(UNKNOWN_TEXT.replace("\"body\": \"hello\",", "")).is_none());
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pending::PendingItem;
+
+/// Where a submission is on its way through moderation
+///
+/// The moves between them are the table in [`Status::after`], and every change goes through
+/// [`apply_transition`], which refuses any other. That is what keeps moderators racing each
+/// other from publishing a rejected submission, or publishing one twice.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Status {
+    /// Waiting in the mod room queue
+    #[default]
+    Pending,
+    /// Waiting in the queue, but held for discussion by a `hold_emoji`
+    Held,
+    /// Approved, and being published or waiting to be confirmed in the preview room
+    Approved,
+    Rejected,
+    /// Dropped without a decision, because its submitter was erased
+    Expired,
+    Published,
+}
+
+/// Something which happens to a submission
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transition {
+    Hold,
+    /// The last hold on it was removed
+    Release,
+    /// Enough moderators approved it
+    Approve,
+    /// Its approval fell through, as publishing failed or the preview was cancelled
+    Cancel,
+    Publish,
+    /// Enough moderators rejected it
+    Reject,
+    Expire,
+    /// `!requeue` put it back in the queue
+    Requeue,
+}
+
+/// A transition which is not allowed from the status a submission is in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Illegal {
+    pub from: Status,
+    pub transition: Transition,
+}
+
+impl fmt::Display for Illegal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "can't {:?} a submission which is {:?}",
+            self.transition, self.from
+        )
+    }
+}
+
+impl Status {
+    /// The status `transition` leads to from this one, or `None` if it isn't allowed
+    ///
+    /// | From                    | Transition | To        |
+    /// |-------------------------|------------|-----------|
+    /// | Pending, Held           | Hold       | Held      |
+    /// | Held                    | Release    | Pending   |
+    /// | Pending, Held           | Approve    | Approved  |
+    /// | Approved                | Cancel     | Pending   |
+    /// | Approved                | Publish    | Published |
+    /// | Pending, Held           | Reject     | Rejected  |
+    /// | Pending, Held, Approved | Expire     | Expired   |
+    /// | Rejected                | Requeue    | Pending   |
+    pub fn after(self, transition: Transition) -> Option<Status> {
+        use Status::*;
+        use Transition::*;
+
+        match (self, transition) {
+            (Pending | Held, Hold) => Some(Held),
+            (Held, Release) => Some(Pending),
+            (Pending | Held, Approve) => Some(Approved),
+            (Approved, Cancel) => Some(Pending),
+            (Approved, Publish) => Some(Published),
+            // Once approved, it has to be cancelled in the preview room before it can be rejected
+            (Pending | Held, Reject) => Some(Rejected),
+            (Pending | Held | Approved, Expire) => Some(Expired),
+            (Rejected, Requeue) => Some(Pending),
+            _ => None,
+        }
+    }
+
+    /// Whether it has left the queue for good, unless it is requeued
+    pub fn is_final(self) -> bool {
+        matches!(self, Status::Rejected | Status::Expired | Status::Published)
+    }
+}
+
+/// Move `item` on by `transition`, returning its new status, or leave it as it was if that move
+/// isn't allowed
+pub fn apply_transition(item: &mut PendingItem, transition: Transition) -> Result<Status, Illegal> {
+    let status = item.status.after(transition).ok_or(Illegal {
+        from: item.status,
+        transition,
+    })?;
+    item.status = status;
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pending, testing};
+
+    const STATUSES: &[Status] = &[
+        Status::Pending,
+        Status::Held,
+        Status::Approved,
+        Status::Rejected,
+        Status::Expired,
+        Status::Published,
+    ];
+
+    const TRANSITIONS: &[Transition] = &[
+        Transition::Hold,
+        Transition::Release,
+        Transition::Approve,
+        Transition::Cancel,
+        Transition::Publish,
+        Transition::Reject,
+        Transition::Expire,
+        Transition::Requeue,
+    ];
+
+    /// The table in [`Status::after`], row by row
+    const LEGAL: &[(Status, Transition, Status)] = &[
+        (Status::Pending, Transition::Hold, Status::Held),
+        (Status::Held, Transition::Hold, Status::Held),
+        (Status::Held, Transition::Release, Status::Pending),
+        (Status::Pending, Transition::Approve, Status::Approved),
+        (Status::Held, Transition::Approve, Status::Approved),
+        (Status::Approved, Transition::Cancel, Status::Pending),
+        (Status::Approved, Transition::Publish, Status::Published),
+        (Status::Pending, Transition::Reject, Status::Rejected),
+        (Status::Held, Transition::Reject, Status::Rejected),
+        (Status::Pending, Transition::Expire, Status::Expired),
+        (Status::Held, Transition::Expire, Status::Expired),
+        (Status::Approved, Transition::Expire, Status::Expired),
+        (Status::Rejected, Transition::Requeue, Status::Pending),
+    ];
+
+    #[test]
+    fn transition_table() {
+        for &from in STATUSES {
+            for &transition in TRANSITIONS {
+                let expected = LEGAL
+                    .iter()
+                    .find(|(legal_from, legal, _)| *legal_from == from && *legal == transition)
+                    .map(|(_, _, to)| *to);
+                assert_eq!(
+                    from.after(transition),
+                    expected,
+                    "{:?} after {:?}",
+                    from,
+                    transition
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn only_a_requeue_leaves_a_final_status() {
+        for &status in STATUSES {
+            let requeued = status.after(Transition::Requeue).is_some();
+            let stuck = TRANSITIONS
+                .iter()
+                .all(|transition| status.after(*transition).is_none());
+            assert_eq!(status.is_final(), requeued || stuck, "{:?}", status);
+        }
+    }
+
+    #[test]
+    fn illegal_moves_leave_the_item_alone() {
+        let _test = testing::isolate();
+        let mod_event_id = testing::queue("$a:example.org", "@sub:example.org", "hello");
+        let mut item = pending::get(&mod_event_id).unwrap();
+
+        assert_eq!(apply_transition(&mut item, Transition::Reject), Ok(Status::Rejected));
+        assert_eq!(
+            apply_transition(&mut item, Transition::Publish),
+            Err(Illegal {
+                from: Status::Rejected,
+                transition: Transition::Publish,
+            })
+        );
+        assert_eq!(item.status, Status::Rejected);
+        assert_eq!(apply_transition(&mut item, Transition::Requeue), Ok(Status::Pending));
+    }
+}
@@ -0,0 +1,32 @@
+use serde::Deserialize;
+use serde_json::{value::RawValue, Value};
+
+/// The field in the content of everything we forward which names the instance that forwarded
+/// it
+///
+/// Clients ignore fields they don't know, so it is invisible to readers, but it survives being
+/// copied by other bots which pass the content along as it is.
+pub const FIELD: &str = "org.4warder.instance";
+
+/// Mark `content` as forwarded by `instance_id`
+pub fn mark(content: &mut Value, instance_id: &str) {
+    if let Value::Object(content) = content {
+        content.insert(FIELD.to_owned(), Value::String(instance_id.to_owned()));
+    }
+}
+
+/// The instance which forwarded an event with `content`, if any did
+pub fn instance(content: &Value) -> Option<&str> {
+    content.get(FIELD)?.as_str()
+}
+
+/// The instance which forwarded `event`, given as the raw JSON of the whole event
+pub fn instance_of_event(event: &RawValue) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Event {
+        content: Value,
+    }
+
+    let event: Event = serde_json::from_str(event.get()).ok()?;
+    instance(&event.content).map(str::to_owned)
+}
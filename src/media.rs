@@ -0,0 +1,128 @@
+use matrix_sdk::{
+    ruma::{
+        api::client::r0::media::{create_content, get_content},
+        MxcUri,
+    },
+    Client,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{self, Config},
+    error::FourwarderError,
+    timeout,
+};
+
+/// Where media can be referenced in the content of an event
+///
+/// Encrypted media keeps its url in a `file` object, next to the key it was encrypted with.
+const LOCATIONS: &[&[&str]] = &[
+    &["url"],
+    &["info", "thumbnail_url"],
+    &["file", "url"],
+    &["info", "thumbnail_file", "url"],
+];
+
+/// What forwarded media points at
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaMode {
+    /// The original file, on the server it was uploaded to
+    #[default]
+    Reference,
+    /// A copy we upload to our own homeserver, so it outlives the original
+    Reupload,
+}
+
+/// Upload our own copy of every file `content` refers to, and point it at those instead
+///
+/// This only does anything in `reupload` mode. Encrypted files are copied as they are, still
+/// encrypted, so the key in the content keeps working for the copy. A file which can't be copied
+/// is logged and left pointing at the original.
+pub async fn rehost(client: &Client, config: &Config, content: &mut Value) {
+    if config.media_mode != MediaMode::Reupload || config.dry_run {
+        return;
+    }
+
+    for location in LOCATIONS {
+        let url = match location
+            .iter()
+            .try_fold(&mut *content, |value, key| value.get_mut(*key))
+        {
+            Some(Value::String(url)) => url,
+            _ => continue,
+        };
+        match reupload(client, &MxcUri::from(url.as_str())).await {
+            Ok(copy) => {
+                tracing::debug!("Uploaded {} again as {}", url, copy);
+                *url = copy.to_string();
+            }
+            Err(e) => tracing::warn!(
+                "Could not upload {} again, referencing it instead: {}",
+                url,
+                e
+            ),
+        }
+    }
+}
+
+/// The mxc urls of every file `content` refers to
+pub fn urls(content: &Value) -> Vec<&str> {
+    LOCATIONS
+        .iter()
+        .filter_map(|location| {
+            location
+                .iter()
+                .try_fold(content, |value, key| value.get(*key))?
+                .as_str()
+        })
+        .collect()
+}
+
+/// The mxc url of the file itself, leaving out its thumbnail
+pub fn file_url(content: &Value) -> Option<&str> {
+    content
+        .get("url")
+        .or_else(|| content.get("file")?.get("url"))?
+        .as_str()
+}
+
+/// Download the file at `url`, as it is stored, which for encrypted media is still encrypted
+pub async fn download(client: &Client, url: &MxcUri) -> Result<get_content::Response, FourwarderError> {
+    let request = get_content::Request::from_url(url)
+        .map_err(|_| FourwarderError::Logic("Media was given with an invalid mxc url"))?;
+    Ok(timeout::within(
+        "downloading a file",
+        config::get().fetch_timeout,
+        client.send(request, None),
+    )
+    .await?)
+}
+
+/// The SHA-256 of the file at `url` as lowercase hex, hashed as it is stored like [`download`]
+pub async fn sha256(client: &Client, url: &str) -> Result<String, FourwarderError> {
+    let file = download(client, &MxcUri::from(url)).await?.file;
+    Ok(hex_sha256(&file))
+}
+
+/// The SHA-256 of `bytes` as lowercase hex
+pub fn hex_sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+async fn reupload(client: &Client, url: &MxcUri) -> Result<MxcUri, FourwarderError> {
+    let original = download(client, url).await?;
+
+    let mut request = create_content::Request::new(&original.file);
+    request.content_type = original.content_type.as_deref();
+    let copy = client
+        .send(request, None)
+        .await
+        .map_err(matrix_sdk::Error::Http)?;
+    Ok(copy.content_uri)
+}
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use matrix_sdk::{
+    ruma::{
+        events::{
+            room::member::{MemberEventContent, MembershipState},
+            AnySyncStateEvent, EventType, SyncStateEvent,
+        },
+        RoomId, UserId,
+    },
+    Client,
+};
+
+use crate::error::FourwarderError;
+
+/// When `user_id` became a member of `room_id` as they are now, in milliseconds since the Unix
+/// epoch, or `None` if we don't know of them joining it
+///
+/// Account creation times aren't exposed to bots, so this is the time of their latest member
+/// event in the room. A display name or avatar change is one too, so it counts as joining again.
+pub async fn joined_at(
+    client: &Client,
+    room_id: &RoomId,
+    user_id: &UserId,
+) -> Result<Option<u64>, FourwarderError> {
+    let room = match client.get_joined_room(room_id) {
+        Some(room) => room,
+        None => return Ok(None),
+    };
+    let event = room
+        .get_state_event(EventType::RoomMember, user_id.as_str())
+        .await?;
+    Ok(match event.map(|event| event.deserialize()) {
+        Some(Ok(AnySyncStateEvent::RoomMember(event))) => join_time(&event),
+        _ => None,
+    })
+}
+
+/// When `event` made its user a member, if it leaves them joined
+fn join_time(event: &SyncStateEvent<MemberEventContent>) -> Option<u64> {
+    (event.content.membership == MembershipState::Join).then(|| event.origin_server_ts.get().into())
+}
+
+/// How much longer `sender` had to be in `room_id` before a submission they sent at
+/// `submitted_at` would have been taken, or `None` if they were in it for `min_age` already
+///
+/// A sender whose join we can't find is let through, as the bot may not have their member
+/// event yet.
+pub async fn too_new(
+    client: &Client,
+    room_id: &RoomId,
+    sender: &UserId,
+    submitted_at: u64,
+    min_age: Duration,
+) -> Result<Option<Duration>, FourwarderError> {
+    let joined_at = match joined_at(client, room_id, sender).await? {
+        Some(joined_at) => joined_at,
+        None => {
+            tracing::debug!("Found no join of {} to {}", sender, room_id);
+            return Ok(None);
+        }
+    };
+    Ok(remaining(joined_at, submitted_at, min_age))
+}
+
+/// How much of `min_age` was left when a member who joined at `joined_at` submitted at
+/// `submitted_at`
+fn remaining(joined_at: u64, submitted_at: u64, min_age: Duration) -> Option<Duration> {
+    let age = Duration::from_millis(submitted_at.saturating_sub(joined_at));
+    min_age.checked_sub(age).filter(|left| !left.is_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, user, START_MILLIS};
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn member_event(membership: &str, at: u64) -> SyncStateEvent<MemberEventContent> {
+        serde_json::from_value(serde_json::json!({
+            "type": "m.room.member",
+            "event_id": "$join:example.org",
+            "sender": "@sub:example.org",
+            "state_key": "@sub:example.org",
+            "origin_server_ts": at,
+            "content": { "membership": membership },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn a_fresh_join_is_too_new() {
+        let joined_at = join_time(&member_event("join", START_MILLIS)).unwrap();
+        let submitted_at = START_MILLIS + 60 * 60 * 1000;
+
+        assert_eq!(
+            remaining(joined_at, submitted_at, DAY),
+            Some(DAY - Duration::from_secs(60 * 60))
+        );
+    }
+
+    #[test]
+    fn an_old_join_is_accepted() {
+        let joined_at = join_time(&member_event("join", START_MILLIS)).unwrap();
+
+        assert_eq!(remaining(joined_at, START_MILLIS + 2 * DAY.as_millis() as u64, DAY), None);
+        assert_eq!(remaining(joined_at, START_MILLIS + DAY.as_millis() as u64, DAY), None);
+    }
+
+    #[test]
+    fn only_joins_count() {
+        assert_eq!(join_time(&member_event("leave", START_MILLIS)), None);
+        assert_eq!(join_time(&member_event("invite", START_MILLIS)), None);
+    }
+
+    #[test]
+    fn trusted_senders_skip_the_check() {
+        let _test = testing::isolate();
+        let config = testing::config(
+            r#"
+min_membership_age_secs = 86400
+priority_senders = ["@vip:example.org"]
+moderators = ["@mod:example.org"]
+"#,
+        );
+
+        assert!(config.is_trusted(&user("@vip:example.org")));
+        assert!(config.is_trusted(&user("@mod:example.org")));
+        assert!(!config.is_trusted(&user("@sub:example.org")));
+    }
+}
@@ -0,0 +1,523 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::error::FourwarderError;
+
+/// Every message the bot sends, in English, which is also used for anything a translation leaves out
+///
+/// `{name}` is replaced with the argument of that name.
+const ENGLISH: &[(&str, &str)] = &[
+    ("dropped", "🗑️ Dropped a message from {sender}: {reason}"),
+    (
+        "queue_full_notice",
+        "{sender}, the moderation queue is full so your submission was not sent. Please try again later.",
+    ),
+    (
+        "membership_age_notice",
+        "{sender}, new members can't submit yet, so this one was not sent. You can submit in {left}.",
+    ),
+    (
+        "quota_notice",
+        "{sender}, you have reached the limit of {quota} submissions, so this one was not sent. You can submit again from {at}.",
+    ),
+    (
+        "message_type_notice",
+        "{sender}, {msgtype} messages can't be submitted here, so yours was not sent.",
+    ),
+    (
+        "media_caption_required",
+        "{sender}, please send that again with a caption describing it, so everyone can follow along. Yours was not sent.",
+    ),
+    (
+        "queue_full_alert",
+        "⚠️ {count} submissions are waiting on a decision, the queue is full",
+    ),
+    ("queue_recovered", "✅ The queue is back to normal"),
+    ("not_allowed", "Sorry {sender}, you're not allowed to do that"),
+    ("invalid_user", "{user} is not a valid user id"),
+    ("karma", "{user} has {karma} karma"),
+    ("karma_reset", "Reset the karma of {user}"),
+    ("karma_usage", "Usage: {prefix}karma <user> or {prefix}karma reset <user>"),
+    ("shadowbanned", "Shadowbanned {user}"),
+    ("already_shadowbanned", "{user} is already shadowbanned"),
+    ("unshadowbanned", "Lifted the shadowban on {user}"),
+    ("not_shadowbanned", "{user} is not shadowbanned"),
+    (
+        "shadowban_usage",
+        "Usage: {prefix}shadowban <user> or {prefix}unshadowban <user>",
+    ),
+    ("queue_empty", "The queue is empty"),
+    ("queue_priority", "[priority {priority}]"),
+    ("queue_held", "[held]"),
+    ("queue_claimed", "[claimed by {moderator}]"),
+    ("queue_scheduled", "[scheduled for {at}]"),
+    (
+        "escalation",
+        "⏰ A submission from {sender} has waited {waited} for a decision: {link} {users}",
+    ),
+    (
+        "bumped",
+        "Bumped the submission from {sender} to priority {priority}",
+    ),
+    (
+        "bump_decided",
+        "That submission was decided on in the meantime",
+    ),
+    (
+        "show",
+        "Submission from {sender} in {room}, {ago} ago: {link}",
+    ),
+    ("show_content_warning", "[content warning: {reason}]"),
+    ("show_votes", "[{approvals} approvals, {rejections} rejections]"),
+    (
+        "backfilled",
+        "Handled {forwarded} submissions from the input rooms' history, skipped {skipped} already handled",
+    ),
+    (
+        "backfilled_capped",
+        "Read back only the last {limit} events in each input room. Handled {forwarded} submissions from them, skipped {skipped} already handled",
+    ),
+    (
+        "backfill_usage",
+        "Usage: {prefix}backfill <events to read back in each input room>",
+    ),
+    (
+        "delegated",
+        "{user} can approve and reject submissions for the next {duration}",
+    ),
+    (
+        "delegate_everyone",
+        "Everyone in the mod room can already approve and reject submissions, as `moderators` is empty",
+    ),
+    ("delegate_moderator", "{user} is already one of the moderators"),
+    (
+        "delegate_usage",
+        "Usage: {prefix}delegate <user> <minutes>",
+    ),
+    (
+        "ratelimit_show",
+        "Each sender can make {per_minute} submissions a minute, {throttled} of them are at the limit now",
+    ),
+    (
+        "ratelimit_off",
+        "Submissions aren't rate limited, {prefix}ratelimit set <per minute> limits them",
+    ),
+    ("ratelimit_set", "Each sender can now make {per_minute} submissions a minute"),
+    ("ratelimit_reset", "{user} can make a full minute's worth of submissions again"),
+    ("ratelimit_change_failed", "Could not change the rate limit: {error}"),
+    (
+        "ratelimit_usage",
+        "Usage: {prefix}ratelimit show, {prefix}ratelimit set <per minute> or {prefix}ratelimit reset <user>",
+    ),
+    ("pseudonym", "Author {number}"),
+    ("revealed", "{pseudonym} is {user}"),
+    ("no_such_pseudonym", "Nobody is called {pseudonym}"),
+    (
+        "reveal_usage",
+        "Usage: {prefix}reveal <pseudonym, or its number>",
+    ),
+    ("show_usage", "Usage: {prefix}show <position in {prefix}queue>"),
+    (
+        "preview",
+        "Preview, not published. Approving with ✅ would post this to {room}:",
+    ),
+    (
+        "preview_usage",
+        "Usage: {prefix}preview <position in {prefix}queue>",
+    ),
+    ("bump_missing", "There is no submission {position} in the queue"),
+    ("bump_usage", "Usage: {prefix}bump <position in {prefix}queue>"),
+    (
+        "filters_reloaded",
+        "Reloaded the filters, the blocklist has {entries} entries",
+    ),
+    (
+        "filters_not_reloaded",
+        "Could not reload the filters, the old ones are still in use: {error}",
+    ),
+    (
+        "scheduled",
+        "The submission from {sender} will be published at {at}",
+    ),
+    ("schedule_past", "{at} has already passed"),
+    (
+        "schedule_held",
+        "That submission is held for discussion, it can be scheduled once it is released",
+    ),
+    (
+        "schedule_claimed",
+        "{claimant} has claimed that submission, so it is theirs to schedule",
+    ),
+    (
+        "schedule_needs_approvals",
+        "Not scheduled: with your approval it would have a weight of {weight}, and approving it takes {required}",
+    ),
+    (
+        "schedule_usage",
+        "Usage: {prefix}schedule <position in {prefix}queue> <time, like 2024-06-01T09:00>",
+    ),
+    (
+        "unscheduled",
+        "Put the submission from {sender} back in the queue, it won't be published on schedule",
+    ),
+    ("not_scheduled", "Submission {position} isn't scheduled"),
+    ("unschedule_usage", "Usage: {prefix}unschedule <position in {prefix}queue>"),
+    ("seq_set", "The next published submission will be #{seq}"),
+    ("setseq_usage", "Usage: {prefix}setseq <number of the next published submission>"),
+    ("requeued", "Put the submission from {sender} back in the queue"),
+    (
+        "requeue_missing",
+        "There is no decided submission {event_id}, decisions are only kept for {retention}",
+    ),
+    (
+        "requeue_approved",
+        "{event_id} was approved, only rejected submissions can be requeued",
+    ),
+    ("requeue_usage", "Usage: {prefix}requeue <event id of the mod room copy>"),
+    (
+        "erased",
+        "Erased {user}: redacted {redacted} messages and removed {pending} pending submissions",
+    ),
+    (
+        "erased_with_failures",
+        "Erased {user}: redacted {redacted} messages and removed {pending} pending submissions, \
+         but {failed} redactions failed and can be retried",
+    ),
+    ("erase_usage", "Usage: {prefix}erase <user>"),
+    (
+        "confirm_prompt",
+        "About to run {prefix}{command}. React ✅ to this or send {prefix}{command} {token} within {window} to go ahead",
+    ),
+    ("exported", "Exported the state and the last {records} audit records"),
+    (
+        "exported_truncated",
+        "Exported the state and the last {records} audit records, {left_out} older records were left out to keep the file small",
+    ),
+    (
+        "stats",
+        "{count} decisions in the last 24 hours, taking {median} on median, {p95} at the 95th percentile and {max} at most",
+    ),
+    ("instructions", "How to moderate here, react to a submission with:"),
+    ("instructions_approve", "{emoji} to approve and publish it"),
+    ("instructions_reject", "{emoji} to reject it"),
+    (
+        "instructions_hold",
+        "{emoji} to hold it for discussion, until every {emoji} is removed",
+    ),
+    (
+        "instructions_claim",
+        "{emoji} to claim it while you review it, so others know to leave it to you",
+    ),
+    (
+        "instructions_cw",
+        "{emoji} to publish it behind a content warning, replying to it first gives the reason",
+    ),
+    (
+        "instructions_plain",
+        "{emoji} to publish it without a content warning",
+    ),
+    (
+        "instructions_category",
+        "{emoji} to publish it as {category}, in {room}",
+    ),
+    (
+        "instructions_rejection_reason",
+        "{emoji} only counts once someone replies to the submission with why it is rejected",
+    ),
+    ("instructions_reply", "Replying {keywords} to it approves it too"),
+    (
+        "instructions_quorum",
+        "A submission needs approvals weighing {approval} to be published, and rejections weighing {rejection} to be rejected",
+    ),
+    ("instructions_commands", "Commands: {commands}"),
+    ("help_reactions", "Reacting to a submission in the mod room"),
+    ("help_commands", "Commands you can use"),
+    ("help_topics", "More help"),
+    (
+        "help_topic_approval",
+        "{prefix}help approval for how submissions are approved",
+    ),
+    (
+        "help_topic_filters",
+        "{prefix}help filters for what keeps messages out of the queue",
+    ),
+    (
+        "help_topic_command",
+        "{prefix}help <command> for how to use a command",
+    ),
+    ("help_approval", "Approving and rejecting"),
+    (
+        "help_preview",
+        "Approved submissions are posted to {room} first, and only published once someone reacts {emoji} there",
+    ),
+    (
+        "help_input_room",
+        "Approving reactions work on submissions in their input rooms too",
+    ),
+    ("help_filters", "Filters"),
+    (
+        "help_submission_prefix",
+        "Only messages starting with {prefix} are submissions",
+    ),
+    ("help_no_filters", "No filters are set up"),
+    ("help_filter_list", "Filters, in the order they run: {filters}"),
+    ("help_rate_limit", "Each sender can submit {count} times a minute"),
+    (
+        "help_quota",
+        "Each sender can have {count} submissions taken every {window}",
+    ),
+    (
+        "help_membership_age",
+        "Senders must have been in the input room for {age} before they can submit",
+    ),
+    (
+        "help_reload_filters",
+        "{prefix}reload-filters reloads them from the config",
+    ),
+    ("help_command", "{prefix}{command}"),
+    ("help_no_arguments", "Send {prefix}{command} on its own"),
+    (
+        "help_unknown",
+        "There is no help on {command}, {prefix}help lists what there is",
+    ),
+    (
+        "help_usage",
+        "Usage: {prefix}help, {prefix}help approval, {prefix}help filters or {prefix}help <command>",
+    ),
+    ("instructions_admin_commands", "Commands for admins: {commands}"),
+    ("stats_empty", "No decisions in the last 24 hours"),
+    ("stats_filtered", "Filtered since starting: {filters}"),
+    ("stats_filter_dropped", "{filter} dropped {count}"),
+    ("stats_filter_held", "{filter} held {count}"),
+    ("stats_rooms", "Submissions per input room over the last {days} days:"),
+    (
+        "stats_room",
+        "{room}: {received} received, {forwarded} forwarded, {rejected} rejected, {expired} expired",
+    ),
+    ("stats_rooms_empty", "No submissions in the last {days} days"),
+    ("stats_training", "Decisions on training submissions:"),
+    (
+        "stats_training_moderator",
+        "{moderator}: {approved} approved, {rejected} rejected",
+    ),
+    ("stats_training_empty", "No decisions on training submissions yet"),
+    (
+        "stats_usage",
+        "Usage: {prefix}stats, {prefix}stats rooms [days] for up to {max} days, or {prefix}stats training",
+    ),
+    ("room_invalid", "{room} is not a valid room id or alias"),
+    ("room_join_failed", "Could not join {room}: {error}"),
+    ("room_change_failed", "Could not change the rooms: {error}"),
+    ("not_input", "{room} is not an input room"),
+    ("added_input", "Now taking submissions from {room}"),
+    ("added_output", "Now publishing to {room} by default"),
+    ("removed_input", "No longer taking submissions from {room}"),
+    (
+        "addinput_usage",
+        "Usage: {prefix}addinput <room> or {prefix}addinput <room> <output room>",
+    ),
+    ("addoutput_usage", "Usage: {prefix}addoutput <room>"),
+    ("removeinput_usage", "Usage: {prefix}removeinput <room>"),
+    ("muted_room", "Ignoring the messages in {room} until it is unmuted"),
+    ("unmuted_room", "Taking submissions from {room} again"),
+    ("already_muted", "{room} is already muted"),
+    ("not_muted", "{room} is not muted"),
+    ("mute_room_usage", "Usage: {prefix}mute-room <room>"),
+    ("unmute_room_usage", "Usage: {prefix}unmute-room <room>"),
+    (
+        "status",
+        "Initial sync: {initial_sync} · Last sync: {last_sync} · Sync token age: {token_age} · Stalled: {stalled} · Joined rooms: {rooms} · Queue: {queue} · Moderation: {moderate} · Paused: {paused} · Dry run: {dry_run} · Muted rooms: {muted} · State: {state} · Uptime: {uptime}",
+    ),
+    ("status_yes", "yes"),
+    ("status_no", "no"),
+    ("status_none", "none"),
+    ("thread_root", "🧵 {category}"),
+    ("index", "📚 Latest submissions"),
+    ("index_empty", "Nothing has been published yet"),
+    ("index_untitled", "(untitled)"),
+    (
+        "session",
+        "Device: {device_id} ({device_name}) · Logged in: {age} ago · Other sessions: {others}, {prefix}logout-others logs them out",
+    ),
+    ("session_none", "The bot is not logged in"),
+    ("session_usage", "Usage: {prefix}session"),
+    ("no_other_sessions", "There are no other sessions to log out"),
+    ("logged_out_others", "Logged out {count} other sessions"),
+    (
+        "logout_others_failed",
+        "Could not log out the other sessions: {error}",
+    ),
+    ("logout_others_usage", "Usage: {prefix}logout-others"),
+    (
+        "train_posted",
+        "Posted a training submission, decide on it like any other. Approving it publishes it to {room}",
+    ),
+    (
+        "train_posted_nowhere",
+        "Posted a training submission, decide on it like any other. Approving it publishes it nowhere",
+    ),
+    ("train_usage", "Usage: {prefix}train"),
+    ("status_never", "never"),
+    ("status_ago", "{duration} ago"),
+    (
+        "selftest_passed",
+        "✅ Self-test passed all {checks} checks",
+    ),
+    (
+        "selftest_failed",
+        "❌ Self-test failed {failed} of {checks} checks",
+    ),
+    (
+        "selftest_message",
+        "🧪 4warder_bot self-test, this message will be removed right away",
+    ),
+    ("selftest_route", "A submission in {room} would go to {destination}"),
+    (
+        "selftest_route_held",
+        "A submission in {room} would be held for {destination}: {reason}",
+    ),
+    (
+        "selftest_route_dropped",
+        "A submission in {room} would be dropped: {reason}",
+    ),
+    ("selftest_joined", "Joined {room}"),
+    ("selftest_not_joined", "Not in {room}: {error}"),
+    ("selftest_sent", "Sent and redacted a test message in {room}"),
+    ("selftest_dry_run", "Would send a test message to {room}, this is a dry run"),
+    ("selftest_send_failed", "Could not send to {room}: {error}"),
+    (
+        "selftest_redact_failed",
+        "Sent a test message to {room} but could not redact it: {error}",
+    ),
+    ("selftest_usage", "Usage: {prefix}selftest or {prefix}selftest send"),
+    (
+        "paused",
+        "⏸️ Paused forwarding, submissions and approvals are kept until {prefix}resume",
+    ),
+    ("already_paused", "Forwarding is already paused, {prefix}resume resumes it"),
+    (
+        "resumed",
+        "▶️ Resumed forwarding and caught up on {count} submissions and approvals",
+    ),
+    ("not_paused", "Forwarding isn't paused"),
+    (
+        "dead_lettered",
+        "⚠️ Could not forward {link} from {sender} in {room} (attempt {attempts}): {reason}\nFix the cause, then {prefix}retry-failed forwards it again",
+    ),
+    ("no_dead_letters", "No submissions failed to forward"),
+    (
+        "retried_failed",
+        "Forwarded {retried} failed submissions again, {failed} of them failed again",
+    ),
+    (
+        "claimed_decision",
+        "⚠️ {moderator} decided on a submission {claimant} claimed to review",
+    ),
+    (
+        "rejection_reason_required",
+        "{emoji} needs a reason first: reply to the submission with why it is rejected, and the {emoji} counts",
+    ),
+    (
+        "rejected_notice",
+        "{sender}, your submission was not published: {reason}",
+    ),
+    ("delivery_failed", "⚠️ Could not deliver {submission} to {failed}"),
+    (
+        "delivery_partly_failed",
+        "⚠️ Delivered {submission} to {delivered}, but not to {failed}",
+    ),
+    ("quote_attribution", "{sender} wrote:"),
+    ("content_warning", "[content warning: {reason}]"),
+    ("content_warning_no_reason", "[content warning]"),
+];
+
+/// The only language we ship a catalog for, others come from `messages_file`
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// The text of every message the bot sends, in the configured language
+#[derive(Debug, Default)]
+pub struct Messages {
+    /// Translations which replace the English text, keyed like [`ENGLISH`]
+    overrides: HashMap<String, String>,
+}
+
+impl Messages {
+    /// Build the catalog for `language`, taking translations from the TOML table at `path`
+    pub fn load(language: &str, path: Option<&str>) -> Result<Self, FourwarderError> {
+        let path = match path {
+            Some(path) => path,
+            None if language == DEFAULT_LANGUAGE => return Ok(Messages::default()),
+            None => {
+                return Err(FourwarderError::Config(
+                    "Only `en` is built in, other languages need a `messages_file`",
+                ))
+            }
+        };
+
+        let overrides: HashMap<String, String> = toml::from_str(&std::fs::read_to_string(path)?)?;
+        if let Some(key) = overrides.keys().find(|key| english(key).is_none()) {
+            tracing::error!("Unknown key `{}` in {}", key, path);
+            return Err(FourwarderError::Config(
+                "`messages_file` contains a key which isn't a message",
+            ));
+        }
+
+        Ok(Messages { overrides })
+    }
+
+    /// The text of message `key`, with `{name}` placeholders filled in from `args`
+    pub fn get(&self, key: &str, args: &[(&str, &(dyn Display + Sync))]) -> String {
+        let template = self
+            .overrides
+            .get(key)
+            .map(String::as_str)
+            .or_else(|| english(key))
+            .unwrap_or(key);
+
+        fill(template, args)
+    }
+
+    /// Whether there is a message called `key`
+    pub fn has(&self, key: &str) -> bool {
+        english(key).is_some()
+    }
+}
+
+fn english(key: &str) -> Option<&'static str> {
+    ENGLISH
+        .iter()
+        .find(|(english_key, _)| *english_key == key)
+        .map(|(_, text)| *text)
+}
+
+/// Replace `{name}` in `template`, leaving unknown placeholders as they are
+///
+/// This is a single pass, so arguments which themselves contain braces are left alone.
+pub fn fill(template: &str, args: &[(&str, &(dyn Display + Sync))]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let arg = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            args.iter()
+                .find(|(arg, _)| *arg == name)
+                .map(|(_, value)| (end, value))
+        });
+        match arg {
+            Some((end, value)) => {
+                out.push_str(&value.to_string());
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{LazyLock, Mutex},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use crate::{error::FourwarderError, health};
+
+enum Value {
+    Gauge(f64),
+    /// Counts by their labels, already rendered like `filter="spam"`
+    Counter(BTreeMap<String, u64>),
+    Histogram {
+        /// Upper bounds, in ascending order
+        buckets: &'static [f64],
+        /// How many observations fell in each bucket, not counting the ones below
+        counts: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+struct Metric {
+    help: &'static str,
+    value: Value,
+}
+
+static METRICS: LazyLock<Mutex<BTreeMap<&'static str, Metric>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/// Set a gauge, creating it if this is the first time it is set
+pub fn set_gauge(name: &'static str, help: &'static str, value: f64) {
+    METRICS.lock().unwrap().insert(
+        name,
+        Metric {
+            help,
+            value: Value::Gauge(value),
+        },
+    );
+}
+
+/// Add one to a counter with `labels`, creating it if this is the first time it is counted
+pub fn increment(name: &'static str, help: &'static str, labels: &[(&str, &str)]) {
+    let labels = labels
+        .iter()
+        .map(|(label, value)| format!("{}=\"{}\"", label, value.replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut metrics = METRICS.lock().unwrap();
+    let metric = metrics.entry(name).or_insert_with(|| Metric {
+        help,
+        value: Value::Counter(BTreeMap::new()),
+    });
+    if let Value::Counter(counts) = &mut metric.value {
+        *counts.entry(labels).or_default() += 1;
+    }
+}
+
+/// Add an observation to a histogram, creating it with `buckets` if this is the first one
+pub fn observe(name: &'static str, help: &'static str, buckets: &'static [f64], value: f64) {
+    let mut metrics = METRICS.lock().unwrap();
+    let metric = metrics.entry(name).or_insert_with(|| Metric {
+        help,
+        value: Value::Histogram {
+            buckets,
+            counts: vec![0; buckets.len()],
+            sum: 0.0,
+            count: 0,
+        },
+    });
+
+    if let Value::Histogram {
+        buckets,
+        counts,
+        sum,
+        count,
+    } = &mut metric.value
+    {
+        if let Some(bucket) = buckets.iter().position(|bound| value <= *bound) {
+            counts[bucket] += 1;
+        }
+        *sum += value;
+        *count += 1;
+    }
+}
+
+/// Every metric in the Prometheus text format
+pub fn render() -> String {
+    let mut out = String::new();
+    for (name, metric) in METRICS.lock().unwrap().iter() {
+        match &metric.value {
+            Value::Gauge(value) => {
+                let _ = write!(
+                    out,
+                    "# HELP {name} {}\n# TYPE {name} gauge\n{name} {}\n",
+                    metric.help, value
+                );
+            }
+            Value::Counter(counts) => {
+                let _ = writeln!(out, "# HELP {name} {}\n# TYPE {name} counter", metric.help);
+                for (labels, count) in counts {
+                    let _ = writeln!(out, "{name}{{{}}} {}", labels, count);
+                }
+            }
+            Value::Histogram {
+                buckets,
+                counts,
+                sum,
+                count,
+            } => {
+                let _ = writeln!(
+                    out,
+                    "# HELP {name} {}\n# TYPE {name} histogram",
+                    metric.help
+                );
+                let mut cumulative = 0;
+                for (bound, bucket) in buckets.iter().zip(counts) {
+                    cumulative += bucket;
+                    let _ = writeln!(out, "{name}_bucket{{le=\"{}\"}} {}", bound, cumulative);
+                }
+                let _ = write!(
+                    out,
+                    "{name}_bucket{{le=\"+Inf\"}} {count}\n{name}_sum {sum}\n{name}_count {count}\n"
+                );
+            }
+        }
+    }
+    out
+}
+
+/// Answer every HTTP request on `address` with the current metrics, or with how healthy we are
+/// for `/health`
+///
+/// Apart from its path the request is never looked at, so any other path gets the metrics. The
+/// health check answers `200 OK` once the first sync is done, and `503 Service Unavailable`
+/// before then and while syncing is stalled.
+pub async fn serve(address: SocketAddr) -> Result<(), FourwarderError> {
+    let listener = TcpListener::bind(address).await?;
+    tracing::info!("Serving metrics on {}", address);
+
+    tokio::spawn(async move {
+        loop {
+            let mut stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!("Could not accept a metrics connection: {}", e);
+                    continue;
+                }
+            };
+
+            tokio::spawn(async move {
+                // Only the headers matter, and we ignore them anyway
+                let mut request = [0; 1024];
+                let read = stream.read(&mut request).await.unwrap_or_default();
+                let path = std::str::from_utf8(&request[..read])
+                    .ok()
+                    .and_then(|request| request.split(' ').nth(1));
+
+                let response = if path == Some("/health") {
+                    let (status, body) = if health::is_healthy() {
+                        ("200 OK", "ok\n")
+                    } else {
+                        ("503 Service Unavailable", "unhealthy\n")
+                    };
+                    format!(
+                        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    )
+                } else {
+                    let body = render();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                };
+                if let Err(e) = stream.write_all(response.as_bytes()).await {
+                    tracing::warn!("Could not send metrics: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use crate::{config, error::FourwarderError};
+
+/// Rewrite the config file at `path` in the current format, for `4warder migrate-config`
+///
+/// The original is kept next to it as `<path>.bak`. The migrated config is loaded like it
+/// would be at startup before anything is replaced, so a config which wouldn't start the bot
+/// is never written.
+pub fn run(path: &str) -> Result<(), FourwarderError> {
+    let old: toml::Value = toml::from_str(&std::fs::read_to_string(path)?)?;
+    let mut new = old.clone();
+    let table = new
+        .as_table_mut()
+        .ok_or(FourwarderError::Config("The config is not a TOML table"))?;
+
+    let mut notes = Vec::new();
+    // A single top-level input room becomes the first `[[rooms]]` entry, where every other
+    // input room goes, inheriting the top-level `output_room_id` and `moderate` like before
+    if let Some(input_room_id) = table.remove("input_room_id") {
+        let mut entry = toml::value::Table::new();
+        entry.insert("input_room_id".to_owned(), input_room_id);
+        let rooms = table
+            .entry("rooms")
+            .or_insert_with(|| toml::Value::Array(Vec::new()));
+        match rooms.as_array_mut() {
+            Some(rooms) => rooms.insert(0, toml::Value::Table(entry)),
+            None => return Err(FourwarderError::Config("`rooms` is not a list of tables")),
+        }
+        notes.push("moved `input_room_id` into the first `[[rooms]]` entry");
+    }
+
+    if notes.is_empty() {
+        println!("{} is already in the current format", path);
+        return Ok(());
+    }
+
+    let toml = toml::to_string(&new)
+        .map_err(|_| FourwarderError::Logic("The migrated config could not be written as TOML"))?;
+    let migrated_path = format!("{}.migrated", path);
+    std::fs::write(&migrated_path, &toml)?;
+    if let Err(e) = config::load_config(&migrated_path) {
+        println!(
+            "The migrated config doesn't load, left it in {} and {} untouched: {}",
+            migrated_path, path, e
+        );
+        return Err(e);
+    }
+
+    let backup_path = format!("{}.bak", path);
+    std::fs::copy(path, &backup_path)?;
+    std::fs::rename(&migrated_path, path)?;
+
+    println!("Migrated {}, the original is in {}", path, backup_path);
+    for note in notes {
+        println!("- {}", note);
+    }
+    println!(
+        "Changed keys: {}",
+        config::changed_keys(&old, &new).join(", ")
+    );
+    Ok(())
+}
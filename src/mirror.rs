@@ -0,0 +1,147 @@
+use matrix_sdk::{
+    deserialized_responses::SyncResponse,
+    ruma::{EventId, RoomId, UserId},
+    Client,
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    audit::{self, Action},
+    config::Config,
+    dedup,
+    error::FourwarderError,
+    hash_denylist::Denial,
+    marker, media,
+    pause::{self, Deferred},
+    send, shadowban,
+};
+
+/// Event types we either handle ourselves or must never copy to another room
+const NOT_MIRRORED: &[&str] = &[
+    "m.room.message",
+    "m.reaction",
+    "m.room.redaction",
+    // Unreadable anywhere but the room it was encrypted for
+    "m.room.encrypted",
+];
+
+/// The parts of a raw event we look at before copying it
+#[derive(Deserialize)]
+struct RawEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    event_id: EventId,
+    sender: UserId,
+    state_key: Option<String>,
+    content: Value,
+}
+
+/// Copy every message event we have no handler for from unmoderated input rooms to their
+/// output room, with its content untouched
+///
+/// This is what `mirror_unknown_events` turns on. State events, reactions, redactions and our
+/// own events are never copied, nor is anything a 4warder instance already forwarded, so two
+/// bots mirroring into each other's rooms can't loop.
+pub async fn relay(
+    client: &Client,
+    config: &Config,
+    response: &SyncResponse,
+) -> Result<(), FourwarderError> {
+    if !config.mirror_unknown_events {
+        return Ok(());
+    }
+    let own_user_id = client.user_id().await.ok_or(FourwarderError::Logic(
+        "We mirrored events before logging in",
+    ))?;
+
+    for (room_id, room) in &response.rooms.join {
+        let output_room_id = match config.input_room(room_id) {
+            Some(settings) if !settings.moderate => settings.output_room_id,
+            _ => continue,
+        };
+
+        for event in &room.timeline.events {
+            let event: RawEvent = match serde_json::from_str(event.event.json().get()) {
+                Ok(event) => event,
+                Err(_) => continue,
+            };
+            if event.state_key.is_some()
+                || NOT_MIRRORED.contains(&event.event_type.as_str())
+                || event.sender == own_user_id
+                || marker::instance(&event.content).is_some()
+                || shadowban::is_shadowbanned(&event.sender)
+                || !dedup::first_time(&event.event_id, config.dedup_capacity)
+            {
+                continue;
+            }
+
+            if let Some(denylist) = &config.hash_denylist {
+                if let Some(denial) = denylist.check(client, &event.content).await {
+                    tracing::info!("Not mirroring {}, {:?}", event.event_id, denial);
+                    let sha256 = match denial {
+                        Denial::Listed { sha256 } => Some(sha256),
+                        Denial::Unchecked => None,
+                    };
+                    audit::record(
+                        None,
+                        Action::HashDenied {
+                            event_id: event.event_id,
+                            sender: event.sender,
+                            sha256,
+                        },
+                    );
+                    continue;
+                }
+            }
+
+            if pause::is_paused() {
+                tracing::info!("Deferring {}, forwarding is paused", event.event_id);
+                pause::defer(Deferred::Mirror {
+                    room_id: output_room_id.clone(),
+                    event_type: event.event_type,
+                    content: event.content,
+                });
+                continue;
+            }
+
+            tracing::info!(
+                "Mirroring {} event {} from {} to {}",
+                event.event_type,
+                event.event_id,
+                room_id,
+                output_room_id
+            );
+            if let Err(e) = send(
+                client,
+                config,
+                output_room_id,
+                &event.event_type,
+                event.content,
+            )
+            .await
+            {
+                tracing::warn!(
+                    "Could not mirror {} to {}: {}",
+                    event.event_id,
+                    output_room_id,
+                    e
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy an event's content to `room_id`, with the media in it rehosted if `media_mode` says so
+pub async fn send(
+    client: &Client,
+    config: &Config,
+    room_id: &RoomId,
+    event_type: &str,
+    mut content: Value,
+) -> Result<(), FourwarderError> {
+    media::rehost(client, config, &mut content).await;
+    send::raw(client, room_id, event_type, content).await
+}
@@ -0,0 +1,160 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Characters which render as nothing, and are used to sneak words past filters
+pub fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}' // soft hyphen
+            | '\u{034F}' // combining grapheme joiner
+            | '\u{061C}' // arabic letter mark
+            | '\u{115F}'..='\u{1160}' // hangul fillers
+            | '\u{17B4}'..='\u{17B5}' // khmer inherent vowels
+            | '\u{180E}' // mongolian vowel separator
+            | '\u{200B}'..='\u{200F}' // zero-width spaces, joiners and direction marks
+            | '\u{202A}'..='\u{202E}' // bidi embeddings and overrides
+            | '\u{2060}'..='\u{2064}' // word joiner and invisible operators
+            | '\u{2066}'..='\u{206F}' // bidi isolates and deprecated format characters
+            | '\u{3164}' // hangul filler
+            | '\u{FEFF}' // zero-width no-break space
+            | '\u{FFA0}' // halfwidth hangul filler
+    ) || (c.is_control() && !c.is_whitespace())
+}
+
+/// Normalize a reaction for comparing against the emoji we act on
+///
+/// Clients differ in whether they send `✅` or `✅\u{FE0F}`, and some add the moderator's skin
+/// tone, so variation selectors and skin tone modifiers are stripped.
+pub fn normalize_emoji(emoji: &str) -> String {
+    emoji
+        .chars()
+        .filter(|c| !matches!(c, '\u{FE0E}' | '\u{FE0F}' | '\u{1F3FB}'..='\u{1F3FF}'))
+        .collect()
+}
+
+/// Normalize `text` for matching against word lists and patterns
+///
+/// This strips invisible and control characters and applies NFKC, so that `sl\u{200C}ur` and
+/// full-width `ｓｌｕｒ` both match `slur`. It does not change case. Only ever match against the
+/// result, the original text is what should be forwarded.
+pub fn normalize_for_matching(text: &str) -> String {
+    text.chars().filter(|c| !is_invisible(*c)).nfkc().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use matrix_sdk::ruma::events::room::message::TextMessageEventContent;
+
+    use super::*;
+    use crate::{
+        blocklist::Blocklist,
+        content_dedup::{self, ContentDedup},
+        filters::{FilterDecision, IncomingMessage, MessageFilter},
+        include::Include,
+        testing::{self, event, room, user},
+    };
+
+    /// `slur` with something invisible in the middle, and in full-width forms
+    const DISGUISED: &[&str] = &[
+        "sl\u{200B}ur",
+        "sl\u{200C}ur",
+        "s\u{00AD}l\u{00AD}u\u{00AD}r",
+        "sl\u{2060}ur",
+        "\u{FEFF}slur",
+        "s\u{202E}lur",
+        "ｓｌｕｒ",
+    ];
+
+    fn blocklisted(body: &str) -> bool {
+        let text = TextMessageEventContent::plain(body);
+        let msg = IncomingMessage {
+            room_id: &room("!input:example.org"),
+            event_id: &event("$a:example.org"),
+            sender: &user("@sub:example.org"),
+            text: &text,
+        };
+        matches!(
+            Blocklist::new(&["slur".to_owned()]).evaluate(&msg),
+            FilterDecision::Drop(_)
+        )
+    }
+
+    #[test]
+    fn invisible_characters_are_stripped() {
+        for disguised in DISGUISED {
+            assert_eq!(normalize_for_matching(disguised), "slur", "{:?}", disguised);
+        }
+        assert_eq!(normalize_for_matching("Slur"), "Slur");
+    }
+
+    #[test]
+    fn blocklist_sees_through_disguises() {
+        for disguised in DISGUISED {
+            assert!(blocklisted(&format!("what a {}!", disguised)), "{:?}", disguised);
+        }
+        assert!(!blocklisted("slurp"));
+        assert!(!blocklisted("sl\u{200B}urp"));
+    }
+
+    #[test]
+    fn patterns_see_through_disguises() {
+        let include = Include::new(&["#submit".to_owned()]).unwrap();
+
+        assert!(include.matches("#sub\u{200B}mit this"));
+        assert!(include.matches("＃ｓｕｂｍｉｔ this"));
+        assert!(!include.matches("#submitted"));
+    }
+
+    #[test]
+    fn dedup_sees_through_disguises() {
+        let _test = testing::isolate();
+        let first_time = |text: &str| {
+            content_dedup::first_time(
+                ContentDedup::PerField,
+                Duration::from_secs(60),
+                10,
+                &testing::user("@sub:example.org"),
+                text,
+                None,
+            )
+        };
+
+        assert!(first_time("buy my slur"));
+        for disguised in DISGUISED {
+            assert!(!first_time(&format!("buy my {}", disguised)), "{:?}", disguised);
+        }
+    }
+
+    #[test]
+    fn emoji_match_with_or_without_a_variation_selector() {
+        assert_eq!(normalize_emoji("✅\u{FE0F}"), normalize_emoji("✅"));
+        assert_eq!(normalize_emoji("❌\u{FE0E}"), "❌");
+        assert_eq!(normalize_emoji("⏸️"), "⏸");
+        assert_ne!(normalize_emoji("✅"), normalize_emoji("❌"));
+    }
+
+    #[test]
+    fn emoji_match_whatever_the_skin_tone() {
+        assert_eq!(normalize_emoji("👍\u{1F3FD}"), "👍");
+        assert_eq!(normalize_emoji("👍\u{1F3FF}\u{FE0F}"), "👍");
+    }
+
+    #[test]
+    fn configured_emoji_are_normalized_too() {
+        let config = testing::config(
+            r#"
+hold_emoji = "⏸️"
+
+[[categories]]
+name = "news"
+approval_emoji = "📰\uFE0F"
+output_room_id = "!news:example.org"
+"#,
+        );
+
+        assert_eq!(config.hold_emoji, normalize_emoji("⏸"));
+        assert!(config.category(&normalize_emoji("📰")).is_some());
+        assert!(config.category(&normalize_emoji("📰\u{FE0F}")).is_some());
+    }
+}
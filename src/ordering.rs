@@ -0,0 +1,75 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+};
+
+use matrix_sdk::ruma::RoomId;
+use serde::Deserialize;
+use tokio::sync::Notify;
+
+/// Which order submissions are published in, when several are published at once
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderBy {
+    /// The order they were approved in
+    #[default]
+    Approval,
+    /// The order they were originally submitted in
+    OriginalTs,
+}
+
+/// The publishes waiting for their turn in one output room
+#[derive(Default)]
+struct Line {
+    /// Ordered by key, then by arrival
+    waiting: Mutex<BTreeSet<(u64, u64)>>,
+    changed: Notify,
+}
+
+static LINES: LazyLock<Mutex<HashMap<RoomId, Arc<Line>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Breaks ties between publishes with the same key, in the order they arrived
+static TICKETS: AtomicU64 = AtomicU64::new(0);
+
+/// The right to publish to an output room, given up when dropped
+pub struct Turn {
+    line: Arc<Line>,
+    place: (u64, u64),
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        self.line.waiting.lock().unwrap().remove(&self.place);
+        self.line.changed.notify_waiters();
+    }
+}
+
+/// Wait until nothing which should be published before us is waiting on `room_id`
+///
+/// Only one publish to a room happens at a time. `key` is what to order by, lower first, and
+/// publishes with the same key go in the order they arrived.
+pub async fn turn(room_id: &RoomId, key: u64) -> Turn {
+    let line = LINES
+        .lock()
+        .unwrap()
+        .entry(room_id.clone())
+        .or_default()
+        .clone();
+    let place = (key, TICKETS.fetch_add(1, Ordering::SeqCst));
+    line.waiting.lock().unwrap().insert(place);
+
+    loop {
+        // Listen before looking, so a turn given up in between isn't missed
+        let changed = line.changed.notified();
+        if line.waiting.lock().unwrap().first() == Some(&place) {
+            break;
+        }
+        changed.await;
+    }
+
+    Turn { line, place }
+}
@@ -0,0 +1,123 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use matrix_sdk::{
+    ruma::{
+        api::client::r0::message::get_message_events,
+        events::{room::message::MessageType, AnyMessageEvent, AnyRoomEvent},
+        RoomId, UInt,
+    },
+    Client,
+};
+
+use crate::{config::Config, error::FourwarderError, rooms, timeout};
+
+/// The most events asked for in one request while scanning
+const PAGE_SIZE: u32 = 100;
+
+/// The bodies of what we posted to each output room before we started
+static PUBLISHED: LazyLock<Mutex<HashMap<RoomId, Vec<String>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Read back what we last posted to each output room, up to `startup_scan_messages` events
+///
+/// `from` is the sync token to page back from. This is best effort, a room which can't be
+/// read is logged and skipped.
+pub async fn scan(client: &Client, config: &Config, from: &str) -> Result<(), FourwarderError> {
+    let limit = match config.startup_scan_messages {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+    let own_user_id = client.user_id().await.ok_or(FourwarderError::Logic(
+        "We scanned output rooms before logging in",
+    ))?;
+
+    let mut output_rooms: Vec<&RoomId> = config
+        .input_rooms
+        .iter()
+        .filter_map(|room| config.input_room(&room.room_id))
+        .map(|settings| settings.output_room_id)
+        .chain([&config.output_room_id])
+        .collect();
+    output_rooms.sort();
+    output_rooms.dedup();
+
+    for room_id in output_rooms {
+        let room = match rooms::joined(client, room_id).await {
+            Ok(room) => room,
+            Err(e) => {
+                tracing::warn!("Could not scan {}: {}", room_id, e);
+                continue;
+            }
+        };
+        let mut bodies = Vec::new();
+        let mut token = from.to_owned();
+        let mut scanned = 0;
+        while scanned < limit {
+            let mut request = get_message_events::Request::backward(room_id, &token);
+            request.limit = UInt::from(PAGE_SIZE.min((limit - scanned) as u32));
+            let response = match timeout::within(
+                "fetching history",
+                config.fetch_timeout,
+                room.messages(request),
+            )
+            .await
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::warn!("Could not scan {}: {}", room_id, e);
+                    break;
+                }
+            };
+            if response.chunk.is_empty() {
+                break;
+            }
+            scanned += response.chunk.len();
+
+            for event in &response.chunk {
+                let msg = match event.deserialize() {
+                    Ok(AnyRoomEvent::Message(AnyMessageEvent::RoomMessage(msg)))
+                        if msg.sender == own_user_id =>
+                    {
+                        msg
+                    }
+                    _ => continue,
+                };
+                // Whatever `output_msgtype` was when it was published
+                match msg.content.msgtype {
+                    MessageType::Text(text) => bodies.push(text.body),
+                    MessageType::Notice(notice) => bodies.push(notice.body),
+                    _ => {}
+                }
+            }
+            match response.end {
+                Some(end) if end != token => token = end,
+                _ => break,
+            }
+        }
+
+        tracing::info!(
+            "Scanned {} events in {}, {} were ours",
+            scanned,
+            room_id,
+            bodies.len()
+        );
+        PUBLISHED.lock().unwrap().insert(room_id.clone(), bodies);
+    }
+
+    Ok(())
+}
+
+/// Whether the startup scan found `body` in `room_id` already
+///
+/// The output template, content warnings and sender's name all go in front of the submission,
+/// so a body we posted counts if it ends with `body`.
+pub fn already_published(room_id: &RoomId, body: &str) -> bool {
+    PUBLISHED
+        .lock()
+        .unwrap()
+        .get(room_id)
+        .is_some_and(|bodies| bodies.iter().any(|published| published.ends_with(body)))
+}
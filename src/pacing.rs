@@ -0,0 +1,63 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        LazyLock, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use matrix_sdk::ruma::RoomId;
+
+use crate::{clock, metrics};
+
+/// When we were last let through to each output room
+static LAST_SENT: LazyLock<Mutex<HashMap<RoomId, Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Publishes to paced rooms which haven't been sent yet
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+
+/// A publish counted in the pacing queue, taken out again when dropped
+pub struct Queued {
+    _private: (),
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        let queued = QUEUED.fetch_sub(1, Ordering::SeqCst) - 1;
+        set_queued(queued);
+    }
+}
+
+/// Count a publish to a paced room from when it is made until it is sent or given up
+pub fn queue() -> Queued {
+    let queued = QUEUED.fetch_add(1, Ordering::SeqCst) + 1;
+    set_queued(queued);
+    Queued { _private: () }
+}
+
+fn set_queued(queued: usize) {
+    metrics::set_gauge(
+        "fourwarder_pacing_queue_depth",
+        "Publishes waiting for `min_send_interval_ms` to pass",
+        queued as f64,
+    );
+}
+
+/// Wait until at least `interval` has passed since the last send to `room_id`
+///
+/// The caller must hold the room's [`crate::ordering::Turn`], so only one send to a room waits
+/// here at a time.
+pub async fn wait(room_id: &RoomId, interval: Duration) {
+    let last_sent = LAST_SENT.lock().unwrap().get(room_id).copied();
+    let since = last_sent.map(|last_sent| clock::now().saturating_duration_since(last_sent));
+    if let Some(wait) = since.and_then(|since| interval.checked_sub(since)) {
+        tracing::debug!("Waiting {:?} before sending to {}", wait, room_id);
+        tokio::time::sleep(wait).await;
+    }
+    LAST_SENT
+        .lock()
+        .unwrap()
+        .insert(room_id.clone(), clock::now());
+}
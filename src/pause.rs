@@ -0,0 +1,84 @@
+use matrix_sdk::ruma::{
+    events::room::message::TextMessageEventContent, EventId, MilliSecondsSinceUnixEpoch, RoomId,
+    UserId,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::state;
+
+/// Something which happened while forwarding was paused, to be done once it is resumed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Deferred {
+    /// A submission to an input room
+    Submission {
+        room_id: RoomId,
+        event_id: EventId,
+        sender: UserId,
+        origin_server_ts: MilliSecondsSinceUnixEpoch,
+        text: TextMessageEventContent,
+    },
+    /// An approval in the mod room, by reaction or reply
+    Approval {
+        room_id: RoomId,
+        moderator: UserId,
+        mod_event_id: EventId,
+        emoji: String,
+    },
+    /// A ✅ on a copy in the preview room
+    Confirmation {
+        moderator: UserId,
+        mod_event_id: EventId,
+        emoji: String,
+    },
+    /// An event for `mirror_unknown_events`, already bound for its output room
+    Mirror {
+        room_id: RoomId,
+        event_type: String,
+        // Not a `RawValue`, which can't be read back from the state file's `Value`
+        content: Value,
+    },
+}
+
+impl Deferred {
+    /// Who sent it, if it is a submission
+    pub fn sender(&self) -> Option<&UserId> {
+        match self {
+            Deferred::Submission { sender, .. } => Some(sender),
+            _ => None,
+        }
+    }
+}
+
+pub fn is_paused() -> bool {
+    state::read(|state| state.paused)
+}
+
+/// Returns `false` if forwarding was already paused
+pub fn pause() -> bool {
+    state::update(|state| !std::mem::replace(&mut state.paused, true))
+}
+
+/// Remember `deferred` to be done once forwarding is resumed
+pub fn defer(deferred: Deferred) {
+    state::update(|state| state.deferred.push(deferred));
+}
+
+/// Forget every deferred submission from `sender`
+pub fn remove_sender(sender: &UserId) {
+    state::update(|state| {
+        state
+            .deferred
+            .retain(|deferred| deferred.sender() != Some(sender))
+    });
+}
+
+/// Resume forwarding, returning what was deferred in the meantime, oldest first
+///
+/// Returns `None` if forwarding wasn't paused.
+pub fn resume() -> Option<Vec<Deferred>> {
+    state::update(|state| {
+        std::mem::replace(&mut state.paused, false).then(|| std::mem::take(&mut state.deferred))
+    })
+}
@@ -0,0 +1,702 @@
+use matrix_sdk::ruma::{
+    events::room::message::TextMessageEventContent, EventId, MilliSecondsSinceUnixEpoch, RoomId,
+    UserId,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    activity::{self, Outcome},
+    clock::now_millis,
+    lifecycle::{self, Status, Transition},
+    state,
+};
+
+/// A submission which has been copied into the mod room
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingItem {
+    /// The input room the submission was originally posted in
+    pub source_room_id: RoomId,
+    /// The submission in the input room, missing for items queued by older versions
+    #[serde(default)]
+    pub source_event_id: Option<EventId>,
+    pub sender: UserId,
+    pub origin_server_ts: MilliSecondsSinceUnixEpoch,
+    /// The submission as it was posted, before anything was added to the mod room copy
+    pub content: TextMessageEventContent,
+    /// Higher comes first, see [`NORMAL_PRIORITY`]
+    #[serde(default)]
+    pub priority: u32,
+    /// The reason for a content warning, taken from a moderator's reply to the mod room copy
+    #[serde(default)]
+    pub content_warning: Option<String>,
+    /// The `hold_emoji` reactions on the mod room copy, it is held for discussion while there
+    /// are any
+    #[serde(default)]
+    pub holds: Vec<EventId>,
+    /// Moderators who have approved it so far, see [`crate::quorum`]
+    #[serde(default)]
+    pub approvers: Vec<UserId>,
+    /// Moderators who have rejected it so far
+    #[serde(default)]
+    pub rejecters: Vec<UserId>,
+    /// Why it is being rejected, taken from a moderator's reply when `require_rejection_reason`
+    /// is set
+    #[serde(default)]
+    pub rejection_reason: Option<String>,
+    /// Moderators whose ❌ only counts once there is a `rejection_reason`
+    #[serde(default)]
+    pub awaiting_reason: Vec<UserId>,
+    /// Moderators who have approved it toward each category so far, by category name
+    #[serde(default)]
+    pub category_approvers: BTreeMap<String, Vec<UserId>>,
+    /// Whether someone has been pinged about it waiting too long
+    #[serde(default)]
+    pub escalated: bool,
+    /// The reactions the submission has in its input room, by the event id of each, see
+    /// `show_source_reactions`
+    #[serde(default)]
+    pub source_reactions: BTreeMap<EventId, SourceReaction>,
+    /// Its copy in `preview_room_id`, once it has been approved there
+    #[serde(default)]
+    pub preview: Option<Preview>,
+    /// When `!schedule` is to publish it, it stays approved but unpublished until then
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    /// Whether `!train` posted it, so it is published to `training_output_room_id` if anywhere,
+    /// and never to a real output room
+    #[serde(default)]
+    pub training: bool,
+    /// Who reacted with `claim_emoji` to review it, see [`PendingItem::claimant`]
+    #[serde(default)]
+    pub claim: Option<Claim>,
+    /// Only ever changed by [`lifecycle::apply_transition`]
+    #[serde(default)]
+    pub status: Status,
+}
+
+/// An approved submission waiting for confirmation in the preview room
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Preview {
+    /// The copy in the preview room
+    pub event_id: EventId,
+    /// What the submission was approved with in the mod room
+    pub emoji: String,
+}
+
+/// A moderator's `claim_emoji` on a pending submission, to say they are reviewing it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Claim {
+    pub moderator: UserId,
+    /// The reaction, which gives up the claim when it is removed
+    pub reaction: EventId,
+    /// Milliseconds since the Unix epoch
+    pub at: u64,
+}
+
+/// An approved submission waiting for the time `!schedule` set
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Schedule {
+    /// Milliseconds since the Unix epoch
+    pub at: u64,
+    /// Who scheduled it, who is recorded as having approved it
+    pub moderator: UserId,
+}
+
+impl PendingItem {
+    /// A submission just taken from `source_room_id`, with nothing done to it yet
+    pub fn new(
+        source_room_id: RoomId,
+        source_event_id: Option<EventId>,
+        sender: UserId,
+        origin_server_ts: MilliSecondsSinceUnixEpoch,
+        content: TextMessageEventContent,
+        priority: u32,
+    ) -> Self {
+        PendingItem {
+            source_room_id,
+            source_event_id,
+            sender,
+            origin_server_ts,
+            content,
+            priority,
+            content_warning: None,
+            holds: Vec::new(),
+            approvers: Vec::new(),
+            rejecters: Vec::new(),
+            rejection_reason: None,
+            awaiting_reason: Vec::new(),
+            category_approvers: BTreeMap::new(),
+            escalated: false,
+            source_reactions: BTreeMap::new(),
+            preview: None,
+            schedule: None,
+            training: false,
+            claim: None,
+            status: Status::Pending,
+        }
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.status == Status::Held
+    }
+
+    /// Who is reviewing it, unless their claim is older than `timeout` and has lapsed
+    pub fn claimant(&self, timeout: Duration) -> Option<&UserId> {
+        self.claim
+            .as_ref()
+            .filter(|claim| now_millis().saturating_sub(claim.at) < timeout.as_millis() as u64)
+            .map(|claim| &claim.moderator)
+    }
+
+    /// The most common `limit` reactions in the input room, like `👍 5, ❤️ 2`
+    pub fn source_reaction_summary(&self, limit: usize) -> Option<String> {
+        reaction_summary(&self.source_reaction_counts(), limit)
+    }
+
+    /// How many people reacted with each emoji in the input room, each counting once per emoji
+    /// however many times they reacted with it
+    pub fn source_reaction_counts(&self) -> BTreeMap<String, u32> {
+        reaction_counts(self.source_reactions.values())
+    }
+}
+
+/// How many people reacted with each emoji among `reactions`, each counting once per emoji
+pub fn reaction_counts<'a>(
+    reactions: impl IntoIterator<Item = &'a SourceReaction>,
+) -> BTreeMap<String, u32> {
+    let distinct: BTreeSet<(&str, &UserId)> = reactions
+        .into_iter()
+        .map(|reaction| (reaction.emoji.as_str(), &reaction.sender))
+        .collect();
+    let mut counts = BTreeMap::new();
+    for (emoji, _) in distinct {
+        *counts.entry(emoji.to_owned()).or_default() += 1;
+    }
+    counts
+}
+
+/// A reaction to a submission in its input room, or to the original of a published one
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SourceReaction {
+    pub sender: UserId,
+    /// Normalized with [`crate::normalize::normalize_emoji`]
+    pub emoji: String,
+}
+
+/// The most common `limit` of `reactions`, like `👍 5, ❤️ 2`, or `None` if there are none
+pub fn reaction_summary(reactions: &BTreeMap<String, u32>, limit: usize) -> Option<String> {
+    let mut reactions: Vec<(&String, &u32)> = reactions.iter().collect();
+    reactions.sort_by(|(_, a), (_, b)| b.cmp(a));
+    let summary = reactions
+        .into_iter()
+        .take(limit)
+        .map(|(emoji, count)| format!("{} {}", emoji, count))
+        .collect::<Vec<_>>()
+        .join(", ");
+    (!summary.is_empty()).then_some(summary)
+}
+
+/// The priority of an ordinary submission
+pub const NORMAL_PRIORITY: u32 = 0;
+/// The priority of a submission from a `priority_senders` entry, or matching `priority_keywords`
+pub const HIGH_PRIORITY: u32 = 1;
+
+pub fn insert(mod_event_id: EventId, item: PendingItem) {
+    state::update(|state| state.pending.insert(mod_event_id, item));
+}
+
+/// Look up the pending item for a message in the mod room
+///
+/// This is `None` for messages which have already been decided on, or which were forwarded
+/// before the bot kept track of them.
+pub fn get(mod_event_id: &EventId) -> Option<PendingItem> {
+    state::read(|state| state.pending.get(mod_event_id).cloned())
+}
+
+/// Move a pending item on by `transition`, returning it as it is now
+///
+/// Items which reach a final status are removed, and counted for their input room's
+/// [`activity`]. This is `None` if there is no such item, or if
+/// it can't make that move, which is logged as it usually means moderators raced each other.
+pub fn transition(mod_event_id: &EventId, transition: Transition) -> Option<PendingItem> {
+    let (item, status) = state::update(|state| {
+        let item = state.pending.get_mut(mod_event_id)?;
+        match lifecycle::apply_transition(item, transition) {
+            Ok(status) if status.is_final() => {
+                Some((state.pending.remove(mod_event_id)?, Some(status)))
+            }
+            Ok(_) => Some((item.clone(), None)),
+            Err(illegal) => {
+                tracing::info!("Leaving {} as it is, {}", mod_event_id, illegal);
+                None
+            }
+        }
+    })?;
+
+    let outcome = match status {
+        Some(Status::Published) => Some(Outcome::Forwarded),
+        Some(Status::Rejected) => Some(Outcome::Rejected),
+        Some(Status::Expired) => Some(Outcome::Expired),
+        _ => None,
+    };
+    // Training submissions were never posted in an input room
+    if let (Some(outcome), false) = (outcome, item.training) {
+        activity::record(&item.source_room_id, outcome);
+    }
+    Some(item)
+}
+
+/// How many submissions are waiting on a decision
+pub fn len() -> usize {
+    state::read(|state| state.pending.len())
+}
+
+/// Every pending item, highest priority first and oldest first within the same priority
+pub fn queue() -> Vec<(EventId, PendingItem)> {
+    let mut queue: Vec<(EventId, PendingItem)> = state::read(|state| {
+        state
+            .pending
+            .iter()
+            .map(|(id, item)| (id.clone(), item.clone()))
+            .collect()
+    });
+    queue.sort_by(|(_, a), (_, b)| {
+        b.priority
+            .cmp(&a.priority)
+            .then(a.origin_server_ts.cmp(&b.origin_server_ts))
+    });
+    queue
+}
+
+/// Raise the priority of a pending item by one, returning its new priority
+pub fn bump(mod_event_id: &EventId) -> Option<u32> {
+    state::update(|state| {
+        let item = state.pending.get_mut(mod_event_id)?;
+        item.priority += 1;
+        Some(item.priority)
+    })
+}
+
+/// Set the reason for a content warning on a pending item, returning `false` if there is none
+pub fn set_content_warning(mod_event_id: &EventId, reason: String) -> bool {
+    state::update(|state| match state.pending.get_mut(mod_event_id) {
+        Some(item) => {
+            item.content_warning = Some(reason);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Hold back `moderator`'s ❌ on a pending item until it has a rejection reason
+///
+/// Returns whether they are the first waiting on one, so it is asked for only once.
+pub fn await_rejection_reason(mod_event_id: &EventId, moderator: &UserId) -> bool {
+    state::update(|state| match state.pending.get_mut(mod_event_id) {
+        Some(item) if !item.awaiting_reason.contains(moderator) => {
+            item.awaiting_reason.push(moderator.clone());
+            item.awaiting_reason.len() == 1
+        }
+        _ => false,
+    })
+}
+
+/// Give a pending item waiting on a rejection reason its reason, returning the moderators whose
+/// ❌ can count now
+///
+/// Returns `None` if there is no such item or nobody is waiting on a reason for it.
+pub fn give_rejection_reason(mod_event_id: &EventId, reason: String) -> Option<Vec<UserId>> {
+    state::update(|state| {
+        let item = state.pending.get_mut(mod_event_id)?;
+        if item.awaiting_reason.is_empty() {
+            return None;
+        }
+        item.rejection_reason = Some(reason);
+        Some(std::mem::take(&mut item.awaiting_reason))
+    })
+}
+
+/// Hold a pending item for discussion because of `reaction`, returning `false` if there is none
+/// or it can no longer be held
+pub fn hold(mod_event_id: &EventId, reaction: EventId) -> bool {
+    state::update(|state| {
+        let item = match state.pending.get_mut(mod_event_id) {
+            Some(item) => item,
+            None => return false,
+        };
+        if let Err(illegal) = lifecycle::apply_transition(item, Transition::Hold) {
+            tracing::info!("Not holding {}, {}", mod_event_id, illegal);
+            return false;
+        }
+        if !item.holds.contains(&reaction) {
+            item.holds.push(reaction);
+        }
+        true
+    })
+}
+
+/// Forget a hold whose reaction was removed
+///
+/// This returns the mod room event id of the item if that released it, which is when it was the
+/// last hold on an item still held.
+pub fn release(reaction: &EventId) -> Option<EventId> {
+    state::update(|state| {
+        let (mod_event_id, item) = state
+            .pending
+            .iter_mut()
+            .find(|(_, item)| item.holds.contains(reaction))?;
+        item.holds.retain(|hold| hold != reaction);
+        if !item.holds.is_empty() || !item.is_held() {
+            return None;
+        }
+        lifecycle::apply_transition(item, Transition::Release).ok()?;
+        Some(mod_event_id.clone())
+    })
+}
+
+/// Claim a pending item for `moderator` to review, returning `false` if there is none waiting in
+/// the queue, or another moderator's claim on it hasn't lapsed after `timeout`
+pub fn claim(
+    mod_event_id: &EventId,
+    moderator: &UserId,
+    reaction: EventId,
+    timeout: Duration,
+) -> bool {
+    state::update(|state| {
+        let item = match state.pending.get_mut(mod_event_id) {
+            Some(item) if matches!(item.status, Status::Pending | Status::Held) => item,
+            _ => return false,
+        };
+        if item
+            .claimant(timeout)
+            .is_some_and(|claimant| claimant != moderator)
+        {
+            return false;
+        }
+        item.claim = Some(Claim {
+            moderator: moderator.clone(),
+            reaction,
+            at: now_millis(),
+        });
+        true
+    })
+}
+
+/// Give up the claim made with `reaction`, which was removed, returning the mod room event id
+/// of the item it was on
+pub fn unclaim(reaction: &EventId) -> Option<EventId> {
+    state::update(|state| {
+        let (mod_event_id, item) = state.pending.iter_mut().find(|(_, item)| {
+            item.claim
+                .as_ref()
+                .is_some_and(|claim| &claim.reaction == reaction)
+        })?;
+        item.claim = None;
+        Some(mod_event_id.clone())
+    })
+}
+
+/// Count `sender`'s reaction `reaction_id` to a submission in its input room, returning `false`
+/// if it isn't pending
+pub fn count_source_reaction(
+    room_id: &RoomId,
+    event_id: &EventId,
+    reaction_id: EventId,
+    sender: &UserId,
+    emoji: &str,
+) -> bool {
+    state::update(|state| {
+        match state.pending.values_mut().find(|item| {
+            &item.source_room_id == room_id && item.source_event_id.as_ref() == Some(event_id)
+        }) {
+            Some(item) => {
+                item.source_reactions.insert(
+                    reaction_id,
+                    SourceReaction {
+                        sender: sender.clone(),
+                        emoji: emoji.to_owned(),
+                    },
+                );
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Stop counting the reaction `reaction_id` in `room_id`, now that it was redacted, returning
+/// whether it was counted
+pub fn uncount_source_reaction(room_id: &RoomId, reaction_id: &EventId) -> bool {
+    state::update(|state| {
+        state
+            .pending
+            .values_mut()
+            .filter(|item| &item.source_room_id == room_id)
+            .any(|item| item.source_reactions.remove(reaction_id).is_some())
+    })
+}
+
+/// Look up the pending item for the submission `event_id` in its input room `room_id`
+pub fn by_source(room_id: &RoomId, event_id: &EventId) -> Option<(EventId, PendingItem)> {
+    state::read(|state| {
+        state
+            .pending
+            .iter()
+            .find(|(_, item)| {
+                &item.source_room_id == room_id && item.source_event_id.as_ref() == Some(event_id)
+            })
+            .map(|(id, item)| (id.clone(), item.clone()))
+    })
+}
+
+/// Record that a pending item has been posted to the preview room
+pub fn set_preview(mod_event_id: &EventId, preview: Preview) {
+    state::update(|state| {
+        if let Some(item) = state.pending.get_mut(mod_event_id) {
+            item.preview = Some(preview);
+        }
+    });
+}
+
+/// Send a pending item back from the preview room to the mod room queue
+pub fn clear_preview(mod_event_id: &EventId) {
+    state::update(|state| {
+        if let Some(item) = state.pending.get_mut(mod_event_id) {
+            match lifecycle::apply_transition(item, Transition::Cancel) {
+                Ok(_) => item.preview = None,
+                Err(illegal) => tracing::info!("Leaving {} as it is, {}", mod_event_id, illegal),
+            }
+        }
+    });
+}
+
+/// Approve a pending item to be published at the time in `schedule`, returning `false` if it is
+/// held, or has been approved or decided already
+pub fn schedule(mod_event_id: &EventId, schedule: Schedule) -> bool {
+    state::update(|state| {
+        let item = match state.pending.get_mut(mod_event_id) {
+            // A held item waits for its discussion, however it would be approved
+            Some(item) if item.status == Status::Pending => item,
+            _ => return false,
+        };
+        if lifecycle::apply_transition(item, Transition::Approve).is_err() {
+            return false;
+        }
+        item.schedule = Some(schedule);
+        true
+    })
+}
+
+/// Send a scheduled item back to the queue, returning `false` if it wasn't scheduled
+pub fn unschedule(mod_event_id: &EventId) -> bool {
+    state::update(|state| match state.pending.get_mut(mod_event_id) {
+        Some(item) if item.schedule.is_some() => {
+            match lifecycle::apply_transition(item, Transition::Cancel) {
+                Ok(_) => item.schedule = None,
+                Err(illegal) => tracing::info!("Leaving {} as it is, {}", mod_event_id, illegal),
+            }
+            item.schedule.is_none()
+        }
+        _ => false,
+    })
+}
+
+/// Take the schedules of the items which are due by `now`, leaving the items approved to be
+/// published
+pub fn take_due(now: u64) -> Vec<(EventId, Schedule)> {
+    state::update(|state| {
+        state
+            .pending
+            .iter_mut()
+            .filter(|(_, item)| item.schedule.as_ref().is_some_and(|schedule| schedule.at <= now))
+            .filter_map(|(id, item)| item.schedule.take().map(|schedule| (id.clone(), schedule)))
+            .collect()
+    })
+}
+
+/// Look up the pending item whose copy in the preview room is `preview_event_id`
+pub fn by_preview(preview_event_id: &EventId) -> Option<(EventId, PendingItem)> {
+    state::read(|state| {
+        state
+            .pending
+            .iter()
+            .find(|(_, item)| {
+                item.preview
+                    .as_ref()
+                    .is_some_and(|preview| &preview.event_id == preview_event_id)
+            })
+            .map(|(id, item)| (id.clone(), item.clone()))
+    })
+}
+
+/// Expire every pending item submitted by `sender`, returning them
+pub fn remove_sender(sender: &UserId) -> Vec<(EventId, PendingItem)> {
+    let ids: Vec<EventId> = state::read(|state| {
+        state
+            .pending
+            .iter()
+            .filter(|(_, item)| &item.sender == sender)
+            .map(|(id, _)| id.clone())
+            .collect()
+    });
+    ids.into_iter()
+        .filter_map(|id| transition(&id, Transition::Expire).map(|item| (id, item)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tally,
+        testing::{self, event, room, user},
+    };
+
+    /// A submission `$source:example.org` from the input room, queued as `$mod:example.org`
+    fn queued() -> EventId {
+        let mod_event_id = testing::queue("$mod:example.org", "@sub:example.org", "hello");
+        state::update(|state| {
+            state.pending.get_mut(&mod_event_id).unwrap().source_event_id =
+                Some(event("$source:example.org"));
+        });
+        mod_event_id
+    }
+
+    fn react(reaction_id: &str, sender: &str, emoji: &str) -> bool {
+        count_source_reaction(
+            &room("!input:example.org"),
+            &event("$source:example.org"),
+            event(reaction_id),
+            &user(sender),
+            emoji,
+        )
+    }
+
+    fn summary(mod_event_id: &EventId) -> Option<String> {
+        get(mod_event_id).unwrap().source_reaction_summary(5)
+    }
+
+    #[test]
+    fn each_sender_counts_once() {
+        let _test = testing::isolate();
+        let mod_event_id = queued();
+
+        assert!(react("$r1:example.org", "@a:example.org", "👍"));
+        assert!(react("$r2:example.org", "@a:example.org", "👍"));
+        assert!(react("$r3:example.org", "@b:example.org", "👍"));
+        assert!(react("$r4:example.org", "@a:example.org", "❤️"));
+
+        assert_eq!(summary(&mod_event_id).as_deref(), Some("👍 2, ❤️ 1"));
+    }
+
+    #[test]
+    fn redacted_reactions_stop_counting() {
+        let _test = testing::isolate();
+        let mod_event_id = queued();
+        let input = room("!input:example.org");
+        react("$r1:example.org", "@a:example.org", "👍");
+        react("$r2:example.org", "@a:example.org", "👍");
+        react("$r3:example.org", "@b:example.org", "👍");
+
+        assert!(uncount_source_reaction(&input, &event("$r3:example.org")));
+        assert_eq!(summary(&mod_event_id).as_deref(), Some("👍 1"));
+
+        // `@a` still has their other one
+        assert!(uncount_source_reaction(&input, &event("$r1:example.org")));
+        assert_eq!(summary(&mod_event_id).as_deref(), Some("👍 1"));
+
+        assert!(uncount_source_reaction(&input, &event("$r2:example.org")));
+        assert_eq!(summary(&mod_event_id), None);
+        assert!(!uncount_source_reaction(&input, &event("$r2:example.org")));
+    }
+
+    /// `$source:example.org` published as `$out:example.org`, with its reactions tallied
+    fn published() -> EventId {
+        let out = event("$out:example.org");
+        crate::history::record(
+            out.clone(),
+            room("!output:example.org"),
+            user("@sub:example.org"),
+            "hello",
+        );
+        tally::track(
+            &out,
+            &room("!input:example.org"),
+            &event("$source:example.org"),
+            TextMessageEventContent::plain("hello"),
+        );
+        out
+    }
+
+    fn tally_reaction(reaction_id: &str, sender: &str, emoji: &str) -> bool {
+        tally::count(
+            &room("!input:example.org"),
+            &event("$source:example.org"),
+            event(reaction_id),
+            &user(sender),
+            emoji,
+        )
+    }
+
+    fn tallied(out: &EventId) -> BTreeMap<String, u32> {
+        state::read(|state| state.published[out].tally.as_ref().unwrap().counts())
+    }
+
+    #[test]
+    fn tallies_count_each_sender_once() {
+        let _test = testing::isolate();
+        let out = published();
+
+        assert!(tally_reaction("$r1:example.org", "@a:example.org", "👍"));
+        assert!(tally_reaction("$r2:example.org", "@a:example.org", "👍"));
+        assert!(tally_reaction("$r3:example.org", "@b:example.org", "👍"));
+        assert!(tally_reaction("$r4:example.org", "@a:example.org", "❤️"));
+
+        let counts = tallied(&out);
+        assert_eq!(reaction_summary(&counts, 5).as_deref(), Some("👍 2, ❤️ 1"));
+    }
+
+    #[test]
+    fn redacted_reactions_leave_the_tally() {
+        let _test = testing::isolate();
+        let out = published();
+        let input = room("!input:example.org");
+        tally_reaction("$r1:example.org", "@a:example.org", "👍");
+        tally_reaction("$r2:example.org", "@b:example.org", "👍");
+
+        assert!(tally::uncount(&input, &event("$r2:example.org")));
+        assert_eq!(tallied(&out)["👍"], 1);
+
+        // Undoing and reacting again is still one
+        assert!(tally::uncount(&input, &event("$r1:example.org")));
+        assert!(tally_reaction("$r3:example.org", "@a:example.org", "👍"));
+        assert_eq!(tallied(&out)["👍"], 1);
+
+        assert!(tally::uncount(&input, &event("$r3:example.org")));
+        assert!(tallied(&out).is_empty());
+        assert!(!tally::uncount(&input, &event("$r3:example.org")));
+    }
+
+    #[test]
+    fn reactions_to_other_messages_are_not_counted() {
+        let _test = testing::isolate();
+        queued();
+
+        assert!(!count_source_reaction(
+            &room("!input:example.org"),
+            &event("$other:example.org"),
+            event("$r1:example.org"),
+            &user("@a:example.org"),
+            "👍",
+        ));
+        assert!(!uncount_source_reaction(
+            &room("!elsewhere:example.org"),
+            &event("$r1:example.org")
+        ));
+    }
+}
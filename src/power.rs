@@ -0,0 +1,165 @@
+use std::{
+    collections::BTreeSet,
+    fmt,
+    sync::{LazyLock, Mutex},
+};
+
+use matrix_sdk::{
+    room::Joined,
+    ruma::{
+        events::{room::power_levels::PowerLevelsEventContent, AnySyncStateEvent, EventType},
+        RoomId, UserId,
+    },
+    Client,
+};
+
+use crate::{config::Config, error::FourwarderError};
+
+/// Something the bot needs enough power for in a room
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Capability {
+    Send,
+    Redact,
+    React,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Capability::Send => "send messages",
+            Capability::Redact => "redact messages",
+            Capability::React => "react to messages",
+        })
+    }
+}
+
+/// What we found we lack the power for, the last time each room was checked
+static DENIED: LazyLock<Mutex<BTreeSet<(RoomId, Capability)>>> =
+    LazyLock::new(|| Mutex::new(BTreeSet::new()));
+
+/// Whether we have the power for `capability` in `room_id`
+///
+/// Rooms we haven't checked yet count as allowed, so the homeserver gets the final say.
+pub fn allowed(room_id: &RoomId, capability: Capability) -> bool {
+    !DENIED
+        .lock()
+        .unwrap()
+        .contains(&(room_id.clone(), capability))
+}
+
+/// What the bot needs to be able to do in `room_id`, given the features turned on
+fn needed(config: &Config, room_id: &RoomId) -> Vec<Capability> {
+    let is_input = config.input_room(room_id).is_some();
+    let is_output = room_id == &config.output_room_id
+        || config
+            .input_rooms
+            .iter()
+            .filter_map(|room| config.input_room(&room.room_id))
+            .any(|settings| settings.output_room_id == room_id);
+    let is_preview = config.preview_room_id.as_ref() == Some(room_id);
+
+    let mut needed = Vec::new();
+    // Input rooms are only posted to when turning submissions away from a full queue
+    let drops_when_full = config
+        .queue_limit
+        .as_ref()
+        .is_some_and(|limit| limit.drop_when_full);
+    if !is_input || drops_when_full {
+        needed.push(Capability::Send);
+    }
+    // `!erase` redacts in the output rooms and the mod room
+    if is_output
+        || is_preview
+        || room_id == &config.mod_room_id
+        || (is_input && config.redact_source_on_publish)
+    {
+        needed.push(Capability::Redact);
+    }
+    if is_input && config.react_on_source_decision {
+        needed.push(Capability::React);
+    }
+    needed
+}
+
+/// Check our power in every room the bot uses
+pub async fn check(client: &Client, config: &Config) -> Result<(), FourwarderError> {
+    let mut room_ids: Vec<&RoomId> = config.rooms().collect();
+    room_ids.sort();
+    room_ids.dedup();
+    for room_id in room_ids {
+        check_room(client, config, room_id).await?;
+    }
+    Ok(())
+}
+
+/// Check our power in `room_id`, warning about anything we need but may not do
+///
+/// Rooms we aren't in yet are skipped, they are checked once we join.
+pub async fn check_room(
+    client: &Client,
+    config: &Config,
+    room_id: &RoomId,
+) -> Result<(), FourwarderError> {
+    let room = match client.get_joined_room(room_id) {
+        Some(room) => room,
+        None => return Ok(()),
+    };
+    let own_user_id = client.user_id().await.ok_or(FourwarderError::Logic(
+        "We checked our power levels before logging in",
+    ))?;
+    let power_levels = power_levels(&room).await?;
+    let own_level = match room.get_member(&own_user_id).await? {
+        Some(member) => member.power_level(),
+        None => i64::from(user_level(&power_levels, &own_user_id)),
+    };
+
+    let mut denied = DENIED.lock().unwrap();
+    for capability in needed(config, room_id) {
+        let required = match capability {
+            Capability::Send => power_levels
+                .events
+                .get(&EventType::RoomMessage)
+                .copied()
+                .unwrap_or(power_levels.events_default),
+            Capability::Redact => power_levels.redact,
+            Capability::React => power_levels
+                .events
+                .get(&EventType::Reaction)
+                .copied()
+                .unwrap_or(power_levels.events_default),
+        };
+        let required = i64::from(required);
+        let key = (room_id.clone(), capability);
+        if own_level < required {
+            if denied.insert(key) {
+                tracing::warn!(
+                    "We can't {} in {}, that needs power level {} and we have {}",
+                    capability,
+                    room_id,
+                    required,
+                    own_level
+                );
+            }
+        } else if denied.remove(&key) {
+            tracing::info!("We can {} in {} again", capability, room_id);
+        }
+    }
+    Ok(())
+}
+
+/// The power levels of `room`, or the defaults if it has none
+async fn power_levels(room: &Joined) -> Result<PowerLevelsEventContent, FourwarderError> {
+    let event = room.get_state_event(EventType::RoomPowerLevels, "").await?;
+    Ok(match event.map(|event| event.deserialize()) {
+        Some(Ok(AnySyncStateEvent::RoomPowerLevels(event))) => event.content,
+        _ => PowerLevelsEventContent::default(),
+    })
+}
+
+fn user_level(power_levels: &PowerLevelsEventContent, user: &UserId) -> matrix_sdk::ruma::Int {
+    power_levels
+        .users
+        .get(user)
+        .copied()
+        .unwrap_or(power_levels.users_default)
+}
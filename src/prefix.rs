@@ -0,0 +1,59 @@
+use matrix_sdk::ruma::events::room::message::{MessageFormat, TextMessageEventContent};
+
+/// `text` without `prefix` and the whitespace after it, or `None` if it doesn't start with
+/// `prefix`, for `submission_prefix`
+///
+/// The prefix matches regardless of case, and has to be a word of its own, so `!submit` doesn't
+/// match `!submitted`. HTML has the prefix taken out after any tags it starts with, like `<p>`;
+/// where it can't be found there, the submission goes on as plain text.
+pub fn strip(text: &TextMessageEventContent, prefix: &str) -> Option<TextMessageEventContent> {
+    let body = strip_str(text.body.trim_start(), prefix)?;
+    if body.is_empty() {
+        return None;
+    }
+
+    let mut text = text.clone();
+    text.body = body.to_owned();
+    if let Some(formatted) = &mut text.formatted {
+        let stripped = if formatted.format == MessageFormat::Html {
+            strip_html(&formatted.body, prefix)
+        } else {
+            None
+        };
+        match stripped {
+            Some(html) => formatted.body = html,
+            None => text.formatted = None,
+        }
+    }
+    Some(text)
+}
+
+/// What follows `prefix` at the start of `text`, without the whitespace in between
+fn strip_str<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    if !head.eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+    let rest = &text[prefix.len()..];
+    let runs_on = prefix.chars().next_back().is_some_and(char::is_alphanumeric)
+        && rest.chars().next().is_some_and(char::is_alphanumeric);
+    if runs_on {
+        return None;
+    }
+    Some(rest.trim_start())
+}
+
+/// `html` with `prefix` taken out after the tags and whitespace it starts with
+fn strip_html(html: &str, prefix: &str) -> Option<String> {
+    let mut start = 0;
+    loop {
+        let rest = html[start..].trim_start();
+        start = html.len() - rest.len();
+        if !rest.starts_with('<') {
+            break;
+        }
+        start += rest.find('>')? + 1;
+    }
+    let rest = strip_str(&html[start..], prefix)?;
+    Some(format!("{}{}", &html[..start], rest))
+}
@@ -0,0 +1,99 @@
+use std::ops::Range;
+
+use matrix_sdk::ruma::events::room::message::{MessageFormat, TextMessageEventContent};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::{blocklist::word_matches, normalize::is_invisible};
+
+/// Replaces words from `profanity_mask` with asterisks, instead of dropping the message
+pub struct ProfanityMask<'a> {
+    /// Normalized and lowercased
+    words: &'a [String],
+}
+
+impl<'a> ProfanityMask<'a> {
+    pub fn new(words: &'a [String]) -> Self {
+        ProfanityMask { words }
+    }
+
+    /// Mask both the plain and formatted body of a text message
+    pub fn apply(&self, text: &TextMessageEventContent) -> TextMessageEventContent {
+        if self.words.is_empty() {
+            return text.clone();
+        }
+
+        let mut text = text.clone();
+        text.body = self.apply_plain(&text.body);
+        if let Some(formatted) = &mut text.formatted {
+            if formatted.format == MessageFormat::Html {
+                formatted.body = self.apply_html(&formatted.body);
+            }
+        }
+        text
+    }
+
+    /// Match against the normalized text, but mask the characters of the original
+    ///
+    /// Each character is normalized together with the combining marks following it, so decomposed
+    /// accents still match, and we know which characters of the original each match covers.
+    fn apply_plain(&self, text: &str) -> String {
+        let mut normalized = String::with_capacity(text.len());
+        // The byte range of each cluster in `text`, and where its normalized form starts
+        let mut clusters: Vec<(Range<usize>, usize)> = Vec::new();
+
+        let mut chars = text.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            let mut end = start + c.len_utf8();
+            while let Some((i, mark)) = chars.next_if(|(_, c)| is_combining_mark(*c)) {
+                end = i + mark.len_utf8();
+            }
+
+            clusters.push((start..end, normalized.len()));
+            let cluster: String = text[start..end]
+                .chars()
+                .filter(|c| !is_invisible(*c))
+                .nfkc()
+                .collect();
+            normalized.push_str(&cluster.to_lowercase());
+        }
+
+        let mut masked = vec![false; clusters.len()];
+        for word in self.words {
+            for match_start in word_matches(&normalized, word) {
+                let match_end = match_start + word.len();
+                for (i, (original, start)) in clusters.iter().enumerate() {
+                    let end = clusters.get(i + 1).map_or(normalized.len(), |next| next.1);
+                    let visible = text[original.clone()].chars().any(|c| !is_invisible(c));
+                    if visible && *start < match_end && end > match_start {
+                        masked[i] = true;
+                    }
+                }
+            }
+        }
+
+        clusters
+            .iter()
+            .zip(masked)
+            .map(|((original, _), masked)| if masked { "*" } else { &text[original.clone()] })
+            .collect()
+    }
+
+    /// Mask the text of an HTML body, leaving its tags alone
+    fn apply_html(&self, html: &str) -> String {
+        let mut out = String::with_capacity(html.len());
+        let mut rest = html;
+
+        while let Some(tag_start) = rest.find('<') {
+            out.push_str(&self.apply_plain(&rest[..tag_start]));
+
+            let tag_len = rest[tag_start..]
+                .find('>')
+                .map_or(rest.len() - tag_start, |end| end + 1);
+            out.push_str(&rest[tag_start..tag_start + tag_len]);
+            rest = &rest[tag_start + tag_len..];
+        }
+        out.push_str(&self.apply_plain(rest));
+
+        out
+    }
+}
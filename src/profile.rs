@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use matrix_sdk::{
+    ruma::{
+        api::client::r0::profile::get_profile,
+        events::room::message::{FormattedBody, MessageFormat, TextMessageEventContent},
+        MxcUri, UserId,
+    },
+    Client,
+};
+
+use crate::{clock, cw::escape_html};
+
+/// How long a fetched profile is used before it is fetched again
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The avatar shown next to a sender's name is this many pixels square
+const AVATAR_SIZE: u32 = 24;
+
+static CACHE: LazyLock<Mutex<HashMap<UserId, (Instant, Profile)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// What a submitter calls themselves
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub display_name: Option<String>,
+    pub avatar_url: Option<MxcUri>,
+}
+
+/// Look up `user`'s profile, from the cache if we fetched it recently
+///
+/// A profile which can't be fetched is logged and treated as empty, so the submission is
+/// still published under the user's id.
+pub async fn get(client: &Client, user: &UserId) -> Profile {
+    if let Some((fetched_at, profile)) = CACHE.lock().unwrap().get(user) {
+        if clock::now().saturating_duration_since(*fetched_at) < CACHE_TTL {
+            return profile.clone();
+        }
+    }
+
+    match client.send(get_profile::Request::new(user), None).await {
+        Ok(response) => {
+            let profile = Profile {
+                display_name: response.displayname,
+                avatar_url: response.avatar_url,
+            };
+            CACHE
+                .lock()
+                .unwrap()
+                .insert(user.clone(), (clock::now(), profile.clone()));
+            profile
+        }
+        Err(e) => {
+            tracing::warn!("Could not fetch the profile of {}: {}", user, e);
+            Profile::default()
+        }
+    }
+}
+
+impl Profile {
+    /// Put `user`'s name and avatar in front of `text`, so it reads as if they posted it
+    pub fn present(
+        &self,
+        user: &UserId,
+        text: &TextMessageEventContent,
+    ) -> TextMessageEventContent {
+        let name = self
+            .display_name
+            .clone()
+            .unwrap_or_else(|| user.to_string());
+        let html = match &text.formatted {
+            Some(formatted) if formatted.format == MessageFormat::Html => formatted.body.clone(),
+            _ => escape_html(&text.body).replace('\n', "<br>"),
+        };
+        let avatar = match &self.avatar_url {
+            Some(avatar_url) => format!(
+                "<img src=\"{}\" width=\"{size}\" height=\"{size}\" alt=\"\"> ",
+                escape_html(avatar_url.as_str()),
+                size = AVATAR_SIZE
+            ),
+            None => String::new(),
+        };
+
+        let mut presented = text.clone();
+        presented.body = format!("{}: {}", name, text.body);
+        presented.formatted = Some(FormattedBody::html(format!(
+            "{}<strong>{}</strong>: {}",
+            avatar,
+            escape_html(&name),
+            html
+        )));
+        presented
+    }
+}
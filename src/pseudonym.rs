@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use matrix_sdk::ruma::UserId;
+
+use crate::{clock::now_millis, messages::Messages, state};
+
+/// The number of `user`'s pseudonym, giving them the next one if they have none yet
+///
+/// Numbers are never reused, so a pseudonym always means the same sender.
+pub fn number(user: &UserId) -> u64 {
+    state::update(|state| {
+        state.pseudonyms_used.insert(user.clone(), now_millis());
+        if let Some(number) = state.pseudonyms.get(user) {
+            return *number;
+        }
+        state.last_pseudonym += 1;
+        state.pseudonyms.insert(user.clone(), state.last_pseudonym);
+        state.last_pseudonym
+    })
+}
+
+/// The number `user`'s pseudonym has or would have, without giving them one
+pub fn peek(user: &UserId) -> u64 {
+    state::read(|state| {
+        state
+            .pseudonyms
+            .get(user)
+            .copied()
+            .unwrap_or(state.last_pseudonym + 1)
+    })
+}
+
+/// The pseudonym numbered `number`, like `Author 3`
+pub fn name(messages: &Messages, number: u64) -> String {
+    messages.get("pseudonym", &[("number", &number)])
+}
+
+/// Who is behind `pseudonym`, which can be given as the full pseudonym or just its number
+pub fn reveal(messages: &Messages, pseudonym: &str) -> Option<UserId> {
+    let pseudonym = pseudonym.trim();
+    let number = pseudonym.parse::<u64>().ok();
+    state::read(|state| {
+        state
+            .pseudonyms
+            .iter()
+            .find(|(_, n)| {
+                number == Some(**n) || name(messages, **n).eq_ignore_ascii_case(pseudonym)
+            })
+            .map(|(user, _)| user.clone())
+    })
+}
+
+/// Forget `user`'s pseudonym, so it can't be traced back to them any more
+pub fn forget(user: &UserId) {
+    state::update(|state| {
+        state.pseudonyms_used.remove(user);
+        state.pseudonyms.remove(user)
+    });
+}
+
+/// Forget the pseudonyms of senders who haven't had one used for `retention`, returning how many
+/// there were
+///
+/// They get a new number if they submit again, so an old pseudonym never comes to mean someone
+/// else, but `!reveal` can no longer tell who it was. Pseudonyms from before their use was
+/// tracked count as used at the first prune.
+pub fn prune(retention: Duration) -> usize {
+    let now = now_millis();
+    state::update(|state| {
+        for user in state.pseudonyms.keys() {
+            state.pseudonyms_used.entry(user.clone()).or_insert(now);
+        }
+        let used = &mut state.pseudonyms_used;
+        used.retain(|_, used_at| now.saturating_sub(*used_at) <= retention.as_millis() as u64);
+        let before = state.pseudonyms.len();
+        state.pseudonyms.retain(|user, _| used.contains_key(user));
+        before - state.pseudonyms.len()
+    })
+}
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use matrix_sdk::{
+    ruma::{events::room::message::MessageEventContent, UserId},
+    Client,
+};
+
+use crate::{
+    blocklist::contains_word,
+    config::Config,
+    error::FourwarderError,
+    metrics,
+    normalize::normalize_for_matching,
+    pending::{self, HIGH_PRIORITY, NORMAL_PRIORITY},
+    send,
+};
+
+/// The `max_queue_size` settings
+pub struct QueueLimit {
+    /// The mod room is alerted once this many submissions are pending
+    pub max: usize,
+    /// The alert clears once fewer than this many submissions are pending
+    pub low_watermark: usize,
+    /// Drop new submissions while the queue is full, instead of queueing them anyway
+    pub drop_when_full: bool,
+}
+
+/// Whether the mod room has been told the queue is full, and not yet that it recovered
+static ALERTED: AtomicBool = AtomicBool::new(false);
+
+/// The priority a new submission enters the queue with
+pub fn priority(config: &Config, sender: &UserId, body: &str) -> u32 {
+    let body = normalize_for_matching(body).to_lowercase();
+    if config
+        .priority_senders
+        .iter()
+        .any(|pattern| pattern.matches(sender))
+        || config
+            .priority_keywords
+            .iter()
+            .any(|keyword| contains_word(&body, keyword))
+    {
+        HIGH_PRIORITY
+    } else {
+        NORMAL_PRIORITY
+    }
+}
+
+/// Whether a new submission should be dropped rather than queued
+///
+/// Only normal priority submissions are dropped, so important ones still get through.
+pub fn rejects_new(config: &Config, priority: u32) -> bool {
+    config.queue_limit.as_ref().is_some_and(|limit| {
+        limit.drop_when_full && priority == NORMAL_PRIORITY && pending::len() >= limit.max
+    })
+}
+
+/// Update the queue depth metric, and alert the mod room if the queue filled up or recovered
+///
+/// Call this whenever a submission enters or leaves the queue.
+pub async fn check(client: &Client, config: &Config) -> Result<(), FourwarderError> {
+    let depth = pending::len();
+    metrics::set_gauge(
+        "fourwarder_queue_depth",
+        "Submissions waiting on a decision in the mod room",
+        depth as f64,
+    );
+
+    let limit = match &config.queue_limit {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+
+    let notice = if depth >= limit.max && !ALERTED.swap(true, Ordering::SeqCst) {
+        config
+            .messages
+            .get("queue_full_alert", &[("count", &depth)])
+    } else if depth < limit.low_watermark && ALERTED.swap(false, Ordering::SeqCst) {
+        config.messages.get("queue_recovered", &[])
+    } else {
+        return Ok(());
+    };
+
+    tracing::warn!("{}", notice);
+    send::message(
+        client,
+        &config.mod_room_id,
+        MessageEventContent::notice_plain(notice),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use std::fmt;
+
+use matrix_sdk::ruma::{EventId, UserId};
+
+use crate::{category::Category, config::Config, state};
+
+/// Which way a moderator reacted to a submission
+#[derive(Clone, Copy, Debug)]
+pub enum Vote<'a> {
+    Approve,
+    /// Approving toward a category, which is counted apart from plain approvals
+    ApproveAs(&'a Category),
+    Reject,
+}
+
+impl fmt::Display for Vote<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Vote::Approve => write!(f, "approve"),
+            Vote::ApproveAs(category) => write!(f, "approve for {}", category.name),
+            Vote::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+/// Count `moderator`'s vote on a pending item, returning whether it can now be decided that way
+///
+/// Each moderator counts once per side, however many times they react. Messages we have no
+/// record of have nowhere to count votes, so they are only decided by a moderator whose weight
+/// alone is enough.
+pub fn vote(config: &Config, mod_event_id: &EventId, moderator: &UserId, vote: Vote) -> bool {
+    let required = required(config, vote);
+    let weight = state::update(|state| {
+        let item = state.pending.get_mut(mod_event_id)?;
+        let voters = match vote {
+            Vote::Approve => &mut item.approvers,
+            Vote::ApproveAs(category) => item
+                .category_approvers
+                .entry(category.name.clone())
+                .or_default(),
+            Vote::Reject => &mut item.rejecters,
+        };
+        if !voters.contains(moderator) {
+            voters.push(moderator.clone());
+        }
+        Some(weight(config, voters))
+    })
+    .unwrap_or_else(|| config.weight(moderator));
+
+    tracing::info!(
+        "{} voted to {} {}, weight {}/{}",
+        moderator,
+        vote,
+        mod_event_id,
+        weight,
+        required
+    );
+    weight >= required
+}
+
+/// Whether `moderator`'s vote would let a pending item be decided that way, along with the votes
+/// it already has, without counting it
+///
+/// Returns the weight it would have and the weight it needs, or `None` if it isn't pending.
+pub fn would_pass(
+    config: &Config,
+    mod_event_id: &EventId,
+    moderator: &UserId,
+    vote: Vote,
+) -> Option<(u32, u32)> {
+    state::read(|state| {
+        let item = state.pending.get(mod_event_id)?;
+        let mut voters = match vote {
+            Vote::Approve => item.approvers.clone(),
+            Vote::ApproveAs(category) => item
+                .category_approvers
+                .get(&category.name)
+                .cloned()
+                .unwrap_or_default(),
+            Vote::Reject => item.rejecters.clone(),
+        };
+        if !voters.contains(moderator) {
+            voters.push(moderator.clone());
+        }
+        Some((weight(config, &voters), required(config, vote)))
+    })
+}
+
+/// The weight votes `vote`'s way need to add up to
+fn required(config: &Config, vote: Vote) -> u32 {
+    match vote {
+        Vote::Approve => config.required_approval_weight,
+        Vote::ApproveAs(category) => category.required_approval_weight,
+        Vote::Reject => config.required_rejection_weight,
+    }
+}
+
+/// The summed weight of `voters`
+fn weight(config: &Config, voters: &[UserId]) -> u32 {
+    voters.iter().map(|voter| config.weight(voter)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, user};
+
+    const WEIGHTS: &str = r#"
+required_approval_weight = 3
+required_rejection_weight = 2
+
+[moderator_weights]
+"@lead:example.org" = 2
+"#;
+
+    fn vote_as(config: &Config, mod_event_id: &EventId, moderator: &str, way: Vote) -> bool {
+        vote(config, mod_event_id, &user(moderator), way)
+    }
+
+    #[test]
+    fn weights_add_up_to_the_quorum() {
+        let _test = testing::isolate();
+        let config = testing::config(WEIGHTS);
+        let mod_event_id = testing::queue("$a:example.org", "@sub:example.org", "hello");
+
+        assert!(!vote_as(&config, &mod_event_id, "@lead:example.org", Vote::Approve));
+        assert!(vote_as(&config, &mod_event_id, "@mod:example.org", Vote::Approve));
+    }
+
+    #[test]
+    fn light_votes_need_more_of_them() {
+        let _test = testing::isolate();
+        let config = testing::config(WEIGHTS);
+        let mod_event_id = testing::queue("$a:example.org", "@sub:example.org", "hello");
+
+        assert!(!vote_as(&config, &mod_event_id, "@a:example.org", Vote::Approve));
+        assert!(!vote_as(&config, &mod_event_id, "@b:example.org", Vote::Approve));
+        assert!(vote_as(&config, &mod_event_id, "@c:example.org", Vote::Approve));
+    }
+
+    #[test]
+    fn each_moderator_counts_once() {
+        let _test = testing::isolate();
+        let config = testing::config(WEIGHTS);
+        let mod_event_id = testing::queue("$a:example.org", "@sub:example.org", "hello");
+
+        assert!(!vote_as(&config, &mod_event_id, "@lead:example.org", Vote::Approve));
+        assert!(!vote_as(&config, &mod_event_id, "@lead:example.org", Vote::Approve));
+        assert_eq!(
+            would_pass(&config, &mod_event_id, &user("@lead:example.org"), Vote::Approve),
+            Some((2, 3))
+        );
+    }
+
+    #[test]
+    fn approvals_and_rejections_are_counted_apart() {
+        let _test = testing::isolate();
+        let config = testing::config(WEIGHTS);
+        let mod_event_id = testing::queue("$a:example.org", "@sub:example.org", "hello");
+
+        assert!(!vote_as(&config, &mod_event_id, "@a:example.org", Vote::Approve));
+        assert!(!vote_as(&config, &mod_event_id, "@b:example.org", Vote::Reject));
+        assert!(vote_as(&config, &mod_event_id, "@lead:example.org", Vote::Reject));
+    }
+
+    #[test]
+    fn untracked_messages_need_the_whole_quorum_from_one_vote() {
+        let _test = testing::isolate();
+        let config = testing::config(WEIGHTS);
+        let untracked = testing::event("$untracked:example.org");
+
+        assert!(!vote_as(&config, &untracked, "@mod:example.org", Vote::Approve));
+        assert!(!vote_as(&config, &untracked, "@lead:example.org", Vote::Approve));
+        assert!(!vote_as(&config, &untracked, "@mod:example.org", Vote::Reject));
+        assert!(vote_as(&config, &untracked, "@lead:example.org", Vote::Reject));
+    }
+
+    #[test]
+    fn untracked_messages_are_decided_by_one_vote_without_a_quorum() {
+        let _test = testing::isolate();
+        let config = testing::config("");
+        let untracked = testing::event("$untracked:example.org");
+
+        assert!(vote_as(&config, &untracked, "@mod:example.org", Vote::Approve));
+        assert!(vote_as(&config, &untracked, "@mod:example.org", Vote::Reject));
+    }
+}
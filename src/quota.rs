@@ -0,0 +1,155 @@
+use std::{collections::VecDeque, time::Duration};
+
+use matrix_sdk::ruma::UserId;
+
+use crate::{clock::now_millis, config::Config, state};
+
+/// When `sender` can submit again, in milliseconds since the Unix epoch, or `None` if they are
+/// still within `daily_submission_quota`
+///
+/// Only submissions which were taken count toward it, see [`count`]. Trusted senders never
+/// exceed it.
+pub fn exceeded(config: &Config, sender: &UserId) -> Option<u64> {
+    let quota = config.daily_submission_quota?;
+    if config.is_trusted(sender) {
+        return None;
+    }
+    let now = now_millis();
+    state::read(|state| {
+        let taken = state.submission_quota.get(sender)?;
+        resets_at(taken, quota, config.submission_quota_window, now)
+    })
+}
+
+/// Count a submission `sender` just had taken, by being queued or published, toward their
+/// `daily_submission_quota`
+pub fn count(config: &Config, sender: &UserId) {
+    if config.daily_submission_quota.is_none() || config.is_trusted(sender) {
+        return;
+    }
+    let now = now_millis();
+    let window = config.submission_quota_window;
+    state::update(|state| {
+        let taken = state.submission_quota.entry(sender.clone()).or_default();
+        forget_before(taken, window, now);
+        taken.push_back(now);
+    });
+}
+
+/// Forget the submissions which have left the window of every sender, and senders left with
+/// none, returning how many senders were forgotten
+pub fn prune(window: Duration) -> usize {
+    let now = now_millis();
+    state::update(|state| {
+        let before = state.submission_quota.len();
+        for taken in state.submission_quota.values_mut() {
+            forget_before(taken, window, now);
+        }
+        state.submission_quota.retain(|_, taken| !taken.is_empty());
+        before - state.submission_quota.len()
+    })
+}
+
+/// Forget when `sender`'s submissions were taken
+pub fn remove_sender(sender: &UserId) {
+    state::update(|state| state.submission_quota.remove(sender));
+}
+
+/// When the oldest of `taken` leaves the `window` ending `now`, if `quota` of them are in it
+///
+/// The window rolls rather than starting at midnight, so a sender gets one more submission each
+/// time one of theirs is a whole window old.
+fn resets_at(taken: &VecDeque<u64>, quota: u32, window: Duration, now: u64) -> Option<u64> {
+    let window = window.as_millis() as u64;
+    let in_window: Vec<u64> = taken
+        .iter()
+        .copied()
+        .filter(|at| now.saturating_sub(*at) < window)
+        .collect();
+    // The `quota`th newest is the one which has to leave for there to be room again
+    let len = in_window.len();
+    (len >= quota as usize).then(|| in_window[len - quota as usize] + window)
+}
+
+/// Drop the times in `taken` which are a whole `window` before `now`
+fn forget_before(taken: &mut VecDeque<u64>, window: Duration, now: u64) {
+    let window = window.as_millis() as u64;
+    while taken
+        .front()
+        .is_some_and(|at| now.saturating_sub(*at) >= window)
+    {
+        taken.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, user};
+
+    const QUOTA: &str = r#"
+daily_submission_quota = 2
+auto_approve_karma = 3
+priority_senders = ["@vip:example.org"]
+moderators = ["@mod:example.org"]
+admins = ["@admin:example.org"]
+"#;
+
+    /// Count a submission from `sender`, as if it was just taken
+    fn submit(config: &Config, sender: &str) -> Option<u64> {
+        let exceeded = exceeded(config, &user(sender));
+        if exceeded.is_none() {
+            count(config, &user(sender));
+        }
+        exceeded
+    }
+
+    #[test]
+    fn quota_resets_after_the_window() {
+        let test = testing::isolate();
+        let config = testing::config(QUOTA);
+
+        assert_eq!(submit(&config, "@sub:example.org"), None);
+        test.clock.advance(Duration::from_secs(60));
+        assert_eq!(submit(&config, "@sub:example.org"), None);
+        let resets_at = submit(&config, "@sub:example.org");
+        assert_eq!(resets_at, Some(testing::START_MILLIS + 86_400_000));
+
+        test.clock.advance(Duration::from_secs(86_400 - 60));
+        assert_eq!(submit(&config, "@sub:example.org"), None);
+        // The second is still in the window
+        assert!(submit(&config, "@sub:example.org").is_some());
+    }
+
+    #[test]
+    fn trusted_senders_are_exempt() {
+        let _test = testing::isolate();
+        let config = testing::config(QUOTA);
+        crate::karma::adjust(&user("@known:example.org"), 4);
+
+        for sender in [
+            "@vip:example.org",
+            "@mod:example.org",
+            "@admin:example.org",
+            "@known:example.org",
+        ] {
+            for _ in 0..5 {
+                assert_eq!(submit(&config, sender), None, "{} was limited", sender);
+            }
+        }
+        crate::karma::adjust(&user("@sub:example.org"), 3);
+        for _ in 0..2 {
+            assert_eq!(submit(&config, "@sub:example.org"), None);
+        }
+        assert!(submit(&config, "@sub:example.org").is_some());
+    }
+
+    #[test]
+    fn empty_moderators_trust_no_one() {
+        let _test = testing::isolate();
+        let config = testing::config("daily_submission_quota = 1");
+
+        assert_eq!(submit(&config, "@sub:example.org"), None);
+        assert!(submit(&config, "@sub:example.org").is_some());
+    }
+}
@@ -0,0 +1,45 @@
+use matrix_sdk::ruma::events::room::message::{
+    FormattedBody, MessageFormat, TextMessageEventContent,
+};
+use serde::Deserialize;
+
+use crate::cw::escape_html;
+
+/// How published submissions are set apart from what the bot says itself
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteStyle {
+    /// As a message of their own, like the submitter had posted them
+    #[default]
+    Plain,
+    /// Quoted, under a line saying who wrote them if `attribution` is given
+    Blockquote,
+}
+
+/// Quote `text` under `attribution`, with `> ` in front of every line of the plain text
+///
+/// The HTML is quoted whole, so a spoiler or anything else already in it stays inside the quote.
+pub fn blockquote(
+    text: &TextMessageEventContent,
+    attribution: Option<&str>,
+) -> TextMessageEventContent {
+    let html = match &text.formatted {
+        Some(formatted) if formatted.format == MessageFormat::Html => formatted.body.clone(),
+        _ => escape_html(&text.body).replace('\n', "<br>"),
+    };
+    let quoted: Vec<String> = text
+        .body
+        .lines()
+        .map(|line| format!("> {}", line))
+        .collect();
+
+    let mut text = text.clone();
+    text.body = quoted.join("\n");
+    let mut formatted = format!("<blockquote>{}</blockquote>", html);
+    if let Some(attribution) = attribution {
+        text.body = format!("{}\n{}", attribution, text.body);
+        formatted = format!("<p>{}</p>{}", escape_html(attribution), formatted);
+    }
+    text.formatted = Some(FormattedBody::html(formatted));
+    text
+}
@@ -0,0 +1,131 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use matrix_sdk::ruma::UserId;
+
+use crate::{
+    clock,
+    filters::{FilterDecision, IncomingMessage, MessageFilter},
+};
+
+/// How far back submissions count toward `rate_limit_per_minute`
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// When each sender's submissions in the last [`WINDOW`] arrived, oldest first
+///
+/// This outlives the filter, which is rebuilt whenever the config changes, so changing the limit
+/// doesn't forget who is being throttled.
+static RECENT: LazyLock<Mutex<HashMap<UserId, VecDeque<Instant>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Drops submissions from senders who already sent `rate_limit_per_minute` in the last minute
+pub struct RateLimit {
+    per_minute: u32,
+}
+
+impl RateLimit {
+    pub fn new(per_minute: u32) -> Self {
+        RateLimit { per_minute }
+    }
+}
+
+impl MessageFilter for RateLimit {
+    fn name(&self) -> &'static str {
+        "rate_limit"
+    }
+
+    fn evaluate(&self, msg: &IncomingMessage) -> FilterDecision {
+        let now = clock::now();
+        let mut recent = RECENT.lock().unwrap();
+        // Senders who have gone quiet are forgotten, so this doesn't grow forever
+        recent.retain(|_, sent| {
+            sent.back()
+                .is_some_and(|at| now.duration_since(*at) < WINDOW)
+        });
+        let sent = recent.entry(msg.sender.clone()).or_default();
+        while sent
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= WINDOW)
+        {
+            sent.pop_front();
+        }
+
+        // Dropped submissions don't count, so a sender who keeps trying isn't locked out
+        if sent.len() >= self.per_minute as usize {
+            return FilterDecision::Drop(format!(
+                "more than {} submissions in a minute",
+                self.per_minute
+            ));
+        }
+        sent.push_back(now);
+        FilterDecision::Allow
+    }
+}
+
+/// How many senders are at the limit of `per_minute` right now
+pub fn throttled(per_minute: u32) -> usize {
+    let now = clock::now();
+    RECENT
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|sent| {
+            sent.iter()
+                .filter(|at| now.duration_since(**at) < WINDOW)
+                .count()
+                >= per_minute as usize
+        })
+        .count()
+}
+
+/// Forget `user`'s recent submissions, so they can send a full minute's worth again
+///
+/// Returns `false` if they hadn't sent any.
+pub fn reset(user: &UserId) -> bool {
+    RECENT.lock().unwrap().remove(user).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::ruma::{events::room::message::TextMessageEventContent, EventId, RoomId};
+
+    use super::*;
+    use crate::testing::{self, user};
+
+    fn submit(filter: &RateLimit, sender: &UserId) -> bool {
+        let room_id = RoomId::try_from("!input:example.org").unwrap();
+        let event_id = EventId::try_from("$event:example.org").unwrap();
+        let text = TextMessageEventContent::plain("hello");
+        let msg = IncomingMessage {
+            room_id: &room_id,
+            event_id: &event_id,
+            sender,
+            text: &text,
+        };
+        matches!(filter.evaluate(&msg), FilterDecision::Allow)
+    }
+
+    #[test]
+    fn limit_lifts_once_the_minute_is_over() {
+        let test = testing::isolate();
+        let filter = RateLimit::new(2);
+        let sender = user("@eager:example.org");
+
+        assert!(submit(&filter, &sender));
+        test.clock.advance(Duration::from_secs(30));
+        assert!(submit(&filter, &sender));
+        assert!(!submit(&filter, &sender));
+        assert_eq!(throttled(2), 1);
+
+        // The first one leaves the window, the second is still in it
+        test.clock.advance(Duration::from_secs(30));
+        assert!(submit(&filter, &sender));
+        assert!(!submit(&filter, &sender));
+        test.clock.advance(Duration::from_secs(60));
+        assert_eq!(throttled(2), 0);
+        assert!(submit(&filter, &sender));
+    }
+}
@@ -0,0 +1,290 @@
+use std::{sync::Arc, time::Duration};
+
+use matrix_sdk::{
+    room::Joined,
+    ruma::{RoomId, RoomIdOrAliasId, UserId},
+    Client,
+};
+use tokio::sync::Semaphore;
+
+use crate::{
+    audit::{self, Action},
+    config::{self, Config, CONFIG_LOCATION},
+    error::FourwarderError,
+};
+
+/// How many times to look for a room we should be in before giving up
+const JOINED_ATTEMPTS: u32 = 5;
+const JOINED_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// How many invites are accepted at once at startup
+const CONCURRENT_JOINS: usize = 4;
+/// How long accepting one invite may take, joining a very large room can take minutes
+const JOIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Accept our invites to the rooms the config uses, several at a time so one slow room doesn't
+/// hold up the others
+///
+/// Only failing to join the mod room or the output room stops startup. Any other room is logged
+/// and left, as [`joined`] tries to join it again when it is needed.
+pub async fn accept_invites(client: &Client, config: &Config) -> Result<(), FourwarderError> {
+    let invited: Vec<_> = client
+        .invited_rooms()
+        .into_iter()
+        .filter(|room| config.rooms().any(|room_id| room_id == room.room_id()))
+        .collect();
+    if invited.is_empty() {
+        return Ok(());
+    }
+    tracing::info!("Accepting {} invites", invited.len());
+
+    let slots = Arc::new(Semaphore::new(CONCURRENT_JOINS));
+    let joins: Vec<_> = invited
+        .into_iter()
+        .map(|room| {
+            let slots = slots.clone();
+            tokio::spawn(async move {
+                let _slot = slots.acquire().await;
+                let joined = tokio::time::timeout(JOIN_TIMEOUT, room.accept_invitation()).await;
+                (room.room_id().clone(), joined)
+            })
+        })
+        .collect();
+
+    let mut failed = None;
+    for join in joins {
+        let (room_id, joined) = match join.await {
+            Ok(joined) => joined,
+            Err(e) => {
+                tracing::warn!("Accepting an invite was cut short: {}", e);
+                continue;
+            }
+        };
+        match joined {
+            Ok(Ok(())) => {
+                tracing::info!("Joined {}", room_id);
+                continue;
+            }
+            Ok(Err(e)) => tracing::warn!("Could not join {}: {}", room_id, e),
+            Err(_) => tracing::warn!(
+                "Joining {} took longer than {}s, carrying on without it",
+                room_id,
+                JOIN_TIMEOUT.as_secs()
+            ),
+        }
+        if room_id == config.mod_room_id || room_id == config.output_room_id {
+            failed = Some(room_id);
+        }
+    }
+
+    match failed {
+        Some(room_id) => Err(FourwarderError::Join(room_id)),
+        None => Ok(()),
+    }
+}
+
+/// Get a room we are supposed to be in
+///
+/// Right after startup our own join may not have synced yet, so this retries for a little while.
+/// If we were kicked from the room we try to join it again.
+pub async fn joined(client: &Client, room_id: &RoomId) -> Result<Joined, FourwarderError> {
+    for attempt in 1..=JOINED_ATTEMPTS {
+        if let Some(room) = client.get_joined_room(room_id) {
+            return Ok(room);
+        }
+
+        if client.get_left_room(room_id).is_some() {
+            tracing::warn!("We are no longer in {}, joining it again", room_id);
+            if let Err(e) = client.join_room_by_id(room_id).await {
+                tracing::warn!("Could not join {} again: {}", room_id, e);
+            }
+        }
+
+        if attempt < JOINED_ATTEMPTS {
+            tokio::time::sleep(JOINED_RETRY_DELAY).await;
+        }
+    }
+
+    tracing::error!(
+        "Still not in {} after {} attempts",
+        room_id,
+        JOINED_ATTEMPTS
+    );
+    Err(FourwarderError::Logic(
+        "We could not get a joined room that we definetly have joined before",
+    ))
+}
+
+/// Join `room`, which may be an alias, returning its id
+///
+/// The reply to send is returned as the error if `room` isn't valid or can't be joined.
+async fn join(client: &Client, config: &Config, room: &str) -> Result<RoomId, String> {
+    let room_or_alias = RoomIdOrAliasId::try_from(room)
+        .map_err(|_| config.messages.get("room_invalid", &[("room", &room)]))?;
+
+    match client.join_room_by_id_or_alias(&room_or_alias, &[]).await {
+        Ok(response) => Ok(response.room_id),
+        Err(e) => {
+            tracing::warn!("Could not join {}: {}", room, e);
+            Err(config
+                .messages
+                .get("room_join_failed", &[("room", &room), ("error", &e)]))
+        }
+    }
+}
+
+/// Apply `edit` to the config, replying with `done` if it worked
+fn commit(config: &Config, done: String, edit: impl FnOnce(&mut toml::value::Table)) -> String {
+    match config::edit(CONFIG_LOCATION, edit) {
+        Ok(_) => done,
+        Err(e) => {
+            tracing::warn!("Could not change the rooms: {}", e);
+            config.messages.get("room_change_failed", &[("error", &e)])
+        }
+    }
+}
+
+/// Start taking submissions from `room`, publishing them to `output` if given
+pub async fn add_input(
+    client: &Client,
+    config: &Config,
+    room: &str,
+    output: Option<&str>,
+) -> Result<String, FourwarderError> {
+    let room_id = match join(client, config, room).await {
+        Ok(room_id) => room_id,
+        Err(reply) => return Ok(reply),
+    };
+    let output_room_id = match output {
+        Some(output) => match join(client, config, output).await {
+            Ok(room_id) => Some(room_id),
+            Err(reply) => return Ok(reply),
+        },
+        None => None,
+    };
+
+    let done = config.messages.get("added_input", &[("room", &room_id)]);
+    Ok(commit(config, done, |table| {
+        let mut entry = toml::value::Table::new();
+        entry.insert("input_room_id".to_owned(), room_id.to_string().into());
+        if let Some(output_room_id) = output_room_id {
+            entry.insert(
+                "output_room_id".to_owned(),
+                output_room_id.to_string().into(),
+            );
+        }
+
+        let rooms = table
+            .entry("rooms")
+            .or_insert_with(|| toml::Value::Array(Vec::new()));
+        if let Some(rooms) = rooms.as_array_mut() {
+            rooms.push(entry.into());
+        }
+    }))
+}
+
+/// Publish to `room` by default, instead of the current `output_room_id`
+pub async fn set_output(
+    client: &Client,
+    config: &Config,
+    room: &str,
+) -> Result<String, FourwarderError> {
+    let room_id = match join(client, config, room).await {
+        Ok(room_id) => room_id,
+        Err(reply) => return Ok(reply),
+    };
+
+    let done = config.messages.get("added_output", &[("room", &room_id)]);
+    Ok(commit(config, done, |table| {
+        table.insert("output_room_id".to_owned(), room_id.to_string().into());
+    }))
+}
+
+/// Mute the input room `room`, so its messages are ignored until it is unmuted, or unmute it
+///
+/// This changes `muted_rooms`, so a mute only outlasts a restart with `persist_runtime_changes`.
+pub fn set_muted(config: &Config, sender: &UserId, room: &str, muted: bool) -> String {
+    let room_id = match RoomId::try_from(room) {
+        Ok(room_id) => room_id,
+        Err(_) => return config.messages.get("room_invalid", &[("room", &room)]),
+    };
+    // A room which is no longer an input room can still be unmuted, to tidy up `muted_rooms`
+    if muted && config.input_room(&room_id).is_none() {
+        return config.messages.get("not_input", &[("room", &room_id)]);
+    }
+    if config.is_muted(&room_id) == muted {
+        let key = if muted { "already_muted" } else { "not_muted" };
+        return config.messages.get(key, &[("room", &room_id)]);
+    }
+
+    let done = config.messages.get(
+        if muted { "muted_room" } else { "unmuted_room" },
+        &[("room", &room_id)],
+    );
+    let reply = commit(config, done, |table| {
+        let rooms = table
+            .entry("muted_rooms")
+            .or_insert_with(|| toml::Value::Array(Vec::new()));
+        if let Some(rooms) = rooms.as_array_mut() {
+            rooms.retain(|entry| entry.as_str() != Some(room_id.as_str()));
+            if muted {
+                rooms.push(room_id.to_string().into());
+            }
+        }
+    });
+
+    if config::get().is_muted(&room_id) == muted {
+        tracing::info!(
+            "{} {} {}",
+            sender,
+            if muted { "muted" } else { "unmuted" },
+            room_id
+        );
+        let room_id = room_id.clone();
+        audit::record(
+            Some(sender),
+            if muted {
+                Action::Muted { room_id }
+            } else {
+                Action::Unmuted { room_id }
+            },
+        );
+    }
+    reply
+}
+
+/// Stop taking submissions from `room`, leaving it if the bot no longer needs to be there
+pub async fn remove_input(
+    client: &Client,
+    config: &Config,
+    room: &str,
+) -> Result<String, FourwarderError> {
+    let room_id = match RoomId::try_from(room) {
+        Ok(room_id) => room_id,
+        Err(_) => return Ok(config.messages.get("room_invalid", &[("room", &room)])),
+    };
+    if config.input_room(&room_id).is_none() {
+        return Ok(config.messages.get("not_input", &[("room", &room_id)]));
+    }
+
+    let done = config.messages.get("removed_input", &[("room", &room_id)]);
+    let reply = commit(config, done, |table| {
+        if table.get("input_room_id").and_then(toml::Value::as_str) == Some(room_id.as_str()) {
+            table.remove("input_room_id");
+        }
+        if let Some(rooms) = table.get_mut("rooms").and_then(toml::Value::as_array_mut) {
+            rooms.retain(|entry| {
+                entry.get("input_room_id").and_then(toml::Value::as_str) != Some(room_id.as_str())
+            });
+        }
+    });
+
+    let config = config::get();
+    if !config.rooms().any(|other| other == &room_id) {
+        if let Some(room) = client.get_joined_room(&room_id) {
+            room.leave().await?;
+        }
+    }
+
+    Ok(reply)
+}
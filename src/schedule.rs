@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use matrix_sdk::Client;
+use tokio::task::JoinHandle;
+
+use crate::{
+    clock,
+    config::{self, Config},
+    leader,
+    lifecycle::Transition,
+    pause, pending, APPROVE_EMOJI,
+};
+
+/// How often scheduled submissions are checked for ones which are due
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Publish scheduled submissions once they are due, checking every [`CHECK_INTERVAL`] until the
+/// returned task is aborted
+///
+/// Schedules are kept with the pending items in the state file, so they survive restarts, and
+/// ones which fell due while the bot was down are published on the first check.
+pub fn spawn(client: Client) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            publish_due(&client, &config::get()).await;
+        }
+    })
+}
+
+/// Publish every scheduled submission which is due, unless forwarding is paused
+///
+/// One which fails to publish goes back in the queue, like a failed approval.
+async fn publish_due(client: &Client, config: &Config) {
+    if !leader::is_active() || pause::is_paused() {
+        return;
+    }
+
+    for (mod_event_id, schedule) in pending::take_due(clock::now_millis()) {
+        tracing::info!(
+            "Publishing {}, which {} scheduled",
+            mod_event_id,
+            schedule.moderator
+        );
+        if let Err(e) = crate::publish_approved(
+            client,
+            config,
+            &config.mod_room_id,
+            &schedule.moderator,
+            &mod_event_id,
+            APPROVE_EMOJI,
+        )
+        .await
+        {
+            tracing::error!("Could not publish scheduled {}: {}", mod_event_id, e);
+            pending::transition(&mod_event_id, Transition::Cancel);
+        }
+    }
+}
+
+/// Milliseconds since the Unix epoch for `text`, a time like `2024-06-01T09:00` which is
+/// `utc_offset` minutes ahead of UTC
+pub fn parse_time(text: &str, utc_offset: i32) -> Option<u64> {
+    let (date, time) = text.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let (hour, minute) = time.split_once(':')?;
+    let (hour, minute) = (hour.parse::<i64>().ok()?, minute.parse::<i64>().ok()?);
+
+    if !(1970..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+    {
+        return None;
+    }
+    // A day which doesn't exist, like the 31st of June, doesn't come back the same
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    let minutes = days * 24 * 60 + hour * 60 + minute - i64::from(utc_offset);
+    u64::try_from(minutes * 60 * 1000).ok()
+}
+
+/// `millis` since the Unix epoch as a time like `2024-06-01T09:00`, `utc_offset` minutes ahead
+/// of UTC
+pub fn format_time(millis: u64, utc_offset: i32) -> String {
+    let minutes = (millis / 1000 / 60) as i64 + i64::from(utc_offset);
+    let (year, month, day) = civil_from_days(minutes.div_euclid(24 * 60));
+    let minute_of_day = minutes.rem_euclid(24 * 60);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}",
+        year,
+        month,
+        day,
+        minute_of_day / 60,
+        minute_of_day % 60
+    )
+}
+
+/// Minutes ahead of UTC for an offset like `+02:00` or `-05:30`
+pub fn parse_offset(text: &str) -> Option<i32> {
+    let (sign, offset) = match text.strip_prefix('-') {
+        Some(offset) => (-1, offset),
+        None => (1, text.strip_prefix('+')?),
+    };
+    let (hours, minutes) = offset.split_once(':')?;
+    let (hours, minutes) = (hours.parse::<i32>().ok()?, minutes.parse::<i32>().ok()?);
+    if !(0..=14).contains(&hours) || !(0..60).contains(&minutes) {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// Days since the Unix epoch of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Counting from March puts the leap day at the end of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date `days` after the Unix epoch, as year, month and day
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
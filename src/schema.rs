@@ -0,0 +1,164 @@
+use crate::config::RawConfig;
+
+/// The fields of [`RawConfig`] without a default, filled in with placeholders to check every
+/// other key on its own
+const REQUIRED: &[&str] = &[
+    "homeserver",
+    "username",
+    "password",
+    "mod_room_id",
+    "output_room_id",
+];
+
+/// Every key in `source` whose value doesn't fit the config's layout, and every required key
+/// missing from it, one line each like "field `moderate` expected a boolean, found integer `3`"
+///
+/// Deserializing the whole config stops at the first problem, and a missing field hides any
+/// wrong type, so each top-level key is checked on its own. Problems within a table or list, like
+/// a `[[rooms]]` entry, are only found one per key.
+pub fn problems(source: &toml::Value) -> Vec<String> {
+    let table = match source.as_table() {
+        Some(table) => table,
+        None => return vec!["the config is not a table".to_owned()],
+    };
+
+    let mut problems: Vec<String> = REQUIRED
+        .iter()
+        .filter(|field| !table.contains_key(**field))
+        .map(|field| format!("field `{}` is missing", field))
+        .collect();
+    for (key, value) in table {
+        let mut probe: toml::value::Table = REQUIRED
+            .iter()
+            .map(|field| (field.to_string(), toml::Value::String(String::new())))
+            .collect();
+        probe.insert(key.clone(), value.clone());
+        if let Err(e) = toml::Value::Table(probe).try_into::<RawConfig>() {
+            problems.push(describe(&e.to_string()));
+        }
+    }
+    problems
+}
+
+/// `message`, a serde error like "invalid type: string \"two\", expected u32 for key `x`", said
+/// the other way around, starting with the key it is about
+fn describe(message: &str) -> String {
+    // Only errors from parsing text say where they are, and the key says that better
+    let message = match message.rfind(" at line ") {
+        Some(at) => &message[..at],
+        None => message,
+    };
+    let (message, key) = match message.rsplit_once(" for key `") {
+        Some((message, key)) => (message, Some(key.trim_end_matches('`'))),
+        None => (message, None),
+    };
+
+    let missing = message
+        .strip_prefix("missing field `")
+        .and_then(|field| field.strip_suffix('`'));
+    let invalid = message
+        .strip_prefix("invalid type: ")
+        .and_then(|types| types.split_once(", expected "));
+    match (key, missing, invalid) {
+        (Some(key), Some(field), _) => format!("field `{}.{}` is missing", key, field),
+        (None, Some(field), _) => format!("field `{}` is missing", field),
+        (Some(key), None, Some((found, expected))) => {
+            format!("field `{}` expected {}, found {}", key, expected, found)
+        }
+        (Some(key), None, None) => format!("field `{}`: {}", key, message),
+        (None, None, _) => message.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config, error::FourwarderError};
+
+    fn problems(source: &str) -> Vec<String> {
+        match config::validate(&toml::from_str(source).unwrap()) {
+            Err(FourwarderError::Schema(problems)) => problems,
+            Err(e) => panic!("expected schema problems, got {}", e),
+            Ok(_) => panic!("expected schema problems, the config was accepted"),
+        }
+    }
+
+    #[test]
+    fn missing_fields() {
+        assert_eq!(
+            problems(r#"homeserver = "https://example.org""#),
+            [
+                "field `username` is missing",
+                "field `password` is missing",
+                "field `mod_room_id` is missing",
+                "field `output_room_id` is missing",
+            ]
+        );
+    }
+
+    #[test]
+    fn every_wrong_type_is_found() {
+        let problems = problems(
+            r#"
+homeserver = "https://example.org"
+username = "bot"
+password = "hunter2"
+mod_room_id = "!mod:example.org"
+output_room_id = "!output:example.org"
+moderate = 3
+max_queue_size = "two"
+"#,
+        );
+        assert_eq!(
+            problems,
+            [
+                "field `max_queue_size` expected usize, found string \"two\"",
+                "field `moderate` expected a boolean, found integer `3`",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_missing_field_does_not_hide_a_wrong_type() {
+        let problems = problems(
+            r#"
+username = "bot"
+password = "hunter2"
+mod_room_id = "!mod:example.org"
+output_room_id = "!output:example.org"
+admins = "@alice:example.org"
+"#,
+        );
+        assert_eq!(
+            problems,
+            [
+                "field `homeserver` is missing",
+                "field `admins` expected a sequence, found string \"@alice:example.org\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn problems_in_tables() {
+        let problems = problems(
+            r#"
+homeserver = "https://example.org"
+username = "bot"
+password = "hunter2"
+mod_room_id = "!mod:example.org"
+output_room_id = "!output:example.org"
+
+[[rooms]]
+moderate = true
+"#,
+        );
+        assert_eq!(problems, ["field `rooms.input_room_id` is missing"]);
+    }
+
+    #[test]
+    fn a_config_which_is_not_a_table() {
+        assert_eq!(
+            super::problems(&toml::Value::Integer(1)),
+            ["the config is not a table"]
+        );
+    }
+}
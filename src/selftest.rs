@@ -0,0 +1,169 @@
+use std::fmt::Display;
+
+use matrix_sdk::{
+    ruma::{
+        events::room::message::{MessageEventContent, TextMessageEventContent},
+        EventId, RoomId, UserId,
+    },
+    Client,
+};
+
+use crate::{
+    config::Config,
+    error::FourwarderError,
+    filters::{self, FilterDecision, IncomingMessage},
+    rooms, send,
+};
+
+/// The id the synthetic submission is evaluated under, which no real event can have
+const SELFTEST_EVENT_ID: &str = "$4warder_selftest";
+
+/// One line of the self-test report
+struct Check {
+    passed: bool,
+    detail: String,
+}
+
+/// Run a synthetic submission from `sender` through every input room, and check that we can
+/// post to every room we need
+///
+/// With `send`, a marked test message is actually posted to each output room and redacted
+/// straight after, which also checks that we may redact there.
+pub async fn run(
+    client: &Client,
+    config: &Config,
+    sender: &UserId,
+    send: bool,
+) -> Result<String, FourwarderError> {
+    let messages = &config.messages;
+    let mut checks = Vec::new();
+
+    let event_id = EventId::try_from(SELFTEST_EVENT_ID).expect("The self-test event id is valid");
+    let text = TextMessageEventContent::plain(messages.get("selftest_message", &[]));
+    for input in &config.input_rooms {
+        let room_id = &input.room_id;
+        let settings = match config.input_room(room_id) {
+            Some(settings) => settings,
+            None => continue,
+        };
+        let decision = filters::evaluate(
+            &config.filters,
+            &IncomingMessage {
+                room_id,
+                event_id: &event_id,
+                sender,
+                text: &text,
+            },
+        );
+        let destination = match &decision {
+            FilterDecision::Hold(_) => &config.mod_room_id,
+            _ if settings.moderate => &config.mod_room_id,
+            _ => settings.output_room_id,
+        };
+        let args: &[(&str, &(dyn Display + Sync))] =
+            &[("room", room_id), ("destination", destination)];
+        checks.push(match decision {
+            FilterDecision::Allow => Check {
+                passed: true,
+                detail: messages.get("selftest_route", args),
+            },
+            FilterDecision::Hold(reason) => Check {
+                passed: true,
+                detail: messages.get(
+                    "selftest_route_held",
+                    &[
+                        ("room", room_id),
+                        ("destination", destination),
+                        ("reason", &reason),
+                    ],
+                ),
+            },
+            FilterDecision::Drop(reason) => Check {
+                passed: false,
+                detail: messages.get(
+                    "selftest_route_dropped",
+                    &[("room", room_id), ("reason", &reason)],
+                ),
+            },
+        });
+    }
+
+    let mut room_ids: Vec<&RoomId> = config.rooms().collect();
+    room_ids.sort();
+    room_ids.dedup();
+    for room_id in room_ids {
+        checks.push(match rooms::joined(client, room_id).await {
+            Ok(_) => Check {
+                passed: true,
+                detail: messages.get("selftest_joined", &[("room", room_id)]),
+            },
+            Err(e) => Check {
+                passed: false,
+                detail: messages.get("selftest_not_joined", &[("room", room_id), ("error", &e)]),
+            },
+        });
+    }
+
+    if send {
+        let mut output_room_ids: Vec<&RoomId> = config
+            .input_rooms
+            .iter()
+            .filter_map(|input| config.input_room(&input.room_id))
+            .map(|settings| settings.output_room_id)
+            .chain([&config.output_room_id])
+            .collect();
+        output_room_ids.sort();
+        output_room_ids.dedup();
+        for room_id in output_room_ids {
+            checks.push(send_test_message(client, config, room_id).await);
+        }
+    }
+
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    let args: &[(&str, &(dyn Display + Sync))] = &[("checks", &checks.len()), ("failed", &failed)];
+    let mut report = if failed == 0 {
+        messages.get("selftest_passed", args)
+    } else {
+        messages.get("selftest_failed", args)
+    };
+    for check in checks {
+        report.push('\n');
+        report.push_str(if check.passed { "✅ " } else { "❌ " });
+        report.push_str(&check.detail);
+    }
+
+    tracing::info!("{} ran a self-test, {} checks failed", sender, failed);
+    Ok(report)
+}
+
+/// Post a marked test message to `room_id` and redact it again
+async fn send_test_message(client: &Client, config: &Config, room_id: &RoomId) -> Check {
+    let messages = &config.messages;
+    let content = MessageEventContent::notice_plain(messages.get("selftest_message", &[]));
+    match send::message(client, room_id, content).await {
+        Ok(Some(event_id)) => {
+            match send::redact(client, room_id, &event_id, Some("Self-test")).await {
+                Ok(()) => Check {
+                    passed: true,
+                    detail: messages.get("selftest_sent", &[("room", room_id)]),
+                },
+                Err(e) => Check {
+                    passed: false,
+                    detail: messages.get(
+                        "selftest_redact_failed",
+                        &[("room", room_id), ("error", &e)],
+                    ),
+                },
+            }
+        }
+        // Dry run, nothing was sent
+        Ok(None) => Check {
+            passed: true,
+            detail: messages.get("selftest_dry_run", &[("room", room_id)]),
+        },
+        Err(e) => Check {
+            passed: false,
+            detail: messages.get("selftest_send_failed", &[("room", room_id), ("error", &e)]),
+        },
+    }
+}
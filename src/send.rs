@@ -0,0 +1,309 @@
+use std::{
+    future::Future,
+    io::Cursor,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
+
+use matrix_sdk::{
+    ruma::{
+        api::client::r0::message::send_message_event,
+        events::room::message::{MessageEventContent, MessageType},
+        serde::Raw,
+        EventId, RoomId,
+    },
+    uuid::Uuid,
+    Client,
+};
+use serde_json::Value;
+
+use mime::Mime;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::{
+    auth, config,
+    error::{self, ErrorClass, FourwarderError},
+    marker, metrics, rooms, thread, timeout,
+};
+
+/// How much of a message body to include when logging it
+const SUMMARY_LEN: usize = 80;
+
+/// Limits how many sends are in flight at once, sized by `max_concurrent_sends` on first use
+///
+/// Waiters are let through in the order they arrived, so sends keep the order they were made in.
+static SENDS: OnceLock<Semaphore> = OnceLock::new();
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// A slot from [`SENDS`], given back when dropped
+struct InFlight {
+    _permit: SemaphorePermit<'static>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let in_flight = IN_FLIGHT.fetch_sub(1, Ordering::SeqCst) - 1;
+        set_in_flight(in_flight);
+    }
+}
+
+/// Wait for a free send slot
+async fn in_flight() -> InFlight {
+    let permit = SENDS
+        .get_or_init(|| Semaphore::new(config::get().max_concurrent_sends))
+        .acquire()
+        .await
+        .expect("The send semaphore is never closed");
+    let in_flight = IN_FLIGHT.fetch_add(1, Ordering::SeqCst) + 1;
+    set_in_flight(in_flight);
+
+    InFlight { _permit: permit }
+}
+
+fn set_in_flight(in_flight: usize) {
+    metrics::set_gauge(
+        "fourwarder_sends_in_flight",
+        "Messages, redactions and files being sent right now",
+        in_flight as f64,
+    );
+}
+
+/// Send a message to `room_id`, returning the id of the new event
+///
+/// With `dry_run` set this only logs what would have been sent and returns `None`.
+/// If our access token was rejected or we were rate limited, it is retried once, see [`retrying`].
+pub async fn message(
+    client: &Client,
+    room_id: &RoomId,
+    content: MessageEventContent,
+) -> Result<Option<EventId>, FourwarderError> {
+    if config::get().dry_run {
+        tracing::info!(
+            target: "dry_run",
+            room_id = %room_id,
+            body = %summary(&content),
+            "Would send message"
+        );
+        return Ok(None);
+    }
+
+    let _in_flight = in_flight().await;
+    let response = retrying(client, || client.room_send(room_id, content.clone(), None)).await?;
+
+    Ok(Some(response.event_id))
+}
+
+/// Send a copy of a submission to `room_id`, returning the id of the new event
+///
+/// Unlike [`message`], the copy is marked with our `instance_id`, so no instance of the bot
+/// forwards it again. With `dry_run` set this only logs what would have been sent and returns
+/// `None`.
+pub async fn forwarded(
+    client: &Client,
+    room_id: &RoomId,
+    content: MessageEventContent,
+    thread_root: Option<&EventId>,
+) -> Result<Option<EventId>, FourwarderError> {
+    let config = config::get();
+    if config.dry_run {
+        tracing::info!(
+            target: "dry_run",
+            room_id = %room_id,
+            body = %summary(&content),
+            "Would send message"
+        );
+        return Ok(None);
+    }
+
+    let mut content = serde_json::to_value(&content)?;
+    if let Some(root) = thread_root {
+        thread::relate(&mut content, root);
+    }
+    marker::mark(&mut content, &config.instance_id);
+    let event_id = send_event(client, room_id, "m.room.message", &content).await?;
+    Ok(Some(event_id))
+}
+
+/// Send an event of any type to `room_id`, with `content` passed through as it is apart from
+/// being marked with our `instance_id` like [`forwarded`]
+///
+/// With `dry_run` set this only logs what would have been sent.
+pub async fn raw(
+    client: &Client,
+    room_id: &RoomId,
+    event_type: &str,
+    mut content: Value,
+) -> Result<(), FourwarderError> {
+    let config = config::get();
+    if config.dry_run {
+        tracing::info!(
+            target: "dry_run",
+            room_id = %room_id,
+            event_type,
+            "Would send event"
+        );
+        return Ok(());
+    }
+
+    marker::mark(&mut content, &config.instance_id);
+    send_event(client, room_id, event_type, &content).await?;
+    Ok(())
+}
+
+/// Send an event built by hand, retrying once like [`message`]
+async fn send_event(
+    client: &Client,
+    room_id: &RoomId,
+    event_type: &str,
+    content: &Value,
+) -> Result<EventId, FourwarderError> {
+    let content = serde_json::value::to_raw_value(content)?;
+    let _in_flight = in_flight().await;
+    // The same transaction id on the retry, so the homeserver can't post it twice
+    let txn_id = Uuid::new_v4().to_string();
+    let request = || {
+        send_message_event::Request::new_raw(
+            room_id,
+            &txn_id,
+            event_type,
+            Raw::from_json(content.clone()),
+        )
+    };
+    let response = retrying(client, || async {
+        client
+            .send(request(), None)
+            .await
+            .map_err(matrix_sdk::Error::Http)
+    })
+    .await?;
+
+    Ok(response.event_id)
+}
+
+/// Redact an event in `room_id`
+///
+/// With `dry_run` set this only logs what would have been redacted.
+/// If our access token was rejected or we were rate limited, it is retried once.
+pub async fn redact(
+    client: &Client,
+    room_id: &RoomId,
+    event_id: &EventId,
+    reason: Option<&str>,
+) -> Result<(), FourwarderError> {
+    if config::get().dry_run {
+        tracing::info!(
+            target: "dry_run",
+            room_id = %room_id,
+            event_id = %event_id,
+            reason = reason.unwrap_or_default(),
+            "Would redact event"
+        );
+        return Ok(());
+    }
+
+    let room = rooms::joined(client, room_id).await?;
+
+    let _in_flight = in_flight().await;
+    retrying(client, || async {
+        room.redact(event_id, reason, None)
+            .await
+            .map_err(matrix_sdk::Error::Http)
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Upload `data` and post it to `room_id` as a file called `name`
+///
+/// With `dry_run` set this only logs what would have been sent.
+/// If our access token was rejected or we were rate limited, it is retried once.
+pub async fn attachment(
+    client: &Client,
+    room_id: &RoomId,
+    name: &str,
+    content_type: &Mime,
+    data: &[u8],
+) -> Result<(), FourwarderError> {
+    if config::get().dry_run {
+        tracing::info!(
+            target: "dry_run",
+            room_id = %room_id,
+            name,
+            bytes = data.len(),
+            "Would send file"
+        );
+        return Ok(());
+    }
+
+    let room = rooms::joined(client, room_id).await?;
+
+    let _in_flight = in_flight().await;
+    retrying(client, || async {
+        room.send_attachment(name, content_type, &mut Cursor::new(data), None)
+            .await
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Make a request with `send`, trying it once more if it failed in a way that can be fixed
+///
+/// If our access token was rejected we log in again first, and if we were rate limited we wait
+/// as long as the homeserver asks. Transport errors aren't retried, since the request may have
+/// gone through before the connection failed, and neither is a send which took longer than
+/// `send_timeout_secs`.
+async fn retrying<T, F, Fut>(client: &Client, mut send: F) -> Result<T, FourwarderError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, matrix_sdk::Error>>,
+{
+    let limit = config::get().send_timeout;
+    let mut attempt = || timeout::within("sending", limit, send());
+    let generation = auth::generation();
+    let response = match attempt().await {
+        Err(e) => match error::classify(&e) {
+            ErrorClass::Auth => {
+                auth::reauthenticate(client, generation).await?;
+                attempt().await?
+            }
+            ErrorClass::RateLimited(retry_after) => {
+                tracing::warn!(
+                    "Rate limited, sending again in {}ms",
+                    retry_after.as_millis()
+                );
+                tokio::time::sleep(retry_after).await;
+                attempt().await?
+            }
+            ErrorClass::Transport | ErrorClass::Permanent => return Err(e.into()),
+        },
+        Ok(response) => response,
+    };
+    auth::succeeded();
+
+    Ok(response)
+}
+
+/// A short, single line description of a message for logging
+fn summary(content: &MessageEventContent) -> String {
+    let body = match &content.msgtype {
+        MessageType::Text(text) => text.body.as_str(),
+        MessageType::Notice(notice) => notice.body.as_str(),
+        other => other.msgtype(),
+    };
+
+    truncate(body)
+}
+
+/// The first line of `body`, cut short if it is long
+pub fn truncate(body: &str) -> String {
+    let mut summary: String = body.lines().next().unwrap_or_default().to_owned();
+    if summary.chars().count() > SUMMARY_LEN {
+        summary = summary.chars().take(SUMMARY_LEN).collect();
+        summary.push('…');
+    }
+    summary
+}
@@ -0,0 +1,21 @@
+use crate::state;
+
+/// Take the number for the next published submission
+///
+/// This goes through the state lock, so concurrent approvals never get the same number.
+pub fn next() -> u64 {
+    state::update(|state| {
+        state.last_seq += 1;
+        state.last_seq
+    })
+}
+
+/// The number the next published submission will get, without taking it
+pub fn peek() -> u64 {
+    state::read(|state| state.last_seq + 1)
+}
+
+/// Make `seq` the number of the next published submission
+pub fn set_next(seq: u64) {
+    state::update(|state| state.last_seq = seq.saturating_sub(1));
+}
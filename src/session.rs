@@ -0,0 +1,98 @@
+use matrix_sdk::{
+    ruma::{
+        api::client::r0::uiaa::{AuthData, Password, UserIdentifier},
+        DeviceIdBox, UserId,
+    },
+    Client,
+};
+
+use crate::{
+    audit::{self, Action},
+    auth, clock,
+    config::Config,
+    error::FourwarderError,
+    stats,
+};
+
+/// What `!session` says about our own login, never including the access token
+pub async fn describe(client: &Client, config: &Config) -> Result<String, FourwarderError> {
+    let messages = &config.messages;
+    let device_id = match client.device_id().await {
+        Some(device_id) => device_id,
+        None => return Ok(messages.get("session_none", &[])),
+    };
+    let others = others(client, &device_id).await?;
+    let age = match auth::logged_in_at() {
+        Some(at) => stats::format_duration(clock::now().duration_since(at).as_millis() as u64),
+        None => messages.get("status_never", &[]),
+    };
+    Ok(messages.get(
+        "session",
+        &[
+            ("device_id", &device_id),
+            ("device_name", &config.device_name),
+            ("age", &age),
+            ("others", &others.len()),
+            ("prefix", &config.command_prefix),
+        ],
+    ))
+}
+
+/// Log out every session of the bot's account but this one, for `!logout-others`
+///
+/// Deleting devices needs the account's password on most homeservers, so the request is made
+/// again with `password` if the homeserver asks for it.
+pub async fn logout_others(
+    client: &Client,
+    config: &Config,
+    sender: &UserId,
+) -> Result<String, FourwarderError> {
+    let messages = &config.messages;
+    let device_id = match client.device_id().await {
+        Some(device_id) => device_id,
+        None => return Ok(messages.get("session_none", &[])),
+    };
+    let others = others(client, &device_id).await?;
+    if others.is_empty() {
+        return Ok(messages.get("no_other_sessions", &[]));
+    }
+    if config.dry_run {
+        tracing::info!(target: "dry_run", count = others.len(), "Would log out other sessions");
+        return Ok(messages.get("logged_out_others", &[("count", &others.len())]));
+    }
+
+    if let Err(e) = client.delete_devices(&others, None).await {
+        let session = match e.uiaa_response() {
+            Some(info) => info.session.clone(),
+            None => return Err(matrix_sdk::Error::Http(e).into()),
+        };
+        let user_id = client.user_id().await.map(|user_id| user_id.to_string());
+        let user = user_id.as_deref().unwrap_or(&config.username);
+        let mut password = Password::new(UserIdentifier::MatrixId(user), &config.password);
+        password.session = session.as_deref();
+        client
+            .delete_devices(&others, Some(AuthData::Password(password)))
+            .await
+            .map_err(matrix_sdk::Error::Http)?;
+    }
+
+    let devices: Vec<String> = others.iter().map(ToString::to_string).collect();
+    tracing::info!(
+        "{} logged out our other sessions: {}",
+        sender,
+        devices.join(", ")
+    );
+    audit::record(Some(sender), Action::LoggedOutOthers { devices });
+    Ok(messages.get("logged_out_others", &[("count", &others.len())]))
+}
+
+/// The devices of the bot's account other than `ours`
+async fn others(client: &Client, ours: &DeviceIdBox) -> Result<Vec<DeviceIdBox>, FourwarderError> {
+    let devices = client.devices().await.map_err(matrix_sdk::Error::Http)?;
+    Ok(devices
+        .devices
+        .into_iter()
+        .map(|device| device.device_id)
+        .filter(|device_id| device_id != ours)
+        .collect())
+}
@@ -0,0 +1,17 @@
+use matrix_sdk::ruma::UserId;
+
+use crate::state;
+
+pub fn is_shadowbanned(user: &UserId) -> bool {
+    state::read(|state| state.shadowbanned.contains(user))
+}
+
+/// Returns `false` if `user` was already shadowbanned
+pub fn add(user: &UserId) -> bool {
+    state::update(|state| state.shadowbanned.insert(user.clone()))
+}
+
+/// Returns `false` if `user` wasn't shadowbanned
+pub fn remove(user: &UserId) -> bool {
+    state::update(|state| state.shadowbanned.remove(user))
+}
@@ -0,0 +1,235 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use matrix_sdk::{
+    ruma::{
+        api::client::r0::{
+            session::logout,
+            state::{get_state_events, get_state_events_for_key},
+        },
+        events::EventType,
+        RoomId,
+    },
+    Client,
+};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{auth, error::FourwarderError};
+
+/// What a room id written as `space:<name>` starts with
+const PREFIX: &str = "space:";
+
+/// The rooms in `space_id` by name, as they were when we started
+static CHILDREN: OnceLock<HashMap<String, RoomId>> = OnceLock::new();
+
+/// The parts of the config needed to look into the space, read before the config is loaded
+#[derive(Deserialize)]
+struct Connection {
+    homeserver: String,
+    username: String,
+    password: String,
+    space_id: Option<String>,
+}
+
+/// Look up the rooms in `space_id` which the config at `path` names, before it is loaded
+///
+/// This logs in with a session of its own, which is logged out again once the space has been
+/// read. Does nothing if the config names no rooms by space.
+pub async fn resolve(path: &str) -> Result<(), FourwarderError> {
+    let source: toml::Value = toml::from_str(&std::fs::read_to_string(path)?)?;
+    let mut names = Vec::new();
+    references(&source, &mut |name| names.push(name.to_owned()));
+    if names.is_empty() {
+        CHILDREN.get_or_init(HashMap::new);
+        return Ok(());
+    }
+
+    let connection: Connection = source.try_into()?;
+    let space_id = connection
+        .space_id
+        .as_deref()
+        .ok_or(FourwarderError::Config(
+            "Rooms are named by space, but `space_id` is not set",
+        ))?;
+    let space_id = RoomId::try_from(space_id)
+        .map_err(|_| FourwarderError::Config("`space_id` is not a valid `RoomId`"))?;
+    let homeserver = Url::parse(&connection.homeserver)
+        .map_err(|e| FourwarderError::Matrix(matrix_sdk::Error::Url(e)))?;
+
+    let client = Client::new(homeserver)?;
+    client
+        .login(
+            &connection.username,
+            &connection.password,
+            None,
+            Some(&format!("{} (reading the space)", auth::DEVICE_NAME)),
+        )
+        .await?;
+    let children = children(&client, &space_id).await;
+    if let Err(e) = client.send(logout::Request::new(), None).await {
+        tracing::warn!("Could not log out after reading the space: {}", e);
+    }
+    let children = children?;
+
+    for name in &names {
+        if !children.contains_key(name) {
+            tracing::error!("There is no room called `{}` in {}", name, space_id);
+            return Err(FourwarderError::Config(
+                "The config names a room which isn't in `space_id`",
+            ));
+        }
+    }
+    tracing::info!(
+        "Found {} of the {} rooms in {} by name",
+        names.len(),
+        children.len(),
+        space_id
+    );
+    CHILDREN.get_or_init(|| children);
+    Ok(())
+}
+
+/// The rooms in `space_id` we can read the name of, by name
+///
+/// Rooms with the same name as another are left out, as we couldn't tell which one is meant.
+async fn children(
+    client: &Client,
+    space_id: &RoomId,
+) -> Result<HashMap<String, RoomId>, FourwarderError> {
+    let state = client
+        .send(get_state_events::Request::new(space_id), None)
+        .await
+        .map_err(matrix_sdk::Error::Http)?
+        .room_state;
+
+    #[derive(Deserialize)]
+    struct StateEvent {
+        #[serde(rename = "type")]
+        event_type: String,
+        state_key: String,
+        content: serde_json::Map<String, serde_json::Value>,
+    }
+
+    #[derive(Deserialize)]
+    struct NameContent {
+        name: Option<String>,
+    }
+
+    let mut children = HashMap::new();
+    let mut ambiguous = Vec::new();
+    for event in state {
+        let event: StateEvent = match serde_json::from_str(event.json().get()) {
+            Ok(event) => event,
+            Err(_) => continue,
+        };
+        // A child which was removed from the space keeps its state event, but with no content
+        if event.event_type != "m.space.child" || event.content.is_empty() {
+            continue;
+        }
+        let room_id = match RoomId::try_from(event.state_key.as_str()) {
+            Ok(room_id) => room_id,
+            Err(_) => continue,
+        };
+
+        let request = get_state_events_for_key::Request::new(&room_id, EventType::RoomName, "");
+        let name = match client.send(request, None).await {
+            Ok(response) => match serde_json::from_str(response.content.json().get()) {
+                Ok(NameContent { name: Some(name) }) if !name.is_empty() => name,
+                _ => continue,
+            },
+            Err(e) => {
+                tracing::debug!("Could not read the name of {}: {}", room_id, e);
+                continue;
+            }
+        };
+        if children.insert(name.clone(), room_id).is_some() {
+            ambiguous.push(name);
+        }
+    }
+    for name in ambiguous {
+        tracing::warn!("Several rooms in {} are called `{}`", space_id, name);
+        children.remove(&name);
+    }
+
+    Ok(children)
+}
+
+/// Replace every room written as `space:<name>` in `source` with its id
+///
+/// Names are looked up among the rooms found by [`resolve`] at startup, so naming a room added
+/// to the space since then needs a restart.
+pub fn substitute(source: &mut toml::Value) -> Result<(), FourwarderError> {
+    let children = CHILDREN.get();
+    let mut missing = None;
+    substitute_in(source, &mut |name| {
+        let room_id = children.and_then(|children| children.get(name));
+        if room_id.is_none() {
+            missing = Some(name.to_owned());
+        }
+        room_id.map(ToString::to_string)
+    });
+
+    match missing {
+        Some(name) => {
+            tracing::error!("There is no room called `{}` in `space_id`", name);
+            Err(FourwarderError::Config(
+                "The config names a room which isn't in `space_id`",
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Replace every room written as `space:<name>` in `source` with a made-up id, returning the
+/// names
+///
+/// This is for checking a config without connecting, when the space can't be looked up. Each
+/// name gets an id of its own, so rooms which must differ still do.
+pub fn stand_in(source: &mut toml::Value) -> Vec<String> {
+    let mut names = Vec::new();
+    substitute_in(source, &mut |name| {
+        if !names.iter().any(|known| known == name) {
+            names.push(name.to_owned());
+        }
+        let index = names.iter().position(|known| known == name).unwrap();
+        Some(format!("!space-{}:stand-in.invalid", index))
+    });
+    names
+}
+
+/// Call `f` with the name in every `space:<name>` given for a room id in `value`
+fn references(value: &toml::Value, f: &mut impl FnMut(&str)) {
+    let mut source = value.clone();
+    substitute_in(&mut source, &mut |name| {
+        f(name);
+        None
+    });
+}
+
+/// Replace every `space:<name>` given for a room id in `value` with what `f` gives for the name,
+/// if anything
+///
+/// Only keys ending in `room_id` hold room ids, so other settings which happen to start with
+/// the prefix are left alone.
+fn substitute_in(value: &mut toml::Value, f: &mut impl FnMut(&str) -> Option<String>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                match value {
+                    toml::Value::String(room) if key.ends_with("room_id") => {
+                        if let Some(replacement) = room.strip_prefix(PREFIX).and_then(&mut *f) {
+                            *room = replacement;
+                        }
+                    }
+                    _ => substitute_in(value, f),
+                }
+            }
+        }
+        toml::Value::Array(values) => {
+            for value in values {
+                substitute_in(value, f);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,146 @@
+use serde::Deserialize;
+
+use crate::{
+    filters::{FilterDecision, IncomingMessage, MessageFilter},
+    normalize::normalize_for_matching,
+    urls,
+};
+
+/// The `[spam]` table, scoring is disabled when it is left out
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SpamConfig {
+    /// Submissions scoring at least this are flagged, and go through the mod room even if the
+    /// input room is unmoderated
+    pub flag_threshold: f64,
+    /// Submissions scoring at least this are dropped, never if unset
+    pub drop_threshold: Option<f64>,
+    pub weights: SpamWeights,
+    /// Phrases which are matched case-insensitively
+    pub phrases: Vec<String>,
+}
+
+impl Default for SpamConfig {
+    fn default() -> Self {
+        SpamConfig {
+            flag_threshold: 0.5,
+            drop_threshold: None,
+            weights: SpamWeights::default(),
+            phrases: [
+                "act now",
+                "click here",
+                "crypto giveaway",
+                "free money",
+                "limited time offer",
+                "work from home",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+/// How much each heuristic contributes to the score, relative to the others
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SpamWeights {
+    pub links: f64,
+    pub repeated: f64,
+    pub caps: f64,
+    pub phrases: f64,
+}
+
+impl Default for SpamWeights {
+    fn default() -> Self {
+        SpamWeights {
+            links: 1.0,
+            repeated: 1.0,
+            caps: 1.0,
+            phrases: 2.0,
+        }
+    }
+}
+
+impl MessageFilter for SpamConfig {
+    fn name(&self) -> &'static str {
+        "spam"
+    }
+
+    fn evaluate(&self, msg: &IncomingMessage) -> FilterDecision {
+        let score = self.score(&msg.text.body);
+
+        if self
+            .drop_threshold
+            .is_some_and(|threshold| score >= threshold)
+        {
+            FilterDecision::Drop(format!("likely spam (score {:.2})", score))
+        } else if score >= self.flag_threshold {
+            FilterDecision::Hold(format!("possible spam (score {:.2})", score))
+        } else {
+            FilterDecision::Allow
+        }
+    }
+}
+
+impl SpamConfig {
+    /// Score `body` between 0 (clean) and 1 (certainly spam)
+    pub fn score(&self, body: &str) -> f64 {
+        let weights = &self.weights;
+        let total = weights.links + weights.repeated + weights.caps + weights.phrases;
+        if total <= 0.0 {
+            return 0.0;
+        }
+
+        let score = weights.links * link_ratio(body)
+            + weights.repeated * repeated_ratio(body)
+            + weights.caps * caps_ratio(body)
+            + weights.phrases * self.phrase_score(body);
+        score / total
+    }
+
+    /// 0.5 per known phrase present, up to 1
+    fn phrase_score(&self, body: &str) -> f64 {
+        let body = normalize_for_matching(body).to_lowercase();
+        let matches = self
+            .phrases
+            .iter()
+            .filter(|phrase| body.contains(&normalize_for_matching(phrase).to_lowercase()))
+            .count();
+        (matches as f64 / 2.0).min(1.0)
+    }
+}
+
+/// The fraction of words which are links
+fn link_ratio(body: &str) -> f64 {
+    let words = body.split_whitespace().count();
+    if words == 0 {
+        return 0.0;
+    }
+    urls::find_urls(body).len() as f64 / words as f64
+}
+
+/// The fraction of characters which are part of a run of three or more of the same character
+fn repeated_ratio(body: &str) -> f64 {
+    let chars: Vec<char> = body.chars().filter(|c| !c.is_whitespace()).collect();
+    if chars.is_empty() {
+        return 0.0;
+    }
+
+    let repeated: usize = chars
+        .chunk_by(|a, b| a == b)
+        .map(<[char]>::len)
+        .filter(|run| *run >= 3)
+        .sum();
+    repeated as f64 / chars.len() as f64
+}
+
+/// The fraction of letters which are uppercase, ignoring messages too short to shout
+fn caps_ratio(body: &str) -> f64 {
+    const MIN_LETTERS: usize = 8;
+
+    let letters: Vec<char> = body.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() < MIN_LETTERS {
+        return 0.0;
+    }
+    letters.iter().filter(|c| c.is_uppercase()).count() as f64 / letters.len() as f64
+}
@@ -0,0 +1,357 @@
+use matrix_sdk::ruma::events::room::message::{
+    FormattedBody, MessageFormat, TextMessageEventContent,
+};
+
+use crate::cw::escape_html;
+
+/// Tags which are never closed, so are never reopened in the next part
+const VOID_TAGS: &[&str] = &["br", "hr", "img"];
+
+/// Tags whose end is a line break in the plain text, and so a good place to split
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "blockquote",
+    "pre",
+    "li",
+    "ul",
+    "ol",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "table",
+    "tr",
+];
+
+/// A piece of a message body, the smallest thing a split can fall between
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Char(char),
+    /// An HTML entity like `&amp;`, shown as a single character
+    Entity(&'a str),
+    /// An HTML tag, with its angle brackets
+    Tag(&'a str),
+}
+
+impl Token<'_> {
+    /// How many characters it takes up when shown
+    fn width(self) -> usize {
+        match self {
+            Token::Char(_) | Token::Entity(_) => 1,
+            Token::Tag(tag) if tag_name(tag) == "br" => 1,
+            Token::Tag(_) => 0,
+        }
+    }
+
+    /// Whether a split right after it falls at the end of a line
+    fn ends_line(self) -> bool {
+        match self {
+            Token::Char(c) => c == '\n',
+            Token::Tag(tag) => {
+                let name = tag_name(tag);
+                name == "br" || (tag.starts_with("</") && BLOCK_TAGS.contains(&name.as_str()))
+            }
+            Token::Entity(_) => false,
+        }
+    }
+
+    fn is_blank(self) -> bool {
+        match self {
+            Token::Char(c) => c.is_whitespace(),
+            Token::Tag(tag) => tag_name(tag) == "br",
+            Token::Entity(_) => false,
+        }
+    }
+}
+
+/// Split `text` into parts of at most `max_chars` characters each, marked like `(1/3)`, or leave
+/// it whole if it is short enough
+///
+/// Parts end at a line break where there is one, otherwise between words, and only mid-word if a
+/// word is longer than a whole part. HTML is split along with the plain text, with any tags which
+/// are open at a split closed at the end of the part and opened again at the start of the next,
+/// so a spoiler or quote goes on in every part. Each part's plain text is then taken from its
+/// HTML, so the two always say the same thing. The marker, and the line break before it, count
+/// toward `max_chars`.
+pub fn split(text: &TextMessageEventContent, max_chars: usize) -> Vec<TextMessageEventContent> {
+    let html = match &text.formatted {
+        Some(formatted) if formatted.format == MessageFormat::Html => Some(formatted.body.as_str()),
+        _ => None,
+    };
+    let tokens = match html {
+        Some(html) => tokenize_html(html),
+        None => text.body.chars().map(Token::Char).collect(),
+    };
+    if ranges(&tokens, max_chars).len() <= 1 {
+        return vec![text.clone()];
+    }
+    // Room for the widest marker, which needs to know how many parts there are, which depends on
+    // the room left for them
+    let mut count = 2;
+    let ranges = loop {
+        let reserved = format!("\n({}/{})", count, count).chars().count();
+        let ranges = ranges(&tokens, max_chars.saturating_sub(reserved).max(1));
+        if ranges.len().to_string().len() <= count.to_string().len() {
+            break ranges;
+        }
+        count = ranges.len();
+    };
+
+    let count = ranges.len();
+    ranges
+        .into_iter()
+        .enumerate()
+        .map(|(index, range)| {
+            let marker = format!("({}/{})", index + 1, count);
+            let mut part = text.clone();
+            match html {
+                Some(_) => {
+                    let html = render_html(&tokens, range);
+                    part.body = format!("{}\n{}", html_to_text(&html), marker);
+                    part.formatted = Some(FormattedBody::html(format!(
+                        "{}<br>{}",
+                        html,
+                        escape_html(&marker)
+                    )));
+                }
+                None => {
+                    let body: String = tokens[range]
+                        .iter()
+                        .filter_map(|token| match token {
+                            Token::Char(c) => Some(*c),
+                            _ => None,
+                        })
+                        .collect();
+                    part.body = format!("{}\n{}", body, marker);
+                }
+            }
+            part
+        })
+        .collect()
+}
+
+/// Where `tokens` split into parts no wider than `max_chars`, with blanks at the ends left out
+fn ranges(tokens: &[Token], max_chars: usize) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < tokens.len() {
+        let mut width = 0;
+        let mut end = start;
+        let mut line_break = None;
+        let mut word_break = None;
+        while end < tokens.len() {
+            let token = tokens[end];
+            if width + token.width() > max_chars && end > start {
+                break;
+            }
+            width += token.width();
+            end += 1;
+            if token.ends_line() {
+                line_break = Some(end);
+            } else if token.is_blank() {
+                word_break = Some(end);
+            }
+        }
+        // A line break right at the start would make for a very short part
+        let cut = if end == tokens.len() {
+            end
+        } else {
+            line_break
+                .filter(|line_break| line_break - start > (end - start) / 2)
+                .or(word_break)
+                .unwrap_or(end)
+        };
+
+        // Tags left over after the last line, like the end of a quote, are closed anyway
+        let range = trim(tokens, start..cut);
+        if tokens[range.clone()].iter().any(|token| token.width() > 0) {
+            ranges.push(range);
+        }
+        start = cut;
+    }
+    ranges
+}
+
+/// `range` without the blank characters and line breaks at either end
+fn trim(tokens: &[Token], mut range: std::ops::Range<usize>) -> std::ops::Range<usize> {
+    while range.start < range.end && tokens[range.start].is_blank() {
+        range.start += 1;
+    }
+    while range.end > range.start && tokens[range.end - 1].is_blank() {
+        range.end -= 1;
+    }
+    range
+}
+
+/// Break HTML into characters, entities and tags
+fn tokenize_html(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '<' => rest.find('>').map(|end| end + 1),
+            '&' => rest
+                .find(';')
+                .filter(|end| {
+                    *end > 1 && rest[1..*end].chars().all(|c| c.is_ascii_alphanumeric() || c == '#')
+                })
+                .map(|end| end + 1),
+            _ => None,
+        };
+        match len {
+            Some(len) if c == '<' => tokens.push(Token::Tag(&rest[..len])),
+            Some(len) => tokens.push(Token::Entity(&rest[..len])),
+            None => tokens.push(Token::Char(c)),
+        }
+        rest = &rest[len.unwrap_or(c.len_utf8())..];
+    }
+    tokens
+}
+
+/// The HTML of `tokens[range]`, with the tags open at its start opened again and the ones still
+/// open at its end closed
+fn render_html(tokens: &[Token], range: std::ops::Range<usize>) -> String {
+    let mut open = open_tags(&tokens[..range.start]);
+    let mut html: String = open.iter().copied().collect();
+    for token in &tokens[range] {
+        match token {
+            Token::Char(c) => html.push(*c),
+            Token::Entity(raw) | Token::Tag(raw) => html.push_str(raw),
+        }
+        if let Token::Tag(tag) = token {
+            track(&mut open, tag);
+        }
+    }
+    for tag in open.iter().rev() {
+        html.push_str(&format!("</{}>", tag_name(tag)));
+    }
+    html
+}
+
+/// The opening tags which are still open after `tokens`, outermost first
+fn open_tags<'a>(tokens: &[Token<'a>]) -> Vec<&'a str> {
+    let mut open = Vec::new();
+    for token in tokens {
+        if let Token::Tag(tag) = token {
+            track(&mut open, tag);
+        }
+    }
+    open
+}
+
+/// Open or close `tag` on the stack of `open` tags
+fn track<'a>(open: &mut Vec<&'a str>, tag: &'a str) {
+    let name = tag_name(tag);
+    if tag.starts_with("</") {
+        if let Some(position) = open.iter().rposition(|open| tag_name(open) == name) {
+            open.truncate(position);
+        }
+    } else if !tag.ends_with("/>") && !VOID_TAGS.contains(&name.as_str()) && !tag.starts_with("<!") {
+        open.push(tag);
+    }
+}
+
+/// The lowercase name of a tag like `<span data-mx-spoiler>` or `</span>`
+fn tag_name(tag: &str) -> String {
+    tag.trim_start_matches('<')
+        .trim_start_matches('/')
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// The plain text of a part's HTML, with line breaks where the HTML has them
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    for token in tokenize_html(html) {
+        match token {
+            Token::Char(c) => text.push(c),
+            Token::Entity(entity) => text.push_str(&unescape(entity)),
+            Token::Tag(_) if token.ends_line() => {
+                text.truncate(text.trim_end_matches(' ').len());
+                text.push('\n');
+            }
+            Token::Tag(_) => {}
+        }
+    }
+    text.trim().to_owned()
+}
+
+fn unescape(entity: &str) -> String {
+    let name = &entity[1..entity.len() - 1];
+    let c = match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => name
+            .strip_prefix("#x")
+            .or_else(|| name.strip_prefix("#X"))
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .or_else(|| name.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+            .and_then(char::from_u32),
+    };
+    c.map_or_else(|| entity.to_owned(), String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: usize = 40;
+
+    /// Four words to a part once the marker has its room, so three parts
+    fn words() -> String {
+        (1..=12).map(|i| format!("word{:02}", i)).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn three_parts_fit_with_their_markers() {
+        let parts = split(&TextMessageEventContent::plain(words()), LIMIT);
+
+        assert_eq!(parts.len(), 3);
+        for (i, part) in parts.iter().enumerate() {
+            assert!(
+                part.body.chars().count() <= LIMIT,
+                "part {} is {} long: {:?}",
+                i + 1,
+                part.body.chars().count(),
+                part.body
+            );
+            assert!(part.body.ends_with(&format!("\n({}/3)", i + 1)));
+        }
+        let words: Vec<&str> = parts
+            .iter()
+            .flat_map(|part| part.body.lines().next().unwrap().split(' '))
+            .collect();
+        assert_eq!(words.join(" "), self::words());
+    }
+
+    #[test]
+    fn three_html_parts_fit_with_their_markers() {
+        let html = format!("<blockquote>{}</blockquote>", words());
+        let parts = split(&TextMessageEventContent::html(words(), html), LIMIT);
+
+        assert_eq!(parts.len(), 3);
+        for (i, part) in parts.iter().enumerate() {
+            assert!(part.body.chars().count() <= LIMIT, "{:?}", part.body);
+            let formatted = &part.formatted.as_ref().unwrap().body;
+            assert!(formatted.starts_with("<blockquote>"), "{}", formatted);
+            assert!(formatted.ends_with(&format!("</blockquote><br>({}/3)", i + 1)));
+        }
+    }
+
+    #[test]
+    fn short_messages_stay_whole() {
+        let text = TextMessageEventContent::plain("word01 word02");
+        let parts = split(&text, LIMIT);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].body, text.body);
+    }
+}
@@ -0,0 +1,397 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
+
+use matrix_sdk::ruma::{EventId, RoomId, UserId};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    activity, config, content_dedup, dead_letter::DeadLetter, decided::DecidedItem,
+    error::FourwarderError, history::PublishedEvent, index::Index, instructions::Pinned,
+    lifecycle::Status, pause::Deferred, pending::PendingItem, stats::Latency,
+    thread::ThreadRoot, training,
+};
+
+/// The name of the state file, in `data_dir`
+pub const STATE_FILE: &str = "4warder_state.json";
+
+/// The version of the state file layout this build writes
+///
+/// Bump it whenever a change to `State` would be misread by an older layout, and add a step to
+/// `MIGRATIONS` that upgrades files from the previous version.
+pub const STATE_SCHEMA_VERSION: u64 = 5;
+
+/// Steps which upgrade a state file, the first from version 1 to 2, the next from 2 to 3 and so
+/// on
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5];
+
+/// Version 1 is every file written before the state was versioned, so a file may be from before
+/// any of what was added along the way, which version 2 always writes
+fn v1_to_v2(raw: &mut Map<String, Value>) {
+    for key in ["pending", "karma", "published"] {
+        raw.entry(key).or_insert_with(|| Value::Object(Map::new()));
+    }
+    for key in ["shadowbanned", "latencies", "processed"] {
+        raw.entry(key).or_insert_with(|| Value::Array(Vec::new()));
+    }
+
+    let pending = raw.get_mut("pending").and_then(Value::as_object_mut);
+    for item in pending.into_iter().flat_map(|pending| pending.values_mut()) {
+        if let Some(item) = item.as_object_mut() {
+            item.entry("priority")
+                .or_insert_with(|| Value::from(crate::pending::NORMAL_PRIORITY));
+            item.entry("content_warning").or_insert(Value::Null);
+        }
+    }
+}
+
+/// Version 3 gives every item a `status`, which version 2 left to be worked out from its other
+/// fields
+fn v2_to_v3(raw: &mut Map<String, Value>) {
+    let pending = raw.get_mut("pending").and_then(Value::as_object_mut);
+    for item in pending.into_iter().flat_map(|pending| pending.values_mut()) {
+        let is_set = |field: &str| {
+            item.get(field).is_some_and(|value| match value {
+                Value::Array(values) => !values.is_empty(),
+                value => !value.is_null(),
+            })
+        };
+        let status = if is_set("preview") {
+            Status::Approved
+        } else if is_set("holds") {
+            Status::Held
+        } else {
+            Status::Pending
+        };
+        set_status(item, status);
+    }
+
+    let decided = raw.get_mut("decided").and_then(Value::as_object_mut);
+    for decided in decided.into_iter().flat_map(|decided| decided.values_mut()) {
+        let status = match decided.get("approved").and_then(Value::as_bool) {
+            Some(true) => Status::Published,
+            _ => Status::Rejected,
+        };
+        if let Some(item) = decided.get_mut("item") {
+            set_status(item, status);
+        }
+    }
+}
+
+/// Version 4 keeps each input room reaction with who sent it, where version 3 only had a count
+/// for each emoji, which can't be told apart by sender, so those counts start over
+fn v3_to_v4(raw: &mut Map<String, Value>) {
+    let forget = |item: &mut Value| {
+        if let Some(item) = item.as_object_mut() {
+            item.insert("source_reactions".to_owned(), Value::Object(Map::new()));
+        }
+    };
+
+    let pending = raw.get_mut("pending").and_then(Value::as_object_mut);
+    pending
+        .into_iter()
+        .flat_map(|pending| pending.values_mut())
+        .for_each(forget);
+    let decided = raw.get_mut("decided").and_then(Value::as_object_mut);
+    decided
+        .into_iter()
+        .flat_map(|decided| decided.values_mut())
+        .filter_map(|decided| decided.get_mut("item"))
+        .for_each(forget);
+}
+
+/// Version 5 does the same for the tallies of published submissions, which keep what their
+/// copies show, so the next refresh takes the old counts off them
+fn v4_to_v5(raw: &mut Map<String, Value>) {
+    let published = raw.get_mut("published").and_then(Value::as_object_mut);
+    let tallies = published
+        .into_iter()
+        .flat_map(|published| published.values_mut())
+        .filter_map(|published| published.get_mut("tally"))
+        .filter_map(Value::as_object_mut);
+    for tally in tallies {
+        tally.insert("reactions".to_owned(), Value::Object(Map::new()));
+    }
+}
+
+fn set_status(item: &mut Value, status: Status) {
+    if let (Some(item), Ok(status)) = (item.as_object_mut(), serde_json::to_value(status)) {
+        item.insert("status".to_owned(), status);
+    }
+}
+
+/// Everything the bot needs to remember across restarts
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct State {
+    /// Submissions waiting on a decision, keyed by the event id of their copy in the mod room
+    pub pending: HashMap<EventId, PendingItem>,
+    /// Approvals minus rejections for each submitter
+    pub karma: HashMap<UserId, i64>,
+    /// Users whose submissions are silently dropped
+    pub shadowbanned: HashSet<UserId>,
+    /// Submissions we posted to output rooms, keyed by their event id there
+    pub published: HashMap<EventId, PublishedEvent>,
+    /// How long recent decisions took, oldest first
+    pub latencies: Vec<Latency>,
+    /// Input events we have already handled, oldest first, so redelivered ones are ignored
+    pub processed: VecDeque<EventId>,
+    /// Recently decided submissions, keyed by the event id of their old copy in the mod room
+    pub decided: HashMap<EventId, DecidedItem>,
+    /// The number given to the last published submission, for `{seq}` in `output_template`
+    pub last_seq: u64,
+    /// Whether `!pause` has stopped all forwarding
+    pub paused: bool,
+    /// What happened while forwarding was paused, oldest first
+    pub deferred: Vec<Deferred>,
+    /// Users `!delegate` lets approve and reject submissions, with when that runs out in
+    /// milliseconds since the Unix epoch
+    pub delegations: HashMap<UserId, u64>,
+    /// What `pin_instructions` pinned in the mod room last
+    pub instructions: Option<Pinned>,
+    /// The number of each sender's pseudonym, for `{pseudonym}` in `output_template`
+    pub pseudonyms: HashMap<UserId, u64>,
+    /// The number given to the last new pseudonym
+    pub last_pseudonym: u64,
+    /// When each sender's pseudonym was last used, in milliseconds since the Unix epoch, for
+    /// `pseudonym_retention_secs`
+    pub pseudonyms_used: HashMap<UserId, u64>,
+    /// Submissions which could not be forwarded, oldest first, kept for `!retry-failed`
+    pub dead_letters: Vec<DeadLetter>,
+    /// What happened to the submissions to each input room, for each of the last
+    /// [`activity::RETENTION_DAYS`] days
+    pub room_activity: HashMap<RoomId, activity::ByDay>,
+    /// The threads started for `[[categories]]` with `output_thread`, by category name
+    pub thread_roots: HashMap<String, ThreadRoot>,
+    /// The hashed text and files of recent submissions, oldest first, for `content_dedup`
+    pub content_seen: VecDeque<content_dedup::Seen>,
+    /// The index `index_room_id` has pinned
+    pub index: Option<Index>,
+    /// When each sender's submissions within `submission_quota_window_secs` were taken, in
+    /// milliseconds since the Unix epoch, oldest first
+    pub submission_quota: HashMap<UserId, VecDeque<u64>>,
+    /// What each moderator decided on `!train` submissions, kept apart from the real decisions
+    pub training: HashMap<UserId, training::Counts>,
+}
+
+/// `State` as it is written to disk, with the version of its layout
+#[derive(Serialize)]
+struct Versioned<'a> {
+    state_schema_version: u64,
+    #[serde(flatten)]
+    state: &'a State,
+}
+
+static STATE: LazyLock<Mutex<State>> = LazyLock::new(|| Mutex::new(State::default()));
+
+/// Where the state file is, which is only known once the config is loaded
+pub fn location() -> PathBuf {
+    config::get().data_dir.join(STATE_FILE)
+}
+
+/// Load the state left behind by the last run, if there was one
+pub fn load() -> Result<(), FourwarderError> {
+    let state = match std::fs::read_to_string(location()) {
+        Ok(raw) => migrate(serde_json::from_str(&raw)?)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => State::default(),
+        Err(e) => return Err(e.into()),
+    };
+
+    *STATE.lock().unwrap() = state;
+    Ok(())
+}
+
+/// The state as the state file has it, with the version of its layout, for `export-state`
+pub fn to_json() -> Result<Value, FourwarderError> {
+    read(|state| {
+        Ok(serde_json::to_value(Versioned {
+            state_schema_version: STATE_SCHEMA_VERSION,
+            state,
+        })?)
+    })
+}
+
+/// Replace the state with `state` and save it, for `import-state`
+pub fn replace(state: State) -> Result<(), FourwarderError> {
+    let mut current = STATE.lock().unwrap();
+    *current = state;
+    save(&current)
+}
+
+/// Upgrade a state file written by an older version of the bot
+///
+/// Files from a newer version are rejected rather than read, as we may not know what their
+/// fields mean.
+pub fn migrate(raw: Value) -> Result<State, FourwarderError> {
+    let mut raw = match raw {
+        Value::Object(raw) => raw,
+        _ => {
+            return Err(FourwarderError::State(
+                "The state file is not a JSON object",
+            ))
+        }
+    };
+    let mut version = match raw.remove("state_schema_version") {
+        None => 1,
+        Some(version) => version.as_u64().ok_or(FourwarderError::State(
+            "`state_schema_version` in the state file is not a number",
+        ))?,
+    };
+    if version == 0 || version > STATE_SCHEMA_VERSION {
+        return Err(FourwarderError::State(
+            "The state file was written by a newer version of the bot",
+        ));
+    }
+
+    while version < STATE_SCHEMA_VERSION {
+        tracing::info!(
+            "Upgrading the state file from version {} to {}",
+            version,
+            version + 1
+        );
+        MIGRATIONS[version as usize - 1](&mut raw);
+        version += 1;
+    }
+
+    Ok(serde_json::from_value(Value::Object(raw))?)
+}
+
+/// Forget everything, for a test starting afresh
+#[cfg(test)]
+pub fn reset() {
+    *STATE.lock().unwrap() = State::default();
+}
+
+/// Read the state without changing it
+pub fn read<R>(f: impl FnOnce(&State) -> R) -> R {
+    f(&STATE.lock().unwrap())
+}
+
+/// Change the state, then save it to disk
+///
+/// Failing to save is logged rather than returned, the change is still kept in memory.
+pub fn update<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    let mut state = STATE.lock().unwrap();
+    let result = f(&mut state);
+
+    if let Err(e) = save(&state) {
+        tracing::error!("Could not save state to {}: {}", location().display(), e);
+    }
+
+    result
+}
+
+/// Change the state without saving it, for what happens too often to write the whole file each
+/// time
+///
+/// The change is saved along with the next [`update`], and lost if the bot stops before one.
+pub fn update_unsaved<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    f(&mut STATE.lock().unwrap())
+}
+
+/// Write the state to a temporary file and move it into place, so a crash can't leave it
+/// half written
+fn save(state: &State) -> Result<(), FourwarderError> {
+    // Tests keep their state in memory
+    if cfg!(test) {
+        return Ok(());
+    }
+    let location = location();
+    let tmp = location.with_extension("json.tmp");
+    let versioned = Versioned {
+        state_schema_version: STATE_SCHEMA_VERSION,
+        state,
+    };
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&versioned)?)?;
+    std::fs::rename(&tmp, &location)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{event, room, user};
+
+    /// A file from before `priority`, shadowbans and time to moderation were kept, and before the
+    /// state was versioned
+    const V1: &str = r#"{
+        "pending": {
+            "$mod:example.org": {
+                "source_room_id": "!input:example.org",
+                "sender": "@sub:example.org",
+                "origin_server_ts": 1700000000000,
+                "content": { "msgtype": "m.text", "body": "hello" }
+            }
+        },
+        "karma": { "@sub:example.org": 2 }
+    }"#;
+
+    #[test]
+    fn v1_files_are_upgraded() {
+        let state = migrate(serde_json::from_str(V1).unwrap()).unwrap();
+
+        let item = &state.pending[&event("$mod:example.org")];
+        assert_eq!(item.source_room_id, room("!input:example.org"));
+        assert_eq!(item.sender, user("@sub:example.org"));
+        assert_eq!(item.content.body, "hello");
+        assert_eq!(item.priority, crate::pending::NORMAL_PRIORITY);
+        assert_eq!(item.content_warning, None);
+        assert_eq!(item.status, Status::Pending);
+        assert_eq!(state.karma[&user("@sub:example.org")], 2);
+        assert!(state.shadowbanned.is_empty());
+        assert!(state.latencies.is_empty());
+        assert!(state.processed.is_empty());
+    }
+
+    #[test]
+    fn v1_to_v2_fills_in_what_version_2_writes() {
+        let mut raw = serde_json::from_str::<Value>(V1).unwrap();
+        v1_to_v2(raw.as_object_mut().unwrap());
+
+        assert_eq!(raw["shadowbanned"], serde_json::json!([]));
+        assert_eq!(raw["published"], serde_json::json!({}));
+        assert_eq!(raw["pending"]["$mod:example.org"]["priority"], 0);
+        assert_eq!(raw["pending"]["$mod:example.org"]["content_warning"], Value::Null);
+    }
+
+    #[test]
+    fn v3_to_v4_drops_anonymous_counts() {
+        let mut raw = serde_json::json!({
+            "pending": { "$mod:example.org": { "source_reactions": { "👍": 3 } } },
+            "decided": { "$old:example.org": { "item": { "source_reactions": { "❤️": 1 } } } },
+        });
+        v3_to_v4(raw.as_object_mut().unwrap());
+
+        assert_eq!(raw["pending"]["$mod:example.org"]["source_reactions"], serde_json::json!({}));
+        assert_eq!(
+            raw["decided"]["$old:example.org"]["item"]["source_reactions"],
+            serde_json::json!({})
+        );
+    }
+
+    #[test]
+    fn v4_to_v5_drops_anonymous_tallies() {
+        let mut raw = serde_json::json!({
+            "published": {
+                "$out:example.org": { "tally": { "reactions": { "👍": 3 }, "shown": { "👍": 3 } } },
+                "$untallied:example.org": { "tally": null },
+            },
+        });
+        v4_to_v5(raw.as_object_mut().unwrap());
+
+        let tally = &raw["published"]["$out:example.org"]["tally"];
+        assert_eq!(tally["reactions"], serde_json::json!({}));
+        assert_eq!(tally["shown"], serde_json::json!({ "👍": 3 }));
+        assert_eq!(raw["published"]["$untallied:example.org"]["tally"], Value::Null);
+    }
+
+    #[test]
+    fn newer_files_are_refused() {
+        let raw = serde_json::json!({ "state_schema_version": STATE_SCHEMA_VERSION + 1 });
+        assert!(matches!(migrate(raw), Err(FourwarderError::State(_))));
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::now_millis, metrics, pending::PendingItem, state};
+
+/// How far back `!stats` looks, in milliseconds
+const WINDOW_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Histogram buckets for time to moderation, in seconds
+const LATENCY_BUCKETS: &[f64] = &[
+    60.0, 300.0, 900.0, 1800.0, 3600.0, 7200.0, 21600.0, 43200.0, 86400.0,
+];
+
+/// How long one submission waited on a decision
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Latency {
+    /// When the decision was made, in milliseconds since the Unix epoch
+    pub decided_at: u64,
+    pub latency_ms: u64,
+}
+
+/// The time to moderation of the decisions within the window
+pub struct Summary {
+    pub count: usize,
+    pub median_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+/// Record that a decision was just made on `item`
+pub fn record_decision(item: &PendingItem) {
+    let decided_at = now_millis();
+    let submitted_at: u64 = item.origin_server_ts.get().into();
+    let latency_ms = decided_at.saturating_sub(submitted_at);
+
+    metrics::observe(
+        "fourwarder_moderation_seconds",
+        "Time between a submission and the decision on it",
+        LATENCY_BUCKETS,
+        latency_ms as f64 / 1000.0,
+    );
+
+    state::update(|state| {
+        state
+            .latencies
+            .retain(|latency| decided_at.saturating_sub(latency.decided_at) <= WINDOW_MS);
+        state.latencies.push(Latency {
+            decided_at,
+            latency_ms,
+        });
+    });
+}
+
+/// Summarize the decisions made within the window, or `None` if there weren't any
+pub fn summary() -> Option<Summary> {
+    let now = now_millis();
+    let mut latencies: Vec<u64> = state::read(|state| {
+        state
+            .latencies
+            .iter()
+            .filter(|latency| now.saturating_sub(latency.decided_at) <= WINDOW_MS)
+            .map(|latency| latency.latency_ms)
+            .collect()
+    });
+    if latencies.is_empty() {
+        return None;
+    }
+    latencies.sort_unstable();
+
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+    Some(Summary {
+        count: latencies.len(),
+        median_ms: percentile(50),
+        p95_ms: percentile(95),
+        max_ms: latencies[latencies.len() - 1],
+    })
+}
+
+/// A short, human readable duration such as `1h 5m` or `42s`
+pub fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
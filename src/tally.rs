@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+
+use matrix_sdk::{
+    ruma::{
+        events::room::message::{MessageFormat, TextMessageEventContent},
+        EventId, RoomId, UserId,
+    },
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::task::JoinHandle;
+
+use crate::{
+    config::{self, Config},
+    cw::escape_html,
+    error::FourwarderError,
+    leader,
+    pending::{self, SourceReaction},
+    send, state,
+};
+
+/// The reactions on a published submission's original in its input room, for
+/// `mirror_source_reactions`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tally {
+    pub source_room_id: RoomId,
+    pub source_event_id: EventId,
+    /// The published copy as it was sent, which the summary is added to
+    pub content: TextMessageEventContent,
+    /// The reactions the original has had since it was published, by the event id of each
+    pub reactions: BTreeMap<EventId, SourceReaction>,
+    /// What the published copy shows now, so an edit which changes nothing isn't sent
+    pub shown: BTreeMap<String, u32>,
+}
+
+impl Tally {
+    /// How many people reacted with each emoji, each counting once per emoji
+    pub fn counts(&self) -> BTreeMap<String, u32> {
+        pending::reaction_counts(self.reactions.values())
+    }
+}
+
+/// Keep the tallies on published submissions up to date, every `mirror_reactions_interval_secs`
+/// until the returned task is aborted
+pub fn spawn(client: Client) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config::get().mirror_reactions_interval);
+        loop {
+            interval.tick().await;
+            let config = config::get();
+            if !config.mirror_source_reactions || !leader::is_active() {
+                continue;
+            }
+            refresh(&client, &config).await;
+        }
+    })
+}
+
+/// Start tallying the reactions on the original of the submission just published as
+/// `event_id`, which went out as `content`
+pub fn track(
+    event_id: &EventId,
+    source_room_id: &RoomId,
+    source_event_id: &EventId,
+    content: TextMessageEventContent,
+) {
+    state::update(|state| {
+        if let Some(published) = state.published.get_mut(event_id) {
+            published.tally = Some(Tally {
+                source_room_id: source_room_id.clone(),
+                source_event_id: source_event_id.clone(),
+                content,
+                reactions: BTreeMap::new(),
+                shown: BTreeMap::new(),
+            });
+        }
+    });
+}
+
+/// Count `sender`'s reaction `reaction_id` in an input room, returning `false` if it isn't to
+/// the original of a published submission
+pub fn count(
+    room_id: &RoomId,
+    event_id: &EventId,
+    reaction_id: EventId,
+    sender: &UserId,
+    emoji: &str,
+) -> bool {
+    state::update(|state| {
+        let tally = state
+            .published
+            .values_mut()
+            .filter_map(|published| published.tally.as_mut())
+            .find(|tally| &tally.source_room_id == room_id && &tally.source_event_id == event_id);
+        match tally {
+            Some(tally) => {
+                tally.reactions.insert(
+                    reaction_id,
+                    SourceReaction {
+                        sender: sender.clone(),
+                        emoji: emoji.to_owned(),
+                    },
+                );
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Stop counting the reaction `reaction_id` in `room_id`, now that it was redacted, returning
+/// whether it was counted
+pub fn uncount(room_id: &RoomId, reaction_id: &EventId) -> bool {
+    state::update(|state| {
+        state
+            .published
+            .values_mut()
+            .filter_map(|published| published.tally.as_mut())
+            .filter(|tally| &tally.source_room_id == room_id)
+            .any(|tally| tally.reactions.remove(reaction_id).is_some())
+    })
+}
+
+/// Edit every published submission whose original got reactions since it was last edited
+///
+/// One which can't be edited is logged and tried again next time.
+async fn refresh(client: &Client, config: &Config) {
+    let changed: Vec<(EventId, RoomId, Tally)> = state::read(|state| {
+        state
+            .published
+            .iter()
+            .filter_map(|(event_id, published)| {
+                let tally = published.tally.as_ref()?;
+                (tally.counts() != tally.shown).then(|| {
+                    (event_id.clone(), published.room_id.clone(), tally.clone())
+                })
+            })
+            .collect()
+    });
+
+    for (event_id, room_id, tally) in changed {
+        let counts = tally.counts();
+        match edit(client, config, &room_id, &event_id, &tally.content, &counts).await {
+            Ok(()) => {
+                tracing::debug!("Updated the reactions on {}", event_id);
+                state::update(|state| {
+                    if let Some(tally) = state
+                        .published
+                        .get_mut(&event_id)
+                        .and_then(|published| published.tally.as_mut())
+                    {
+                        tally.shown = counts;
+                    }
+                });
+            }
+            Err(e) => tracing::warn!("Could not update the reactions on {}: {}", event_id, e),
+        }
+    }
+}
+
+/// Edit the published `event_id`, sent as `content`, to end with the most common
+/// `source_reactions_limit` of `counts`, like `👍 12, ❤️ 3`
+///
+/// With none left, as when every reaction was removed, it goes back to `content` as it was sent.
+async fn edit(
+    client: &Client,
+    config: &Config,
+    room_id: &RoomId,
+    event_id: &EventId,
+    content: &TextMessageEventContent,
+    counts: &BTreeMap<String, u32>,
+) -> Result<(), FourwarderError> {
+    let summary = pending::reaction_summary(counts, config.source_reactions_limit);
+    let mut new_content = json!({
+        "msgtype": config.output_msgtype.as_str(),
+        "body": match &summary {
+            Some(summary) => format!("{}\n\n{}", content.body, summary),
+            None => content.body.clone(),
+        },
+    });
+    if let Some(formatted) = content
+        .formatted
+        .as_ref()
+        .filter(|formatted| formatted.format == MessageFormat::Html)
+    {
+        new_content["format"] = json!("org.matrix.custom.html");
+        new_content["formatted_body"] = json!(match &summary {
+            Some(summary) => format!("{}<p>{}</p>", formatted.body, escape_html(summary)),
+            None => formatted.body.clone(),
+        });
+    }
+
+    // Clients which don't show edits show the fallback, marked as an edit with `*`
+    let mut edit = new_content.clone();
+    edit["body"] = json!(format!("* {}", new_content["body"].as_str().unwrap_or_default()));
+    if let Some(formatted_body) = new_content["formatted_body"].as_str() {
+        edit["formatted_body"] = json!(format!("* {}", formatted_body));
+    }
+    edit["m.new_content"] = new_content;
+    edit["m.relates_to"] = json!({ "rel_type": "m.replace", "event_id": event_id });
+    send::raw(client, room_id, "m.room.message", edit).await
+}
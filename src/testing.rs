@@ -0,0 +1,92 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use matrix_sdk::ruma::{
+    events::room::message::TextMessageEventContent, EventId, MilliSecondsSinceUnixEpoch, RoomId,
+    UInt, UserId,
+};
+
+use crate::{
+    clock::{self, FakeClock},
+    config::{self, Config},
+    error::FourwarderError,
+    pending::{self, PendingItem, NORMAL_PRIORITY},
+    state,
+};
+
+/// What the fake clock reads when a test starts, in milliseconds since the Unix epoch
+pub const START_MILLIS: u64 = 1_700_000_000_000;
+
+/// Held by whichever test is using the state and the clock, which are shared by the process
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// A test's hold on the state and the clock, until it is dropped
+pub struct Isolated {
+    _serial: MutexGuard<'static, ()>,
+    pub clock: Arc<FakeClock>,
+}
+
+/// Wait for any other test using the state or the clock, then start with empty state and a
+/// fake clock at [`START_MILLIS`]
+pub fn isolate() -> Isolated {
+    // A test which failed while holding it leaves nothing behind which `reset` doesn't clear
+    let serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    state::reset();
+    let clock = Arc::new(FakeClock::new(START_MILLIS));
+    clock::set(clock.clone());
+    Isolated {
+        _serial: serial,
+        clock,
+    }
+}
+
+/// A config with only the keys every config needs, plus the TOML in `extra`
+pub fn config(extra: &str) -> Config {
+    try_config(extra).unwrap()
+}
+
+/// [`config`], or what is wrong with it
+pub fn try_config(extra: &str) -> Result<Config, FourwarderError> {
+    let source = format!(
+        r#"
+homeserver = "https://example.org"
+username = "bot"
+password = "hunter2"
+mod_room_id = "!mod:example.org"
+output_room_id = "!output:example.org"
+input_room_id = "!input:example.org"
+{}
+"#,
+        extra
+    );
+    config::validate(&toml::from_str(&source).unwrap())
+}
+
+pub fn user(id: &str) -> UserId {
+    UserId::try_from(id).unwrap()
+}
+
+pub fn room(id: &str) -> RoomId {
+    RoomId::try_from(id).unwrap()
+}
+
+pub fn event(id: &str) -> EventId {
+    EventId::try_from(id).unwrap()
+}
+
+/// A submission `body` from `sender` in `!input:example.org`, waiting in the queue as
+/// `mod_event_id`
+pub fn queue(mod_event_id: &str, sender: &str, body: &str) -> EventId {
+    let mod_event_id = event(mod_event_id);
+    pending::insert(
+        mod_event_id.clone(),
+        PendingItem::new(
+            room("!input:example.org"),
+            None,
+            user(sender),
+            MilliSecondsSinceUnixEpoch(UInt::new_wrapping(START_MILLIS)),
+            TextMessageEventContent::plain(body),
+            NORMAL_PRIORITY,
+        ),
+    );
+    mod_event_id
+}
@@ -0,0 +1,104 @@
+use matrix_sdk::{
+    ruma::{events::room::message::MessageEventContent, EventId, RoomId},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::{
+    category::Category, config::Config, error::FourwarderError, instructions, send, state,
+};
+
+/// Held while a root is being created, so two approvals at once don't start two threads
+static CREATING: Mutex<()> = Mutex::const_new(());
+
+/// A thread we started for a category with `output_thread`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ThreadRoot {
+    pub room_id: RoomId,
+    pub event_id: EventId,
+}
+
+/// The root of the thread submissions approved toward `category` are published in, or `None` if
+/// they go in the output room's main timeline
+///
+/// Without an `output_thread_root`, the first submission starts a thread of its own with a
+/// pinned post naming the category, which is kept in the state file. A new one is started if the
+/// category's output room changes. In a dry run nothing is posted, so there is no thread.
+pub async fn root(
+    client: &Client,
+    config: &Config,
+    category: &Category,
+) -> Result<Option<EventId>, FourwarderError> {
+    if let Some(root) = &category.output_thread_root {
+        return Ok(Some(root.clone()));
+    }
+    if !category.output_thread {
+        return Ok(None);
+    }
+
+    let _creating = CREATING.lock().await;
+    let started = |state: &state::State| {
+        state
+            .thread_roots
+            .get(&category.name)
+            .filter(|root| root.room_id == category.output_room_id)
+            .map(|root| root.event_id.clone())
+    };
+    if let Some(event_id) = state::read(started) {
+        return Ok(Some(event_id));
+    }
+
+    let text = config
+        .messages
+        .get("thread_root", &[("category", &category.name)]);
+    let event_id = match send::message(
+        client,
+        &category.output_room_id,
+        MessageEventContent::notice_plain(text),
+    )
+    .await?
+    {
+        Some(event_id) => event_id,
+        None => return Ok(None),
+    };
+    tracing::info!(
+        "Started a thread for `{}` in {} as {}",
+        category.name,
+        category.output_room_id,
+        event_id
+    );
+    state::update(|state| {
+        state.thread_roots.insert(
+            category.name.clone(),
+            ThreadRoot {
+                room_id: category.output_room_id.clone(),
+                event_id: event_id.clone(),
+            },
+        )
+    });
+    // The thread works just as well unpinned, so failing to pin it isn't worth failing over
+    if let Err(e) =
+        instructions::pin(client, &category.output_room_id, event_id.clone(), None).await
+    {
+        tracing::warn!("Could not pin the thread for `{}`: {}", category.name, e);
+    }
+    Ok(Some(event_id))
+}
+
+/// Make `content` part of the thread starting at `root`, replying to the root for clients
+/// without threads
+pub fn relate(content: &mut Value, root: &EventId) {
+    if let Some(content) = content.as_object_mut() {
+        content.insert(
+            "m.relates_to".to_owned(),
+            json!({
+                "rel_type": "m.thread",
+                "event_id": root,
+                "is_falling_back": true,
+                "m.in_reply_to": { "event_id": root },
+            }),
+        );
+    }
+}
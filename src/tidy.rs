@@ -0,0 +1,178 @@
+use matrix_sdk::ruma::events::room::message::{
+    FormattedBody, MessageFormat, TextMessageEventContent,
+};
+
+use crate::cw::escape_html;
+
+/// Cleans up whitespace and formatting in text bound for an output room
+///
+/// The mod room copy is never tidied, so moderators see a submission as it was sent.
+pub struct Tidy {
+    trim: bool,
+    markdown: bool,
+}
+
+impl Tidy {
+    /// `trim` is `trim_output`, `markdown` is `markdown_to_html`
+    pub fn new(trim: bool, markdown: bool) -> Self {
+        Tidy { trim, markdown }
+    }
+
+    pub fn apply(&self, text: &TextMessageEventContent) -> TextMessageEventContent {
+        let mut text = text.clone();
+        if self.trim {
+            text.body = trim_plain(&text.body);
+            if let Some(formatted) = &mut text.formatted {
+                if formatted.format == MessageFormat::Html {
+                    formatted.body = trim_html(&formatted.body);
+                }
+            }
+        }
+        // Text which is already formatted is left as its sender formatted it
+        if self.markdown && text.formatted.is_none() {
+            text.formatted = markdown_to_html(&text.body).map(FormattedBody::html);
+        }
+        text
+    }
+}
+
+/// Trim the end of every line, and the blank lines around the text, and collapse runs of blank
+/// lines into one
+fn trim_plain(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim_end) {
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    if lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+/// Trim whitespace and line breaks around HTML, and collapse runs of more than two line breaks,
+/// which leave the same gap as a blank line
+fn trim_html(html: &str) -> String {
+    let breaks = ["<br>", "<br/>", "<br />"];
+    let mut trimmed = String::with_capacity(html.len());
+    // Starting as if after a run of breaks drops the ones at the start
+    let mut run = 2;
+    let mut rest = html.trim();
+    while !rest.is_empty() {
+        if let Some(tag) = breaks.iter().find(|tag| rest.starts_with(**tag)) {
+            run += 1;
+            if run <= 2 {
+                trimmed.push_str(tag);
+            }
+            rest = rest[tag.len()..].trim_start();
+            continue;
+        }
+        run = 0;
+        let next = rest.chars().next().expect("rest is not empty");
+        trimmed.push(next);
+        rest = &rest[next.len_utf8()..];
+    }
+
+    let mut trimmed = trimmed.as_str();
+    while let Some(tag) = breaks.iter().find(|tag| trimmed.ends_with(**tag)) {
+        trimmed = trimmed[..trimmed.len() - tag.len()].trim_end();
+    }
+    trimmed.to_owned()
+}
+
+/// HTML for plain text written with Markdown-ish markup, or `None` if it has none
+///
+/// Only the common inline markup is understood: `**bold**`, `*italics*` or `_italics_`,
+/// `` `code` `` and `[links](https://…)`. Blank lines separate paragraphs.
+fn markdown_to_html(text: &str) -> Option<String> {
+    let mut marked_up = false;
+    let paragraphs: Vec<String> = text
+        .split("\n\n")
+        .filter(|paragraph| !paragraph.trim().is_empty())
+        .map(|paragraph| {
+            let lines: Vec<String> = paragraph
+                .lines()
+                .map(|line| {
+                    let escaped = escape_html(line);
+                    let html = inline(&escaped);
+                    marked_up |= html != escaped;
+                    html
+                })
+                .collect();
+            format!("<p>{}</p>", lines.join("<br>"))
+        })
+        .collect();
+    marked_up.then(|| paragraphs.concat())
+}
+
+/// Turn the inline markup in one escaped line into HTML
+///
+/// Markup which isn't closed on the same line is left as it is.
+fn inline(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut previous: Option<char> = None;
+    while let Some(c) = rest.chars().next() {
+        if let Some((tag, consumed)) = markup(rest, previous) {
+            html.push_str(&tag);
+            previous = rest[..consumed].chars().last();
+            rest = &rest[consumed..];
+            continue;
+        }
+        html.push(c);
+        previous = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    html
+}
+
+/// The HTML for the markup `text` starts with, and how many bytes of `text` it covers
+fn markup(text: &str, previous: Option<char>) -> Option<(String, usize)> {
+    if let Some(code) = text.strip_prefix('`') {
+        let end = code.find('`').filter(|end| *end > 0)?;
+        return Some((format!("<code>{}</code>", &code[..end]), end + 2));
+    }
+    if let Some(strong) = text.strip_prefix("**") {
+        let inner = &strong[..strong.find("**")?];
+        return is_span(inner).then(|| {
+            (
+                format!("<strong>{}</strong>", inline(inner)),
+                inner.len() + 4,
+            )
+        });
+    }
+    if let Some(link) = text.strip_prefix('[') {
+        let label_end = link.find("](")?;
+        let target = &link[label_end + 2..];
+        let url = &target[..target.find(')')?];
+        let safe = url.starts_with("https://") || url.starts_with("http://");
+        return (safe && label_end > 0 && !url.contains(char::is_whitespace)).then(|| {
+            let label = inline(&link[..label_end]);
+            (
+                format!("<a href=\"{}\">{}</a>", url, label),
+                1 + label_end + 2 + url.len() + 1,
+            )
+        });
+    }
+
+    let delimiter = text.chars().next().filter(|c| *c == '*' || *c == '_')?;
+    // `snake_case` and `2*3*4` aren't emphasis
+    if previous.is_some_and(char::is_alphanumeric) {
+        return None;
+    }
+    let em = &text[1..];
+    let end = em.find(delimiter)?;
+    let inner = &em[..end];
+    if !is_span(inner) || em[end + 1..].starts_with(char::is_alphanumeric) {
+        return None;
+    }
+    Some((format!("<em>{}</em>", inline(inner)), end + 2))
+}
+
+/// Whether `inner` can be the text between a pair of delimiters, which it can't be if it is
+/// empty or starts or ends with a space
+fn is_span(inner: &str) -> bool {
+    !inner.is_empty() && inner.trim() == inner
+}
@@ -0,0 +1,36 @@
+use std::{future::Future, io, time::Duration};
+
+use crate::metrics;
+
+/// Wait at most `limit` for `request`, so a stalled connection can't hold up whatever is waiting
+/// on it
+///
+/// A request which takes longer fails with a timed out [`matrix_sdk::Error::Io`], which
+/// [`crate::error::classify`] sorts as a transport error, so it is handled like a dropped
+/// connection. It is logged as `operation` timing out, and counted in the
+/// `fourwarder_timeouts_total` metric.
+pub async fn within<T, E, Fut>(
+    operation: &'static str,
+    limit: Duration,
+    request: Fut,
+) -> Result<T, matrix_sdk::Error>
+where
+    Fut: Future<Output = Result<T, E>>,
+    E: Into<matrix_sdk::Error>,
+{
+    match tokio::time::timeout(limit, request).await {
+        Ok(response) => response.map_err(Into::into),
+        Err(_) => {
+            tracing::warn!("Gave up on {} after {}s", operation, limit.as_secs());
+            metrics::increment(
+                "fourwarder_timeouts_total",
+                "Matrix requests given up on for taking too long, by operation",
+                &[("operation", operation)],
+            );
+            Err(matrix_sdk::Error::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{} timed out after {}s", operation, limit.as_secs()),
+            )))
+        }
+    }
+}
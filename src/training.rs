@@ -0,0 +1,133 @@
+use matrix_sdk::{
+    ruma::{
+        events::room::message::{MessageEventContent, MessageType, TextMessageEventContent},
+        MilliSecondsSinceUnixEpoch, UInt, UserId,
+    },
+    Client,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{self, Action},
+    clock,
+    config::Config,
+    delivery::{Destination, Report},
+    error::FourwarderError,
+    pending::{self, PendingItem, NORMAL_PRIORITY},
+    queue, send, state,
+};
+
+/// What training submissions start with, in the mod room and wherever they are published
+const TAG: &str = "[TRAINING]";
+
+/// Harmless submissions to practice on, one picked for each `!train`
+const SAMPLES: &[&str] = &[
+    "Does anyone know a good place for lunch near the station?",
+    "Reminder that the community garden needs volunteers this weekend.",
+    "Just finished my first 10k, thanks for all the tips in here!",
+    "Found a blue umbrella on the number 12 bus this morning, message me if it's yours.",
+    "What is everyone reading at the moment?",
+];
+
+/// How many training submissions one moderator approved and rejected
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counts {
+    pub approved: u64,
+    pub rejected: u64,
+}
+
+/// Post a training submission to the mod room for `moderator`, for `!train`
+///
+/// It is queued like any other submission, but marked as training, so deciding on it goes to
+/// `training_output_room_id` or nowhere instead of a real output room. Its sender is the bot
+/// itself, so no one's karma changes.
+pub async fn post(
+    client: &Client,
+    config: &Config,
+    moderator: &UserId,
+) -> Result<String, FourwarderError> {
+    let messages = &config.messages;
+    let posted = || match &config.training_output_room_id {
+        Some(room_id) => messages.get("train_posted", &[("room", room_id)]),
+        None => messages.get("train_posted_nowhere", &[]),
+    };
+    let own_user_id = client.user_id().await.ok_or(FourwarderError::Logic(
+        "Posting a training submission before logging in",
+    ))?;
+    let sample = SAMPLES[(clock::now_millis() / 1000) as usize % SAMPLES.len()];
+    let content = TextMessageEventContent::plain(format!("{} {}", TAG, sample));
+
+    let mod_event_id = send::forwarded(
+        client,
+        &config.mod_room_id,
+        MessageEventContent::new(MessageType::Text(content.clone())),
+        None,
+    )
+    .await?;
+    let mod_event_id = match mod_event_id {
+        Some(mod_event_id) => mod_event_id,
+        // Dry run, so there is nothing to queue against
+        None => return Ok(posted()),
+    };
+
+    pending::insert(
+        mod_event_id.clone(),
+        PendingItem {
+            training: true,
+            ..PendingItem::new(
+                config.mod_room_id.clone(),
+                None,
+                own_user_id,
+                MilliSecondsSinceUnixEpoch(UInt::new_wrapping(clock::now_millis())),
+                content,
+                NORMAL_PRIORITY,
+            )
+        },
+    );
+    queue::check(client, config).await?;
+    audit::record(
+        Some(moderator),
+        Action::TrainingPosted {
+            event_id: mod_event_id,
+        },
+    );
+
+    Ok(posted())
+}
+
+/// Publish an approved training submission to `training_output_room_id`
+///
+/// Without one the report is empty, as there is nowhere it should go.
+pub async fn publish(client: &Client, config: &Config, text: &TextMessageEventContent) -> Report {
+    let mut report = Report::default();
+    if let Some(room_id) = &config.training_output_room_id {
+        let content = config.output_msgtype.content(text.clone());
+        let result = send::forwarded(client, room_id, content, None).await;
+        report.push(Destination::Room(room_id.clone()), result.map(|_| ()));
+    }
+    report
+}
+
+/// Count a decision by `moderator` on a training submission, apart from the real ones
+pub fn record(moderator: &UserId, approved: bool) {
+    state::update(|state| {
+        let counts = state.training.entry(moderator.clone()).or_default();
+        if approved {
+            counts.approved += 1;
+        } else {
+            counts.rejected += 1;
+        }
+    });
+}
+
+/// What each moderator decided on training submissions, most decisions first
+pub fn totals() -> Vec<(UserId, Counts)> {
+    let mut totals: Vec<(UserId, Counts)> =
+        state::read(|state| state.training.clone().into_iter().collect());
+    totals.sort_by(|(a_moderator, a), (b_moderator, b)| {
+        (b.approved + b.rejected)
+            .cmp(&(a.approved + a.rejected))
+            .then_with(|| a_moderator.as_str().cmp(b_moderator.as_str()))
+    });
+    totals
+}
@@ -0,0 +1,311 @@
+use std::ops::Range;
+
+use matrix_sdk::ruma::events::room::message::{MessageFormat, TextMessageEventContent};
+use serde::Deserialize;
+use url::Url;
+
+/// What to do with links in messages forwarded to an output room
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UrlPolicy {
+    /// Forward links unaltered
+    #[default]
+    Keep,
+    /// Remove every link
+    Strip,
+    /// Remove links unless their host is in `url_allowlist`
+    Allowlist,
+    /// Defang links unless their host is in `url_allowlist`, so they can't be clicked
+    Rewrite,
+}
+
+/// What happens to a single link
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Keep,
+    Strip,
+    Rewrite,
+}
+
+/// Applies a [`UrlPolicy`] to message bodies
+pub struct UrlFilter<'a> {
+    policy: UrlPolicy,
+    /// Lowercase hosts, each of which also allows its subdomains
+    allowlist: &'a [String],
+}
+
+impl<'a> UrlFilter<'a> {
+    pub fn new(policy: UrlPolicy, allowlist: &'a [String]) -> Self {
+        UrlFilter { policy, allowlist }
+    }
+
+    /// Apply the policy to both the plain and formatted body of a text message
+    pub fn apply(&self, text: &TextMessageEventContent) -> TextMessageEventContent {
+        if self.policy == UrlPolicy::Keep {
+            return text.clone();
+        }
+
+        let mut text = text.clone();
+        text.body = self.apply_plain(&text.body);
+        if let Some(formatted) = &mut text.formatted {
+            if formatted.format == MessageFormat::Html {
+                formatted.body = self.apply_html(&formatted.body);
+            }
+        }
+        text
+    }
+
+    fn action(&self, url: &str) -> Action {
+        match self.policy {
+            UrlPolicy::Keep => Action::Keep,
+            UrlPolicy::Strip => Action::Strip,
+            UrlPolicy::Allowlist if self.is_allowed(url) => Action::Keep,
+            UrlPolicy::Allowlist => Action::Strip,
+            UrlPolicy::Rewrite if self.is_allowed(url) => Action::Keep,
+            UrlPolicy::Rewrite => Action::Rewrite,
+        }
+    }
+
+    fn is_allowed(&self, url: &str) -> bool {
+        let host = match Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+        {
+            Some(host) => host,
+            None => return false,
+        };
+
+        self.allowlist.iter().any(|allowed| {
+            host == *allowed
+                || host
+                    .strip_suffix(allowed.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+    }
+
+    fn apply_plain(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+
+        for range in find_urls(text) {
+            let url = &text[range.clone()];
+            out.push_str(&text[last..range.start]);
+            match self.action(url) {
+                Action::Keep => out.push_str(url),
+                Action::Strip => {}
+                Action::Rewrite => out.push_str(&defang(url)),
+            }
+            last = range.end;
+        }
+        out.push_str(&text[last..]);
+
+        out
+    }
+
+    /// Apply the policy to the text of an HTML body, and to the `href` of its anchors
+    ///
+    /// Anchors whose link is not kept are unwrapped, leaving their text in place.
+    fn apply_html(&self, html: &str) -> String {
+        let mut out = String::with_capacity(html.len());
+        // The href of the anchor we are currently inside of, if we unwrapped it
+        let mut unwrapped: Option<(&str, Action)> = None;
+        let mut rest = html;
+
+        while let Some(tag_start) = rest.find('<') {
+            out.push_str(&self.apply_plain(&rest[..tag_start]));
+
+            let tag_len = rest[tag_start..]
+                .find('>')
+                .map_or(rest.len() - tag_start, |end| end + 1);
+            let tag = &rest[tag_start..tag_start + tag_len];
+            rest = &rest[tag_start + tag_len..];
+
+            let name = tag_name(tag);
+            if name.eq_ignore_ascii_case("a") {
+                // An anchor whose link we can't read could be hiding anything
+                let action = href(tag).map_or(Action::Strip, |href| self.action(href));
+                if action == Action::Keep {
+                    out.push_str(tag);
+                } else {
+                    unwrapped = Some((href(tag).unwrap_or_default(), action));
+                }
+            } else if name.eq_ignore_ascii_case("/a") && unwrapped.is_some() {
+                if let Some((href, Action::Rewrite)) = unwrapped {
+                    out.push_str(" (");
+                    out.push_str(&defang(href));
+                    out.push(')');
+                }
+                unwrapped = None;
+            } else {
+                out.push_str(tag);
+            }
+        }
+        out.push_str(&self.apply_plain(rest));
+
+        out
+    }
+}
+
+/// Find the byte ranges of every http(s) link in `text`
+pub fn find_urls(text: &str) -> Vec<Range<usize>> {
+    const SCHEMES: [&str; 2] = ["http://", "https://"];
+    // ASCII lowercasing keeps byte offsets the same
+    let lower = text.to_ascii_lowercase();
+
+    let mut urls = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = SCHEMES
+        .iter()
+        .filter_map(|scheme| lower[pos..].find(scheme))
+        .min()
+    {
+        let start = pos + start;
+        let len = text[start..]
+            .find(|c: char| c.is_whitespace() || "<>\"'".contains(c))
+            .unwrap_or(text.len() - start);
+
+        // Trailing punctuation is far more likely to end the sentence than the link
+        let mut end = start + len;
+        while text[start..end].ends_with(|c| ".,;:!?)]}".contains(c)) {
+            end -= 1;
+        }
+
+        let scheme_len = SCHEMES
+            .iter()
+            .find(|scheme| lower[start..].starts_with(*scheme))
+            .map_or(0, |scheme| scheme.len());
+        if end > start + scheme_len {
+            urls.push(start..end);
+        }
+        pos = start + len;
+    }
+
+    urls
+}
+
+/// Make a link unclickable while leaving it readable, e.g. `⚠️ hxxps://example[.]org/`
+fn defang(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    format!(
+        "⚠️ {}://{}",
+        scheme.replacen("tt", "xx", 1),
+        rest.replace('.', "[.]")
+    )
+}
+
+/// The name of an HTML tag such as `<a href="…">`, including the leading `/` of closing tags
+fn tag_name(tag: &str) -> &str {
+    let inner = tag.trim_start_matches('<');
+    let end = inner
+        .find(|c: char| c.is_whitespace() || c == '>')
+        .unwrap_or(inner.len());
+    &inner[..end]
+}
+
+/// The value of the `href` attribute of an HTML tag
+///
+/// Attribute names are matched ignoring case, with or without spaces around the `=`, and values
+/// can be quoted either way or not at all, as browsers accept all of them.
+fn href(tag: &str) -> Option<&str> {
+    let inner = tag.strip_prefix('<')?;
+    let inner = inner.strip_suffix('>').unwrap_or(inner);
+    let mut rest = inner.trim_start_matches(|c: char| !c.is_whitespace());
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return None;
+        }
+        let name_len = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = &rest[..name_len];
+        rest = rest[name_len..].trim_start();
+
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                let (value, len) = match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let value = &after[1..];
+                        let end = value.find(quote).unwrap_or(value.len());
+                        (&value[..end], (end + 2).min(after.len()))
+                    }
+                    _ => {
+                        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                        (&after[..end], end)
+                    }
+                };
+                rest = &after[len..];
+                Some(value)
+            }
+            None => None,
+        };
+        if name.eq_ignore_ascii_case("href") {
+            return value;
+        }
+        if name_len == 0 && value.is_none() {
+            // Nothing we can make sense of, so don't go round forever
+            rest = rest.get(1..)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn href_parsing() {
+        assert_eq!(href(r#"<a href="https://a.org">"#), Some("https://a.org"));
+        assert_eq!(href("<a HREF='https://a.org'>"), Some("https://a.org"));
+        assert_eq!(href(r#"<a href = "https://a.org">"#), Some("https://a.org"));
+        assert_eq!(href("<a href=https://a.org>"), Some("https://a.org"));
+        assert_eq!(
+            href(r#"<a data-href="https://b.org" href="https://a.org">"#),
+            Some("https://a.org")
+        );
+        assert_eq!(href(r#"<a title="x">"#), None);
+    }
+
+    #[test]
+    fn mixed_links_in_plain_text() {
+        let allowlist = vec!["example.org".to_owned()];
+        let filter = |policy| UrlFilter::new(policy, &allowlist);
+        let body = "see https://example.org/ok and https://evil.test/phish.";
+        assert_eq!(
+            filter(UrlPolicy::Allowlist).apply_plain(body),
+            "see https://example.org/ok and ."
+        );
+        assert_eq!(
+            filter(UrlPolicy::Rewrite).apply_plain(body),
+            "see https://example.org/ok and ⚠️ hxxps://evil[.]test/phish."
+        );
+        assert_eq!(filter(UrlPolicy::Strip).apply_plain(body), "see  and .");
+    }
+
+    #[test]
+    fn mixed_links_in_html() {
+        let allowlist = vec!["example.org".to_owned()];
+        let filter = |policy| UrlFilter::new(policy, &allowlist);
+        let html = concat!(
+            r#"<a href="https://docs.example.org/">docs</a> "#,
+            "<a href=https://evil.test>unquoted</a> ",
+            "<A HREF = 'https://evil.test/x'>spaced</A> ",
+            "<a>no link</a>"
+        );
+        assert_eq!(
+            filter(UrlPolicy::Allowlist).apply_html(html),
+            r#"<a href="https://docs.example.org/">docs</a> unquoted spaced no link"#
+        );
+        assert_eq!(
+            filter(UrlPolicy::Rewrite).apply_html(html),
+            concat!(
+                r#"<a href="https://docs.example.org/">docs</a> "#,
+                "unquoted (⚠️ hxxps://evil[.]test) ",
+                "spaced (⚠️ hxxps://evil[.]test/x) ",
+                "no link"
+            )
+        );
+    }
+}
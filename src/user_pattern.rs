@@ -0,0 +1,149 @@
+use matrix_sdk::ruma::UserId;
+
+use crate::error::FourwarderError;
+
+/// A user id, or a pattern matching many, as given in `admins`, `moderators`, `priority_senders`,
+/// `moderator_weights` and the `moderators` of a category
+///
+/// `*` in the localpart matches any run of characters, so `@bot_*:example.org` matches every
+/// user on example.org whose name starts with `bot_` and `@*:spam.example` matches everyone on
+/// spam.example. The server name has to be given in full.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserPattern {
+    localpart: String,
+    server_name: String,
+}
+
+impl UserPattern {
+    /// Returns `None` if `pattern` is neither a user id nor a pattern with `*` in its localpart
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let (localpart, server_name) = pattern.strip_prefix('@')?.split_once(':')?;
+        if !localpart.contains('*') {
+            UserId::try_from(pattern).ok()?;
+        }
+        if localpart.is_empty() || server_name.is_empty() || server_name.contains('*') {
+            return None;
+        }
+
+        Some(UserPattern {
+            localpart: localpart.to_owned(),
+            server_name: server_name.to_lowercase(),
+        })
+    }
+
+    /// Whether this names exactly one user, rather than matching many
+    pub fn is_exact(&self) -> bool {
+        !self.localpart.contains('*')
+    }
+
+    pub fn matches(&self, user: &UserId) -> bool {
+        user.server_name().as_str().to_lowercase() == self.server_name
+            && glob(&self.localpart, user.localpart())
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any run of characters
+fn glob(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // There is always a first part, even if it is empty
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match text.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let mut parts: Vec<&str> = parts.collect();
+    let last = match parts.pop() {
+        Some(last) => last,
+        // No `*` at all, so the first part had to be the whole text
+        None => return rest.is_empty(),
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Parse every pattern in `patterns`, failing with `error` if any is invalid
+pub fn parse_all(
+    patterns: &[String],
+    error: &'static str,
+) -> Result<Vec<UserPattern>, FourwarderError> {
+    patterns
+        .iter()
+        .map(|pattern| UserPattern::parse(pattern))
+        .collect::<Option<_>>()
+        .ok_or(FourwarderError::Config(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, user};
+
+    fn matches(pattern: &str, user_id: &str) -> bool {
+        UserPattern::parse(pattern).unwrap().matches(&user(user_id))
+    }
+
+    #[test]
+    fn literal() {
+        assert!(UserPattern::parse("@alice:example.org").unwrap().is_exact());
+        assert!(matches("@alice:example.org", "@alice:example.org"));
+        assert!(matches("@alice:Example.org", "@alice:example.org"));
+        assert!(!matches("@alice:example.org", "@alice2:example.org"));
+        assert!(!matches("@alice:example.org", "@alice:example.com"));
+    }
+
+    #[test]
+    fn localpart_glob() {
+        assert!(!UserPattern::parse("@bot_*:example.org").unwrap().is_exact());
+        assert!(matches("@bot_*:example.org", "@bot_:example.org"));
+        assert!(matches("@bot_*:example.org", "@bot_feeds:example.org"));
+        assert!(matches("@*_bot:example.org", "@feeds_bot:example.org"));
+        assert!(matches("@a*b*c:example.org", "@axxbyyc:example.org"));
+        assert!(!matches("@bot_*:example.org", "@robot_x:example.org"));
+        assert!(!matches("@bot_*:example.org", "@bot_x:example.com"));
+        assert!(!matches("@a*b*c:example.org", "@acb:example.org"));
+    }
+
+    #[test]
+    fn whole_server() {
+        assert!(matches("@*:staff.example.org", "@anyone:staff.example.org"));
+        assert!(!matches("@*:staff.example.org", "@anyone:example.org"));
+        assert!(!matches("@*:staff.example.org", "@staff:example.org"));
+    }
+
+    #[test]
+    fn invalid() {
+        for pattern in ["alice:example.org", "@alice", "@:example.org", "@*:*.org"] {
+            assert_eq!(UserPattern::parse(pattern), None, "{} parsed", pattern);
+        }
+    }
+
+    #[test]
+    fn every_list_matches_the_same_way() {
+        let config = testing::config(
+            r#"
+admins = ["@*:staff.example.org"]
+moderators = ["@*:staff.example.org"]
+priority_senders = ["@*:staff.example.org"]
+
+[moderator_weights]
+"@*:staff.example.org" = 2
+"@lead:staff.example.org" = 5
+"#,
+        );
+        let staff = user("@someone:staff.example.org");
+        let outsider = user("@someone:example.org");
+
+        assert!(config.is_admin(&staff) && !config.is_admin(&outsider));
+        assert!(config.is_moderator(&staff) && !config.is_moderator(&outsider));
+        assert!(config.is_trusted(&staff) && !config.is_trusted(&outsider));
+        assert_eq!(config.weight(&staff), 2);
+        assert_eq!(config.weight(&user("@lead:staff.example.org")), 5);
+        assert_eq!(config.weight(&outsider), 1);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{sync::LazyLock, time::Duration};
+
+use crate::error::FourwarderError;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Shared between every webhook so connections are reused
+pub static HTTP: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+/// What to do with a submission when the moderation webhook can't be reached
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FailMode {
+    /// Handle the submission as if there was no webhook
+    #[default]
+    Open,
+    /// Drop the submission
+    Closed,
+}
+
+/// The moderation webhook's decision on a submission
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Publish straight to the output room
+    Allow,
+    /// Drop the submission
+    Deny,
+    /// Send to the mod room
+    Review,
+}
+
+#[derive(Deserialize)]
+struct DecisionResponse {
+    decision: Decision,
+}
+
+/// The JSON body posted to the moderation webhook
+#[derive(Serialize, Debug)]
+pub struct Submission<'a> {
+    pub event_id: &'a str,
+    pub room_id: &'a str,
+    pub sender: &'a str,
+    pub body: &'a str,
+    pub formatted_body: Option<&'a str>,
+}
+
+/// An external service which decides what happens to each submission
+pub struct ModerationWebhook {
+    pub url: Url,
+    pub timeout: Duration,
+    pub fail_mode: FailMode,
+}
+
+impl ModerationWebhook {
+    /// Ask the webhook what to do with `submission`
+    ///
+    /// Returns `None` if the webhook failed and we fail open.
+    pub async fn decide(&self, submission: &Submission<'_>) -> Option<Decision> {
+        match self.request(submission).await {
+            Ok(decision) => Some(decision),
+            Err(e) => {
+                tracing::warn!(
+                    "Moderation webhook failed for {}, failing {:?}: {}",
+                    submission.event_id,
+                    self.fail_mode,
+                    e
+                );
+                match self.fail_mode {
+                    FailMode::Open => None,
+                    FailMode::Closed => Some(Decision::Deny),
+                }
+            }
+        }
+    }
+
+    async fn request(&self, submission: &Submission<'_>) -> Result<Decision, reqwest::Error> {
+        let response: DecisionResponse = HTTP
+            .post(self.url.clone())
+            .timeout(self.timeout)
+            .json(submission)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response.decision)
+    }
+}
+
+/// The JSON body posted to the output webhook for each published submission
+#[derive(Serialize, Debug)]
+pub struct Published {
+    pub body: String,
+    pub formatted_body: Option<String>,
+    /// These are `None` for submissions forwarded before the bot last started
+    pub sender: Option<String>,
+    /// Milliseconds since the Unix epoch at which the submission was originally posted
+    pub timestamp: Option<u64>,
+    pub source_room_id: Option<String>,
+}
+
+/// An external service which receives every published submission
+#[derive(Clone, Debug)]
+pub struct OutputWebhook {
+    pub url: Url,
+    pub timeout: Duration,
+    /// How many times to retry a failed POST before giving up
+    pub retries: u32,
+}
+
+impl OutputWebhook {
+    /// POST `published` to the webhook, retrying with exponential backoff
+    pub async fn deliver(&self, published: &Published) -> Result<(), FourwarderError> {
+        let mut delay = Duration::from_secs(1);
+        let mut attempt = 0;
+
+        loop {
+            let result = HTTP
+                .post(self.url.clone())
+                .timeout(self.timeout)
+                .json(published)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+
+            match result {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt < self.retries => {
+                    tracing::warn!(
+                        "Output webhook failed, retrying in {}s: {}",
+                        delay.as_secs(),
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(FourwarderError::Http(e)),
+            }
+        }
+    }
+}